@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Admin
+use crate::auth::extract_query_param;
+use crate::error::{GatewayApiError, IntoResponse};
+use crate::events::event_stream;
+use crate::state::AppState;
+use bytes::Bytes;
+use http::StatusCode;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::{Request, Response};
+use log::info;
+use serde_json::Value;
+use std::time::UNIX_EPOCH;
+
+pub fn json_response(
+    status: StatusCode,
+    body: &Value,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let json_vec = serde_json::to_vec(body)?;
+    let full_body = Full::from(Bytes::from(json_vec))
+        .map_err(|never| match never {})
+        .boxed();
+
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(full_body)?)
+}
+
+/// `DELETE /admin/cache[?model=...]`: purges the whole response cache, or only
+/// the entries produced by `model`.
+pub fn purge_cache<B>(
+    req: &Request<B>,
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let model = extract_query_param(req, "model");
+    let removed = match &model {
+        Some(model) => state.cache.invalidate_by_model(model),
+        None => state.cache.clear(),
+    };
+
+    info!(
+        "Purged {} cache entries (model: {})",
+        removed,
+        model.as_deref().unwrap_or("all")
+    );
+    json_response(
+        StatusCode::OK,
+        &serde_json::json!({ "removed": removed, "model": model }),
+    )
+}
+
+/// `GET /admin/cache/stats`: entry counts, hit and miss totals and the
+/// approximate memory held by the response cache.
+pub fn cache_stats(
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    json_response(
+        StatusCode::OK,
+        &serde_json::to_value(state.cache.get_stats())?,
+    )
+}
+
+/// `GET /admin/quota/{key_id}`: reports the remaining token allowance of a
+/// client API key, named by its `api_key_id` so the key stays out of URLs.
+pub fn quota_status(
+    state: &AppState,
+    key_id: &str,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let key_id = percent_encoding::percent_decode_str(key_id).decode_utf8_lossy();
+    let Some(quota) = state.config.security.get_quota_by_key_id(&key_id) else {
+        return Ok(GatewayApiError::client_error(
+            StatusCode::NOT_FOUND,
+            "No quota is configured for this API key",
+            "quota_not_found",
+        )
+        .into_response());
+    };
+
+    json_response(
+        StatusCode::OK,
+        &serde_json::to_value(state.quota.status(quota))?,
+    )
+}
+
+/// `GET /admin/events`: streams the routing decision of every request
+/// proxied while the client stays connected, as server-sent events.
+pub fn routing_events(
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(event_stream(state.events.subscribe()))?)
+}
+
+/// `GET /admin/config`: the live config with secrets redacted, and when it
+/// was loaded as Unix seconds.
+pub fn effective_config(
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let (config, loaded_at) = state.config_manager.current_with_load_time();
+    let loaded_at = loaded_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    json_response(
+        StatusCode::OK,
+        &serde_json::json!({
+            "config_loaded_at": loaded_at,
+            "config": config.sanitized(),
+        }),
+    )
+}
+
+/// `POST /admin/reload`: re-reads and validates the config file, keeping the
+/// current config when the new one is invalid.
+pub async fn reload_config(
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    match state.config_manager.reload_off_runtime().await {
+        Ok(config) => json_response(
+            StatusCode::OK,
+            &serde_json::json!({
+                "status": "reloaded",
+                "policies": config.policies.len(),
+            }),
+        ),
+        Err(e) => Ok(GatewayApiError::client_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Config reload failed, keeping the current config: {}", e),
+            "invalid_config",
+        )
+        .into_response()),
+    }
+}
@@ -0,0 +1,545 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Anthropic
+//!
+//! Translates Anthropic Messages API requests (`/v1/messages`) into OpenAI
+//! chat completions and the responses, including SSE streams, back again.
+//! The opposite direction serves OpenAI requests from LLMs whose
+//! `provider_type` is `anthropic`.
+use crate::error::GatewayApiError;
+use bytes::Bytes;
+use http_body::{Body, Frame};
+use http_body_util::combinators::BoxBody;
+use pin_project_lite::pin_project;
+use serde_json::{json, Map, Value};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MESSAGES_PATH: &str = "/v1/messages";
+pub const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+
+/// Sampling parameters that mean the same thing in both APIs.
+const PASSTHROUGH_FIELDS: [&str; 5] = ["model", "temperature", "top_p", "stream", "nim-llm-router"];
+
+/// `max_tokens` sent to Anthropic, which requires it, when an OpenAI request
+/// leaves it out.
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+fn invalid(message: &str) -> GatewayApiError {
+    GatewayApiError::InvalidRequest {
+        message: message.to_string(),
+    }
+}
+
+/// Flattens a string or an array of content blocks into plain text. Only
+/// `text` blocks carry over; other block types are dropped.
+fn content_to_text(content: &Value) -> Option<String> {
+    match content {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(blocks) => Some(
+            blocks
+                .iter()
+                .filter(|block| block["type"] == "text")
+                .filter_map(|block| block["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        _ => None,
+    }
+}
+
+/// Converts an Anthropic Messages request body into an OpenAI chat
+/// completions request body.
+pub fn to_openai_request(body: &Value) -> Result<Value, GatewayApiError> {
+    let request = body
+        .as_object()
+        .ok_or_else(|| invalid("Request body must be a JSON object"))?;
+    let max_tokens = request
+        .get("max_tokens")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("'max_tokens' is required"))?;
+    let input_messages = request
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid("'messages' must be an array"))?;
+
+    let mut messages = Vec::with_capacity(input_messages.len() + 1);
+    if let Some(system) = request.get("system") {
+        let system = content_to_text(system).ok_or_else(|| invalid("Invalid 'system' field"))?;
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    for message in input_messages {
+        let role = message["role"]
+            .as_str()
+            .ok_or_else(|| invalid("Each message requires a 'role'"))?;
+        let content = content_to_text(&message["content"])
+            .ok_or_else(|| invalid("Each message requires 'content'"))?;
+        messages.push(json!({ "role": role, "content": content }));
+    }
+
+    let mut openai = Map::new();
+    for field in PASSTHROUGH_FIELDS {
+        if let Some(value) = request.get(field) {
+            openai.insert(field.to_string(), value.clone());
+        }
+    }
+    if let Some(stop) = request.get("stop_sequences") {
+        openai.insert("stop".to_string(), stop.clone());
+    }
+    openai.insert("max_tokens".to_string(), json!(max_tokens));
+    openai.insert("messages".to_string(), Value::Array(messages));
+    Ok(Value::Object(openai))
+}
+
+fn stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+fn usage(usage: &Value) -> Value {
+    json!({
+        "input_tokens": usage["prompt_tokens"].as_u64().unwrap_or(0),
+        "output_tokens": usage["completion_tokens"].as_u64().unwrap_or(0),
+    })
+}
+
+/// Converts an OpenAI chat completion response body into an Anthropic
+/// message.
+pub fn to_anthropic_response(body: &Value) -> Value {
+    let choice = &body["choices"][0];
+    let text = choice["message"]["content"].as_str().unwrap_or_default();
+    json!({
+        "id": body["id"],
+        "type": "message",
+        "role": "assistant",
+        "model": body["model"],
+        "content": [{ "type": "text", "text": text }],
+        "stop_reason": choice["finish_reason"].as_str().map(stop_reason),
+        "stop_sequence": Value::Null,
+        "usage": usage(&body["usage"]),
+    })
+}
+
+/// Converts a buffered OpenAI response body, leaving bodies that are not
+/// JSON untouched.
+pub fn convert_response_body(body: &Bytes) -> Bytes {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(json) => Bytes::from(to_anthropic_response(&json).to_string()),
+        Err(_) => body.clone(),
+    }
+}
+
+/// Converts an OpenAI chat completions request body into an Anthropic
+/// Messages request body: system messages move to `system`, and
+/// `max_tokens`, which Anthropic requires, is always set.
+pub fn to_anthropic_request(body: &Value) -> Value {
+    let mut system = Vec::new();
+    let mut messages = Vec::new();
+    for message in body["messages"].as_array().into_iter().flatten() {
+        let content = content_to_text(&message["content"]).unwrap_or_default();
+        match message["role"].as_str() {
+            Some("system") | Some("developer") => system.push(content),
+            role => messages.push(json!({ "role": role, "content": content })),
+        }
+    }
+
+    let mut anthropic = Map::new();
+    for field in PASSTHROUGH_FIELDS {
+        if let Some(value) = body.get(field) {
+            anthropic.insert(field.to_string(), value.clone());
+        }
+    }
+    if !system.is_empty() {
+        anthropic.insert("system".to_string(), json!(system.join("\n")));
+    }
+    match &body["stop"] {
+        Value::String(stop) => {
+            anthropic.insert("stop_sequences".to_string(), json!([stop]));
+        }
+        stop @ Value::Array(_) => {
+            anthropic.insert("stop_sequences".to_string(), stop.clone());
+        }
+        _ => {}
+    }
+    let max_tokens = body["max_completion_tokens"]
+        .as_u64()
+        .or(body["max_tokens"].as_u64())
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    anthropic.insert("max_tokens".to_string(), json!(max_tokens));
+    anthropic.insert("messages".to_string(), Value::Array(messages));
+    Value::Object(anthropic)
+}
+
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// Converts an Anthropic message into an OpenAI chat completion response
+/// body.
+pub fn to_openai_response(body: &Value) -> Value {
+    let text = content_to_text(&body["content"]).unwrap_or_default();
+    let input_tokens = body["usage"]["input_tokens"].as_u64().unwrap_or(0);
+    let output_tokens = body["usage"]["output_tokens"].as_u64().unwrap_or(0);
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    json!({
+        "id": body["id"],
+        "object": "chat.completion",
+        "created": created,
+        "model": body["model"],
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text },
+            "finish_reason": body["stop_reason"].as_str().map(finish_reason),
+        }],
+        "usage": {
+            "prompt_tokens": input_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": input_tokens + output_tokens,
+        },
+    })
+}
+
+/// Rewrites OpenAI chat completion chunks into Anthropic stream events.
+#[derive(Debug, Default)]
+pub struct StreamTranslator {
+    buffer: String,
+    started: bool,
+    stop_reason: Option<&'static str>,
+    usage: Option<Value>,
+    finished: bool,
+}
+
+fn event(name: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+impl StreamTranslator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds upstream bytes and returns the Anthropic events they complete.
+    /// Partial SSE events are held until the rest arrives.
+    pub fn push(&mut self, chunk: &[u8]) -> String {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let mut output = String::new();
+        while let Some(end) = self.buffer.find("\n\n") {
+            let raw: String = self.buffer.drain(..end + 2).collect();
+            for line in raw.lines() {
+                if let Some(data) = line.trim().strip_prefix("data:") {
+                    output.push_str(&self.translate(data.trim()));
+                } else if line.starts_with(':') {
+                    // Comments such as keep-alives pass through unchanged.
+                    output.push_str(line);
+                    output.push_str("\n\n");
+                }
+            }
+        }
+        output
+    }
+
+    /// Emits the closing events if the upstream stream ended without them.
+    pub fn finish(&mut self) -> String {
+        if self.finished || !self.started {
+            return String::new();
+        }
+        self.finished = true;
+        let mut output = event(
+            "content_block_stop",
+            json!({ "type": "content_block_stop", "index": 0 }),
+        );
+        output.push_str(&event(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": self.stop_reason.unwrap_or("end_turn"), "stop_sequence": Value::Null },
+                "usage": self.usage.clone().unwrap_or_else(|| json!({ "output_tokens": 0 })),
+            }),
+        ));
+        output.push_str(&event("message_stop", json!({ "type": "message_stop" })));
+        output
+    }
+
+    fn translate(&mut self, data: &str) -> String {
+        if data == "[DONE]" {
+            return self.finish();
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return String::new();
+        };
+
+        let mut output = String::new();
+        if !self.started {
+            self.started = true;
+            output.push_str(&event(
+                "message_start",
+                json!({
+                    "type": "message_start",
+                    "message": {
+                        "id": chunk["id"],
+                        "type": "message",
+                        "role": "assistant",
+                        "model": chunk["model"],
+                        "content": [],
+                        "stop_reason": Value::Null,
+                        "stop_sequence": Value::Null,
+                        "usage": { "input_tokens": 0, "output_tokens": 0 },
+                    },
+                }),
+            ));
+            output.push_str(&event(
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": 0,
+                    "content_block": { "type": "text", "text": "" },
+                }),
+            ));
+        }
+
+        let choice = &chunk["choices"][0];
+        if let Some(text) = choice["delta"]["content"]
+            .as_str()
+            .filter(|t| !t.is_empty())
+        {
+            output.push_str(&event(
+                "content_block_delta",
+                json!({
+                    "type": "content_block_delta",
+                    "index": 0,
+                    "delta": { "type": "text_delta", "text": text },
+                }),
+            ));
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.stop_reason = Some(stop_reason(reason));
+        }
+        if chunk["usage"].is_object() {
+            self.usage = Some(usage(&chunk["usage"]));
+        }
+        output
+    }
+}
+
+pin_project! {
+    /// Wraps an OpenAI SSE response body and re-emits it as Anthropic events.
+    pub struct AnthropicStream {
+        #[pin]
+        inner: BoxBody<Bytes, GatewayApiError>,
+        translator: StreamTranslator,
+        done: bool,
+    }
+}
+
+impl AnthropicStream {
+    pub fn new(inner: BoxBody<Bytes, GatewayApiError>) -> Self {
+        AnthropicStream {
+            inner,
+            translator: StreamTranslator::new(),
+            done: false,
+        }
+    }
+}
+
+impl Body for AnthropicStream {
+    type Data = Bytes;
+    type Error = GatewayApiError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            match this.inner.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    let Ok(data) = frame.into_data() else {
+                        continue;
+                    };
+                    let translated = this.translator.push(&data);
+                    if !translated.is_empty() {
+                        return Poll::Ready(Some(Ok(Frame::data(Bytes::from(translated)))));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    let tail = this.translator.finish();
+                    if tail.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(Frame::data(Bytes::from(tail)))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let body = json!({
+            "model": "claude",
+            "max_tokens": 256,
+            "system": [{ "type": "text", "text": "Be brief." }],
+            "messages": [
+                { "role": "user", "content": [{ "type": "text", "text": "Hello" }] },
+                { "role": "assistant", "content": "Hi" },
+            ],
+            "stop_sequences": ["END"],
+            "nim-llm-router": { "policy": "task_router" },
+        });
+        let openai = to_openai_request(&body).unwrap();
+        assert_eq!(openai["max_tokens"], 256);
+        assert_eq!(openai["stop"], json!(["END"]));
+        assert_eq!(openai["nim-llm-router"]["policy"], "task_router");
+        assert_eq!(
+            openai["messages"],
+            json!([
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Hello" },
+                { "role": "assistant", "content": "Hi" },
+            ])
+        );
+
+        let missing = json!({ "messages": [] });
+        assert!(to_openai_request(&missing).is_err());
+    }
+
+    #[test]
+    fn test_response_translation() {
+        let body = json!({
+            "id": "chatcmpl-1",
+            "model": "meta/llama-3.1-8b-instruct",
+            "choices": [{ "message": { "role": "assistant", "content": "Hi!" }, "finish_reason": "length" }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7 },
+        });
+        let message = to_anthropic_response(&body);
+        assert_eq!(message["type"], "message");
+        assert_eq!(message["content"][0]["text"], "Hi!");
+        assert_eq!(message["stop_reason"], "max_tokens");
+        assert_eq!(
+            message["usage"],
+            json!({ "input_tokens": 5, "output_tokens": 2 })
+        );
+    }
+
+    #[test]
+    fn test_openai_request_and_anthropic_message_round_trip() {
+        let request = json!({
+            "model": "claude-sonnet",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [{"type": "text", "text": "Hi"}]}
+            ],
+            "max_completion_tokens": 64,
+            "stop": "END",
+            "temperature": 0.2,
+            "stream_options": {"include_usage": true}
+        });
+        assert_eq!(
+            to_anthropic_request(&request),
+            json!({
+                "model": "claude-sonnet",
+                "system": "Be brief.",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_tokens": 64,
+                "stop_sequences": ["END"],
+                "temperature": 0.2
+            })
+        );
+        let without_limit = json!({"messages": [{"role": "user", "content": "Hi"}]});
+        assert_eq!(
+            to_anthropic_request(&without_limit)["max_tokens"],
+            DEFAULT_MAX_TOKENS
+        );
+
+        // As returned by the Messages API.
+        let message = json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet",
+            "content": [{"type": "text", "text": "Hello!"}],
+            "stop_reason": "max_tokens",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 12, "output_tokens": 6}
+        });
+        let response = to_openai_response(&message);
+        assert_eq!(response["id"], "msg_01");
+        assert_eq!(response["object"], "chat.completion");
+        assert_eq!(
+            response["choices"],
+            json!([{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hello!"},
+                "finish_reason": "length"
+            }])
+        );
+        assert_eq!(
+            response["usage"],
+            json!({"prompt_tokens": 12, "completion_tokens": 6, "total_tokens": 18})
+        );
+    }
+
+    #[test]
+    fn test_stream_translation_handles_split_events() {
+        let mut translator = StreamTranslator::new();
+        let first = r#"data: {"id":"c1","model":"m","choices":[{"delta":{"content":"Hel"}}]}"#;
+        let mut output = translator.push(&first.as_bytes()[..20]);
+        assert!(output.is_empty());
+        output.push_str(&translator.push(format!("{}\n\n", &first[20..]).as_bytes()));
+        output.push_str(&translator.push(
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n",
+        ));
+
+        let events: Vec<&str> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert!(output.contains(r#""stop_reason":"end_turn""#));
+        assert!(translator.finish().is_empty());
+    }
+}
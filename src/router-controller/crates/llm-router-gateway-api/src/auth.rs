@@ -0,0 +1,520 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Auth
+use crate::config::{ExternalAuthConfig, HmacConfig, SecurityConfig};
+use crate::error::GatewayApiError;
+use http::{HeaderMap, StatusCode, Uri};
+use hyper::Request;
+use log::warn;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::header::AUTHORIZATION;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const SIGNATURE_HEADER: &str = "x-signature";
+pub const TIMESTAMP_HEADER: &str = "x-timestamp";
+
+/// Query parameters a client key may be sent in.
+const CLIENT_KEY_PARAMS: [&str; 2] = ["api_key", "api-key"];
+
+/// Compares two secrets without short-circuiting on the first mismatch.
+pub fn secrets_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && openssl::memcmp::eq(provided.as_bytes(), expected.as_bytes())
+}
+
+pub fn extract_bearer_token<B>(req: &Request<B>) -> Option<&str> {
+    bearer_token(req.headers())
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value).trim())
+}
+
+/// The client key sent with a request: the first of `api_key_headers`
+/// present, else the `api_key` or `api-key` query parameter.
+pub fn provided_client_key(
+    headers: &HeaderMap,
+    uri: &Uri,
+    security: &SecurityConfig,
+) -> Option<String> {
+    security
+        .api_key_headers
+        .iter()
+        .find_map(|name| {
+            if name.eq_ignore_ascii_case(AUTHORIZATION.as_str()) {
+                bearer_token(headers)
+            } else {
+                headers
+                    .get(name.as_str())
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::trim)
+            }
+        })
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .or_else(|| {
+            let query = uri.query()?;
+            form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| CLIENT_KEY_PARAMS.contains(&name.as_ref()))
+                .map(|(_, value)| value.into_owned())
+        })
+}
+
+/// `query` without the client key parameters, so the key is neither logged
+/// nor sent upstream. Other parameters are kept as sent.
+pub fn without_client_key_params(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| {
+            !form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .is_some_and(|(name, _)| CLIENT_KEY_PARAMS.contains(&name.as_ref()))
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Returns the configured client key sent with the request, if any.
+pub fn authenticate_client_key<'a>(
+    headers: &HeaderMap,
+    uri: &Uri,
+    security: &'a SecurityConfig,
+) -> Option<&'a str> {
+    let provided = provided_client_key(headers, uri, security)?;
+    security
+        .api_keys
+        .keys()
+        .into_iter()
+        .find(|expected| secrets_match(&provided, expected))
+}
+
+pub fn extract_query_param<B>(req: &Request<B>, name: &str) -> Option<String> {
+    req.uri().query().and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+/// Checks access to `/metrics`. When `metrics_api_key` is unset the endpoint
+/// stays open; otherwise the key must be sent as a bearer token or `?token=`.
+pub fn is_metrics_request_authorized<B>(req: &Request<B>, security: &SecurityConfig) -> bool {
+    let Some(expected) = security.metrics_api_key.as_deref() else {
+        return true;
+    };
+
+    extract_bearer_token(req).is_some_and(|provided| secrets_match(provided, expected))
+        || extract_query_param(req, "token")
+            .is_some_and(|provided| secrets_match(&provided, expected))
+}
+
+/// Checks access to `/admin/*` routes. The key must be sent as a bearer
+/// token; without an `admin_api_key` the routes are disabled.
+pub fn is_admin_request_authorized<B>(req: &Request<B>, security: &SecurityConfig) -> bool {
+    match security.admin_api_key.as_deref() {
+        Some(expected) => {
+            extract_bearer_token(req).is_some_and(|provided| secrets_match(provided, expected))
+        }
+        None => false,
+    }
+}
+
+/// Verifies `X-Signature`: the hex HMAC-SHA256 of `"{X-Timestamp}.{body}"`
+/// under the shared secret, optionally prefixed with `sha256=`.
+#[derive(Debug, Clone)]
+pub struct HmacLayer<'a> {
+    config: &'a HmacConfig,
+}
+
+fn invalid_signature(message: &str) -> GatewayApiError {
+    GatewayApiError::client_error(StatusCode::UNAUTHORIZED, message, "invalid_signature")
+}
+
+impl<'a> HmacLayer<'a> {
+    pub fn new(config: &'a HmacConfig) -> Self {
+        HmacLayer { config }
+    }
+
+    pub fn sign(&self, timestamp: &str, body: &[u8]) -> String {
+        let key = PKey::hmac(self.config.secret.as_bytes()).expect("HMAC key");
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("HMAC signer");
+        signer.update(timestamp.as_bytes()).expect("HMAC update");
+        signer.update(b".").expect("HMAC update");
+        signer.update(body).expect("HMAC update");
+        signer
+            .sign_to_vec()
+            .expect("HMAC sign")
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Returns `Ok(true)` for a valid signature and `Ok(false)` for an
+    /// unsigned request that may still authenticate some other way.
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<bool, GatewayApiError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        self.verify_at(headers, body, now)
+    }
+
+    fn verify_at(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: i64,
+    ) -> Result<bool, GatewayApiError> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let Some(signature) = header(SIGNATURE_HEADER) else {
+            if self.config.required {
+                return Err(invalid_signature("Missing X-Signature header"));
+            }
+            return Ok(false);
+        };
+        let timestamp = header(TIMESTAMP_HEADER)
+            .ok_or_else(|| invalid_signature("Missing X-Timestamp header"))?;
+        let sent_at: i64 = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| invalid_signature("X-Timestamp must be a Unix timestamp in seconds"))?;
+        if now.abs_diff(sent_at) > self.config.tolerance_secs {
+            return Err(invalid_signature(
+                "X-Timestamp is outside the allowed clock skew",
+            ));
+        }
+
+        let provided = signature.trim();
+        let provided = provided.strip_prefix("sha256=").unwrap_or(provided);
+        if !secrets_match(&provided.to_ascii_lowercase(), &self.sign(timestamp, body)) {
+            return Err(invalid_signature("Request signature does not match"));
+        }
+        Ok(true)
+    }
+}
+
+/// Time the external auth check took, carried in the request extensions so
+/// `auth_duration_seconds` covers every authentication step.
+#[derive(Debug, Clone, Copy)]
+pub struct ExternalAuthDuration(pub Duration);
+
+/// Authorizes client requests with an external auth service, remembering
+/// allowed `Authorization` values for `cache_ttl_secs`.
+#[derive(Debug, Default)]
+pub struct ExternalAuthLayer {
+    /// Expiry of each allowed credential, keyed by its SHA-256 so the
+    /// credentials themselves are not kept.
+    allowed: Mutex<HashMap<[u8; 32], Instant>>,
+}
+
+impl ExternalAuthLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the request on a `2xx` from the auth service. Its `401` and
+    /// `403` are passed on, other statuses become `401`, and an unreachable
+    /// or failing service rejects the request with `503`.
+    pub async fn check(
+        &self,
+        client: &reqwest::Client,
+        config: &ExternalAuthConfig,
+        headers: &HeaderMap,
+    ) -> Result<(), GatewayApiError> {
+        let authorization = headers.get(AUTHORIZATION);
+        let key = openssl::sha::sha256(authorization.map_or(&[][..], |value| value.as_bytes()));
+        let now = Instant::now();
+        {
+            let mut allowed = self.allowed.lock().expect("external auth lock poisoned");
+            match allowed.get(&key) {
+                Some(expires_at) if *expires_at > now => return Ok(()),
+                Some(_) => {
+                    allowed.remove(&key);
+                }
+                None => {}
+            }
+        }
+
+        let mut request = client
+            .get(&config.url)
+            .timeout(Duration::from_millis(config.timeout_ms));
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        let status = match request.send().await {
+            Ok(response) => response.status(),
+            Err(e) => {
+                warn!("External auth service is unreachable: {}", e);
+                return Err(auth_service_unavailable());
+            }
+        };
+
+        if status.is_success() {
+            let ttl = Duration::from_secs(config.cache_ttl_secs);
+            if !ttl.is_zero() {
+                let mut allowed = self.allowed.lock().expect("external auth lock poisoned");
+                allowed.retain(|_, expires_at| *expires_at > now);
+                allowed.insert(key, now + ttl);
+            }
+            return Ok(());
+        }
+        if status.is_server_error() {
+            warn!("External auth service returned {}", status);
+            return Err(auth_service_unavailable());
+        }
+        let (status, message) = match status {
+            StatusCode::FORBIDDEN => (status, "Access denied by the auth service"),
+            _ => (
+                StatusCode::UNAUTHORIZED,
+                "Missing or invalid credentials for the auth service",
+            ),
+        };
+        Err(GatewayApiError::client_error(
+            status,
+            message,
+            "authentication_error",
+        ))
+    }
+}
+
+fn auth_service_unavailable() -> GatewayApiError {
+    GatewayApiError::client_error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The auth service could not be reached; the request was not authorized",
+        "auth_service_unavailable",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn security(metrics_api_key: Option<&str>) -> SecurityConfig {
+        SecurityConfig {
+            metrics_api_key: metrics_api_key.map(str::to_string),
+            ..SecurityConfig::default()
+        }
+    }
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(value) = authorization {
+            builder = builder.header(AUTHORIZATION, value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    fn hmac(required: bool) -> HmacConfig {
+        HmacConfig {
+            secret: "shared-secret".to_string(),
+            required,
+            tolerance_secs: 300,
+        }
+    }
+
+    fn signed_headers(signature: &str, timestamp: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+        headers.insert(TIMESTAMP_HEADER, timestamp.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_hmac_signature_and_clock_skew() {
+        let config = hmac(false);
+        let layer = HmacLayer::new(&config);
+        let body = br#"{"messages":[]}"#;
+        let signature = layer.sign("1700000000", body);
+
+        let headers = signed_headers(&format!("sha256={signature}"), "1700000000");
+        assert!(layer.verify_at(&headers, body, 1_700_000_100).unwrap());
+        // Tampered body, replayed request and unsigned request.
+        assert!(layer.verify_at(&headers, b"{}", 1_700_000_100).is_err());
+        assert!(layer.verify_at(&headers, body, 1_700_000_301).is_err());
+        assert!(!layer.verify_at(&HeaderMap::new(), body, 0).unwrap());
+
+        let config = hmac(true);
+        let err = HmacLayer::new(&config)
+            .verify_at(&HeaderMap::new(), body, 0)
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_client_keys_with_and_without_scopes() {
+        let security: SecurityConfig = serde_yaml::from_str(
+            r#"
+api_keys:
+  cheap-key:
+    allowed_policies: [cheap]
+  full-key: {}
+"#,
+        )
+        .unwrap();
+        let uri = Uri::from_static("/v1/chat/completions");
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer cheap-key".parse().unwrap());
+        let key = authenticate_client_key(&headers, &uri, &security).unwrap();
+        assert!(security.api_keys.allows_policy(key, "cheap"));
+        assert!(!security.api_keys.allows_policy(key, "premium"));
+        assert!(security.api_keys.allows_policy("full-key", "premium"));
+
+        headers.insert(AUTHORIZATION, "Bearer unknown".parse().unwrap());
+        assert!(authenticate_client_key(&headers, &uri, &security).is_none());
+
+        let plain: SecurityConfig = serde_yaml::from_str("api_keys: [plain-key]").unwrap();
+        assert!(plain.api_keys.allows_policy("plain-key", "premium"));
+    }
+
+    #[test]
+    fn test_client_key_headers_in_order_then_query() {
+        let security: SecurityConfig =
+            serde_yaml::from_str("api_key_headers: [X-Api-Key, Authorization]").unwrap();
+        let uri = Uri::from_static("/v1/chat/completions?api-key=from-query");
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer from-bearer".parse().unwrap());
+        assert_eq!(
+            provided_client_key(&headers, &uri, &security).as_deref(),
+            Some("from-bearer")
+        );
+        headers.insert("x-api-key", "from-header".parse().unwrap());
+        assert_eq!(
+            provided_client_key(&headers, &uri, &security).as_deref(),
+            Some("from-header")
+        );
+        assert_eq!(
+            provided_client_key(&HeaderMap::new(), &uri, &security).as_deref(),
+            Some("from-query")
+        );
+    }
+
+    #[test]
+    fn test_client_key_params_are_removed_from_query() {
+        assert_eq!(
+            without_client_key_params("api_key=secret&stream=true&api%2Dkey=other&q=a%20b"),
+            "stream=true&q=a%20b"
+        );
+        assert_eq!(without_client_key_params("api-key=secret"), "");
+    }
+
+    #[test]
+    fn test_metrics_open_without_key() {
+        assert!(is_metrics_request_authorized(
+            &request("/metrics", None),
+            &security(None)
+        ));
+    }
+
+    #[test]
+    fn test_admin_closed_without_key() {
+        let mut security = security(None);
+        let request = request("/admin/config", Some("Bearer anything"));
+        assert!(!is_admin_request_authorized(&request, &security));
+        security.admin_api_key = Some("anything".to_string());
+        assert!(is_admin_request_authorized(&request, &security));
+    }
+
+    #[test]
+    fn test_metrics_key_via_header_or_query() {
+        let security = security(Some("scrape-secret"));
+        assert!(!is_metrics_request_authorized(
+            &request("/metrics", None),
+            &security
+        ));
+        assert!(!is_metrics_request_authorized(
+            &request("/metrics", Some("Bearer wrong")),
+            &security
+        ));
+        assert!(is_metrics_request_authorized(
+            &request("/metrics", Some("Bearer scrape-secret")),
+            &security
+        ));
+        assert!(is_metrics_request_authorized(
+            &request("/metrics?token=scrape-secret", None),
+            &security
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_external_auth_mirrors_service_and_caches_allowed_credentials() {
+        use wiremock::matchers::{header, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let auth = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("authorization", "Bearer good"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&auth)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("authorization", "Bearer banned"))
+            .respond_with(ResponseTemplate::new(403))
+            .expect(2)
+            .mount(&auth)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&auth)
+            .await;
+
+        let layer = ExternalAuthLayer::new();
+        let client = reqwest::Client::new();
+        let config = ExternalAuthConfig {
+            url: format!("{}/check", auth.uri()),
+            timeout_ms: 1000,
+            cache_ttl_secs: 60,
+        };
+        let headers = |authorization: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(value) = authorization {
+                headers.insert(AUTHORIZATION, value.parse().unwrap());
+            }
+            headers
+        };
+        let status = |result: Result<(), GatewayApiError>| result.unwrap_err().status_code();
+
+        for _ in 0..2 {
+            layer
+                .check(&client, &config, &headers(Some("Bearer good")))
+                .await
+                .unwrap();
+            let denied = layer
+                .check(&client, &config, &headers(Some("Bearer banned")))
+                .await;
+            assert_eq!(status(denied), StatusCode::FORBIDDEN);
+        }
+        let missing = layer.check(&client, &config, &headers(None)).await;
+        assert_eq!(status(missing), StatusCode::UNAUTHORIZED);
+
+        // An unreachable service fails closed, even for other credentials.
+        let down = ExternalAuthConfig {
+            url: "http://127.0.0.1:1/check".to_string(),
+            ..config
+        };
+        let unreachable = layer
+            .check(&client, &down, &headers(Some("Bearer other")))
+            .await;
+        assert_eq!(status(unreachable), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,697 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Balancer
+use crate::config::{Llm, LoadBalancingConfig, LoadBalancingStrategy};
+use crate::metrics::REGION_FAILOVER;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Weight of the latest response in an instance's latency average.
+const LATENCY_EWMA_WEIGHT: f64 = 0.3;
+
+/// Latency a failed attempt counts as, so an instance that fails fast does
+/// not look fast, and one that has never answered stops being tried first.
+const FAILURE_LATENCY_PENALTY: Duration = Duration::from_secs(60);
+
+/// Share of `least_latency` requests sent to a random instance, so that the
+/// averages of the slower ones keep up with how they perform now.
+const LATENCY_EXPLORATION_RATE: f64 = 0.1;
+
+/// Points placed on the ring per instance. More points give a more even
+/// spread of keys at the cost of a larger ring.
+const VIRTUAL_NODES: usize = 160;
+
+fn hash(value: &str) -> u64 {
+    let digest = openssl::sha::sha256(value.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+/// A consistent-hash ring over a set of instances. Adding or removing one
+/// instance only remaps the keys that landed on its points.
+#[derive(Debug)]
+pub struct HashRing {
+    instances: Vec<String>,
+    /// `(point, index into instances)`, sorted by point.
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    pub fn new(instances: &[&str]) -> Self {
+        let mut points: Vec<(u64, usize)> = instances
+            .iter()
+            .enumerate()
+            .flat_map(|(index, instance)| {
+                (0..VIRTUAL_NODES)
+                    .map(move |replica| (hash(&format!("{instance}#{replica}")), index))
+            })
+            .collect();
+        points.sort_unstable();
+
+        HashRing {
+            instances: instances.iter().map(|s| s.to_string()).collect(),
+            points,
+        }
+    }
+
+    /// Returns the instance owning `key`. If it is not available, walks the
+    /// ring clockwise to the next available instance so the fallback is the
+    /// same for every request with that key. Falls back to the owner when no
+    /// instance is available.
+    pub fn get(&self, key: &str, is_available: impl Fn(&str) -> bool) -> Option<&str> {
+        if self.points.is_empty() {
+            return None;
+        }
+
+        let start = self.points.partition_point(|(point, _)| *point < hash(key));
+        let mut walk = self.points[start..]
+            .iter()
+            .chain(&self.points[..start])
+            .map(|(_, index)| self.instances[*index].as_str());
+        let owner = walk.next()?;
+        if is_available(owner) {
+            return Some(owner);
+        }
+        Some(
+            walk.find(|instance| is_available(instance))
+                .unwrap_or(owner),
+        )
+    }
+}
+
+/// Instances of `llm` in `local_region`, or in the first other region (in
+/// config order) with an available instance once none of the local ones is
+/// available. All instances when no local region is set or `llm` has none
+/// in it. Failovers are only counted when `record` is set.
+fn region_instances<'a>(
+    config: &LoadBalancingConfig,
+    llm: &'a Llm,
+    is_available: impl Fn(&str) -> bool,
+    record: bool,
+) -> Vec<&'a str> {
+    let Some(local) = config.local_region.as_deref() else {
+        return llm.api_bases();
+    };
+    let tagged = llm.instance_regions();
+    let in_region = |region: &str| -> Vec<&'a str> {
+        tagged
+            .iter()
+            .filter(|(_, tag)| *tag == Some(region))
+            .map(|(instance, _)| *instance)
+            .collect()
+    };
+
+    let local_instances = in_region(local);
+    if local_instances.is_empty() {
+        return llm.api_bases();
+    }
+    if local_instances
+        .iter()
+        .any(|instance| is_available(instance))
+    {
+        return local_instances;
+    }
+    let mut others: Vec<&str> = Vec::new();
+    for region in tagged.iter().filter_map(|(_, tag)| *tag) {
+        if region == local || others.contains(&region) {
+            continue;
+        }
+        others.push(region);
+        let instances = in_region(region);
+        if instances.iter().any(|instance| is_available(instance)) {
+            if record {
+                REGION_FAILOVER.with_label_values(&[local, region]).inc();
+            }
+            return instances;
+        }
+    }
+    local_instances
+}
+
+/// The available instances, or all of them when none is.
+fn available_or_all<'a>(
+    instances: &[&'a str],
+    is_available: impl Fn(&str) -> bool,
+) -> Vec<&'a str> {
+    let available: Vec<&str> = instances
+        .iter()
+        .copied()
+        .filter(|instance| is_available(instance))
+        .collect();
+    if available.is_empty() {
+        instances.to_vec()
+    } else {
+        available
+    }
+}
+
+/// Counts a request as in flight on an instance until dropped.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Picks which instance of an LLM serves a request.
+#[derive(Debug, Default)]
+pub struct LoadBalancer {
+    counters: Mutex<HashMap<String, usize>>,
+    rings: Mutex<HashMap<Vec<String>, Arc<HashRing>>>,
+    in_flight: Mutex<HashMap<String, Arc<AtomicUsize>>>,
+    /// Moving average of the response latency of each instance, in seconds.
+    latencies: Mutex<HashMap<String, f64>>,
+    /// Source of random choices when seeded; the thread RNG otherwise.
+    rng: Option<Mutex<StdRng>>,
+}
+
+impl LoadBalancer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A balancer whose random choices repeat for the same `seed` and the
+    /// same sequence of requests, for load tests and debugging.
+    pub fn seeded(seed: u64) -> Self {
+        LoadBalancer {
+            rng: Some(Mutex::new(StdRng::seed_from_u64(seed))),
+            ..Self::default()
+        }
+    }
+
+    /// Selects the base URL for `llm`. `session_key` is only used by
+    /// `consistent_hash`; requests without one are spread round robin. With
+    /// a `local_region`, only the instances of one region are considered.
+    pub fn select_instance(
+        &self,
+        config: &LoadBalancingConfig,
+        llm: &Llm,
+        session_key: Option<&str>,
+        is_available: impl Fn(&str) -> bool,
+    ) -> String {
+        let instances = region_instances(config, llm, &is_available, true);
+        if instances.len() == 1 {
+            return instances[0].to_string();
+        }
+
+        match (&config.strategy, session_key) {
+            (LoadBalancingStrategy::ConsistentHash, Some(key)) => self
+                .ring(&instances)
+                .get(key, is_available)
+                .unwrap_or(instances[0])
+                .to_string(),
+            (LoadBalancingStrategy::PowerOfTwo, _) => self.power_of_two(&instances, is_available),
+            (LoadBalancingStrategy::LeastLatency, _) => {
+                self.least_latency(&instances, is_available)
+            }
+            _ => self.round_robin(llm, &instances, is_available, true),
+        }
+    }
+
+    /// The instance [`select_instance`](Self::select_instance) would pick,
+    /// without moving the round robin or random choices on and without
+    /// counting failovers, e.g. to explain a routing decision. Random
+    /// strategies report their most likely pick: the least loaded instance
+    /// for `power_of_two`, the fastest for `least_latency`.
+    pub fn peek_instance(
+        &self,
+        config: &LoadBalancingConfig,
+        llm: &Llm,
+        session_key: Option<&str>,
+        is_available: impl Fn(&str) -> bool,
+    ) -> String {
+        let instances = region_instances(config, llm, &is_available, false);
+        if instances.len() == 1 {
+            return instances[0].to_string();
+        }
+
+        let candidates = available_or_all(&instances, &is_available);
+        match (&config.strategy, session_key) {
+            (LoadBalancingStrategy::ConsistentHash, Some(key)) => self
+                .ring(&instances)
+                .get(key, is_available)
+                .unwrap_or(instances[0])
+                .to_string(),
+            (LoadBalancingStrategy::PowerOfTwo, _) => candidates
+                .iter()
+                .copied()
+                .min_by_key(|instance| self.in_flight(instance))
+                .unwrap_or(instances[0])
+                .to_string(),
+            (LoadBalancingStrategy::LeastLatency, _) => self
+                .fastest(&candidates)
+                .unwrap_or(instances[0])
+                .to_string(),
+            _ => self.round_robin(llm, &instances, is_available, false),
+        }
+    }
+
+    /// Marks a request as in flight on `instance` for as long as the guard
+    /// is held.
+    pub fn start_request(&self, instance: &str) -> InFlightGuard {
+        let count = self.in_flight_counter(instance);
+        count.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard { count }
+    }
+
+    pub fn in_flight(&self, instance: &str) -> usize {
+        self.in_flight_counter(instance).load(Ordering::Relaxed)
+    }
+
+    /// Adds how long `instance` took to respond to its latency average.
+    pub fn record_latency(&self, instance: &str, duration: Duration) {
+        let mut latencies = self.latencies.lock().expect("balancer lock poisoned");
+        let sample = duration.as_secs_f64();
+        latencies
+            .entry(instance.to_string())
+            .and_modify(|average| {
+                *average += LATENCY_EWMA_WEIGHT * (sample - *average);
+            })
+            .or_insert(sample);
+    }
+
+    /// Adds a failed attempt on `instance` to its latency average, as a
+    /// response that took [`FAILURE_LATENCY_PENALTY`].
+    pub fn record_failure(&self, instance: &str) {
+        self.record_latency(instance, FAILURE_LATENCY_PENALTY);
+    }
+
+    /// Moving average of the response latency of `instance`, once it has
+    /// responded or failed.
+    pub fn latency(&self, instance: &str) -> Option<Duration> {
+        let latencies = self.latencies.lock().expect("balancer lock poisoned");
+        latencies
+            .get(instance)
+            .copied()
+            .map(Duration::from_secs_f64)
+    }
+
+    fn in_flight_counter(&self, instance: &str) -> Arc<AtomicUsize> {
+        let mut in_flight = self.in_flight.lock().expect("balancer lock poisoned");
+        in_flight.entry(instance.to_string()).or_default().clone()
+    }
+
+    /// Samples two distinct available instances and picks the less loaded
+    /// one. Unavailable instances are never sampled unless all of them are.
+    fn power_of_two(&self, instances: &[&str], is_available: impl Fn(&str) -> bool) -> String {
+        let candidates = available_or_all(instances, is_available);
+        self.sample(&candidates, 2)
+            .into_iter()
+            .min_by_key(|instance| self.in_flight(instance))
+            .unwrap_or(instances[0])
+            .to_string()
+    }
+
+    /// The available instance with the lowest latency average. Instances
+    /// that have not been tried yet are tried first, and a random one is
+    /// picked for a share of the requests.
+    fn least_latency(&self, instances: &[&str], is_available: impl Fn(&str) -> bool) -> String {
+        let candidates = available_or_all(instances, is_available);
+        let chosen = if self.explore() {
+            self.sample(&candidates, 1).pop()
+        } else {
+            self.fastest(&candidates)
+        };
+        chosen.unwrap_or(instances[0]).to_string()
+    }
+
+    /// The candidate with the lowest latency average, or one that has not
+    /// been tried yet.
+    fn fastest<'a>(&self, candidates: &[&'a str]) -> Option<&'a str> {
+        let latencies = self.latencies.lock().expect("balancer lock poisoned");
+        candidates
+            .iter()
+            .copied()
+            .find(|instance| !latencies.contains_key(*instance))
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| latencies[*a].total_cmp(&latencies[*b]))
+            })
+    }
+
+    /// Whether a `least_latency` request explores.
+    fn explore(&self) -> bool {
+        match &self.rng {
+            Some(rng) => rng
+                .lock()
+                .expect("balancer lock poisoned")
+                .gen_bool(LATENCY_EXPLORATION_RATE),
+            None => rand::thread_rng().gen_bool(LATENCY_EXPLORATION_RATE),
+        }
+    }
+
+    /// `amount` distinct instances picked at random.
+    fn sample<'a>(&self, instances: &[&'a str], amount: usize) -> Vec<&'a str> {
+        match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().expect("balancer lock poisoned");
+                instances
+                    .choose_multiple(&mut *rng, amount)
+                    .copied()
+                    .collect()
+            }
+            None => instances
+                .choose_multiple(&mut rand::thread_rng(), amount)
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// The next instance in turn. The turn only moves on when `advance` is
+    /// set.
+    fn round_robin(
+        &self,
+        llm: &Llm,
+        instances: &[&str],
+        is_available: impl Fn(&str) -> bool,
+        advance: bool,
+    ) -> String {
+        let start = {
+            let mut counters = self.counters.lock().expect("balancer lock poisoned");
+            let counter = counters.entry(llm.name.clone()).or_default();
+            let start = *counter;
+            if advance {
+                *counter = counter.wrapping_add(1);
+            }
+            start
+        };
+
+        (0..instances.len())
+            .map(|offset| instances[(start + offset) % instances.len()])
+            .find(|instance| is_available(instance))
+            .unwrap_or(instances[start % instances.len()])
+            .to_string()
+    }
+
+    fn ring(&self, instances: &[&str]) -> Arc<HashRing> {
+        let key: Vec<String> = instances.iter().map(|s| s.to_string()).collect();
+        let mut rings = self.rings.lock().expect("balancer lock poisoned");
+        rings
+            .entry(key)
+            .or_insert_with(|| Arc::new(HashRing::new(instances)))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Instance;
+
+    fn llm(instances: &[&str]) -> Llm {
+        Llm {
+            name: "Chatbot".to_string(),
+            api_base: instances[0].to_string(),
+            instances: instances[1..]
+                .iter()
+                .map(|s| Instance::Url(s.to_string()))
+                .collect(),
+            ..Llm::default()
+        }
+    }
+
+    fn consistent_hash() -> LoadBalancingConfig {
+        LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::ConsistentHash,
+            ..LoadBalancingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_round_robin_cycles_instances() {
+        let balancer = LoadBalancer::new();
+        let llm = llm(&["http://a", "http://b"]);
+        let config = LoadBalancingConfig::default();
+        let picks: Vec<String> = (0..4)
+            .map(|_| balancer.select_instance(&config, &llm, None, |_| true))
+            .collect();
+        assert_eq!(picks, ["http://a", "http://b", "http://a", "http://b"]);
+    }
+
+    #[test]
+    fn test_peek_does_not_move_round_robin() {
+        let balancer = LoadBalancer::new();
+        let llm = llm(&["http://a", "http://b"]);
+        let config = LoadBalancingConfig::default();
+        for _ in 0..3 {
+            assert_eq!(
+                balancer.peek_instance(&config, &llm, None, |_| true),
+                "http://a"
+            );
+        }
+        assert_eq!(
+            balancer.select_instance(&config, &llm, None, |_| true),
+            "http://a"
+        );
+        assert_eq!(
+            balancer.peek_instance(&config, &llm, None, |_| true),
+            "http://b"
+        );
+    }
+
+    #[test]
+    fn test_least_latency_prefers_fastest_instance_and_explores() {
+        let balancer = LoadBalancer::seeded(7);
+        let config = LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::LeastLatency,
+            ..LoadBalancingConfig::default()
+        };
+        let three = llm(&["http://a", "http://b", "http://c"]);
+        balancer.record_latency("http://a", Duration::from_millis(500));
+        balancer.record_latency("http://b", Duration::from_millis(100));
+        // Unmeasured instances are tried before the averages are compared.
+        let mut picks = Vec::new();
+        for _ in 0..20 {
+            picks.push(balancer.select_instance(&config, &three, None, |_| true));
+            if picks.last().unwrap() == "http://c" {
+                break;
+            }
+        }
+        assert!(picks.contains(&"http://c".to_string()));
+        balancer.record_latency("http://c", Duration::from_secs(1));
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..200 {
+            let pick = balancer.select_instance(&config, &three, None, |_| true);
+            *counts.entry(pick).or_default() += 1;
+        }
+        assert!(counts["http://b"] > 150, "{:?}", counts);
+        assert!(counts.len() > 1, "{:?}", counts);
+        let unavailable = |instance: &str| instance != "http://b";
+        assert_ne!(
+            balancer.select_instance(&config, &three, None, unavailable),
+            "http://b"
+        );
+
+        // The average moves 30% of the way toward each new latency.
+        balancer.record_latency("http://b", Duration::from_millis(1100));
+        let average = balancer.latency("http://b").unwrap().as_secs_f64();
+        assert!((average - 0.4).abs() < 1e-6, "{}", average);
+    }
+
+    #[test]
+    fn test_failures_count_as_slow_responses() {
+        let balancer = LoadBalancer::seeded(7);
+        let config = LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::LeastLatency,
+            ..LoadBalancingConfig::default()
+        };
+        let two = llm(&["http://a", "http://b"]);
+        balancer.record_latency("http://a", Duration::from_secs(5));
+        // An instance that only ever failed is no longer tried first.
+        balancer.record_failure("http://b");
+        assert_eq!(
+            balancer.peek_instance(&config, &two, None, |_| true),
+            "http://a"
+        );
+        assert_eq!(balancer.latency("http://b"), Some(FAILURE_LATENCY_PENALTY));
+
+        // Nor does a fast instance that starts failing stay the fastest.
+        let balancer = LoadBalancer::seeded(7);
+        balancer.record_latency("http://a", Duration::from_secs(5));
+        balancer.record_latency("http://b", Duration::from_millis(100));
+        assert_eq!(
+            balancer.peek_instance(&config, &two, None, |_| true),
+            "http://b"
+        );
+        balancer.record_failure("http://b");
+        assert_eq!(
+            balancer.peek_instance(&config, &two, None, |_| true),
+            "http://a"
+        );
+    }
+
+    #[test]
+    fn test_power_of_two_prefers_less_loaded_available_instance() {
+        let balancer = LoadBalancer::new();
+        let config = LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::PowerOfTwo,
+            ..LoadBalancingConfig::default()
+        };
+
+        let pair = llm(&["http://a", "http://b"]);
+        let busy = balancer.start_request("http://a");
+        for _ in 0..20 {
+            assert_eq!(
+                balancer.select_instance(&config, &pair, None, |_| true),
+                "http://b"
+            );
+        }
+
+        // With one instance open, the other two are always the candidates.
+        let three = llm(&["http://a", "http://b", "http://c"]);
+        for _ in 0..20 {
+            assert_eq!(
+                balancer.select_instance(&config, &three, None, |i| i != "http://b"),
+                "http://c"
+            );
+        }
+
+        drop(busy);
+        assert_eq!(balancer.in_flight("http://a"), 0);
+        let single = llm(&["http://a"]);
+        assert_eq!(
+            balancer.select_instance(&config, &single, None, |_| false),
+            "http://a"
+        );
+    }
+
+    #[test]
+    fn test_consistent_hash_is_sticky_and_stable() {
+        let balancer = LoadBalancer::new();
+        let config = consistent_hash();
+        let three = llm(&["http://a", "http://b", "http://c"]);
+        let four = llm(&["http://a", "http://b", "http://c", "http://d"]);
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("session-{i}")).collect();
+        let before: Vec<String> = keys
+            .iter()
+            .map(|key| balancer.select_instance(&config, &three, Some(key), |_| true))
+            .collect();
+        let again: Vec<String> = keys
+            .iter()
+            .map(|key| balancer.select_instance(&config, &three, Some(key), |_| true))
+            .collect();
+        assert_eq!(before, again);
+
+        let after: Vec<String> = keys
+            .iter()
+            .map(|key| balancer.select_instance(&config, &four, Some(key), |_| true))
+            .collect();
+        let moved = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+        // Roughly a quarter of the keys should move to the new instance, and
+        // only to the new instance.
+        assert!(moved > 150 && moved < 350, "moved {moved} keys");
+        assert!(before
+            .iter()
+            .zip(&after)
+            .all(|(a, b)| a == b || b == "http://d"));
+    }
+
+    #[test]
+    fn test_consistent_hash_skips_unavailable_instance_deterministically() {
+        let balancer = LoadBalancer::new();
+        let config = consistent_hash();
+        let llm = llm(&["http://a", "http://b", "http://c"]);
+
+        let owner = balancer.select_instance(&config, &llm, Some("user-42"), |_| true);
+        let fallback = balancer.select_instance(&config, &llm, Some("user-42"), |i| i != owner);
+        assert_ne!(fallback, owner);
+        assert_eq!(
+            fallback,
+            balancer.select_instance(&config, &llm, Some("user-42"), |i| i != owner)
+        );
+    }
+
+    #[test]
+    fn test_local_region_is_preferred_until_all_its_instances_fail() {
+        let balancer = LoadBalancer::new();
+        let config = LoadBalancingConfig {
+            local_region: Some("region-test-local".to_string()),
+            ..LoadBalancingConfig::default()
+        };
+        let tagged = |url: &str, region: &str| Instance::Tagged {
+            url: url.to_string(),
+            region: Some(region.to_string()),
+        };
+        let llm = Llm {
+            name: "Regional".to_string(),
+            api_base: "http://local-a".to_string(),
+            region: Some("region-test-local".to_string()),
+            instances: vec![
+                tagged("http://remote-a", "region-test-remote"),
+                Instance::Url("http://local-b".to_string()),
+                tagged("http://remote-b", "region-test-remote"),
+            ],
+            ..Llm::default()
+        };
+        let failovers =
+            REGION_FAILOVER.with_label_values(&["region-test-local", "region-test-remote"]);
+
+        let picks: Vec<String> = (0..4)
+            .map(|_| balancer.select_instance(&config, &llm, None, |_| true))
+            .collect();
+        assert!(picks.iter().all(|pick| pick.starts_with("http://local")));
+        assert_eq!(
+            balancer.select_instance(&config, &llm, None, |i| i != "http://local-a"),
+            "http://local-b"
+        );
+        assert_eq!(failovers.get(), 0);
+
+        // Load balancing continues within the region failed over to.
+        let local_down = |i: &str| !i.starts_with("http://local");
+        let picks: Vec<String> = (0..2)
+            .map(|_| balancer.select_instance(&config, &llm, None, local_down))
+            .collect();
+        assert_eq!(picks.len(), 2);
+        assert_ne!(picks[0], picks[1]);
+        assert!(picks.iter().all(|pick| pick.starts_with("http://remote")));
+        assert_eq!(failovers.get(), 2);
+        // Explaining a routing decision is not a failover.
+        balancer.peek_instance(&config, &llm, None, local_down);
+        assert_eq!(failovers.get(), 2);
+    }
+
+    #[test]
+    fn test_seeded_balancers_repeat_their_choices() {
+        let config = LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::PowerOfTwo,
+            ..LoadBalancingConfig::default()
+        };
+        let five = llm(&["http://a", "http://b", "http://c", "http://d", "http://e"]);
+        let picks = |balancer: &LoadBalancer| -> Vec<String> {
+            (0..50)
+                .map(|_| balancer.select_instance(&config, &five, None, |_| true))
+                .collect()
+        };
+
+        let first = picks(&LoadBalancer::seeded(7));
+        assert_eq!(first, picks(&LoadBalancer::seeded(7)));
+        assert_ne!(first, picks(&LoadBalancer::seeded(8)));
+        // Still spread over the instances.
+        assert!(first.iter().any(|pick| pick != &first[0]));
+    }
+}
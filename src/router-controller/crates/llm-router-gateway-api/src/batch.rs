@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batch
+//!
+//! Requests carrying several prompts, split into one request per prompt and
+//! answered with a single multi-choice response.
+use crate::config::BatchingConfig;
+use crate::error::GatewayApiError;
+use http::StatusCode;
+use serde_json::{json, Value};
+
+/// Field of a request listing its prompts as objects whose fields replace
+/// those of the rest of the body.
+pub const BATCH_FIELD: &str = "batch";
+
+/// Marks a request split from a batch. The batch's signature was checked as
+/// a whole; `signed` is the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchItem {
+    pub signed: bool,
+}
+
+/// The bodies a batch request is split into, in order, or `None` when `body`
+/// is not a batch: a completions request with several `prompt`s, or any
+/// request with a `batch` array.
+pub fn split(
+    body: &Value,
+    completions: bool,
+    config: &BatchingConfig,
+) -> Result<Option<Vec<Value>>, GatewayApiError> {
+    let invalid = |message: String| {
+        GatewayApiError::client_error(StatusCode::BAD_REQUEST, message, "invalid_request_body")
+    };
+    let Some(fields) = body.as_object() else {
+        return Ok(None);
+    };
+
+    let items: Vec<Value> = match (fields.get(BATCH_FIELD), fields.get("prompt")) {
+        (Some(Value::Array(overrides)), _) => {
+            let mut base = fields.clone();
+            base.remove(BATCH_FIELD);
+            overrides
+                .iter()
+                .map(|item| {
+                    let item = item.as_object().ok_or_else(|| {
+                        invalid(format!("'{}' must be an array of objects", BATCH_FIELD))
+                    })?;
+                    let mut fields = base.clone();
+                    fields.extend(item.clone());
+                    Ok(Value::Object(fields))
+                })
+                .collect::<Result<_, GatewayApiError>>()?
+        }
+        (Some(_), _) => {
+            return Err(invalid(format!(
+                "'{}' must be an array of objects",
+                BATCH_FIELD
+            )))
+        }
+        (None, Some(Value::Array(prompts))) if completions && prompts.len() > 1 => prompts
+            .iter()
+            .map(|prompt| {
+                let mut fields = fields.clone();
+                fields.insert("prompt".to_string(), prompt.clone());
+                Value::Object(fields)
+            })
+            .collect(),
+        _ => return Ok(None),
+    };
+
+    if items.is_empty() {
+        return Err(invalid(format!("'{}' must not be empty", BATCH_FIELD)));
+    }
+    if items.len() > config.max_batch_size {
+        return Err(invalid(format!(
+            "Batches are limited to {} prompts",
+            config.max_batch_size
+        )));
+    }
+    if items.iter().any(|item| item["stream"] == Value::Bool(true)) {
+        return Err(invalid("Batch requests cannot be streamed".to_string()));
+    }
+    Ok(Some(items))
+}
+
+/// Assembles the responses to a batch's parts, in order, into one response:
+/// the first successful one, with every choice numbered in sequence and the
+/// usage summed. Failed parts, given as their `error`, get a choice with
+/// `finish_reason: "error"`.
+pub fn merge(parts: Vec<Result<Value, Value>>) -> Value {
+    let mut merged = parts
+        .iter()
+        .find_map(|part| part.as_ref().ok())
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    let mut choices = Vec::new();
+    let mut usage: Option<[u64; 3]> = None;
+    for part in parts {
+        match part {
+            Ok(response) => {
+                for choice in response["choices"].as_array().into_iter().flatten() {
+                    let mut choice = choice.clone();
+                    choice["index"] = json!(choices.len());
+                    choices.push(choice);
+                }
+                if response["usage"].is_object() {
+                    let totals = usage.get_or_insert([0; 3]);
+                    for (total, field) in totals.iter_mut().zip([
+                        "prompt_tokens",
+                        "completion_tokens",
+                        "total_tokens",
+                    ]) {
+                        *total += response["usage"][field].as_u64().unwrap_or(0);
+                    }
+                }
+            }
+            Err(error) => choices.push(json!({
+                "index": choices.len(),
+                "finish_reason": "error",
+                "error": error,
+            })),
+        }
+    }
+    merged["choices"] = Value::Array(choices);
+    if let Some([prompt_tokens, completion_tokens, total_tokens]) = usage {
+        merged["usage"] = json!({
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": total_tokens,
+        });
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_prompts_and_batch_overrides() {
+        let config = BatchingConfig::default();
+        let body = json!({"model": "m", "prompt": ["a", "b"], "max_tokens": 5});
+        let items = split(&body, true, &config).unwrap().unwrap();
+        assert_eq!(
+            items[1],
+            json!({"model": "m", "prompt": "b", "max_tokens": 5})
+        );
+        // Chat requests and single prompts go through unchanged.
+        assert!(split(&body, false, &config).unwrap().is_none());
+        let single = json!({"prompt": ["a"]});
+        assert!(split(&single, true, &config).unwrap().is_none());
+
+        let body = json!({"messages": [], "batch": [{"messages": [{"role": "user"}]}, {}]});
+        let items = split(&body, false, &config).unwrap().unwrap();
+        assert_eq!(items[0], json!({"messages": [{"role": "user"}]}));
+        assert_eq!(items[1], json!({"messages": []}));
+
+        let small = BatchingConfig {
+            max_batch_size: 1,
+            ..BatchingConfig::default()
+        };
+        for (body, config) in [
+            (json!({"prompt": ["a", "b"]}), &small),
+            (json!({"prompt": ["a", "b"], "stream": true}), &config),
+            (json!({"batch": [1]}), &config),
+            (json!({"batch": []}), &config),
+        ] {
+            assert!(split(&body, true, config).is_err(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_merge_numbers_choices_and_sums_usage() {
+        let response = |text: &str, tokens: u64| {
+            json!({
+                "id": format!("cmpl-{}", text),
+                "choices": [{"index": 0, "text": text, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": tokens, "total_tokens": tokens + 1},
+            })
+        };
+        let merged = merge(vec![
+            Ok(response("a", 2)),
+            Err(json!({"message": "overloaded"})),
+            Ok(response("c", 4)),
+        ]);
+        assert_eq!(merged["id"], "cmpl-a");
+        assert_eq!(merged["choices"][1]["index"], 1);
+        assert_eq!(merged["choices"][1]["finish_reason"], "error");
+        assert_eq!(merged["choices"][1]["error"]["message"], "overloaded");
+        assert_eq!(merged["choices"][2]["text"], "c");
+        assert_eq!(merged["choices"][2]["index"], 2);
+        assert_eq!(
+            merged["usage"],
+            json!({"prompt_tokens": 2, "completion_tokens": 6, "total_tokens": 8})
+        );
+    }
+}
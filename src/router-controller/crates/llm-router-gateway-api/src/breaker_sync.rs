@@ -0,0 +1,324 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Breaker Sync
+//!
+//! Shares circuit breaker state between the replicas of a deployment through
+//! Redis. Each endpoint has a failure counter, counted across replicas, and a
+//! key that exists while its breaker is open. A replica that cannot reach
+//! Redis keeps its local breakers.
+use crate::circuit_breaker::CircuitBreakerRegistry;
+use crate::config::{CircuitBreakerConfig, SharedCircuitBreakerConfig};
+use log::{debug, info, warn};
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, AsyncConnectionConfig, RedisResult};
+use std::sync::{Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+/// Longest wait for Redis to connect or answer a command.
+const REDIS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Events waiting for Redis. Further events are dropped, so a slow Redis
+/// cannot make the queue grow without bound.
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// What a breaker tells the other replicas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerEvent {
+    /// A failure was counted toward the breaker of the endpoint.
+    Failure(String),
+    /// The breaker opened here.
+    Opened(String),
+    /// A request succeeded after failures, or closed the breaker.
+    Recovered(String),
+}
+
+/// Hands breaker events to the background task without blocking.
+pub type BreakerEvents = Sender<BreakerEvent>;
+
+/// Connection to Redis and the events waiting to be sent to it.
+#[derive(Debug)]
+pub struct BreakerSync {
+    client: redis::Client,
+    config: SharedCircuitBreakerConfig,
+    failure_threshold: u32,
+    open_duration: Duration,
+    sender: BreakerEvents,
+    receiver: Mutex<Option<Receiver<BreakerEvent>>>,
+}
+
+impl BreakerSync {
+    pub fn new(
+        shared: &SharedCircuitBreakerConfig,
+        breaker: &CircuitBreakerConfig,
+    ) -> RedisResult<Self> {
+        let (sender, receiver) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+        Ok(BreakerSync {
+            client: redis::Client::open(shared.redis_url.as_str())?,
+            config: shared.clone(),
+            failure_threshold: breaker.failure_threshold,
+            open_duration: Duration::from_secs(breaker.open_duration_secs),
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        })
+    }
+
+    pub fn events(&self) -> BreakerEvents {
+        self.sender.clone()
+    }
+
+    /// Sends the events to Redis and applies the breakers other replicas
+    /// opened to `registry`, until it is dropped. Only the first call starts
+    /// a task.
+    pub fn spawn(&self, registry: Weak<CircuitBreakerRegistry>) -> Option<JoinHandle<()>> {
+        let mut receiver = self
+            .receiver
+            .lock()
+            .expect("breaker sync lock poisoned")
+            .take()?;
+        let mut redis = Redis {
+            client: self.client.clone(),
+            connection: None,
+            reachable: true,
+        };
+        let keys = Keys(self.config.key_prefix.clone());
+        let failure_threshold = self.failure_threshold;
+        let open_ms = self.open_duration.as_millis() as u64;
+        let sync_interval = Duration::from_millis(self.config.sync_interval_ms);
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(sync_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => {
+                        let Some(event) = event else { return };
+                        // Dropped rather than queued while Redis is down;
+                        // only the sync below tries to reconnect.
+                        if !redis.reachable {
+                            continue;
+                        }
+                        let result = match redis.connection().await {
+                            Ok(connection) => {
+                                publish(connection, &keys, &event, failure_threshold, open_ms)
+                                    .await
+                            }
+                            Err(e) => Err(e),
+                        };
+                        redis.check(result);
+                    }
+                    _ = interval.tick() => {
+                        let Some(registry) = registry.upgrade() else { return };
+                        let result = match redis.connection().await {
+                            Ok(connection) => {
+                                open_remote_breakers(connection, &keys, &registry).await
+                            }
+                            Err(e) => Err(e),
+                        };
+                        redis.check(result);
+                    }
+                }
+            }
+        }))
+    }
+}
+
+/// Redis keys of the endpoints.
+struct Keys(String);
+
+impl Keys {
+    fn failures(&self, endpoint: &str) -> String {
+        format!("{}{}:failures", self.0, endpoint)
+    }
+
+    fn open(&self, endpoint: &str) -> String {
+        format!("{}{}:open", self.0, endpoint)
+    }
+
+    /// Endpoint of an `open` key.
+    fn endpoint<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.0.as_str())?.strip_suffix(":open")
+    }
+}
+
+/// A connection to Redis, made again after failures.
+struct Redis {
+    client: redis::Client,
+    connection: Option<MultiplexedConnection>,
+    reachable: bool,
+}
+
+impl Redis {
+    async fn connection(&mut self) -> RedisResult<&mut MultiplexedConnection> {
+        if self.connection.is_none() {
+            let config = AsyncConnectionConfig::new()
+                .set_connection_timeout(REDIS_TIMEOUT)
+                .set_response_timeout(REDIS_TIMEOUT);
+            let connection = self
+                .client
+                .get_multiplexed_async_connection_with_config(&config)
+                .await?;
+            self.connection = Some(connection);
+        }
+        Ok(self.connection.as_mut().expect("connection was just made"))
+    }
+
+    /// Logs when Redis becomes unreachable or reachable again, and drops the
+    /// connection after a failure.
+    fn check(&mut self, result: RedisResult<()>) {
+        match result {
+            Ok(()) if !self.reachable => {
+                info!("Sharing circuit breaker state through Redis again");
+                self.reachable = true;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                if self.reachable {
+                    warn!(
+                        "Redis is unreachable, circuit breakers are local until it is back: {}",
+                        e
+                    );
+                    self.reachable = false;
+                }
+                self.connection = None;
+            }
+        }
+    }
+}
+
+async fn publish(
+    connection: &mut MultiplexedConnection,
+    keys: &Keys,
+    event: &BreakerEvent,
+    failure_threshold: u32,
+    open_ms: u64,
+) -> RedisResult<()> {
+    let open = |endpoint: &str| {
+        redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(keys.open(endpoint))
+            .arg(1)
+            .arg("PX")
+            .arg(open_ms)
+            .ignore()
+            .del(keys.failures(endpoint))
+            .ignore()
+            .clone()
+    };
+    match event {
+        BreakerEvent::Failure(endpoint) => {
+            let failures = keys.failures(endpoint);
+            let (count,): (u32,) = redis::pipe()
+                .atomic()
+                .incr(&failures, 1)
+                .pexpire(&failures, open_ms as i64)
+                .ignore()
+                .query_async(connection)
+                .await?;
+            if count >= failure_threshold {
+                debug!("{} failures of {} across replicas", count, endpoint);
+                open(endpoint).query_async::<()>(connection).await?;
+            }
+        }
+        BreakerEvent::Opened(endpoint) => open(endpoint).query_async::<()>(connection).await?,
+        // A success here says nothing about the other replicas, so a breaker
+        // opened across replicas stays open until its key expires.
+        BreakerEvent::Recovered(endpoint) => {
+            connection.del::<_, ()>(keys.failures(endpoint)).await?
+        }
+    }
+    Ok(())
+}
+
+/// Opens the local breakers of the endpoints whose `open` key exists, for as
+/// long as the key lives.
+async fn open_remote_breakers(
+    connection: &mut MultiplexedConnection,
+    keys: &Keys,
+    registry: &CircuitBreakerRegistry,
+) -> RedisResult<()> {
+    let pattern = format!("{}*:open", keys.0);
+    let open_keys: Vec<String> = {
+        let mut iter = connection.scan_match::<_, String>(&pattern).await?;
+        let mut open_keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            open_keys.push(key);
+        }
+        open_keys
+    };
+    for key in open_keys {
+        let Some(endpoint) = keys.endpoint(&key) else {
+            continue;
+        };
+        let remaining_ms: i64 = connection.pttl(&key).await?;
+        if remaining_ms > 0 {
+            registry
+                .get(endpoint)
+                .open_remotely(Duration::from_millis(remaining_ms as u64));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::CircuitState;
+    use crate::config::FailureKind;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_endpoint_keys() {
+        let keys = Keys("llm-router:circuit-breaker:".to_string());
+        let open = keys.open("http://nim:8000");
+        assert_eq!(open, "llm-router:circuit-breaker:http://nim:8000:open");
+        assert_eq!(keys.endpoint(&open), Some("http://nim:8000"));
+        assert_eq!(keys.endpoint(&keys.failures("http://nim:8000")), None);
+    }
+
+    #[tokio::test]
+    async fn test_breakers_stay_local_when_redis_is_unreachable() {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            shared: Some(SharedCircuitBreakerConfig {
+                // Nothing listens on port 1.
+                redis_url: "redis://127.0.0.1:1".to_string(),
+                key_prefix: "test:".to_string(),
+                sync_interval_ms: 10,
+            }),
+            ..CircuitBreakerConfig::default()
+        };
+        let registry = Arc::new(CircuitBreakerRegistry::new(&config));
+        let task = registry.spawn_sync().unwrap();
+        assert!(registry.spawn_sync().is_none());
+
+        registry
+            .get("http://breaker-sync-test")
+            .record_failure(FailureKind::Connection);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            registry.state("http://breaker-sync-test"),
+            CircuitState::Open
+        );
+
+        drop(registry);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulkhead
+use crate::config::{ConcurrencyConfig, Llm};
+use crate::error::{GatewayApiError, RoutingErrorType};
+use crate::metrics::CONCURRENCY_REJECTED;
+use crate::priority::{PriorityQueue, WorkerSlot};
+use http::HeaderMap;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A fixed number of slots with a bounded queue in front of it.
+#[derive(Debug)]
+struct Compartment {
+    limit: usize,
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// Leaves the queue when dropped, including when the waiting request is
+/// cancelled because the client disconnected.
+struct QueueTicket<'a>(&'a AtomicUsize);
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Compartment {
+    fn new(limit: usize) -> Self {
+        Compartment {
+            limit,
+            slots: Arc::new(Semaphore::new(limit)),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    async fn acquire(&self, settings: &ConcurrencyConfig) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= settings.queue_depth {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let _ticket = QueueTicket(&self.waiting);
+        let timeout = Duration::from_millis(settings.queue_timeout_ms);
+        tokio::time::timeout(timeout, self.slots.clone().acquire_owned())
+            .await
+            .ok()?
+            .ok()
+    }
+}
+
+/// Slots held by one request. Dropping it, on success, error or client
+/// disconnect, frees them for the next request.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    _worker: Option<WorkerSlot>,
+    _global: Option<OwnedSemaphorePermit>,
+    _llm: Option<OwnedSemaphorePermit>,
+}
+
+/// Caps requests in flight to the LLMs, globally and per LLM, so a traffic
+/// spike queues or fails fast in the gateway instead of overloading the
+/// upstreams.
+#[derive(Debug)]
+pub struct Bulkhead {
+    settings: ConcurrencyConfig,
+    priority: Option<PriorityQueue>,
+    global: Option<Compartment>,
+    per_llm: Mutex<HashMap<String, Arc<Compartment>>>,
+}
+
+impl Bulkhead {
+    pub fn new(settings: &ConcurrencyConfig) -> Self {
+        Bulkhead {
+            settings: settings.clone(),
+            priority: settings.priority.as_ref().map(PriorityQueue::new),
+            global: settings.max_concurrent_requests.map(Compartment::new),
+            per_llm: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn compartment(&self, llm: &Llm) -> Option<Arc<Compartment>> {
+        let limit = llm.max_concurrent_requests?;
+        let mut per_llm = self.per_llm.lock().expect("bulkhead lock poisoned");
+        let compartment = per_llm
+            .entry(llm.name.clone())
+            .or_insert_with(|| Arc::new(Compartment::new(limit)));
+        // A reloaded limit starts a new compartment; requests holding slots of
+        // the old one release them as they finish.
+        if compartment.limit != limit {
+            *compartment = Arc::new(Compartment::new(limit));
+        }
+        Some(compartment.clone())
+    }
+
+    /// Takes a worker of the priority queue, a global slot and a slot of
+    /// `llm`, queueing for each per `server.concurrency`. Fails with 503 when
+    /// any is unavailable.
+    pub async fn acquire(
+        &self,
+        llm: &Llm,
+        headers: &HeaderMap,
+    ) -> Result<ConcurrencyPermit, GatewayApiError> {
+        let worker = match &self.priority {
+            Some(queue) => Some(
+                queue
+                    .acquire(queue.priority_of(headers))
+                    .await
+                    .ok_or_else(|| self.reject(llm, "the priority queue"))?,
+            ),
+            None => None,
+        };
+        let global = match &self.global {
+            Some(compartment) => Some(
+                compartment
+                    .acquire(&self.settings)
+                    .await
+                    .ok_or_else(|| self.reject(llm, "the gateway"))?,
+            ),
+            None => None,
+        };
+        let llm_permit = match self.compartment(llm) {
+            Some(compartment) => Some(
+                compartment
+                    .acquire(&self.settings)
+                    .await
+                    .ok_or_else(|| self.reject(llm, &llm.name))?,
+            ),
+            None => None,
+        };
+        Ok(ConcurrencyPermit {
+            _worker: worker,
+            _global: global,
+            _llm: llm_permit,
+        })
+    }
+
+    /// Takes a global slot and a slot of `llm` without queueing, for a
+    /// second request on behalf of one that already holds a permit, such as
+    /// a hedge. `None` when either is taken.
+    pub fn try_acquire(&self, llm: &Llm) -> Option<ConcurrencyPermit> {
+        let global = match &self.global {
+            Some(compartment) => Some(compartment.slots.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        let llm_permit = match self.compartment(llm) {
+            Some(compartment) => Some(compartment.slots.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        Some(ConcurrencyPermit {
+            _worker: None,
+            _global: global,
+            _llm: llm_permit,
+        })
+    }
+
+    fn reject(&self, llm: &Llm, scope: &str) -> GatewayApiError {
+        warn!("Concurrency limit of {} reached, rejecting request", scope);
+        CONCURRENCY_REJECTED
+            .with_label_values(&[llm.name.as_str()])
+            .inc();
+        GatewayApiError::RoutingError {
+            message: format!("Too many concurrent requests for {}", scope),
+            error_type: RoutingErrorType::TritonUnavailable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn llm(limit: Option<usize>) -> Llm {
+        Llm {
+            name: "bulkhead-test".to_string(),
+            max_concurrent_requests: limit,
+            ..Llm::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_over_limit_and_releases_on_drop() {
+        let bulkhead = Bulkhead::new(&ConcurrencyConfig::default());
+        let llm = llm(Some(1));
+
+        let permit = bulkhead.acquire(&llm, &HeaderMap::new()).await.unwrap();
+        let rejected = bulkhead.acquire(&llm, &HeaderMap::new()).await.unwrap_err();
+        assert_eq!(
+            rejected.status_code(),
+            http::StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        drop(permit);
+        assert!(bulkhead.acquire(&llm, &HeaderMap::new()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_does_not_exceed_limit() {
+        let bulkhead = Bulkhead::new(&ConcurrencyConfig::default());
+        let limited = llm(Some(2));
+
+        let permit = bulkhead.acquire(&limited, &HeaderMap::new()).await.unwrap();
+        let extra = bulkhead.try_acquire(&limited).unwrap();
+        assert!(bulkhead.try_acquire(&limited).is_none());
+        drop(extra);
+        assert!(bulkhead.try_acquire(&limited).is_some());
+        drop(permit);
+        assert!(bulkhead.try_acquire(&llm(None)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_queue_waits_for_slot_within_depth_and_timeout() {
+        let bulkhead = Arc::new(Bulkhead::new(&ConcurrencyConfig {
+            max_concurrent_requests: Some(1),
+            queue_depth: 1,
+            queue_timeout_ms: 5_000,
+            ..ConcurrencyConfig::default()
+        }));
+        let llm = llm(None);
+
+        let permit = bulkhead.acquire(&llm, &HeaderMap::new()).await.unwrap();
+        let queued = tokio::spawn({
+            let bulkhead = bulkhead.clone();
+            let llm = llm.clone();
+            async move { bulkhead.acquire(&llm, &HeaderMap::new()).await.is_ok() }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // The queue is full, so a third request fails fast.
+        assert!(bulkhead.acquire(&llm, &HeaderMap::new()).await.is_err());
+
+        drop(permit);
+        assert!(queued.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout_rejects() {
+        let bulkhead = Bulkhead::new(&ConcurrencyConfig {
+            max_concurrent_requests: Some(1),
+            queue_depth: 4,
+            queue_timeout_ms: 20,
+            ..ConcurrencyConfig::default()
+        });
+        let llm = llm(None);
+
+        let _permit = bulkhead.acquire(&llm, &HeaderMap::new()).await.unwrap();
+        assert!(bulkhead.acquire(&llm, &HeaderMap::new()).await.is_err());
+    }
+}
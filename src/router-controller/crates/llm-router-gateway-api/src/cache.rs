@@ -0,0 +1,737 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache
+use crate::config::{CacheCompression, CacheEviction, CachingConfig, Policy, SemanticCacheConfig};
+use crate::error::GatewayApiError;
+use crate::metrics::{
+    CACHE_COMPRESSED_BYTES, CACHE_HITS, CACHE_MISSES, CACHE_SIZE, CACHE_UNCOMPRESSED_BYTES,
+};
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{debug, warn};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// How often expired entries are dropped and the size gauges refreshed.
+const SIZE_UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A cached upstream response.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// Compressed per `encoding`, if set.
+    pub body: Bytes,
+    pub classifier: String,
+    /// Upstream model that produced the response, used for invalidation.
+    pub model: String,
+    pub encoding: Option<CacheCompression>,
+    /// Body sent upstream and `content_hash` of the response, kept for
+    /// `caching.revalidation`.
+    pub revalidation: Option<(Value, String)>,
+}
+
+impl CachedResponse {
+    /// The body as the upstream sent it.
+    pub fn decoded_body(&self) -> std::io::Result<Bytes> {
+        match self.encoding {
+            Some(compression) => compression.decompress(&self.body).map(Bytes::from),
+            None => Ok(self.body.clone()),
+        }
+    }
+}
+
+impl CacheCompression {
+    /// `Content-Encoding` of a body compressed this way.
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            CacheCompression::Gzip => "gzip",
+            CacheCompression::Zstd => "zstd",
+        }
+    }
+
+    fn compress(&self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CacheCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            CacheCompression::Zstd => zstd::encode_all(body, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    fn decompress(&self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CacheCompression::Gzip => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(body).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            CacheCompression::Zstd => zstd::decode_all(body),
+        }
+    }
+}
+
+/// Whether the client's `Accept-Encoding` allows `encoding`, so a compressed
+/// cached body can be sent as is.
+pub fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let q = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            name.eq_ignore_ascii_case(encoding) && q > 0.0
+        })
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    response: CachedResponse,
+    /// Body size before cache compression.
+    uncompressed_len: usize,
+    expires_at: Instant,
+    /// Tick of `ResponseCache::clock` at the last hit or store. Atomic so
+    /// that hits only need the read lock.
+    last_access: AtomicU64,
+    /// Hash of the request without its messages; semantic hits only match
+    /// entries with the same policy and sampling parameters.
+    scope: String,
+    embedding: Option<Vec<f32>>,
+}
+
+impl CacheEntry {
+    /// Rough heap and inline size of the entry stored under `key`.
+    fn approximate_size(&self, key: &str) -> usize {
+        std::mem::size_of::<CacheEntry>()
+            + key.len()
+            + self.response.body.len()
+            + self.response.classifier.len()
+            + self.response.model.len()
+            + self.scope.len()
+            + self
+                .embedding
+                .as_ref()
+                .map_or(0, |embedding| embedding.len() * std::mem::size_of::<f32>())
+            + self
+                .response
+                .revalidation
+                .as_ref()
+                .map_or(0, |(request, hash)| json_size(request) + hash.len())
+    }
+}
+
+/// Rough heap and inline size of a JSON value.
+fn json_size(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::String(text) => text.len(),
+            Value::Array(items) => items.iter().map(json_size).sum(),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(name, value)| name.len() + json_size(value))
+                .sum(),
+            Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+        }
+}
+
+/// Snapshot of the cache for tuning `max_size` and `ttl_seconds`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CacheStats {
+    /// Entries that have not expired.
+    pub active_entries: usize,
+    /// Entries held, including expired ones not dropped yet.
+    pub total_entries: usize,
+    pub max_size: usize,
+    /// Hits since startup, exact and semantic.
+    pub hits: u64,
+    pub misses: u64,
+    /// Approximate memory held by the entries, in bytes.
+    pub memory_bytes: usize,
+}
+
+#[derive(Debug)]
+pub struct ResponseCache {
+    entries: RwLock<HashMap<String, CacheEntry>>,
+    max_size: usize,
+    ttl: Duration,
+    ttl_jitter: f64,
+    compression: Option<CacheCompression>,
+    eviction: CacheEviction,
+    /// Logical clock ordering accesses for LRU eviction.
+    clock: AtomicU64,
+}
+
+/// Jittered TTLs are never shortened below this, unless the TTL itself is.
+const MIN_JITTERED_TTL: Duration = Duration::from_secs(1);
+
+/// `ttl` scaled by a factor within `1 ± jitter`, picked by `sample` in
+/// `[0, 1)`.
+fn jittered_ttl(ttl: Duration, jitter: f64, sample: f64) -> Duration {
+    if jitter <= 0.0 {
+        return ttl;
+    }
+    let factor = 1.0 + jitter * (2.0 * sample - 1.0);
+    ttl.mul_f64(factor).max(MIN_JITTERED_TTL.min(ttl))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    openssl::sha::sha256(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hashes the request body into an exact-match cache key.
+pub fn generate_key(body: &Value) -> String {
+    sha256_hex(&serde_json::to_vec(body).unwrap_or_default())
+}
+
+/// Hashes what the model generated, the `message` or `text` of each choice,
+/// so responses differing only in `id`, `created` or `usage` match. Bodies
+/// without choices are hashed whole.
+pub fn content_hash(body: &[u8]) -> String {
+    let choices = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|json| json.get("choices")?.as_array().cloned());
+    match choices {
+        Some(choices) => generate_key(&Value::Array(
+            choices
+                .iter()
+                .map(|choice| serde_json::json!([choice.get("message"), choice.get("text")]))
+                .collect(),
+        )),
+        None => sha256_hex(body),
+    }
+}
+
+/// Exact-match key of a request resolved to `policy`, so identical bodies
+/// routed through different policies never share an entry. The policy's
+/// default parameters are part of the key because they are only filled in
+/// once an LLM is chosen, after the cache lookup.
+pub fn generate_policy_key(policy: &Policy, body: &Value) -> String {
+    generate_key(&serde_json::json!([
+        policy.name,
+        policy.default_params(),
+        body
+    ]))
+}
+
+/// Exact-match key of an embeddings request. Only the fields that determine
+/// the vectors are hashed, so e.g. requests differing in `user` share an
+/// entry.
+pub fn generate_embeddings_key(policy: &Policy, body: &Value) -> String {
+    generate_key(&serde_json::json!([
+        policy.name,
+        "embeddings",
+        body.get("nim-llm-router"),
+        body.get("model"),
+        body.get("input"),
+        body.get("encoding_format"),
+        body.get("dimensions")
+    ]))
+}
+
+/// Hashes everything but `messages`, so semantically similar prompts only
+/// match when they were sent with the same routing and sampling parameters,
+/// including the policy's defaults.
+pub fn generate_scope(policy: &Policy, body: &Value) -> String {
+    let mut scope = body.clone();
+    if let Some(map) = scope.as_object_mut() {
+        map.remove("messages");
+    }
+    generate_key(&serde_json::json!([policy.default_params(), scope]))
+}
+
+/// Consults the policy's override of `caching.enabled`, if any.
+pub fn is_cacheable(config: &CachingConfig, policy: &Policy, is_stream: bool) -> bool {
+    policy.cache_enabled(config) && !is_stream
+}
+
+/// Publishes the entry count and the compressed and original size of the
+/// compressed bodies.
+fn update_metrics(entries: &HashMap<String, CacheEntry>) {
+    let compressed = entries
+        .values()
+        .filter(|entry| entry.response.encoding.is_some());
+    let (compressed_bytes, uncompressed_bytes) =
+        compressed.fold((0, 0), |(compressed, uncompressed), entry| {
+            (
+                compressed + entry.response.body.len(),
+                uncompressed + entry.uncompressed_len,
+            )
+        });
+    CACHE_SIZE.set(entries.len() as i64);
+    CACHE_COMPRESSED_BYTES.set(compressed_bytes as i64);
+    CACHE_UNCOMPRESSED_BYTES.set(uncompressed_bytes as i64);
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+impl ResponseCache {
+    pub fn new(config: &CachingConfig) -> Self {
+        ResponseCache {
+            entries: RwLock::new(HashMap::new()),
+            max_size: config.max_size,
+            ttl: Duration::from_secs(config.ttl_seconds),
+            ttl_jitter: config.ttl_jitter,
+            compression: config.compression,
+            eviction: config.eviction,
+            clock: AtomicU64::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Clones the response of a hit and marks it as recently used.
+    fn hit(&self, entry: &CacheEntry) -> CachedResponse {
+        entry.last_access.store(self.tick(), Ordering::Relaxed);
+        entry.response.clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let entries = self.entries.read().expect("cache lock poisoned");
+        entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| self.hit(entry))
+    }
+
+    /// Returns the live entry in `scope` whose embedding is most similar to
+    /// `embedding`, provided the similarity reaches `threshold`.
+    pub fn get_similar(
+        &self,
+        scope: &str,
+        embedding: &[f32],
+        threshold: f32,
+    ) -> Option<CachedResponse> {
+        let now = Instant::now();
+        let entries = self.entries.read().expect("cache lock poisoned");
+        entries
+            .values()
+            .filter(|entry| entry.expires_at > now && entry.scope == scope)
+            .filter_map(|entry| {
+                let similarity = cosine_similarity(entry.embedding.as_deref()?, embedding);
+                Some((similarity, entry))
+            })
+            .filter(|(similarity, _)| *similarity >= threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(similarity, entry)| {
+                debug!("Semantic cache hit with similarity {:.4}", similarity);
+                self.hit(entry)
+            })
+    }
+
+    pub fn set(
+        &self,
+        key: String,
+        scope: String,
+        response: CachedResponse,
+        embedding: Option<Vec<f32>>,
+    ) {
+        self.set_with_ttl(key, scope, response, embedding, self.ttl)
+    }
+
+    /// Like `set`, with a TTL other than the cache-wide one.
+    pub fn set_with_ttl(
+        &self,
+        key: String,
+        scope: String,
+        response: CachedResponse,
+        embedding: Option<Vec<f32>>,
+        ttl: Duration,
+    ) {
+        if self.max_size == 0 {
+            return;
+        }
+
+        let uncompressed_len = response.body.len();
+        let response = match (self.compression, response.encoding) {
+            (Some(compression), None) => match compression.compress(&response.body) {
+                Ok(body) => CachedResponse {
+                    body: body.into(),
+                    encoding: Some(compression),
+                    ..response
+                },
+                Err(e) => {
+                    warn!("Caching response uncompressed: {}", e);
+                    response
+                }
+            },
+            _ => response,
+        };
+
+        let now = Instant::now();
+        let mut entries = self.entries.write().expect("cache lock poisoned");
+        entries.retain(|_, entry| entry.expires_at > now);
+
+        if entries.len() >= self.max_size && !entries.contains_key(&key) {
+            let victim = match self.eviction {
+                CacheEviction::Ttl => entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| key.clone()),
+                CacheEviction::Lru => entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_access.load(Ordering::Relaxed))
+                    .map(|(key, _)| key.clone()),
+            };
+            if let Some(victim) = victim {
+                entries.remove(&victim);
+            }
+        }
+
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                uncompressed_len,
+                expires_at: now + jittered_ttl(ttl, self.ttl_jitter, rand::random()),
+                last_access: AtomicU64::new(self.tick()),
+                scope,
+                embedding,
+            },
+        );
+        update_metrics(&entries);
+    }
+
+    /// Removes every entry and returns how many were removed.
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.write().expect("cache lock poisoned");
+        let removed = entries.len();
+        entries.clear();
+        update_metrics(&entries);
+        removed
+    }
+
+    /// Removes every entry produced by `model` and returns how many were
+    /// removed.
+    pub fn invalidate_by_model(&self, model: &str) -> usize {
+        let mut entries = self.entries.write().expect("cache lock poisoned");
+        let before = entries.len();
+        entries.retain(|_, entry| entry.response.model != model);
+        update_metrics(&entries);
+        before - entries.len()
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        let now = Instant::now();
+        let entries = self.entries.read().expect("cache lock poisoned");
+        CacheStats {
+            active_entries: entries
+                .values()
+                .filter(|entry| entry.expires_at > now)
+                .count(),
+            total_entries: entries.len(),
+            max_size: self.max_size,
+            hits: ["exact", "semantic"]
+                .iter()
+                .map(|match_type| CACHE_HITS.with_label_values(&[match_type]).get())
+                .sum(),
+            misses: CACHE_MISSES.get(),
+            memory_bytes: entries
+                .iter()
+                .map(|(key, entry)| entry.approximate_size(key))
+                .sum(),
+        }
+    }
+
+    /// Drops expired entries and refreshes the size gauges, which are
+    /// otherwise only updated when entries are stored or removed.
+    pub fn update_cache_size(&self) {
+        let now = Instant::now();
+        let mut entries = self.entries.write().expect("cache lock poisoned");
+        entries.retain(|_, entry| entry.expires_at > now);
+        update_metrics(&entries);
+    }
+
+    /// Runs `update_cache_size` periodically for the life of the process.
+    pub fn spawn_size_updater(self: &Arc<Self>) -> JoinHandle<()> {
+        let cache = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SIZE_UPDATE_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => cache.update_cache_size(),
+                    None => return,
+                }
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().expect("cache lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Computes an embedding for `text` using an OpenAI-compatible embeddings
+/// endpoint.
+pub async fn compute_embedding(
+    client: &reqwest::Client,
+    config: &SemanticCacheConfig,
+    text: &str,
+) -> Result<Vec<f32>, GatewayApiError> {
+    let mut request = client.post(&config.embedding_url).json(&serde_json::json!({
+        "model": config.embedding_model,
+        "input": text,
+    }));
+    if let Some(api_key) = &config.api_key {
+        request = request.header(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", api_key))?,
+        );
+    }
+
+    let response = request.send().await?.error_for_status()?;
+    let json: Value = response.json().await?;
+    json["data"][0]["embedding"]
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_f64().map(|f| f as f32))
+                .collect::<Vec<f32>>()
+        })
+        .filter(|embedding| !embedding.is_empty())
+        .ok_or_else(|| {
+            warn!("Embedding response did not contain data[0].embedding");
+            GatewayApiError::UnexpectedError {
+                message: "Invalid embedding response".to_string(),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache(max_size: usize) -> ResponseCache {
+        ResponseCache::new(&CachingConfig {
+            enabled: true,
+            max_size,
+            ..CachingConfig::default()
+        })
+    }
+
+    fn response(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            body: Bytes::from_static(body.as_bytes()),
+            classifier: "Chatbot".to_string(),
+            model: "meta/llama-3.1-8b-instruct".to_string(),
+            encoding: None,
+            revalidation: None,
+        }
+    }
+
+    #[test]
+    fn test_generate_key_is_order_independent() {
+        let a = json!({"model": "m", "messages": [{"role": "user", "content": "hi"}]});
+        let b = json!({"messages": [{"role": "user", "content": "hi"}], "model": "m"});
+        assert_eq!(generate_key(&a), generate_key(&b));
+        assert_ne!(generate_key(&a), generate_key(&json!({"model": "m"})));
+    }
+
+    #[test]
+    fn test_content_hash_ignores_response_metadata() {
+        let first = br#"{"id": "a", "created": 1, "choices": [{"message": {"role": "assistant", "content": "Hi"}}]}"#;
+        let second = br#"{"id": "b", "created": 2, "choices": [{"message": {"role": "assistant", "content": "Hi"}}]}"#;
+        let changed = br#"{"id": "a", "created": 1, "choices": [{"message": {"role": "assistant", "content": "Hello"}}]}"#;
+        assert_eq!(content_hash(first), content_hash(second));
+        assert_ne!(content_hash(first), content_hash(changed));
+        assert_ne!(content_hash(b"not json"), content_hash(b"other"));
+    }
+
+    #[test]
+    fn test_exact_match_and_eviction() {
+        let cache = cache(1);
+        cache.set("a".to_string(), "s".to_string(), response("first"), None);
+        assert_eq!(cache.get("a").unwrap().body, "first");
+
+        cache.set("b".to_string(), "s".to_string(), response("second"), None);
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.get("b").unwrap().body, "second");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_invalidate_by_model_and_clear() {
+        let cache = cache(10);
+        cache.set("a".to_string(), "s".to_string(), response("a"), None);
+        cache.set(
+            "b".to_string(),
+            "s".to_string(),
+            CachedResponse {
+                model: "other-model".to_string(),
+                ..response("b")
+            },
+            None,
+        );
+
+        assert_eq!(cache.invalidate_by_model("meta/llama-3.1-8b-instruct"), 1);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.clear(), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_stats_count_expired_entries_until_size_update() {
+        let cache = cache(10);
+        cache.set("live".to_string(), "s".to_string(), response("live"), None);
+        cache.set_with_ttl(
+            "expired".to_string(),
+            "s".to_string(),
+            response("expired"),
+            Some(vec![0.0; 4]),
+            Duration::ZERO,
+        );
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.active_entries, stats.total_entries), (1, 2));
+        assert_eq!(stats.max_size, 10);
+        assert!(stats.memory_bytes > "liveexpired".len() + 4 * std::mem::size_of::<f32>());
+
+        cache.update_cache_size();
+        let stats = cache.get_stats();
+        assert_eq!((stats.active_entries, stats.total_entries), (1, 1));
+
+        // The request kept for revalidation counts too.
+        let prompt = "p".repeat(1000);
+        let mut revalidated = response("live");
+        revalidated.revalidation = Some((json!({"prompt": prompt}), "hash".to_string()));
+        cache.set("live".to_string(), "s".to_string(), revalidated, None);
+        assert!(cache.get_stats().memory_bytes > stats.memory_bytes + prompt.len());
+    }
+
+    #[test]
+    fn test_ttl_jitter_spreads_expiry_above_floor() {
+        let ttl = Duration::from_secs(100);
+        assert_eq!(jittered_ttl(ttl, 0.0, 0.9), ttl);
+        assert_eq!(jittered_ttl(ttl, 0.1, 0.0), Duration::from_secs(90));
+        assert_eq!(jittered_ttl(ttl, 0.1, 0.5), ttl);
+        assert_eq!(jittered_ttl(ttl, 0.1, 1.0), Duration::from_secs(110));
+
+        let short = Duration::from_millis(1500);
+        assert_eq!(jittered_ttl(short, 0.9, 0.0), MIN_JITTERED_TTL);
+        // A TTL already below the floor is not shortened at all.
+        let tiny = Duration::from_millis(200);
+        assert_eq!(jittered_ttl(tiny, 0.5, 0.0), tiny);
+    }
+
+    #[test]
+    fn test_semantic_lookup_respects_threshold_and_scope() {
+        let cache = cache(10);
+        cache.set(
+            "a".to_string(),
+            "scope".to_string(),
+            response("cached"),
+            Some(vec![1.0, 0.0, 0.0]),
+        );
+
+        let close = [0.99, 0.1, 0.0];
+        let far = [0.0, 1.0, 0.0];
+        assert_eq!(
+            cache.get_similar("scope", &close, 0.95).unwrap().body,
+            "cached"
+        );
+        assert!(cache.get_similar("scope", &far, 0.95).is_none());
+        assert!(cache.get_similar("other", &close, 0.95).is_none());
+    }
+
+    #[test]
+    fn test_compressed_entries_round_trip() {
+        for compression in [CacheCompression::Gzip, CacheCompression::Zstd] {
+            let cache = ResponseCache::new(&CachingConfig {
+                enabled: true,
+                compression: Some(compression),
+                ..CachingConfig::default()
+            });
+            let body = r#"{"choices":[{"message":{"content":"hello hello hello hello"}}]}"#;
+            cache.set("a".to_string(), "s".to_string(), response(body), None);
+
+            let cached = cache.get("a").unwrap();
+            assert_eq!(cached.encoding, Some(compression));
+            assert_ne!(cached.body, body);
+            assert_eq!(cached.decoded_body().unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn test_accept_encoding_negotiation() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_encoding(&headers, "gzip"));
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static("br, GZIP;q=0.8, zstd;q=0"),
+        );
+        assert!(accepts_encoding(&headers, "gzip"));
+        assert!(!accepts_encoding(&headers, "zstd"));
+    }
+
+    #[test]
+    fn test_lru_eviction_keeps_recently_read_entries() {
+        for (eviction, survivor) in [(CacheEviction::Ttl, "b"), (CacheEviction::Lru, "a")] {
+            let cache = ResponseCache::new(&CachingConfig {
+                enabled: true,
+                max_size: 2,
+                eviction,
+                ..CachingConfig::default()
+            });
+            cache.set("a".to_string(), "s".to_string(), response("a"), None);
+            cache.set("b".to_string(), "s".to_string(), response("b"), None);
+            assert!(cache.get("a").is_some());
+
+            cache.set("c".to_string(), "s".to_string(), response("c"), None);
+            assert!(cache.get(survivor).is_some(), "{:?}", eviction);
+            assert!(cache.get("c").is_some());
+            assert_eq!(cache.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,639 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Circuit Breaker
+use crate::breaker_sync::{BreakerEvent, BreakerEvents, BreakerSync};
+use crate::config::{CircuitBreakerConfig, FailureKind};
+use crate::metrics::update_circuit_breaker_status;
+use crate::retry::SendError;
+use http::StatusCode;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally.
+    Closed,
+    /// Too many consecutive failures; the endpoint is skipped.
+    Open,
+    /// The open period has passed; the next request is a trial, and no
+    /// other is admitted until its outcome is recorded.
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Name of the state as serialized, e.g. `half_open`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// When `state` was entered.
+    state_since: Instant,
+}
+
+impl FailureKind {
+    /// Failure a response status stands for, if any.
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            Some(FailureKind::RateLimited)
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Some(FailureKind::Auth)
+        } else if status.is_server_error() {
+            Some(FailureKind::ServerError)
+        } else {
+            None
+        }
+    }
+
+    /// Failure a request error stands for. Errors building or decoding the
+    /// request say nothing about the upstream and are `None`.
+    pub fn from_error(error: &SendError) -> Option<Self> {
+        if error.is_connect() {
+            Some(FailureKind::Connection)
+        } else if error.is_timeout() {
+            Some(FailureKind::Timeout)
+        } else {
+            None
+        }
+    }
+}
+
+/// Called with the endpoint and its new state on every transition of a
+/// breaker, right after the metrics are updated. It runs while the breaker
+/// is locked, so it must return quickly and not query the breaker; spawn a
+/// task for anything slow.
+#[derive(Clone)]
+pub struct StateChangeHook(Arc<StateChangeFn>);
+
+type StateChangeFn = dyn Fn(&str, CircuitState) + Send + Sync;
+
+impl StateChangeHook {
+    pub fn new(hook: impl Fn(&str, CircuitState) + Send + Sync + 'static) -> Self {
+        StateChangeHook(Arc::new(hook))
+    }
+
+    /// Posts `{"endpoint", "state", "timestamp"}` to `url` for every
+    /// transition, e.g. to alert as soon as a breaker opens. Failed
+    /// deliveries are logged and not retried.
+    pub fn webhook(client: reqwest::Client, url: String) -> Self {
+        StateChangeHook::new(move |endpoint, state| {
+            // Transitions outside a runtime (e.g. in tests) are not sent.
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            let request = client.post(&url).json(&serde_json::json!({
+                "endpoint": endpoint,
+                "state": state,
+                "timestamp": SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            }));
+            let endpoint = endpoint.to_string();
+            runtime.spawn(async move {
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => warn!(
+                        "Circuit breaker webhook for {} returned {}",
+                        endpoint,
+                        response.status()
+                    ),
+                    Err(e) => warn!("Circuit breaker webhook for {} failed: {}", endpoint, e),
+                }
+            });
+        })
+    }
+}
+
+impl fmt::Debug for StateChangeHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StateChangeHook")
+    }
+}
+
+/// Tracks consecutive failures of one upstream endpoint.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    endpoint: String,
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+    /// Id of the admission holding the half-open trial, 0 if none.
+    trial: AtomicU64,
+    last_admission: AtomicU64,
+    on_state_change: Option<StateChangeHook>,
+    /// Set when the state is shared with other replicas.
+    events: Option<BreakerEvents>,
+}
+
+impl CircuitBreaker {
+    pub fn new(endpoint: &str, config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            endpoint: endpoint.to_string(),
+            config,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                state_since: Instant::now(),
+            }),
+            trial: AtomicU64::new(0),
+            last_admission: AtomicU64::new(0),
+            on_state_change: None,
+            events: None,
+        }
+    }
+
+    pub fn with_state_change_hook(mut self, hook: StateChangeHook) -> Self {
+        self.on_state_change = Some(hook);
+        self
+    }
+
+    fn with_events(mut self, events: BreakerEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state_at(Instant::now())
+    }
+
+    /// Whether a request would be admitted: always while closed, never while
+    /// open, and while half-open only if no trial is in flight.
+    pub fn is_available(&self) -> bool {
+        match self.state() {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => self.trial.load(Ordering::SeqCst) == 0,
+        }
+    }
+
+    /// Admits a request about to be sent, like `is_available`, except that
+    /// of concurrent requests to a half-open breaker exactly one gets the
+    /// trial. Hold the admission until the outcome is recorded.
+    pub fn admit(self: &Arc<Self>) -> Option<Admission> {
+        match self.state() {
+            CircuitState::Closed => Some(Admission(None)),
+            CircuitState::Open => None,
+            CircuitState::HalfOpen => {
+                let id = self.last_admission.fetch_add(1, Ordering::SeqCst) + 1;
+                self.trial
+                    .compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst)
+                    .ok()?;
+                Some(Admission(Some((self.clone(), id))))
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        // Most successes change nothing and are not worth sharing.
+        if inner.consecutive_failures > 0 || inner.state != CircuitState::Closed {
+            self.publish(BreakerEvent::Recovered(self.endpoint.clone()));
+        }
+        inner.consecutive_failures = 0;
+        self.transition(&mut inner, CircuitState::Closed, Instant::now());
+    }
+
+    /// Counts a failure of `kind` if `circuit_breaker.trip_on` includes it.
+    pub fn record_failure(&self, kind: FailureKind) {
+        self.record_failure_at(kind, Instant::now())
+    }
+
+    fn state_at(&self, now: Instant) -> CircuitState {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        let open_duration = Duration::from_secs(self.config.open_duration_secs);
+        if inner.state == CircuitState::Open
+            && inner
+                .opened_at
+                .is_some_and(|opened_at| now.duration_since(opened_at) >= open_duration)
+        {
+            self.transition(&mut inner, CircuitState::HalfOpen, now);
+        }
+        inner.state
+    }
+
+    fn record_failure_at(&self, kind: FailureKind, now: Instant) {
+        if !self.config.enabled {
+            return;
+        }
+        if !self.config.trip_on.contains(&kind) {
+            debug!(
+                "Not counting {:?} of {} toward its breaker",
+                kind, self.endpoint
+            );
+            return;
+        }
+        // Moves an expired open breaker to half-open first.
+        let state = self.state_at(now);
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        let trips = state == CircuitState::HalfOpen
+            || (state == CircuitState::Closed
+                && inner.consecutive_failures >= self.config.failure_threshold);
+        if trips {
+            inner.opened_at = Some(now);
+            self.transition(&mut inner, CircuitState::Open, now);
+            self.publish(BreakerEvent::Opened(self.endpoint.clone()));
+        } else {
+            self.publish(BreakerEvent::Failure(self.endpoint.clone()));
+        }
+    }
+
+    /// Opens the breaker because another replica opened it, to close again
+    /// after `remaining`. Nothing changes if it is already open.
+    pub fn open_remotely(&self, remaining: Duration) {
+        self.open_remotely_at(remaining, Instant::now())
+    }
+
+    fn open_remotely_at(&self, remaining: Duration, now: Instant) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        if inner.state == CircuitState::Open {
+            return;
+        }
+        // Backdated so the breaker turns half-open when `remaining` is over.
+        let open_duration = Duration::from_secs(self.config.open_duration_secs);
+        let elapsed = open_duration.saturating_sub(remaining);
+        inner.opened_at = Some(now.checked_sub(elapsed).unwrap_or(now));
+        inner.consecutive_failures = 0;
+        self.transition(&mut inner, CircuitState::Open, now);
+    }
+
+    fn publish(&self, event: BreakerEvent) {
+        if let Some(events) = &self.events {
+            // Fails when Redis falls behind, or once the sync task is gone,
+            // e.g. at shutdown; the local breaker is unaffected either way.
+            if let Err(TrySendError::Full(event)) = events.try_send(event) {
+                debug!("Not sharing {:?}, too many breaker events queued", event);
+            }
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: CircuitState, now: Instant) {
+        if inner.state == state {
+            return;
+        }
+        match state {
+            CircuitState::Open if inner.consecutive_failures == 0 => warn!(
+                "Circuit breaker for {} opened by another replica",
+                self.endpoint
+            ),
+            CircuitState::Open => warn!(
+                "Circuit breaker for {} opened after {} consecutive failures",
+                self.endpoint, inner.consecutive_failures
+            ),
+            _ => info!("Circuit breaker for {} is now {:?}", self.endpoint, state),
+        }
+        let time_in_left = now.saturating_duration_since(inner.state_since);
+        update_circuit_breaker_status(&self.endpoint, inner.state, time_in_left, state);
+        inner.state = state;
+        inner.state_since = now;
+        // A recorded outcome ends the trial.
+        self.trial.store(0, Ordering::SeqCst);
+        if let Some(hook) = &self.on_state_change {
+            (hook.0)(&self.endpoint, state);
+        }
+    }
+}
+
+/// A request let through by a breaker. Dropping the admission of a trial
+/// whose outcome was never recorded, e.g. because the request was cancelled
+/// or failed in a way that says nothing about the endpoint, lets the next
+/// request try.
+#[derive(Debug)]
+pub struct Admission(Option<(Arc<CircuitBreaker>, u64)>);
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if let Some((breaker, id)) = &self.0 {
+            // Only frees its own trial, not a later one.
+            let _ = breaker
+                .trial
+                .compare_exchange(*id, 0, Ordering::SeqCst, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Circuit breakers keyed by upstream `api_base`.
+#[derive(Debug)]
+pub struct CircuitBreakerRegistry {
+    config: CircuitBreakerConfig,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+    on_state_change: Option<StateChangeHook>,
+    sync: Option<BreakerSync>,
+}
+
+impl CircuitBreakerRegistry {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        let sync = config.shared.as_ref().and_then(|shared| {
+            BreakerSync::new(shared, config)
+                .inspect_err(|e| error!("Circuit breakers stay local: {}", e))
+                .ok()
+        });
+        CircuitBreakerRegistry {
+            config: config.clone(),
+            breakers: Mutex::new(HashMap::new()),
+            on_state_change: None,
+            sync,
+        }
+    }
+
+    /// Starts sharing breaker state through Redis when
+    /// `circuit_breaker.shared` is set, for the life of the registry.
+    pub fn spawn_sync(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        self.sync.as_ref()?.spawn(Arc::downgrade(self))
+    }
+
+    /// Calls `hook` on every transition of the breakers created afterwards.
+    pub fn with_state_change_hook(mut self, hook: StateChangeHook) -> Self {
+        self.on_state_change = Some(hook);
+        self
+    }
+
+    pub fn get(&self, endpoint: &str) -> Arc<CircuitBreaker> {
+        let mut breakers = self.breakers.lock().expect("circuit breaker lock poisoned");
+        breakers
+            .entry(endpoint.to_string())
+            .or_insert_with(|| {
+                let mut breaker = CircuitBreaker::new(endpoint, self.config.clone());
+                if let Some(hook) = &self.on_state_change {
+                    breaker = breaker.with_state_change_hook(hook.clone());
+                }
+                if let Some(sync) = &self.sync {
+                    breaker = breaker.with_events(sync.events());
+                }
+                Arc::new(breaker)
+            })
+            .clone()
+    }
+
+    fn find(&self, endpoint: &str) -> Option<Arc<CircuitBreaker>> {
+        self.breakers
+            .lock()
+            .expect("circuit breaker lock poisoned")
+            .get(endpoint)
+            .cloned()
+    }
+
+    /// State of the breaker for `endpoint`; closed if it has none yet.
+    pub fn state(&self, endpoint: &str) -> CircuitState {
+        self.find(endpoint)
+            .map_or(CircuitState::Closed, |breaker| breaker.state())
+    }
+
+    pub fn is_available(&self, endpoint: &str) -> bool {
+        self.find(endpoint)
+            .is_none_or(|breaker| breaker.is_available())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::CIRCUIT_BREAKER_STATE_DURATION;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(
+            "http://breaker-test",
+            CircuitBreakerConfig {
+                enabled: true,
+                failure_threshold: 2,
+                open_duration_secs: 30,
+                trip_on: vec![FailureKind::ServerError],
+                webhook_url: None,
+                shared: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_recovers_through_half_open() {
+        let breaker = breaker();
+        let start = Instant::now();
+        breaker.record_failure_at(FailureKind::ServerError, start);
+        assert_eq!(breaker.state_at(start), CircuitState::Closed);
+        breaker.record_failure_at(FailureKind::ServerError, start);
+        assert_eq!(breaker.state_at(start), CircuitState::Open);
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(breaker.state_at(later), CircuitState::HalfOpen);
+        // A failed trial reopens immediately; a successful one closes.
+        breaker.record_failure_at(FailureKind::ServerError, later);
+        assert_eq!(breaker.state_at(later), CircuitState::Open);
+        let much_later = later + Duration::from_secs(30);
+        assert_eq!(breaker.state_at(much_later), CircuitState::HalfOpen);
+        breaker.record_success();
+        assert_eq!(breaker.state_at(much_later), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_admits_a_single_trial() {
+        let breaker = Arc::new(CircuitBreaker::new(
+            "http://breaker-trial-test",
+            CircuitBreakerConfig {
+                open_duration_secs: 0,
+                ..breaker().config
+            },
+        ));
+        breaker.record_failure(FailureKind::ServerError);
+        breaker.record_failure(FailureKind::ServerError);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        let barrier = Arc::new(std::sync::Barrier::new(16));
+        let admissions: Vec<_> = (0..16)
+            .map(|_| {
+                let breaker = breaker.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    breaker.admit()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        let (trials, rejected): (Vec<_>, Vec<_>) =
+            admissions.into_iter().partition(Option::is_some);
+        assert_eq!(trials.len(), 1);
+        assert_eq!(rejected.len(), 15);
+        assert!(!breaker.is_available());
+
+        // A cancelled trial lets the next request try.
+        drop(trials);
+        let trial = breaker.admit().unwrap();
+        assert!(breaker.admit().is_none());
+        breaker.record_success();
+        drop(trial);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.admit().is_some() && breaker.admit().is_some());
+    }
+
+    #[test]
+    fn test_records_time_spent_in_each_state() {
+        let breaker = CircuitBreaker::new("http://breaker-duration-test", breaker().config);
+        let durations = |state: &str| {
+            CIRCUIT_BREAKER_STATE_DURATION
+                .with_label_values(&["http://breaker-duration-test", state])
+                .get_sample_sum()
+        };
+        let start = Instant::now();
+        breaker.record_failure_at(FailureKind::ServerError, start);
+        breaker.record_failure_at(FailureKind::ServerError, start + Duration::from_secs(5));
+        let later = start + Duration::from_secs(45);
+        assert_eq!(breaker.state_at(later), CircuitState::HalfOpen);
+        assert_eq!(durations("open"), 40.0);
+        breaker.record_failure_at(FailureKind::ServerError, later + Duration::from_secs(2));
+        assert_eq!(durations("half_open"), 2.0);
+        assert!(durations("closed") >= 5.0);
+    }
+
+    #[test]
+    fn test_opened_remotely_until_the_remaining_time_is_over() {
+        let breaker = breaker();
+        let start = Instant::now();
+        breaker.open_remotely_at(Duration::from_secs(10), start);
+        assert_eq!(breaker.state_at(start), CircuitState::Open);
+        let later = start + Duration::from_secs(10);
+        assert_eq!(breaker.state_at(later), CircuitState::HalfOpen);
+        // A trial that fails opens it for the full duration again.
+        breaker.record_failure_at(FailureKind::ServerError, later);
+        breaker.open_remotely_at(Duration::from_secs(1), later);
+        let much_later = later + Duration::from_secs(29);
+        assert_eq!(breaker.state_at(much_later), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_success_resets_consecutive_failures() {
+        let breaker = breaker();
+        breaker.record_failure(FailureKind::ServerError);
+        breaker.record_success();
+        breaker.record_failure(FailureKind::ServerError);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_only_configured_failure_kinds_trip() {
+        let breaker = breaker();
+        breaker.record_failure(FailureKind::ServerError);
+        for _ in 0..5 {
+            breaker.record_failure(FailureKind::RateLimited);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        // Ignored failures do not reset the count either.
+        breaker.record_failure(FailureKind::ServerError);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_statuses_are_classified() {
+        assert_eq!(
+            FailureKind::from_status(StatusCode::TOO_MANY_REQUESTS),
+            Some(FailureKind::RateLimited)
+        );
+        assert_eq!(
+            FailureKind::from_status(StatusCode::BAD_GATEWAY),
+            Some(FailureKind::ServerError)
+        );
+        assert_eq!(
+            FailureKind::from_status(StatusCode::FORBIDDEN),
+            Some(FailureKind::Auth)
+        );
+        assert_eq!(FailureKind::from_status(StatusCode::BAD_REQUEST), None);
+    }
+
+    #[test]
+    fn test_state_change_hook_sees_every_transition() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let hook = StateChangeHook::new(move |endpoint, state| {
+            recorded.lock().unwrap().push((endpoint.to_string(), state));
+        });
+        let breaker = breaker().with_state_change_hook(hook);
+
+        let start = Instant::now();
+        breaker.record_failure_at(FailureKind::ServerError, start);
+        breaker.record_failure_at(FailureKind::ServerError, start);
+        breaker.state_at(start + Duration::from_secs(30));
+        breaker.record_success();
+        // Repeated successes are not transitions.
+        breaker.record_success();
+
+        let endpoint = "http://breaker-test".to_string();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (endpoint.clone(), CircuitState::Open),
+                (endpoint.clone(), CircuitState::HalfOpen),
+                (endpoint, CircuitState::Closed),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_is_posted_when_a_breaker_opens() {
+        use wiremock::matchers::{body_partial_json, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "endpoint": "http://breaker-webhook-test",
+                "state": "open"
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&webhook)
+            .await;
+
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            ..CircuitBreakerConfig::default()
+        };
+        let hook = StateChangeHook::webhook(reqwest::Client::new(), webhook.uri());
+        let registry = CircuitBreakerRegistry::new(&config).with_state_change_hook(hook);
+        registry
+            .get("http://breaker-webhook-test")
+            .record_failure(FailureKind::Connection);
+
+        for _ in 0..50 {
+            if !webhook.received_requests().await.unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        webhook.verify().await;
+    }
+}
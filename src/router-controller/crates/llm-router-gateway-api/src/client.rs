@@ -0,0 +1,246 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client
+use crate::config::{ClientConfig, Llm, TlsConfig};
+use crate::dns::CachingResolver;
+use crate::error::ConfigError;
+use log::{info, warn};
+use reqwest::{Certificate, Client, ClientBuilder, Identity};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn read_tls_file(path: &str) -> Result<Vec<u8>, ConfigError> {
+    std::fs::read(path).map_err(|e| ConfigError::InvalidTls {
+        path: path.to_string(),
+        message: e.to_string(),
+    })
+}
+
+pub(crate) fn apply_tls(
+    mut builder: ClientBuilder,
+    tls: &TlsConfig,
+) -> Result<ClientBuilder, ConfigError> {
+    if let Some(ca_cert_path) = &tls.ca_cert_path {
+        let pem = read_tls_file(ca_cert_path)?;
+        let certs = Certificate::from_pem_bundle(&pem).map_err(|e| ConfigError::InvalidTls {
+            path: ca_cert_path.clone(),
+            message: e.to_string(),
+        })?;
+        if certs.is_empty() {
+            return Err(ConfigError::InvalidTls {
+                path: ca_cert_path.clone(),
+                message: "no certificates found".to_string(),
+            });
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+        info!("Loaded custom root CA certificates from {}", ca_cert_path);
+    }
+
+    match (&tls.client_cert_path, &tls.client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_tls_file(cert_path)?;
+            let key = read_tls_file(key_path)?;
+            let identity =
+                Identity::from_pkcs8_pem(&cert, &key).map_err(|e| ConfigError::InvalidTls {
+                    path: cert_path.clone(),
+                    message: e.to_string(),
+                })?;
+            builder = builder.identity(identity);
+            info!("Loaded client certificate from {}", cert_path);
+        }
+        (Some(path), None) | (None, Some(path)) => {
+            return Err(ConfigError::InvalidTls {
+                path: path.clone(),
+                message: "client_cert_path and client_key_path must be set together".to_string(),
+            });
+        }
+        (None, None) => {}
+    }
+
+    Ok(builder)
+}
+
+/// Connection pool settings of one client. reqwest applies them client-wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PoolSettings {
+    max_idle_per_host: Option<usize>,
+    idle_timeout_secs: Option<u64>,
+}
+
+impl PoolSettings {
+    fn global(config: &ClientConfig) -> Self {
+        PoolSettings {
+            max_idle_per_host: config.connection_pool_size,
+            idle_timeout_secs: config.pool_idle_timeout_secs,
+        }
+    }
+
+    fn for_llm(config: &ClientConfig, llm: &Llm) -> Self {
+        PoolSettings {
+            max_idle_per_host: llm.pool_max_idle.or(config.connection_pool_size),
+            idle_timeout_secs: llm.pool_idle_timeout_secs.or(config.pool_idle_timeout_secs),
+        }
+    }
+}
+
+fn build_client(config: &ClientConfig, pool: PoolSettings) -> Result<Client, ConfigError> {
+    let mut builder = Client::builder();
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(timeout) = config.request_timeout_secs {
+        builder = builder.timeout(Duration::from_secs(timeout));
+    }
+    if let Some(timeout) = config.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(timeout));
+    }
+    if let Some(max_idle) = pool.max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = pool.idle_timeout_secs {
+        builder = builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
+    }
+    // Each client has its own cache, like its own connection pool.
+    if let Some(resolver) = CachingResolver::from_config(&config.dns) {
+        builder = builder.dns_resolver(Arc::new(resolver));
+    }
+    builder = apply_tls(builder, &config.tls)?;
+
+    builder
+        .build()
+        .map_err(|e| ConfigError::HttpClient(e.to_string()))
+}
+
+/// Builds the outbound HTTP client shared by the proxy and health checks.
+pub fn create_http_client(config: &ClientConfig) -> Result<Client, ConfigError> {
+    build_client(config, PoolSettings::global(config))
+}
+
+/// Clients used to reach the LLMs. LLMs without pool settings of their own
+/// share the default client; the others get one client per distinct pool
+/// setting, since reqwest cannot size pools per host.
+#[derive(Debug)]
+pub struct UpstreamClients {
+    config: ClientConfig,
+    default: Client,
+    dedicated: Mutex<HashMap<PoolSettings, Client>>,
+}
+
+impl UpstreamClients {
+    pub fn new(config: &ClientConfig, default: Client) -> Self {
+        UpstreamClients {
+            config: config.clone(),
+            default,
+            dedicated: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The client for requests to `llm`. Dedicated clients are built on first
+    /// use, so LLMs added by a config reload get theirs as well.
+    pub fn for_llm(&self, llm: &Llm) -> Client {
+        let pool = PoolSettings::for_llm(&self.config, llm);
+        if pool == PoolSettings::global(&self.config) {
+            return self.default.clone();
+        }
+        let mut dedicated = self.dedicated.lock().expect("client pool lock poisoned");
+        if let Some(client) = dedicated.get(&pool) {
+            return client.clone();
+        }
+        match build_client(&self.config, pool) {
+            Ok(client) => {
+                info!("Created HTTP client for {} with {:?}", llm.name, pool);
+                dedicated.insert(pool, client.clone());
+                client
+            }
+            Err(e) => {
+                // Only TLS settings can fail, and the default client was
+                // already built with them.
+                warn!("Using the default HTTP client for {}: {}", llm.name, e);
+                self.default.clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_client_builds() {
+        assert!(create_http_client(&ClientConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_missing_ca_cert_fails_fast() {
+        let config = ClientConfig {
+            tls: TlsConfig {
+                ca_cert_path: Some("/nonexistent/ca.pem".to_string()),
+                ..TlsConfig::default()
+            },
+            ..ClientConfig::default()
+        };
+        let err = create_http_client(&config).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/ca.pem"));
+    }
+
+    #[test]
+    fn test_client_cert_requires_key() {
+        let config = ClientConfig {
+            tls: TlsConfig {
+                client_cert_path: Some("client.pem".to_string()),
+                ..TlsConfig::default()
+            },
+            ..ClientConfig::default()
+        };
+        assert!(matches!(
+            create_http_client(&config),
+            Err(ConfigError::InvalidTls { .. })
+        ));
+    }
+
+    #[test]
+    fn test_llms_share_clients_by_pool_settings() {
+        let config = ClientConfig {
+            connection_pool_size: Some(8),
+            ..ClientConfig::default()
+        };
+        let clients = UpstreamClients::new(&config, create_http_client(&config).unwrap());
+        let dedicated = || clients.dedicated.lock().unwrap().len();
+        let llm = |name: &str, pool_max_idle: Option<usize>| Llm {
+            name: name.to_string(),
+            pool_max_idle,
+            ..Llm::default()
+        };
+
+        clients.for_llm(&llm("rare", None));
+        clients.for_llm(&llm("explicit-default", Some(8)));
+        assert_eq!(dedicated(), 0);
+
+        clients.for_llm(&llm("classifier", Some(256)));
+        clients.for_llm(&llm("classifier-2", Some(256)));
+        assert_eq!(dedicated(), 1);
+
+        clients.for_llm(&Llm {
+            pool_idle_timeout_secs: Some(5),
+            ..llm("short-lived", None)
+        });
+        assert_eq!(dedicated(), 2);
+    }
+}
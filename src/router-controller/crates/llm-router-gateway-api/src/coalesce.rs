@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Coalesce
+use crate::error::GatewayApiError;
+use bytes::Bytes;
+use http::{HeaderMap, Response, StatusCode};
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+type ProxyResponse = Response<BoxBody<Bytes, GatewayApiError>>;
+
+/// Set by a leader that cached nothing; followers are released when the
+/// leader is dropped.
+type Outcome = Option<Arc<LeaderFailure>>;
+
+/// Cacheable requests currently being sent upstream, by cache key. The first
+/// request for a key leads; identical requests arriving meanwhile wait for it
+/// to finish and then look the cache up again, or get its response if it
+/// failed.
+#[derive(Debug, Default)]
+pub struct RequestCoalescer {
+    in_flight: Arc<Mutex<HashMap<String, watch::Receiver<Outcome>>>>,
+}
+
+/// Role of a request in the flight for its key.
+#[derive(Debug)]
+pub enum Flight {
+    /// Sends the request upstream. Followers are released when it is dropped.
+    Leader(FlightGuard),
+    /// Resolves once the leader finished, successfully or not.
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// Unsuccessful response of a leader, given to each of its followers.
+#[derive(Debug)]
+pub struct LeaderFailure {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl LeaderFailure {
+    pub fn response(&self) -> ProxyResponse {
+        let mut response = Response::new(
+            Full::new(self.body.clone())
+                .map_err(|never| match never {})
+                .boxed(),
+        );
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// Held by the leader until its response is cached or it gave up.
+#[derive(Debug)]
+pub struct FlightGuard {
+    in_flight: Arc<Mutex<HashMap<String, watch::Receiver<Outcome>>>>,
+    key: String,
+    // Dropping it releases the followers.
+    done: watch::Sender<Outcome>,
+}
+
+impl FlightGuard {
+    /// Ends the flight with the leader's `result`. An unsuccessful response
+    /// or an error is handed to all followers at once, so they do not take
+    /// turns sending a request that just failed.
+    pub async fn finish(
+        self,
+        result: Result<ProxyResponse, GatewayApiError>,
+    ) -> Result<ProxyResponse, GatewayApiError> {
+        match result {
+            Ok(response) if !response.status().is_success() => {
+                let (parts, body) = response.into_parts();
+                let body = body.collect().await?.to_bytes();
+                self.fail(parts.status, parts.headers.clone(), body.clone());
+                let body = Full::new(body).map_err(|never| match never {}).boxed();
+                Ok(Response::from_parts(parts, body))
+            }
+            Err(error) => {
+                if let Ok(response) = error.to_response() {
+                    let (parts, body) = response.into_parts();
+                    if let Ok(body) = body.collect().await {
+                        self.fail(parts.status, parts.headers, body.to_bytes());
+                    }
+                }
+                Err(error)
+            }
+            result => result,
+        }
+    }
+
+    fn fail(&self, status: StatusCode, headers: HeaderMap, body: Bytes) {
+        let failure = LeaderFailure {
+            status,
+            headers,
+            body,
+        };
+        self.done.send_replace(Some(Arc::new(failure)));
+    }
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.in_flight
+            .lock()
+            .expect("coalescer lock poisoned")
+            .remove(&self.key);
+    }
+}
+
+impl RequestCoalescer {
+    pub fn new() -> Self {
+        RequestCoalescer::default()
+    }
+
+    pub fn join(&self, key: &str) -> Flight {
+        let mut in_flight = self.in_flight.lock().expect("coalescer lock poisoned");
+        if let Some(done) = in_flight.get(key) {
+            return Flight::Follower(done.clone());
+        }
+        let (done_tx, done_rx) = watch::channel(None);
+        in_flight.insert(key.to_string(), done_rx);
+        Flight::Leader(FlightGuard {
+            in_flight: self.in_flight.clone(),
+            key: key.to_string(),
+            done: done_tx,
+        })
+    }
+}
+
+/// Waits until the leader of a flight finished. Returns its failure, if it
+/// failed.
+pub async fn wait_for_leader(mut done: watch::Receiver<Outcome>) -> Outcome {
+    // Returns once the sender is dropped.
+    while done.changed().await.is_ok() {}
+    let outcome = done.borrow().clone();
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IntoResponse;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_followers_wait_for_leader_and_can_lead_after_it() {
+        let coalescer = RequestCoalescer::new();
+        let Flight::Leader(leader) = coalescer.join("key") else {
+            panic!("first request must lead");
+        };
+        assert!(matches!(coalescer.join("other"), Flight::Leader(_)));
+        let Flight::Follower(done) = coalescer.join("key") else {
+            panic!("identical request must follow");
+        };
+
+        let waiting = tokio::spawn(wait_for_leader(done));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        // A leader that gave up releases the followers, and the next one
+        // leads.
+        drop(leader);
+        assert!(waiting.await.unwrap().is_none());
+        assert!(matches!(coalescer.join("key"), Flight::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_followers_all_get_the_error_of_a_failed_leader() {
+        let coalescer = RequestCoalescer::new();
+        let Flight::Leader(leader) = coalescer.join("key") else {
+            panic!("first request must lead");
+        };
+        let followers: Vec<_> = (0..2)
+            .map(|_| match coalescer.join("key") {
+                Flight::Follower(done) => tokio::spawn(wait_for_leader(done)),
+                Flight::Leader(_) => panic!("identical request must follow"),
+            })
+            .collect();
+
+        let error = GatewayApiError::client_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "upstream unavailable",
+            "unavailable",
+        );
+        let response = error.into_response();
+        let response = leader.finish(Ok(response)).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        for follower in followers {
+            let failure = follower.await.unwrap().expect("leader failed");
+            let response = failure.response();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let shared = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(shared, body);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cohere
+//!
+//! Translates Cohere v2 chat responses (`/v2/chat`) into OpenAI chat
+//! completions for LLMs whose `provider_type` is `cohere`. Requests only need
+//! their fields renamed, which [`crate::transform`] does.
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Text of the `text` blocks of a v2 message's `content`.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn finish_reason(finish_reason: &str) -> Option<&'static str> {
+    match finish_reason {
+        "COMPLETE" | "STOP_SEQUENCE" => Some("stop"),
+        "MAX_TOKENS" => Some("length"),
+        "TOOL_CALL" => Some("tool_calls"),
+        _ => None,
+    }
+}
+
+/// Converts a Cohere v2 chat response into an OpenAI chat completion
+/// response body. Usage is taken from `usage.tokens`, the tokens the model
+/// actually processed, rather than `usage.billed_units`.
+pub fn to_openai_response(body: &Value) -> Value {
+    let message = &body["message"];
+    let mut openai_message = json!({
+        "role": "assistant",
+        "content": content_text(&message["content"]),
+    });
+    // v2 tool calls already have the OpenAI shape.
+    if let Some(tool_calls) = message.get("tool_calls").filter(|calls| !calls.is_null()) {
+        openai_message["tool_calls"] = tool_calls.clone();
+    }
+    let input_tokens = body["usage"]["tokens"]["input_tokens"]
+        .as_u64()
+        .unwrap_or(0);
+    let output_tokens = body["usage"]["tokens"]["output_tokens"]
+        .as_u64()
+        .unwrap_or(0);
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    json!({
+        "id": body["id"],
+        "object": "chat.completion",
+        "created": created,
+        "model": body["model"],
+        "choices": [{
+            "index": 0,
+            "message": openai_message,
+            "finish_reason": body["finish_reason"].as_str().and_then(finish_reason),
+        }],
+        "usage": {
+            "prompt_tokens": input_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": input_tokens + output_tokens,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v2_chat_response_translation() {
+        // As returned by `POST /v2/chat`.
+        let body = json!({
+            "id": "c14c80c3-18eb-4519-9460-6c92edd8cfb4",
+            "finish_reason": "COMPLETE",
+            "message": {
+                "role": "assistant",
+                "content": [{
+                    "type": "text",
+                    "text": "LLMs stand for Large Language Models."
+                }]
+            },
+            "usage": {
+                "billed_units": {"input_tokens": 5, "output_tokens": 9},
+                "tokens": {"input_tokens": 71, "output_tokens": 9}
+            }
+        });
+        let openai = to_openai_response(&body);
+        assert_eq!(openai["id"], "c14c80c3-18eb-4519-9460-6c92edd8cfb4");
+        assert_eq!(
+            openai["choices"][0]["message"],
+            json!({"role": "assistant", "content": "LLMs stand for Large Language Models."})
+        );
+        assert_eq!(openai["choices"][0]["finish_reason"], "stop");
+        assert_eq!(
+            openai["usage"],
+            json!({"prompt_tokens": 71, "completion_tokens": 9, "total_tokens": 80})
+        );
+
+        let truncated = json!({
+            "finish_reason": "MAX_TOKENS",
+            "message": {"role": "assistant", "content": []}
+        });
+        let openai = to_openai_response(&truncated);
+        assert_eq!(openai["choices"][0]["finish_reason"], "length");
+        assert_eq!(openai["choices"][0]["message"]["content"], "");
+    }
+}
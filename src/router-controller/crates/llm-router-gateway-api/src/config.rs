@@ -14,33 +14,1561 @@
 // limitations under the License.
 
 //! Config
+use crate::anthropic::{CHAT_COMPLETIONS_PATH, MESSAGES_PATH};
 use crate::error::ConfigError;
+use crate::secrets::{resolve_secrets, SecretResolver, VaultResolver};
+use crate::signature::{verify_config, CONFIG_PUBLIC_KEY_ENV};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use ipnet::IpNet;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct RouterConfig {
     pub policies: Vec<Policy>,
+    /// Logical policy names whose traffic is split between real policies.
+    #[serde(default)]
+    pub experiments: Vec<Experiment>,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub client: ClientConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub caching: CachingConfig,
+    #[serde(default)]
+    pub load_balancing: LoadBalancingConfig,
+    #[serde(default)]
+    pub observability: ObservabilityConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Cross-origin access for browser clients. Same-origin only by default.
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Where `vault://` references in string values are resolved.
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+    /// Client-facing model names mapped to the `name` or `model` of an LLM,
+    /// e.g. `gpt-4: meta/llama-3.1-70b-instruct`, for manual routing.
+    #[serde(default)]
+    pub model_aliases: BTreeMap<String, String>,
+    /// Policy (or experiment) for requests that name none, routed by the
+    /// classifier. Such requests are rejected when unset.
+    #[serde(default)]
+    pub default_policy: Option<String>,
+}
+
+/// Tenant label for requests whose API key has no configured tenant.
+pub const UNKNOWN_TENANT: &str = "unknown";
+
+/// Stops routing to an upstream instance after repeated failures.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_circuit_breaker_enabled")]
+    pub enabled: bool,
+    /// Consecutive failures that open the breaker.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Seconds an open breaker waits before letting a trial request through.
+    #[serde(default = "default_open_duration_secs")]
+    pub open_duration_secs: u64,
+    /// Kinds of failure that count toward `failure_threshold`. Other
+    /// failures neither count nor reset the count.
+    #[serde(default = "default_trip_on")]
+    pub trip_on: Vec<FailureKind>,
+    /// URL notified with a JSON POST on every breaker transition.
+    pub webhook_url: Option<String>,
+    /// Share failures and openings with the other replicas through Redis.
+    /// Each replica's breakers are independent when unset.
+    pub shared: Option<SharedCircuitBreakerConfig>,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            enabled: default_circuit_breaker_enabled(),
+            failure_threshold: default_failure_threshold(),
+            open_duration_secs: default_open_duration_secs(),
+            trip_on: default_trip_on(),
+            webhook_url: None,
+            shared: None,
+        }
+    }
+}
+
+/// Redis through which the replicas of a deployment share breaker state.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SharedCircuitBreakerConfig {
+    /// e.g. `redis://redis:6379/0`.
+    pub redis_url: String,
+    /// Prefix of the keys of each endpoint.
+    #[serde(default = "default_breaker_key_prefix")]
+    pub key_prefix: String,
+    /// How often breakers opened by other replicas are looked up.
+    #[serde(default = "default_breaker_sync_interval_ms")]
+    pub sync_interval_ms: u64,
+}
+
+fn default_breaker_key_prefix() -> String {
+    "llm-router:circuit-breaker:".to_string()
+}
+
+fn default_breaker_sync_interval_ms() -> u64 {
+    1000
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The upstream could not be reached.
+    Connection,
+    /// The request timed out.
+    Timeout,
+    /// The upstream returned a 5xx.
+    ServerError,
+    /// The upstream returned 429.
+    RateLimited,
+    /// The upstream rejected the provider key with 401 or 403.
+    Auth,
+}
+
+fn default_trip_on() -> Vec<FailureKind> {
+    vec![
+        FailureKind::Connection,
+        FailureKind::Timeout,
+        FailureKind::ServerError,
+    ]
+}
+
+fn default_circuit_breaker_enabled() -> bool {
+    true
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_duration_secs() -> u64 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Seconds to wait for in-flight requests to finish after a shutdown signal.
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+    /// Timeout in seconds for each individual readiness probe.
+    #[serde(default = "default_health_check_timeout_secs")]
+    pub health_check_timeout_secs: u64,
+    /// Overall deadline in seconds for a readiness check; probes still
+    /// running when it passes are reported as unhealthy.
+    #[serde(default = "default_health_check_deadline_secs")]
+    pub health_check_deadline_secs: u64,
+    /// Seconds a readiness result is served from cache before re-probing.
+    #[serde(default = "default_health_cache_secs")]
+    pub health_cache_secs: u64,
+    /// Requests with larger bodies are rejected with 413. Unlimited when unset.
+    pub max_request_body_bytes: Option<u64>,
+    /// Buffered (non-streaming) upstream responses larger than this are
+    /// aborted with 502. Unlimited when unset.
+    pub max_response_body_bytes: Option<u64>,
+    /// How changes to the config file are picked up without a restart.
+    #[serde(default)]
+    pub config_reload: ConfigReloadConfig,
+    /// Limits on requests in flight to the LLMs.
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    /// Sends a throwaway completion to every LLM instance before accepting
+    /// traffic. No warmup when unset.
+    pub warmup: Option<WarmupConfig>,
+    /// Refuse to start when any warmup request fails. Otherwise failures are
+    /// only logged.
+    #[serde(default)]
+    pub warmup_required: bool,
+    /// Probes every policy's Triton server and every LLM `api_base` once
+    /// before accepting traffic, logging the ones that cannot be reached.
+    #[serde(default)]
+    pub validate_connectivity_on_start: bool,
+    /// Refuse to start when the startup connectivity check finds an
+    /// unreachable endpoint. Otherwise they are only logged.
+    #[serde(default)]
+    pub connectivity_required: bool,
+    /// Send an SSE keep-alive comment this often until a streamed response
+    /// produces its first chunk. Disabled when unset.
+    pub stream_keepalive_secs: Option<u64>,
+    /// End a streamed response whose upstream fails midway with a chunk
+    /// marking it truncated, instead of aborting it with an error.
+    #[serde(default)]
+    pub truncate_streams_on_error: bool,
+    /// Splits requests with several prompts into concurrent upstream
+    /// requests. Such requests are sent as they are when unset.
+    pub batching: Option<BatchingConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            drain_timeout_secs: default_drain_timeout_secs(),
+            health_check_timeout_secs: default_health_check_timeout_secs(),
+            health_check_deadline_secs: default_health_check_deadline_secs(),
+            health_cache_secs: default_health_cache_secs(),
+            max_request_body_bytes: None,
+            max_response_body_bytes: None,
+            config_reload: ConfigReloadConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            warmup: None,
+            warmup_required: false,
+            validate_connectivity_on_start: false,
+            connectivity_required: false,
+            stream_keepalive_secs: None,
+            truncate_streams_on_error: false,
+            batching: None,
+        }
+    }
+}
+
+/// What a batch request gets when some of its prompts fail.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchFailurePolicy {
+    /// The error response of the first prompt that failed.
+    #[default]
+    FailAll,
+    /// The successful choices, and a choice with the error for each failed
+    /// prompt. The first failure is returned when every prompt fails.
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BatchingConfig {
+    /// Prompts of one batch sent upstream at the same time.
+    #[serde(default = "default_batch_max_concurrency")]
+    pub max_concurrency: usize,
+    /// Batches with more prompts are rejected with 400.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    #[serde(default)]
+    pub failure_policy: BatchFailurePolicy,
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        BatchingConfig {
+            max_concurrency: default_batch_max_concurrency(),
+            max_batch_size: default_max_batch_size(),
+            failure_policy: BatchFailurePolicy::default(),
+        }
+    }
+}
+
+fn default_batch_max_concurrency() -> usize {
+    4
+}
+
+fn default_max_batch_size() -> usize {
+    64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigReloadMode {
+    /// Reacts to file system events for the config file (inotify, FSEvents,
+    /// kqueue), polling when they are not available.
+    #[default]
+    Watch,
+    /// Checks the file every `poll_interval_secs`, for filesystems where
+    /// change detection is unreliable (e.g. some network mounts).
+    Poll,
+    /// Only reload through `POST /admin/reload`.
+    Off,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReloadConfig {
+    #[serde(default)]
+    pub mode: ConfigReloadMode,
+    #[serde(default = "default_reload_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// A change is applied once the file has been quiet for this long, so a
+    /// burst of editor saves triggers a single reload.
+    #[serde(default = "default_reload_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl Default for ConfigReloadConfig {
+    fn default() -> Self {
+        ConfigReloadConfig {
+            mode: ConfigReloadMode::default(),
+            poll_interval_secs: default_reload_poll_interval_secs(),
+            debounce_ms: default_reload_debounce_ms(),
+        }
+    }
+}
+
+fn default_reload_poll_interval_secs() -> u64 {
+    30
+}
+
+fn default_reload_debounce_ms() -> u64 {
+    250
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Requests in flight to all LLMs together. Unlimited when unset.
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests that may wait for a free slot once a limit is reached. With
+    /// `0` requests over a limit are rejected immediately.
+    #[serde(default)]
+    pub queue_depth: usize,
+    /// How long a queued request waits for a slot before it is rejected.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    /// Queue ordered by request priority in front of the limits above.
+    /// Disabled when unset.
+    pub priority: Option<PriorityQueueConfig>,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig {
+            max_concurrent_requests: None,
+            queue_depth: 0,
+            queue_timeout_ms: default_queue_timeout_ms(),
+            priority: None,
+        }
+    }
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    1000
+}
+
+/// Lets interactive requests overtake batch ones when capacity is short.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PriorityQueueConfig {
+    /// Request header carrying `high`, `normal` or `low`. Requests without a
+    /// valid value are `normal`.
+    #[serde(default = "default_priority_header")]
+    pub header: String,
+    /// Requests let through at once. Further requests wait, and a free slot
+    /// goes to the oldest request of the highest waiting priority.
+    pub workers: usize,
+    /// How long a request waits for a slot before it is rejected with 503.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub max_queue_wait_ms: u64,
+}
+
+fn default_priority_header() -> String {
+    "X-Priority".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WarmupConfig {
+    #[serde(default = "default_warmup_prompt")]
+    pub prompt: String,
+    #[serde(default = "default_warmup_max_tokens")]
+    pub max_tokens: u32,
+    /// Timeout of each warmup request; cold models can take a while.
+    #[serde(default = "default_warmup_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        WarmupConfig {
+            prompt: default_warmup_prompt(),
+            max_tokens: default_warmup_max_tokens(),
+            timeout_secs: default_warmup_timeout_secs(),
+        }
+    }
+}
+
+fn default_warmup_prompt() -> String {
+    "Hello".to_string()
+}
+
+fn default_warmup_max_tokens() -> u32 {
+    1
+}
+
+fn default_warmup_timeout_secs() -> u64 {
+    60
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// When set, `/metrics` requires this key as a bearer token or `?token=`.
+    pub metrics_api_key: Option<String>,
+    /// `/admin/*` routes require this key as a bearer token, and are
+    /// disabled when it is unset.
+    pub admin_api_key: Option<String>,
+    /// Token allowances for client API keys sent as bearer tokens.
+    #[serde(default)]
+    pub quotas: Vec<ApiKeyQuota>,
+    /// Client keys accepted as bearer tokens on the proxy endpoints. Open
+    /// when empty.
+    #[serde(default)]
+    pub api_keys: ApiKeys,
+    /// Headers checked in order for the client key. `Authorization` is read
+    /// as a bearer token; the `api_key` or `api-key` query parameter is the
+    /// fallback.
+    #[serde(default = "default_api_key_headers")]
+    pub api_key_headers: Vec<String>,
+    /// Lets callers sign requests with a shared secret instead of sending a
+    /// bearer token.
+    pub hmac: Option<HmacConfig>,
+    /// Request rate limits per client IP.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Tenant of each client API key, used for `observability.tenant_labels`.
+    #[serde(default)]
+    pub tenants: BTreeMap<String, String>,
+    /// Service asked to authorize every client request.
+    pub external_auth: Option<ExternalAuthConfig>,
+}
+
+fn default_api_key_headers() -> Vec<String> {
+    vec!["Authorization".to_string()]
+}
+
+/// Either a plain list of keys with access to every policy, or a map from
+/// key to the policies it may use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ApiKeys {
+    List(Vec<String>),
+    Scoped(BTreeMap<String, ApiKeyScope>),
+}
+
+impl Default for ApiKeys {
+    fn default() -> Self {
+        ApiKeys::List(Vec::new())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyScope {
+    /// Name reported in metrics instead of the key, e.g. the owning team.
+    pub id: Option<String>,
+    /// Policies (or experiments) this key may use. All of them when unset.
+    pub allowed_policies: Option<Vec<String>>,
+}
+
+/// Short, stable stand-in for an API key that does not reveal it.
+fn hashed_key_id(key: &str) -> String {
+    let digest = openssl::sha::sha256(key.as_bytes());
+    let hex: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("key-{}", hex)
+}
+
+impl ApiKeys {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ApiKeys::List(keys) => keys.is_empty(),
+            ApiKeys::Scoped(keys) => keys.is_empty(),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            ApiKeys::List(keys) => keys.iter().map(String::as_str).collect(),
+            ApiKeys::Scoped(keys) => keys.keys().map(String::as_str).collect(),
+        }
+    }
+
+    /// Identifies a configured key in metrics: its `id`, or a short hash of
+    /// the key so the key itself is never exported.
+    pub fn key_id(&self, key: &str) -> String {
+        let alias = match self {
+            ApiKeys::List(_) => None,
+            ApiKeys::Scoped(keys) => keys.get(key).and_then(|scope| scope.id.clone()),
+        };
+        alias.unwrap_or_else(|| hashed_key_id(key))
+    }
+
+    /// Whether the configured `key` may use `policy`.
+    pub fn allows_policy(&self, key: &str, policy: &str) -> bool {
+        match self {
+            ApiKeys::List(keys) => keys.iter().any(|k| k == key),
+            ApiKeys::Scoped(keys) => keys.get(key).is_some_and(|scope| {
+                scope
+                    .allowed_policies
+                    .as_ref()
+                    .is_none_or(|policies| policies.iter().any(|p| p.trim() == policy.trim()))
+            }),
+        }
+    }
+
+    fn sanitized(&self) -> Self {
+        match self {
+            ApiKeys::List(keys) => ApiKeys::List(vec!["[REDACTED]".to_string(); keys.len()]),
+            // Numbered so that distinct keys stay distinct entries.
+            ApiKeys::Scoped(keys) => ApiKeys::Scoped(
+                keys.values()
+                    .enumerate()
+                    .map(|(i, scope)| (format!("[REDACTED {}]", i + 1), scope.clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            metrics_api_key: None,
+            admin_api_key: None,
+            quotas: Vec::new(),
+            api_keys: ApiKeys::default(),
+            api_key_headers: default_api_key_headers(),
+            hmac: None,
+            rate_limit: RateLimitConfig::default(),
+            tenants: BTreeMap::new(),
+            external_auth: None,
+        }
+    }
+}
+
+impl SecurityConfig {
+    pub fn get_quota(&self, api_key: &str) -> Option<&ApiKeyQuota> {
+        self.quotas.iter().find(|quota| quota.api_key == api_key)
+    }
+
+    /// The quota of the key reported as `key_id` by [`ApiKeys::key_id`], so
+    /// keys can be looked up without being sent.
+    pub fn get_quota_by_key_id(&self, key_id: &str) -> Option<&ApiKeyQuota> {
+        self.quotas
+            .iter()
+            .find(|quota| self.api_keys.key_id(&quota.api_key) == key_id)
+    }
+
+    /// The configured tenant of `api_key`, or `unknown`. Keeps the `tenant`
+    /// label bounded to the configured tenants.
+    pub fn tenant(&self, api_key: Option<&str>) -> &str {
+        api_key
+            .and_then(|key| self.tenants.get(key))
+            .map_or(UNKNOWN_TENANT, String::as_str)
+    }
+}
+
+/// An auth service that receives the client's `Authorization` header and
+/// allows the request with any `2xx`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExternalAuthConfig {
+    pub url: String,
+    #[serde(default = "default_external_auth_timeout_ms")]
+    pub timeout_ms: u64,
+    /// How long an allowed `Authorization` value is trusted without asking
+    /// again. Denials are never cached.
+    #[serde(default = "default_external_auth_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+fn default_external_auth_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_external_auth_cache_ttl_secs() -> u64 {
+    30
+}
+
+/// HMAC-SHA256 request signing. The signature covers `"{timestamp}.{body}"`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HmacConfig {
+    pub secret: String,
+    /// Reject unsigned proxy requests. Otherwise only requests that carry a
+    /// signature are verified.
+    #[serde(default)]
+    pub required: bool,
+    /// Largest accepted difference between the timestamp header and the
+    /// router's clock, in seconds.
+    #[serde(default = "default_hmac_tolerance_secs")]
+    pub tolerance_secs: u64,
+}
+
+fn default_hmac_tolerance_secs() -> u64 {
+    300
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the gateway, e.g. `https://app.example.com`,
+    /// or `*` for any. CORS is off when empty.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send, or `*` for any.
+    #[serde(default = "default_cors_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Let browsers send cookies and `Authorization` with requests.
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response.
+    #[serde(default = "default_cors_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_cors_allowed_methods(),
+            allowed_headers: default_cors_allowed_headers(),
+            allow_credentials: false,
+            max_age_secs: default_cors_max_age_secs(),
+        }
+    }
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "DELETE"].map(str::to_string).to_vec()
+}
+
+fn default_cors_allowed_headers() -> Vec<String> {
+    ["authorization", "content-type", "x-request-id"]
+        .map(str::to_string)
+        .to_vec()
+}
+
+fn default_cors_max_age_secs() -> u64 {
+    600
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// No per-IP limit when unset.
+    pub per_ip: Option<PerIpRateLimit>,
+    /// Peers allowed to report the client IP in `X-Forwarded-For`. The header
+    /// is ignored for requests from any other peer.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    /// Upper bound on the number of client IPs tracked at once.
+    #[serde(default = "default_max_tracked_ips")]
+    pub max_tracked_ips: usize,
+    /// Status of responses to requests over a limit, `429` or `503`.
+    #[serde(default = "default_rate_limit_rejection_status")]
+    pub rejection_status: u16,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            per_ip: None,
+            trusted_proxies: Vec::new(),
+            max_tracked_ips: default_max_tracked_ips(),
+            rejection_status: default_rate_limit_rejection_status(),
+        }
+    }
+}
+
+fn default_max_tracked_ips() -> usize {
+    100_000
+}
+
+fn default_rate_limit_rejection_status() -> u16 {
+    429
+}
+
+/// At most `requests` per client IP in any sliding `window_secs`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct PerIpRateLimit {
+    pub requests: u32,
+    pub window_secs: u64,
+}
+
+/// Token caps for one client API key. Windows reset at UTC midnight and on
+/// the first of each UTC month.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyQuota {
+    pub api_key: String,
+    pub daily_tokens: Option<u64>,
+    pub monthly_tokens: Option<u64>,
+}
+
+/// Response caching for non-streaming requests. Disabled by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CachingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cache_ttl_seconds")]
+    pub ttl_seconds: u64,
+    #[serde(default = "default_cache_max_size")]
+    pub max_size: usize,
+    /// Opt-in embedding-similarity lookup used when the exact match misses.
+    pub semantic: Option<SemanticCacheConfig>,
+    /// Compresses cached response bodies. Stored uncompressed when unset.
+    pub compression: Option<CacheCompression>,
+    /// Which entry is evicted when the cache is full.
+    #[serde(default)]
+    pub eviction: CacheEviction,
+    /// Re-fetches a sample of cache hits to check they still match what the
+    /// LLM produces.
+    pub revalidation: Option<CacheRevalidationConfig>,
+    /// Fraction by which each entry's TTL is randomly lengthened or
+    /// shortened, so entries stored together do not all expire together.
+    #[serde(default)]
+    pub ttl_jitter: f64,
+}
+
+impl Default for CachingConfig {
+    fn default() -> Self {
+        CachingConfig {
+            enabled: false,
+            ttl_seconds: default_cache_ttl_seconds(),
+            max_size: default_cache_max_size(),
+            semantic: None,
+            compression: None,
+            eviction: CacheEviction::default(),
+            revalidation: None,
+            ttl_jitter: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheEviction {
+    /// The entry closest to expiry, i.e. the oldest one under a uniform TTL.
+    #[default]
+    Ttl,
+    /// The entry least recently served or stored.
+    Lru,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheCompression {
+    Gzip,
+    Zstd,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SemanticCacheConfig {
+    /// OpenAI-compatible embeddings endpoint, e.g. `https://host/v1/embeddings`.
+    pub embedding_url: String,
+    pub embedding_model: String,
+    pub api_key: Option<String>,
+    /// Minimum cosine similarity for a cached response to be reused.
+    #[serde(default = "default_similarity_threshold")]
+    pub similarity_threshold: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CacheRevalidationConfig {
+    /// Fraction of cache hits re-fetched in the background, from 0.0 to 1.0.
+    pub sample_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SecretsConfig {
+    pub vault: Option<VaultConfig>,
+}
+
+/// HashiCorp Vault access. Unset fields fall back to the standard
+/// `VAULT_ADDR`, `VAULT_TOKEN` and `VAULT_NAMESPACE` environment variables.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    pub address: Option<String>,
+    pub token: Option<String>,
+    pub namespace: Option<String>,
+    #[serde(default = "default_vault_timeout_secs")]
+    pub timeout_secs: u64,
+    /// TLS settings for Vault connections. Defaults to `client.tls`.
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig {
+            address: None,
+            token: None,
+            namespace: None,
+            timeout_secs: default_vault_timeout_secs(),
+            tls: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ObservabilityConfig {
+    /// Log prompts and completions. Emails, phone numbers, provider API keys
+    /// and anything matching `redact_patterns` are replaced first.
+    #[serde(default)]
+    pub log_bodies: bool,
+    /// Extra regular expressions to redact from logged bodies.
+    #[serde(default)]
+    pub redact_patterns: Vec<String>,
+    /// Emit one access-log line per proxied request.
+    #[serde(default = "default_access_log")]
+    pub access_log: bool,
+    /// Write access-log lines as JSON objects instead of `key=value` pairs.
+    #[serde(default)]
+    pub json_logging: bool,
+    /// Also count requests and tokens per tenant of `security.tenants`.
+    #[serde(default)]
+    pub tenant_labels: bool,
+    /// Add `X-LLM-Router-*` response headers naming the policy, model,
+    /// upstream and cache outcome of each request.
+    #[serde(default)]
+    pub routing_headers: bool,
+    /// Fraction of requests whose info and debug lines are logged, from
+    /// `0.0` to `1.0`. Warnings and errors are always logged.
+    #[serde(default = "default_log_sample_rate")]
+    pub sample_rate: f64,
+    /// Add a `Server-Timing` header with the model selection, upstream and
+    /// overhead durations of each proxied request.
+    #[serde(default)]
+    pub server_timing: bool,
+}
+
+impl Default for ObservabilityConfig {
+    fn default() -> Self {
+        ObservabilityConfig {
+            log_bodies: false,
+            redact_patterns: Vec::new(),
+            access_log: default_access_log(),
+            json_logging: false,
+            tenant_labels: false,
+            routing_headers: false,
+            sample_rate: default_log_sample_rate(),
+            server_timing: false,
+        }
+    }
+}
+
+fn default_access_log() -> bool {
+    true
+}
+
+fn default_log_sample_rate() -> f64 {
+    1.0
+}
+
+/// How requests are spread across an LLM's `api_base` and `instances`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancingStrategy {
+    #[default]
+    RoundRobin,
+    /// Pins each session to one instance using a hash ring, so backends
+    /// that reuse KV cache across turns keep seeing the same users.
+    ConsistentHash,
+    /// Samples two instances and sends the request to the one with fewer
+    /// requests in flight.
+    PowerOfTwo,
+    /// Sends the request to the instance with the lowest moving average of
+    /// response latency, trying the others now and then.
+    LeastLatency,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LoadBalancingConfig {
+    #[serde(default)]
+    pub strategy: LoadBalancingStrategy,
+    /// Header carrying the session key for `consistent_hash`; the client IP
+    /// is used when the header is absent.
+    #[serde(default = "default_session_header")]
+    pub session_header: String,
+    /// Region whose instances are preferred. Another region is only used
+    /// while none of an LLM's instances in this one is available.
+    pub local_region: Option<String>,
+    /// Seeds the random choices of `power_of_two` and `least_latency`, so the
+    /// same requests are spread the same way on every run. Random per
+    /// process when unset.
+    pub seed: Option<u64>,
+}
+
+impl Default for LoadBalancingConfig {
+    fn default() -> Self {
+        LoadBalancingConfig {
+            strategy: LoadBalancingStrategy::default(),
+            session_header: default_session_header(),
+            local_region: None,
+            seed: None,
+        }
+    }
+}
+
+/// Settings for the outbound HTTP client used to reach Triton and the LLMs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Speak HTTP/2 without ALPN negotiation (e.g. for h2c upstreams).
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// Default total timeout for upstream LLM requests, including reading
+    /// the body, overridable per LLM.
+    #[serde(alias = "total_timeout_secs")]
+    pub request_timeout_secs: Option<u64>,
+    /// Upper bound on the timeout clients may request per call with
+    /// `X-Request-Timeout-Ms`. The header is ignored when unset.
+    pub max_request_timeout_ms: Option<u64>,
+    /// Timeout for establishing a connection. No limit when unset.
+    pub connect_timeout_secs: Option<u64>,
+    /// Timeout for an LLM's response headers and first body chunk to arrive
+    /// on streaming requests. Stalled upstreams are detected even when the
+    /// total timeout is long enough for streams.
+    pub first_byte_timeout_secs: Option<u64>,
+    /// Idle connections kept per upstream host, overridable per LLM.
+    /// Unbounded when unset.
+    pub connection_pool_size: Option<usize>,
+    /// How long an idle pooled connection is kept, overridable per LLM.
+    /// reqwest's default of 90 seconds when unset.
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Client request headers copied to the upstream LLM request; `*`
+    /// forwards all of them. Nothing is forwarded by default.
+    #[serde(default)]
+    pub forward_headers: Vec<String>,
+    /// Headers never forwarded, in addition to hop-by-hop headers,
+    /// `Authorization`, `Cookie` and `Host`.
+    #[serde(default)]
+    pub strip_headers: Vec<String>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub dns: DnsConfig,
+}
+
+/// Name resolution of upstream hosts. The system resolver is asked on every
+/// new connection unless a cache or pins are set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DnsConfig {
+    /// How long resolved addresses are reused. Not cached when unset.
+    pub cache_ttl_secs: Option<u64>,
+    /// Addresses used for a hostname instead of resolving it, e.g. for
+    /// air-gapped deployments.
+    #[serde(default)]
+    pub static_hosts: BTreeMap<String, Vec<IpAddr>>,
+}
+
+/// Retries of upstream LLM requests that fail to connect, time out or get a
+/// 502/503/504. Disabled unless `max_retries` is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    #[serde(default)]
+    pub max_retries: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Growth factor of the backoff between attempts.
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default)]
+    pub jitter: Jitter,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 0,
+            initial_backoff_ms: default_initial_backoff_ms(),
+            multiplier: default_backoff_multiplier(),
+            max_backoff_ms: default_max_backoff_ms(),
+            jitter: Jitter::default(),
+        }
+    }
+}
+
+/// Randomization applied to each backoff, as described in
+/// <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Jitter {
+    /// The exponential backoff as is.
+    #[default]
+    None,
+    /// Uniform between zero and the exponential backoff.
+    Full,
+    /// Half the exponential backoff plus a uniform share of the other half.
+    Equal,
+    /// Uniform between the initial backoff and three times the previous
+    /// delay, capped at `max_backoff_ms`.
+    Decorrelated,
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    100
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_backoff_ms() -> u64 {
+    5000
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM bundle of additional root certificates to trust.
+    pub ca_cert_path: Option<String>,
+    /// PEM client certificate for mTLS; requires `client_key_path`.
+    pub client_cert_path: Option<String>,
+    /// PKCS#8 PEM private key matching `client_cert_path`.
+    pub client_key_path: Option<String>,
+}
+
+fn default_cache_ttl_seconds() -> u64 {
+    300
+}
+
+fn default_cache_max_size() -> usize {
+    1000
+}
+
+fn default_similarity_threshold() -> f32 {
+    0.95
+}
+
+fn default_vault_timeout_secs() -> u64 {
+    10
+}
+
+fn default_session_header() -> String {
+    "X-Session-Id".to_string()
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    30
+}
+
+fn default_health_check_timeout_secs() -> u64 {
+    2
+}
+
+fn default_health_check_deadline_secs() -> u64 {
+    5
+}
+
+fn default_health_cache_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Policy {
     pub name: String,
     pub url: String,
     pub llms: Vec<Llm>,
+    /// Mirrors a sample of this policy's traffic to a candidate LLM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowConfig>,
+    /// Overrides of the global `caching` settings for this policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caching: Option<PolicyCachingConfig>,
+    /// System prompt added to every chat request routed through this policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<SystemPromptConfig>,
+    /// Also retry requests that timed out after they were sent, accepting
+    /// that the LLM may produce a duplicate completion.
+    #[serde(default)]
+    pub retry_on_timeout: bool,
+    /// Also send a non-streaming request to a second instance of the LLM
+    /// when the first has not responded within this many milliseconds, and
+    /// use whichever response comes first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hedge_after_ms: Option<u64>,
+    /// Limit per client IP on requests routed through this policy, enforced
+    /// on top of `security.rate_limit.per_ip`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<PerIpRateLimit>,
+    /// Completion returned to non-streaming requests, marked as degraded,
+    /// once every instance of the chosen LLM failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_response: Option<serde_json::Value>,
+    /// Upper bound on `max_tokens`, filled in for requests that leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_limit: Option<u64>,
+    /// Top-level request fields removed before forwarding, e.g. `logprobs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_params: Vec<String>,
+    /// Names of the LLMs requests may be sent to, whichever way they were
+    /// selected. All of the policy's LLMs when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+    /// LLM in `allowed_models` that gets requests for which another LLM
+    /// was selected. They are rejected when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    /// Bounds on the values clients may set for request fields, e.g.
+    /// `temperature: {max: 1.0}` or `n: {max: 1}`. Requests outside them
+    /// are rejected with 400, or adjusted with `clamp_params`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub param_constraints: BTreeMap<String, ParamConstraint>,
+    /// Bring values outside `param_constraints` within them instead of
+    /// rejecting the request.
+    #[serde(default)]
+    pub clamp_params: bool,
+}
+
+/// Values a client may set for one request field. Fields the client leaves
+/// out are not checked.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ParamConstraint {
+    /// Smallest number allowed.
+    pub min: Option<f64>,
+    /// Largest number allowed.
+    pub max: Option<f64>,
+    /// The only values allowed, of any type.
+    pub allowed: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyCachingConfig {
+    pub enabled: Option<bool>,
+    pub ttl_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptMode {
+    /// Put `content` before the client's system message.
+    #[default]
+    Prepend,
+    /// Replace the client's system messages with `content`.
+    Override,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SystemPromptConfig {
+    pub content: String,
+    #[serde(default)]
+    pub mode: SystemPromptMode,
+    /// Template for the `prompt` of completion requests, which have no
+    /// messages; `{prompt}` is replaced with the client's prompt. They are
+    /// left untouched when unset.
+    pub prompt_template: Option<String>,
+}
+
+/// A/B split: requests naming `name` as their policy are routed through one
+/// of the `variants`, picked at random in proportion to their weights.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub name: String,
+    pub variants: Vec<ExperimentVariant>,
+    /// Echo the chosen policy in an `X-Experiment-Variant` response header.
+    #[serde(default)]
+    pub echo_header: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentVariant {
+    pub policy: String,
+    pub weight: u32,
+}
+
+impl Experiment {
+    /// Picks the variant for `roll`, a number in `[0, total weight)`.
+    pub fn choose_variant(&self, roll: u64) -> Option<&ExperimentVariant> {
+        let mut remaining = roll;
+        self.variants.iter().find(|variant| {
+            let weight = variant.weight as u64;
+            if remaining < weight {
+                true
+            } else {
+                remaining -= weight;
+                false
+            }
+        })
+    }
+
+    pub fn total_weight(&self) -> u64 {
+        self.variants
+            .iter()
+            .map(|variant| variant.weight as u64)
+            .sum()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ShadowConfig {
+    /// Name of the LLM in this policy's `llms` that receives the mirror.
+    pub llm: String,
+    /// Fraction of requests mirrored, from `0.0` to `1.0`.
+    pub sample_rate: f64,
+}
+
+/// An entry of `Llm::instances`: a base URL, optionally with its region.
+/// Unknown fields of a tagged entry are rejected, so a misspelt `region`
+/// is not silently dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Instance {
+    Url(String),
+    Tagged { url: String, region: Option<String> },
+}
+
+impl Instance {
+    pub fn url(&self) -> &str {
+        match self {
+            Instance::Url(url) | Instance::Tagged { url, .. } => url,
+        }
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        match self {
+            Instance::Url(_) => None,
+            Instance::Tagged { region, .. } => region.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Llm {
     pub name: String,
     pub api_base: String,
     pub api_key: String,
     pub model: String,
+    /// Per-request timeout for this LLM. Takes precedence over
+    /// `client.request_timeout_secs`; when neither is set requests never time out.
+    pub request_timeout_secs: Option<u64>,
+    /// Additional base URLs serving the same model. Requests are balanced
+    /// across `api_base` and these according to `load_balancing`.
+    #[serde(default)]
+    pub instances: Vec<Instance>,
+    /// Region of `api_base` and of the `instances` not tagged with one, for
+    /// `load_balancing.local_region`.
+    pub region: Option<String>,
+    /// Prices used for `llm_cost_usd_total`. No cost is recorded when unset.
+    pub pricing: Option<TokenPricing>,
+    /// Requests in flight to this LLM across all its instances. Queued and
+    /// rejected per `server.concurrency`. Unlimited when unset.
+    pub max_concurrent_requests: Option<usize>,
+    /// API scheme of the upstream.
+    #[serde(default)]
+    pub provider_type: ProviderType,
+    /// `api-version` query parameter sent to Azure OpenAI.
+    pub api_version: Option<String>,
+    /// Idle connections kept per instance of this LLM. Takes precedence over
+    /// `client.connection_pool_size`.
+    pub pool_max_idle: Option<usize>,
+    /// Takes precedence over `client.pool_idle_timeout_secs`.
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Sampling parameters added to requests for this LLM that omit them.
+    pub default_params: Option<DefaultParams>,
+    /// Whether the model accepts `tools`/`functions`. Assumed when unset.
+    pub supports_tools: Option<bool>,
+    /// LLM of the same policy that gets requests with tools when this one
+    /// does not support them. They are rejected with 400 when unset.
+    pub tools_fallback: Option<String>,
+    /// Added to this LLM's classifier score before the highest one is
+    /// picked, to nudge routing towards or away from it.
+    #[serde(default)]
+    pub bias: f64,
+    /// Gzip request bodies sent to this LLM, which must accept
+    /// `Content-Encoding: gzip`.
+    #[serde(default)]
+    pub compress_request: bool,
+    /// Smaller bodies are sent as is. `DEFAULT_COMPRESS_REQUEST_MIN_BYTES`
+    /// when unset.
+    pub compress_request_min_bytes: Option<usize>,
+    /// Headers added to every request sent to this LLM, e.g.
+    /// `anthropic-version`. They cannot replace the API key header.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Sent as `OpenAI-Organization`, for billing attribution.
+    pub organization: Option<String>,
+    /// Sent as `OpenAI-Project`, for billing attribution.
+    pub project: Option<String>,
+    /// Send the client's `OpenAI-Organization` and `OpenAI-Project` headers
+    /// instead of `organization` and `project` when the request has them.
+    #[serde(default)]
+    pub forward_organization_headers: bool,
+    /// Path of the endpoint reporting whether the model is loaded, e.g.
+    /// `/v1/health/ready` for NIM. Probed by the readiness check instead of
+    /// `api_base` itself when set.
+    pub ready_path: Option<String>,
+    /// Count the tokens of streamed responses that carry no `usage` and add
+    /// an estimated usage chunk before `[DONE]`.
+    #[serde(default)]
+    pub estimate_stream_usage: bool,
+    /// Requests whose prompt is estimated at more tokens than this are
+    /// rejected with a 400 instead of being forwarded. Unlimited when unset.
+    pub max_context_tokens: Option<u64>,
+    /// How prompt tokens are counted for `max_context_tokens`.
+    #[serde(default)]
+    pub token_estimator: TokenEstimator,
+}
+
+/// Counts the prompt tokens of a request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEstimator {
+    /// Four characters of message text per token.
+    #[default]
+    Heuristic,
+    /// The model's own tokenizer, through the `/tokenize` endpoint of the
+    /// instance, as served by vLLM.
+    Tokenize,
+}
+
+/// Headers carrying the LLM's API key, which `Llm::headers` cannot set.
+const RESERVED_LLM_HEADERS: [&str; 3] = ["authorization", "api-key", "x-api-key"];
+
+/// Chat endpoint of Cohere's v2 API.
+const COHERE_CHAT_PATH: &str = "/v2/chat";
+
+pub const OPENAI_ORGANIZATION_HEADER: &str = "openai-organization";
+pub const OPENAI_PROJECT_HEADER: &str = "openai-project";
+
+/// Size from which request bodies are gzipped for LLMs with
+/// `compress_request`, unless `compress_request_min_bytes` is set.
+pub const DEFAULT_COMPRESS_REQUEST_MIN_BYTES: usize = 1024;
+
+/// Request parameters filled in when the client leaves them out. Values the
+/// client sends are never changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultParams {
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub max_tokens: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderType {
+    /// `{api_base}{path}` with a bearer token.
+    #[default]
+    Openai,
+    /// `{api_base}/openai/deployments/{model}{path}?api-version=...` with an
+    /// `api-key` header; `model` names the deployment.
+    Azure,
+    /// Chat completions go to `{api_base}/v1/messages` with an `x-api-key`
+    /// header, with the fields renamed as in [`crate::transform`].
+    Anthropic,
+    /// Chat completions go to `{api_base}/v2/chat` with a bearer token, with
+    /// the fields renamed as in [`crate::transform`].
+    Cohere,
+}
+
+/// USD per million tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenPricing {
+    #[serde(default)]
+    pub prompt_per_million: f64,
+    #[serde(default)]
+    pub completion_per_million: f64,
+}
+
+impl TokenPricing {
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt_per_million
+            + completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+impl Llm {
+    /// Whether a request body of `len` bytes is gzipped before it is sent.
+    pub fn compresses_request(&self, len: usize) -> bool {
+        self.compress_request
+            && len
+                >= self
+                    .compress_request_min_bytes
+                    .unwrap_or(DEFAULT_COMPRESS_REQUEST_MIN_BYTES)
+    }
+
+    /// All base URLs serving this LLM, starting with `api_base`.
+    pub fn api_bases(&self) -> Vec<&str> {
+        std::iter::once(self.api_base.as_str())
+            .chain(self.instances.iter().map(Instance::url))
+            .collect()
+    }
+
+    /// Every base URL with its region, in the order of `api_bases`.
+    pub fn instance_regions(&self) -> Vec<(&str, Option<&str>)> {
+        let region = self.region.as_deref();
+        std::iter::once((self.api_base.as_str(), region))
+            .chain(
+                self.instances
+                    .iter()
+                    .map(|instance| (instance.url(), instance.region().or(region))),
+            )
+            .collect()
+    }
+
+    /// URL of the endpoint a request for `path_and_query` (e.g.
+    /// `/v1/chat/completions`) is sent to on the instance `api_base`.
+    pub fn upstream_url(&self, api_base: &str, path_and_query: &str) -> String {
+        match self.provider_type {
+            ProviderType::Openai => format!("{}{}", api_base, path_and_query),
+            ProviderType::Anthropic | ProviderType::Cohere => {
+                let chat = match self.provider_type {
+                    ProviderType::Anthropic => MESSAGES_PATH,
+                    _ => COHERE_CHAT_PATH,
+                };
+                match path_and_query.strip_prefix(CHAT_COMPLETIONS_PATH) {
+                    Some(query) => format!("{}{}{}", api_base, chat, query),
+                    None => format!("{}{}", api_base, path_and_query),
+                }
+            }
+            ProviderType::Azure => {
+                let (path, query) = path_and_query
+                    .split_once('?')
+                    .unwrap_or((path_and_query, ""));
+                let operation = path.strip_prefix("/v1").unwrap_or(path);
+                let mut url = format!(
+                    "{}/openai/deployments/{}{}?api-version={}",
+                    api_base.trim_end_matches('/'),
+                    self.model,
+                    operation,
+                    self.api_version.as_deref().unwrap_or_default()
+                );
+                if !query.is_empty() {
+                    url.push('&');
+                    url.push_str(query);
+                }
+                url
+            }
+        }
+    }
+
+    pub fn supports_tools(&self) -> bool {
+        self.supports_tools.unwrap_or(true)
+    }
+
+    /// The configured `headers`, then `organization` and `project`. Invalid
+    /// ones, rejected when the config is loaded, are skipped.
+    pub fn custom_headers(&self) -> HeaderMap {
+        let organization = [
+            (OPENAI_ORGANIZATION_HEADER, &self.organization),
+            (OPENAI_PROJECT_HEADER, &self.project),
+        ];
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .chain(
+                organization
+                    .iter()
+                    .filter_map(|(name, value)| Some((*name, value.as_ref()?))),
+            )
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    /// `custom_headers`, with the client's organization and project headers
+    /// in place of the configured ones when `forward_organization_headers`.
+    pub fn upstream_headers(&self, incoming: &HeaderMap) -> HeaderMap {
+        let mut headers = self.custom_headers();
+        if self.forward_organization_headers {
+            for name in [OPENAI_ORGANIZATION_HEADER, OPENAI_PROJECT_HEADER] {
+                if let Some(value) = incoming.get(name) {
+                    headers.insert(name, value.clone());
+                }
+            }
+        }
+        headers
+    }
+
+    /// Header carrying `api_key` to the upstream.
+    pub fn auth_header(&self) -> (&'static str, String) {
+        match self.provider_type {
+            ProviderType::Openai | ProviderType::Cohere => {
+                ("authorization", format!("Bearer {}", self.api_key))
+            }
+            ProviderType::Azure => ("api-key", self.api_key.clone()),
+            ProviderType::Anthropic => ("x-api-key", self.api_key.clone()),
+        }
+    }
 }
 
 impl RouterConfig {
+    /// Loads the config file, verifying its signature first when
+    /// `LLM_ROUTER_CONFIG_PUBLIC_KEY` names a public key.
     pub fn load_config(path: &str) -> Result<RouterConfig> {
+        let public_key = std::env::var(CONFIG_PUBLIC_KEY_ENV).ok();
+        Self::load_signed_config(path, public_key.as_deref())
+    }
+
+    pub fn load_signed_config(path: &str, public_key: Option<&str>) -> Result<RouterConfig> {
         let content = std::fs::read_to_string(path)?;
-        let config: RouterConfig = serde_yaml::from_str(&content)?;
+        if let Some(public_key) = public_key {
+            let fingerprint = verify_config(path, content.as_bytes(), public_key)?;
+            info!(
+                "Verified signature of {} made with key {}",
+                path, fingerprint
+            );
+        }
+        Self::from_yaml(&content)
+    }
+
+    /// Parses and validates a YAML config, expanding `${VAR}` references in
+    /// every string value first, then fetching `vault://` secrets.
+    pub fn from_yaml(content: &str) -> Result<RouterConfig> {
+        let mut raw: serde_yaml::Value = serde_yaml::from_str(content)?;
+        resolve_env_vars(&mut raw);
+        let secrets: SecretsConfig = match raw.get("secrets") {
+            Some(secrets) => serde_yaml::from_value(secrets.clone())?,
+            None => SecretsConfig::default(),
+        };
+        let mut vault = secrets.vault.unwrap_or_default();
+        if vault.tls.is_none() {
+            vault.tls = match raw.get("client").and_then(|client| client.get("tls")) {
+                Some(tls) => Some(serde_yaml::from_value(tls.clone())?),
+                None => None,
+            };
+        }
+        let mut resolvers: Vec<Box<dyn SecretResolver>> = vec![Box::new(VaultResolver::new(vault))];
+        resolve_secrets(&mut raw, &mut resolvers)?;
+        let config: RouterConfig = serde_yaml::from_value(raw)?;
         validate_config(&config)?;
         Ok(config)
     }
@@ -56,6 +1584,12 @@ impl RouterConfig {
         self.policies.get(index).cloned()
     }
 
+    pub fn get_experiment_by_name(&self, name: &str) -> Option<&Experiment> {
+        self.experiments
+            .iter()
+            .find(|experiment| experiment.name.trim() == name.trim())
+    }
+
     pub fn sanitized(&self) -> Self {
         let sanitized_policies = self
             .policies
@@ -66,6 +1600,12 @@ impl RouterConfig {
                     .iter()
                     .map(|llm| Llm {
                         api_key: "[REDACTED]".to_string(),
+                        // Custom headers may carry credentials too.
+                        headers: llm
+                            .headers
+                            .keys()
+                            .map(|name| (name.clone(), "[REDACTED]".to_string()))
+                            .collect(),
                         ..llm.clone()
                     })
                     .collect();
@@ -78,11 +1618,81 @@ impl RouterConfig {
 
         RouterConfig {
             policies: sanitized_policies,
+            security: SecurityConfig {
+                metrics_api_key: redact(&self.security.metrics_api_key),
+                admin_api_key: redact(&self.security.admin_api_key),
+                quotas: self
+                    .security
+                    .quotas
+                    .iter()
+                    .map(|quota| ApiKeyQuota {
+                        api_key: "[REDACTED]".to_string(),
+                        ..quota.clone()
+                    })
+                    .collect(),
+                api_keys: self.security.api_keys.sanitized(),
+                api_key_headers: self.security.api_key_headers.clone(),
+                hmac: self.security.hmac.as_ref().map(|hmac| HmacConfig {
+                    secret: "[REDACTED]".to_string(),
+                    ..hmac.clone()
+                }),
+                rate_limit: self.security.rate_limit.clone(),
+                tenants: self
+                    .security
+                    .tenants
+                    .iter()
+                    .map(|(key, tenant)| (hashed_key_id(key), tenant.clone()))
+                    .collect(),
+                external_auth: self.security.external_auth.clone(),
+            },
+            secrets: SecretsConfig {
+                vault: self.secrets.vault.as_ref().map(|vault| VaultConfig {
+                    token: redact(&vault.token),
+                    ..vault.clone()
+                }),
+            },
+            caching: CachingConfig {
+                semantic: self
+                    .caching
+                    .semantic
+                    .as_ref()
+                    .map(|semantic| SemanticCacheConfig {
+                        api_key: redact(&semantic.api_key),
+                        ..semantic.clone()
+                    }),
+                ..self.caching.clone()
+            },
+            circuit_breaker: CircuitBreakerConfig {
+                // The URL may carry the Redis password.
+                shared: self.circuit_breaker.shared.as_ref().map(|shared| {
+                    SharedCircuitBreakerConfig {
+                        redis_url: "[REDACTED]".to_string(),
+                        ..shared.clone()
+                    }
+                }),
+                ..self.circuit_breaker.clone()
+            },
+            ..self.clone()
         }
     }
 }
 
 impl Policy {
+    /// Whether responses of this policy are cached, given the global settings.
+    pub fn cache_enabled(&self, global: &CachingConfig) -> bool {
+        self.caching
+            .as_ref()
+            .and_then(|caching| caching.enabled)
+            .unwrap_or(global.enabled)
+    }
+
+    pub fn cache_ttl_seconds(&self, global: &CachingConfig) -> u64 {
+        self.caching
+            .as_ref()
+            .and_then(|caching| caching.ttl_seconds)
+            .unwrap_or(global.ttl_seconds)
+    }
+
     pub fn get_llm_by_name(&self, name: &str) -> Option<Llm> {
         self.llms
             .iter()
@@ -90,6 +1700,33 @@ impl Policy {
             .cloned()
     }
 
+    /// Index of the LLM a manual routing request for `requested` goes to.
+    /// An LLM name matches first; otherwise an alias in `aliases` is
+    /// resolved to an LLM name or backend model. Returns whether an alias
+    /// was used.
+    pub fn find_llm(
+        &self,
+        requested: &str,
+        aliases: &BTreeMap<String, String>,
+    ) -> Option<(usize, bool)> {
+        if let Some(index) = self.llms.iter().position(|llm| llm.name == requested) {
+            return Some((index, false));
+        }
+        let target = aliases.get(requested)?;
+        self.llms
+            .iter()
+            .position(|llm| &llm.name == target || &llm.model == target)
+            .map(|index| (index, true))
+    }
+
+    /// Default parameters of the LLMs that set them, by LLM name.
+    pub fn default_params(&self) -> BTreeMap<&str, &DefaultParams> {
+        self.llms
+            .iter()
+            .filter_map(|llm| Some((llm.name.as_str(), llm.default_params.as_ref()?)))
+            .collect()
+    }
+
     pub fn get_llm_by_index(&self, index: usize) -> Option<Llm> {
         self.llms.get(index).cloned()
     }
@@ -101,35 +1738,670 @@ impl Policy {
 
 pub type Result<T> = std::result::Result<T, ConfigError>;
 
+fn redact(secret: &Option<String>) -> Option<String> {
+    secret.as_ref().map(|_| "[REDACTED]".to_string())
+}
+
+/// Replaces `${VAR}` references with the value of the environment variable.
+/// Unset variables are left in place and logged.
+fn substitute_env_vars(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+
+        let name = &after[..end];
+        match std::env::var(name) {
+            Ok(value) => output.push_str(&value),
+            Err(_) => {
+                warn!(
+                    "Environment variable '{}' referenced in config is not set",
+                    name
+                );
+                output.push_str(&rest[start..start + 2 + end + 1]);
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn resolve_env_vars(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::String(s) if s.contains("${") => *s = substitute_env_vars(s),
+        serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(resolve_env_vars),
+        serde_yaml::Value::Mapping(map) => map.values_mut().for_each(resolve_env_vars),
+        serde_yaml::Value::Tagged(tagged) => resolve_env_vars(&mut tagged.value),
+        _ => {}
+    }
+}
+
+/// Checks that `value` is an absolute `http(s)` URL.
+fn check_url(errors: &mut Vec<ConfigError>, field: String, value: &str) {
+    match reqwest::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => errors.push(ConfigError::InvalidField {
+            field,
+            message: format!("unsupported scheme '{}' in '{}'", url.scheme(), value),
+        }),
+        Err(e) => errors.push(ConfigError::InvalidField {
+            field,
+            message: format!("'{}' is not a valid URL: {}", value, e),
+        }),
+    }
+}
+
+/// Runs every check and reports all problems at once.
 fn validate_config(config: &RouterConfig) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for experiment in &config.experiments {
+        if experiment.total_weight() == 0 {
+            errors.push(ConfigError::InvalidExperiment {
+                experiment: experiment.name.clone(),
+                message: "variants must have a positive total weight".to_string(),
+            });
+        }
+        for variant in &experiment.variants {
+            if config.get_policy_by_name(&variant.policy).is_none() {
+                errors.push(ConfigError::InvalidExperiment {
+                    experiment: experiment.name.clone(),
+                    message: format!("unknown policy '{}'", variant.policy),
+                });
+            }
+        }
+    }
+
+    if let Some(shared) = &config.circuit_breaker.shared {
+        if let Err(e) = redis::Client::open(shared.redis_url.as_str()) {
+            errors.push(ConfigError::InvalidField {
+                field: "circuit_breaker.shared.redis_url".to_string(),
+                message: e.to_string(),
+            });
+        }
+        if shared.sync_interval_ms == 0 {
+            errors.push(ConfigError::InvalidField {
+                field: "circuit_breaker.shared.sync_interval_ms".to_string(),
+                message: "must be at least 1".to_string(),
+            });
+        }
+    }
+    if config.client.dns.cache_ttl_secs == Some(0) {
+        errors.push(ConfigError::InvalidField {
+            field: "client.dns.cache_ttl_secs".to_string(),
+            message: "must be at least 1".to_string(),
+        });
+    }
+    for (host, addrs) in &config.client.dns.static_hosts {
+        if addrs.is_empty() {
+            errors.push(ConfigError::InvalidField {
+                field: format!("client.dns.static_hosts.{}", host),
+                message: "must list at least one address".to_string(),
+            });
+        }
+    }
+
+    let retry = &config.client.retry;
+    if retry.multiplier.is_nan() || retry.multiplier < 1.0 {
+        errors.push(ConfigError::InvalidField {
+            field: "client.retry.multiplier".to_string(),
+            message: "must be at least 1.0".to_string(),
+        });
+    }
+    if let Some(per_ip) = &config.security.rate_limit.per_ip {
+        if per_ip.requests == 0 || per_ip.window_secs == 0 {
+            errors.push(ConfigError::InvalidField {
+                field: "security.rate_limit.per_ip".to_string(),
+                message: "requests and window_secs must be at least 1".to_string(),
+            });
+        }
+    }
+    if !matches!(config.security.rate_limit.rejection_status, 429 | 503) {
+        errors.push(ConfigError::InvalidField {
+            field: "security.rate_limit.rejection_status".to_string(),
+            message: "must be 429 or 503".to_string(),
+        });
+    }
+    for (alias, target) in &config.model_aliases {
+        let known = config.policies.iter().any(|policy| {
+            policy
+                .llms
+                .iter()
+                .any(|llm| &llm.name == target || &llm.model == target)
+        });
+        if !known {
+            errors.push(ConfigError::InvalidField {
+                field: format!("model_aliases.{}", alias),
+                message: format!("'{}' is not the name or model of any LLM", target),
+            });
+        }
+    }
+    if let Some(default_policy) = &config.default_policy {
+        if config.get_policy_by_name(default_policy).is_none()
+            && config.get_experiment_by_name(default_policy).is_none()
+        {
+            errors.push(ConfigError::InvalidField {
+                field: "default_policy".to_string(),
+                message: format!("'{}' is not a policy or experiment", default_policy),
+            });
+        }
+    }
+    if config.server.concurrency.max_concurrent_requests == Some(0) {
+        errors.push(ConfigError::InvalidField {
+            field: "server.concurrency.max_concurrent_requests".to_string(),
+            message: "must be at least 1".to_string(),
+        });
+    }
+    if config
+        .server
+        .concurrency
+        .priority
+        .as_ref()
+        .is_some_and(|priority| priority.workers == 0)
+    {
+        errors.push(ConfigError::InvalidField {
+            field: "server.concurrency.priority.workers".to_string(),
+            message: "must be at least 1".to_string(),
+        });
+    }
+
+    if let ApiKeys::Scoped(keys) = &config.security.api_keys {
+        for policy in keys
+            .values()
+            .flat_map(|scope| scope.allowed_policies.iter().flatten())
+        {
+            if config.get_policy_by_name(policy).is_none()
+                && config.get_experiment_by_name(policy).is_none()
+            {
+                errors.push(ConfigError::InvalidField {
+                    field: "security.api_keys.allowed_policies".to_string(),
+                    message: format!("unknown policy '{}'", policy),
+                });
+            }
+        }
+    }
+
     for policy in &config.policies {
         if policy.name.is_empty() {
-            return Err(ConfigError::MissingPolicyField {
+            errors.push(ConfigError::MissingPolicyField {
                 policy: policy.name.clone(),
                 field: "name".to_string(),
             });
         }
+        check_url(
+            &mut errors,
+            format!("policies.{}.url", policy.name),
+            &policy.url,
+        );
+
+        if let Some(shadow) = &policy.shadow {
+            if policy.get_llm_by_name(&shadow.llm).is_none() {
+                errors.push(ConfigError::UnknownShadowLlm {
+                    policy: policy.name.clone(),
+                    llm: shadow.llm.clone(),
+                });
+            }
+            if !(0.0..=1.0).contains(&shadow.sample_rate) {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("policies.{}.shadow.sample_rate", policy.name),
+                    message: "must be between 0.0 and 1.0".to_string(),
+                });
+            }
+        }
+
+        if policy.hedge_after_ms == Some(0) {
+            errors.push(ConfigError::InvalidField {
+                field: format!("policies.{}.hedge_after_ms", policy.name),
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if policy.max_tokens_limit == Some(0) {
+            errors.push(ConfigError::InvalidField {
+                field: format!("policies.{}.max_tokens_limit", policy.name),
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if policy
+            .forbidden_params
+            .iter()
+            .any(|param| param == "nim-llm-router")
+        {
+            errors.push(ConfigError::InvalidField {
+                field: format!("policies.{}.forbidden_params", policy.name),
+                message: "cannot include nim-llm-router".to_string(),
+            });
+        }
+        for (param, constraint) in &policy.param_constraints {
+            let field = format!("policies.{}.param_constraints.{}", policy.name, param);
+            let message = match (constraint.min, constraint.max) {
+                (Some(min), Some(max)) if min > max => "min must not exceed max",
+                (Some(bound), _) | (_, Some(bound)) if !bound.is_finite() => {
+                    "min and max must be finite numbers"
+                }
+                _ if constraint.allowed.as_ref().is_some_and(Vec::is_empty) => {
+                    "allowed must not be empty"
+                }
+                _ => continue,
+            };
+            errors.push(ConfigError::InvalidField {
+                field,
+                message: message.to_string(),
+            });
+        }
+        for name in &policy.allowed_models {
+            if policy.get_llm_by_name(name).is_none() {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("policies.{}.allowed_models", policy.name),
+                    message: format!("'{}' is not an LLM of this policy", name),
+                });
+            }
+        }
+        if let Some(default_model) = &policy.default_model {
+            if !policy.allowed_models.contains(default_model) {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("policies.{}.default_model", policy.name),
+                    message: format!("'{}' is not in allowed_models", default_model),
+                });
+            }
+        }
+
+        if policy
+            .fallback_response
+            .as_ref()
+            .is_some_and(|response| !response.is_object())
+        {
+            errors.push(ConfigError::InvalidField {
+                field: format!("policies.{}.fallback_response", policy.name),
+                message: "must be a JSON object".to_string(),
+            });
+        }
+
+        if let Some(rate_limit) = &policy.rate_limit {
+            if rate_limit.requests == 0 || rate_limit.window_secs == 0 {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("policies.{}.rate_limit", policy.name),
+                    message: "requests and window_secs must be at least 1".to_string(),
+                });
+            }
+        }
+
+        if let Some(template) = policy
+            .system_prompt
+            .as_ref()
+            .and_then(|system_prompt| system_prompt.prompt_template.as_ref())
+        {
+            if !template.contains("{prompt}") {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("policies.{}.system_prompt.prompt_template", policy.name),
+                    message: "must contain {prompt}".to_string(),
+                });
+            }
+        }
 
         for llm in &policy.llms {
-            if llm.api_base.is_empty() {
-                return Err(ConfigError::MissingLlmField {
+            for (field, value) in [
+                ("api_base", &llm.api_base),
+                ("model", &llm.model),
+                ("api_key", &llm.api_key),
+            ] {
+                if value.is_empty() {
+                    errors.push(ConfigError::MissingLlmField {
+                        llm: llm.name.clone(),
+                        field: field.to_string(),
+                    });
+                }
+            }
+            if llm.provider_type == ProviderType::Azure && llm.api_version.is_none() {
+                errors.push(ConfigError::MissingLlmField {
                     llm: llm.name.clone(),
-                    field: "api_base".to_string(),
+                    field: "api_version".to_string(),
                 });
             }
-            if llm.model.is_empty() {
-                return Err(ConfigError::MissingLlmField {
-                    llm: llm.name.clone(),
-                    field: "model".to_string(),
+            for (index, api_base) in llm.api_bases().into_iter().enumerate() {
+                if api_base.is_empty() {
+                    continue;
+                }
+                let field = if index == 0 {
+                    format!("llms.{}.api_base", llm.name)
+                } else {
+                    format!("llms.{}.instances[{}]", llm.name, index - 1)
+                };
+                check_url(&mut errors, field, api_base);
+            }
+            let regions = llm.instance_regions();
+            if regions.iter().any(|(_, region)| region.is_some())
+                && regions.iter().any(|(_, region)| region.is_none())
+            {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("llms.{}.region", llm.name),
+                    message: "either every instance has a region or none does".to_string(),
                 });
             }
-            if llm.api_key.is_empty() {
-                return Err(ConfigError::MissingLlmField {
-                    llm: llm.name.clone(),
-                    field: "api_key".to_string(),
+            if !llm.bias.is_finite() {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("llms.{}.bias", llm.name),
+                    message: "must be a finite number".to_string(),
+                });
+            }
+            if llm.max_context_tokens == Some(0) {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("llms.{}.max_context_tokens", llm.name),
+                    message: "must be at least 1".to_string(),
+                });
+            }
+            if llm
+                .ready_path
+                .as_ref()
+                .is_some_and(|path| !path.starts_with('/'))
+            {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("llms.{}.ready_path", llm.name),
+                    message: "must start with '/'".to_string(),
+                });
+            }
+            for (field, value) in [
+                ("organization", &llm.organization),
+                ("project", &llm.project),
+            ] {
+                if value
+                    .as_ref()
+                    .is_some_and(|value| HeaderValue::from_str(value).is_err())
+                {
+                    errors.push(ConfigError::InvalidField {
+                        field: format!("llms.{}.{}", llm.name, field),
+                        message: "is not a valid header value".to_string(),
+                    });
+                }
+            }
+            for (name, value) in &llm.headers {
+                let field = format!("llms.{}.headers.{}", llm.name, name);
+                let message = match HeaderName::from_bytes(name.as_bytes()) {
+                    Err(_) => "is not a valid header name",
+                    Ok(name) if RESERVED_LLM_HEADERS.contains(&name.as_str()) => {
+                        "cannot replace the API key header"
+                    }
+                    Ok(_) if HeaderValue::from_str(value).is_err() => "is not a valid header value",
+                    Ok(_) => continue,
+                };
+                errors.push(ConfigError::InvalidField {
+                    field,
+                    message: message.to_string(),
+                });
+            }
+            if llm.max_concurrent_requests == Some(0) {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("llms.{}.max_concurrent_requests", llm.name),
+                    message: "must be at least 1".to_string(),
                 });
             }
+            if let Some(fallback) = &llm.tools_fallback {
+                let message = match policy.get_llm_by_name(fallback) {
+                    None => Some(format!("'{}' is not an LLM of this policy", fallback)),
+                    Some(fallback) if !fallback.supports_tools() => {
+                        Some(format!("'{}' does not support tools", fallback.name))
+                    }
+                    Some(_) => None,
+                };
+                if let Some(message) = message {
+                    errors.push(ConfigError::InvalidField {
+                        field: format!("llms.{}.tools_fallback", llm.name),
+                        message,
+                    });
+                }
+            }
         }
     }
-    Ok(())
+
+    if config.cors.allow_credentials && config.cors.allowed_origins.iter().any(|o| o == "*") {
+        errors.push(ConfigError::InvalidField {
+            field: "cors.allowed_origins".to_string(),
+            message: "'*' cannot be combined with allow_credentials; list the origins".to_string(),
+        });
+    }
+    if !(0.0..1.0).contains(&config.caching.ttl_jitter) {
+        errors.push(ConfigError::InvalidField {
+            field: "caching.ttl_jitter".to_string(),
+            message: "must be at least 0.0 and below 1.0".to_string(),
+        });
+    }
+    if let Some(batching) = &config.server.batching {
+        for (field, value) in [
+            ("max_concurrency", batching.max_concurrency),
+            ("max_batch_size", batching.max_batch_size),
+        ] {
+            if value == 0 {
+                errors.push(ConfigError::InvalidField {
+                    field: format!("server.batching.{}", field),
+                    message: "must be at least 1".to_string(),
+                });
+            }
+        }
+    }
+    if !(0.0..=1.0).contains(&config.observability.sample_rate) {
+        errors.push(ConfigError::InvalidField {
+            field: "observability.sample_rate".to_string(),
+            message: "must be between 0.0 and 1.0".to_string(),
+        });
+    }
+    if let Some(revalidation) = &config.caching.revalidation {
+        if !(0.0..=1.0).contains(&revalidation.sample_rate) {
+            errors.push(ConfigError::InvalidField {
+                field: "caching.revalidation.sample_rate".to_string(),
+                message: "must be between 0.0 and 1.0".to_string(),
+            });
+        }
+    }
+    if let Some(external_auth) = &config.security.external_auth {
+        check_url(
+            &mut errors,
+            "security.external_auth.url".to_string(),
+            &external_auth.url,
+        );
+    }
+    if let Some(semantic) = &config.caching.semantic {
+        check_url(
+            &mut errors,
+            "caching.semantic.embedding_url".to_string(),
+            &semantic.embedding_url,
+        );
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(ConfigError::Multiple(errors)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_vars_are_substituted_in_all_fields() {
+        std::env::set_var("LLM_ROUTER_TEST_NIM_HOST", "http://nim.internal:9000");
+        std::env::set_var("LLM_ROUTER_TEST_API_KEY", "secret");
+        let yaml = r#"
+policies:
+  - name: test_policy
+    url: ${LLM_ROUTER_TEST_NIM_HOST}/v2/models/router/infer
+    llms:
+      - name: Chatbot
+        api_base: ${LLM_ROUTER_TEST_NIM_HOST}/v1
+        api_key: ${LLM_ROUTER_TEST_API_KEY}
+        model: meta/${LLM_ROUTER_TEST_UNSET_VAR}
+"#;
+        let config = RouterConfig::from_yaml(yaml).unwrap();
+        let policy = &config.policies[0];
+        let llm = &policy.llms[0];
+        assert_eq!(
+            policy.url,
+            "http://nim.internal:9000/v2/models/router/infer"
+        );
+        assert_eq!(llm.api_base, "http://nim.internal:9000/v1");
+        assert_eq!(llm.api_key, "secret");
+        assert_eq!(llm.model, "meta/${LLM_ROUTER_TEST_UNSET_VAR}");
+    }
+
+    #[test]
+    fn test_llm_headers_are_validated() {
+        std::env::set_var("LLM_ROUTER_TEST_API_VERSION", "2023-06-01");
+        let yaml = |headers: &str| {
+            format!(
+                r#"
+policies:
+  - name: test_policy
+    url: http://triton:8000/v2/models/router/infer
+    llms:
+      - name: Chatbot
+        api_base: http://nim.internal/v1
+        api_key: secret
+        model: meta/llama-3.1-8b-instruct
+        headers: {}
+"#,
+                headers
+            )
+        };
+        let config = RouterConfig::from_yaml(&yaml(
+            r#"{"anthropic-version": "${LLM_ROUTER_TEST_API_VERSION}"}"#,
+        ))
+        .unwrap();
+        let headers = config.policies[0].llms[0].custom_headers();
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+        assert_eq!(
+            config.sanitized().policies[0].llms[0].headers["anthropic-version"],
+            "[REDACTED]"
+        );
+
+        for (headers, message) in [
+            (
+                r#"{"Authorization": "Bearer other"}"#,
+                "cannot replace the API key header",
+            ),
+            (r#"{"bad header": "x"}"#, "is not a valid header name"),
+            (r#"{"x-routing": "a\nb"}"#, "is not a valid header value"),
+        ] {
+            let error = RouterConfig::from_yaml(&yaml(headers)).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_experiment_variants_follow_weights_and_must_exist() {
+        let yaml = r#"
+policies:
+  - name: stable
+    url: http://triton:8000/v2/models/router/infer
+    llms: []
+  - name: candidate
+    url: http://triton:8000/v2/models/router/infer
+    llms: []
+experiments:
+  - name: task_router
+    variants:
+      - policy: stable
+        weight: 90
+      - policy: candidate
+        weight: 10
+"#;
+        let config = RouterConfig::from_yaml(yaml).unwrap();
+        let experiment = config.get_experiment_by_name("task_router").unwrap();
+        assert_eq!(experiment.total_weight(), 100);
+        assert_eq!(experiment.choose_variant(89).unwrap().policy, "stable");
+        assert_eq!(experiment.choose_variant(90).unwrap().policy, "candidate");
+        assert!(experiment.choose_variant(100).is_none());
+
+        let unknown = yaml.replace("policy: candidate", "policy: missing");
+        assert!(matches!(
+            RouterConfig::from_yaml(&unknown),
+            Err(ConfigError::InvalidExperiment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let yaml = r#"
+policies: []
+load_balancing:
+  stratgy: consistent_hash
+"#;
+        let error = RouterConfig::from_yaml(yaml).unwrap_err().to_string();
+        assert!(error.contains("unknown field `stratgy`"), "{error}");
+
+        let tagged = "{url: http://nim-a, region: us-east}";
+        assert!(serde_yaml::from_str::<Instance>(tagged).is_ok());
+        let misspelt = "{url: http://nim-a, regoin: us-east}";
+        assert!(serde_yaml::from_str::<Instance>(misspelt).is_err());
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let yaml = r#"
+policies:
+  - name: test_policy
+    url: not a url
+    llms:
+      - name: Chatbot
+        api_base: ftp://nim.internal
+        api_key: ""
+        model: meta/llama-3.1-8b-instruct
+model_aliases:
+  gpt-4: meta/llama-3.1-8b-instruct
+  gpt-3.5-turbo: Missing
+default_policy: missing_policy
+server:
+  concurrency:
+    priority:
+      workers: 0
+security:
+  rate_limit:
+    rejection_status: 500
+"#;
+        let Err(ConfigError::Multiple(errors)) = RouterConfig::from_yaml(yaml) else {
+            panic!("expected aggregated errors");
+        };
+        assert_eq!(errors.len(), 7);
+        let message = ConfigError::Multiple(errors).to_string();
+        assert!(message.starts_with("7 configuration errors:"));
+        assert!(message.contains("server.concurrency.priority.workers"));
+        assert!(message.contains("security.rate_limit.rejection_status"));
+        assert!(message.contains("'missing_policy' is not a policy or experiment"));
+        assert!(message.contains("model_aliases.gpt-3.5-turbo"));
+        assert!(message.contains("policies.test_policy.url"));
+        assert!(message.contains("unsupported scheme 'ftp'"));
+        assert!(message.contains("Missing field 'api_key' in LLM 'Chatbot'"));
+    }
+
+    #[test]
+    fn test_substitute_env_vars_handles_unterminated_reference() {
+        assert_eq!(
+            substitute_env_vars("prefix ${UNTERMINATED"),
+            "prefix ${UNTERMINATED"
+        );
+        assert_eq!(substitute_env_vars("no references"), "no references");
+    }
+
+    #[test]
+    fn test_key_ids_and_token_pricing() {
+        let keys: ApiKeys =
+            serde_yaml::from_str("team-a-key: {id: team-a}\nother-key: {}").unwrap();
+        assert_eq!(keys.key_id("team-a-key"), "team-a");
+        let hashed = keys.key_id("other-key");
+        assert!(hashed.starts_with("key-") && !hashed.contains("other"));
+        assert_eq!(hashed, ApiKeys::default().key_id("other-key"));
+
+        let pricing = TokenPricing {
+            prompt_per_million: 0.5,
+            completion_per_million: 1.5,
+        };
+        assert!((pricing.cost(2_000, 1_000) - 0.0025).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,398 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Config Manager
+use crate::config::{ConfigReloadConfig, ConfigReloadMode, RouterConfig};
+use crate::error::ConfigError;
+use crate::logging::BodyLogger;
+use log::{debug, error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::task::JoinHandle;
+
+/// Holds the live configuration and swaps it on reload. Requests take a
+/// snapshot when they start, so a reload never changes a request mid-flight.
+#[derive(Debug, Clone)]
+pub struct ConfigManager {
+    path: Option<PathBuf>,
+    current: Arc<RwLock<LoadedConfig>>,
+}
+
+#[derive(Debug)]
+struct LoadedConfig {
+    config: Arc<RouterConfig>,
+    /// Built from `config`, so the redaction follows its provider keys.
+    body_logger: Arc<BodyLogger>,
+    loaded_at: SystemTime,
+}
+
+/// Sections read once at startup to build long-lived components.
+fn startup_sections(config: &RouterConfig) -> serde_json::Value {
+    serde_json::json!([
+        config.client,
+        config.caching,
+        config.observability,
+        config.server,
+        config.circuit_breaker
+    ])
+}
+
+/// Identifies a version of the config file. Editors that replace the file
+/// instead of writing in place change it as well.
+type Fingerprint = Option<(SystemTime, u64)>;
+
+fn fingerprint(path: &Path) -> Fingerprint {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Decides when a changed file has settled and should be reloaded.
+#[derive(Debug)]
+struct ChangeDetector {
+    applied: Fingerprint,
+    pending: Option<(Fingerprint, Instant)>,
+    debounce: Duration,
+}
+
+impl ChangeDetector {
+    fn new(applied: Fingerprint, debounce: Duration) -> Self {
+        ChangeDetector {
+            applied,
+            pending: None,
+            debounce,
+        }
+    }
+
+    /// Returns true once `current` differs from the applied version and has
+    /// stayed the same for the debounce period.
+    fn observe(&mut self, current: Fingerprint, now: Instant) -> bool {
+        if current == self.applied {
+            self.pending = None;
+            return false;
+        }
+        match self.pending {
+            Some((pending, since)) if pending == current => {
+                if now.duration_since(since) >= self.debounce {
+                    self.applied = current;
+                    self.pending = None;
+                    return true;
+                }
+                false
+            }
+            _ => {
+                self.pending = Some((current, now));
+                false
+            }
+        }
+    }
+}
+
+impl ConfigManager {
+    pub fn new(config: RouterConfig, path: Option<PathBuf>) -> Result<Self, ConfigError> {
+        Ok(ConfigManager {
+            path,
+            current: Arc::new(RwLock::new(LoadedConfig {
+                body_logger: Arc::new(BodyLogger::new(&config)?),
+                config: Arc::new(config),
+                loaded_at: SystemTime::now(),
+            })),
+        })
+    }
+
+    /// The same live config, reloadable from the file at `path`.
+    pub fn with_path(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn current(&self) -> Arc<RouterConfig> {
+        self.current_with_load_time().0
+    }
+
+    /// The live config and when it was loaded or last reloaded.
+    pub fn current_with_load_time(&self) -> (Arc<RouterConfig>, SystemTime) {
+        let current = self.current.read().expect("config lock poisoned");
+        (current.config.clone(), current.loaded_at)
+    }
+
+    /// The live config with the body logger built from it.
+    pub fn current_with_body_logger(&self) -> (Arc<RouterConfig>, Arc<BodyLogger>) {
+        let current = self.current.read().expect("config lock poisoned");
+        (current.config.clone(), current.body_logger.clone())
+    }
+
+    /// Re-reads and validates the config file. The new config replaces the
+    /// current one only if it is valid; otherwise the current one is kept.
+    pub fn reload(&self) -> Result<Arc<RouterConfig>, ConfigError> {
+        let Some(path) = &self.path else {
+            return Err(ConfigError::InvalidField {
+                field: "config_path".to_string(),
+                message: "the gateway was not started from a config file".to_string(),
+            });
+        };
+
+        let loaded = RouterConfig::load_config(&path.to_string_lossy()).and_then(|config| {
+            let body_logger = BodyLogger::new(&config)?;
+            Ok((Arc::new(config), Arc::new(body_logger)))
+        });
+        let (config, body_logger) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!(
+                    "Failed to reload {}, keeping the current config: {}",
+                    path.display(),
+                    e
+                );
+                return Err(e);
+            }
+        };
+
+        let mut current = self.current.write().expect("config lock poisoned");
+        if startup_sections(&current.config) != startup_sections(&config) {
+            warn!("Changes to the client, caching, observability, server and circuit_breaker sections take effect after a restart");
+        }
+        *current = LoadedConfig {
+            config: config.clone(),
+            body_logger,
+            loaded_at: SystemTime::now(),
+        };
+        info!(
+            "Reloaded {} with {} policies",
+            path.display(),
+            config.policies.len()
+        );
+        Ok(config)
+    }
+
+    /// Runs `reload` on the blocking thread pool, since resolving secrets may
+    /// wait on Vault, so the runtime keeps serving requests meanwhile.
+    pub async fn reload_off_runtime(&self) -> Result<Arc<RouterConfig>, ConfigError> {
+        let manager = self.clone();
+        tokio::task::spawn_blocking(move || manager.reload())
+            .await
+            .expect("config reload task panicked")
+    }
+
+    /// Starts reloading the config file whenever it changes. Failed reloads
+    /// keep the current config, as with `reload`. `watch` mode polls when
+    /// the file cannot be watched.
+    pub fn spawn_watcher(&self, settings: &ConfigReloadConfig) -> Option<JoinHandle<()>> {
+        let path = self.path.clone()?;
+        let poll_interval = Duration::from_secs(settings.poll_interval_secs.max(1));
+        match settings.mode {
+            ConfigReloadMode::Watch => match watch(&path) {
+                Ok((watcher, events)) => {
+                    info!("Watching {} for changes", path.display());
+                    let debounce = Duration::from_millis(settings.debounce_ms);
+                    return Some(self.spawn_event_reloads(path, watcher, events, debounce));
+                }
+                Err(e) => warn!(
+                    "Cannot watch {}, checking it every {:?} instead: {}",
+                    path.display(),
+                    poll_interval,
+                    e
+                ),
+            },
+            ConfigReloadMode::Poll => {
+                info!(
+                    "Checking {} for changes every {:?}",
+                    path.display(),
+                    poll_interval
+                )
+            }
+            ConfigReloadMode::Off => return None,
+        }
+        Some(self.spawn_polled_reloads(path, poll_interval))
+    }
+
+    /// Reloads once the file changed and no further events arrived for
+    /// `debounce`, so rapid editor saves trigger a single reload.
+    fn spawn_event_reloads(
+        &self,
+        path: PathBuf,
+        watcher: RecommendedWatcher,
+        mut events: Receiver<()>,
+        debounce: Duration,
+    ) -> JoinHandle<()> {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            // Events stop once the watcher is dropped.
+            let _watcher = watcher;
+            let mut applied = fingerprint(&path);
+            while events.recv().await.is_some() {
+                loop {
+                    match tokio::time::timeout(debounce, events.recv()).await {
+                        Ok(Some(())) => continue,
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+                // Events for other files in the directory change nothing.
+                let current = fingerprint(&path);
+                if current != applied {
+                    applied = current;
+                    debug!("{} changed, reloading", path.display());
+                    let _ = manager.reload_off_runtime().await;
+                }
+            }
+        })
+    }
+
+    fn spawn_polled_reloads(&self, path: PathBuf, tick: Duration) -> JoinHandle<()> {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            // Polling is slow enough that a change seen on one tick has
+            // settled.
+            let mut detector = ChangeDetector::new(fingerprint(&path), Duration::ZERO);
+            let mut interval = tokio::time::interval(tick);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if detector.observe(fingerprint(&path), Instant::now()) {
+                    debug!("{} changed, reloading", path.display());
+                    let _ = manager.reload_off_runtime().await;
+                }
+            }
+        })
+    }
+}
+
+/// Watches the directory of `path`, since editors and Kubernetes replace the
+/// file rather than write to it, which ends a watch on the file itself. Each
+/// event wakes the receiver; events that arrive while it is awake are
+/// merged.
+fn watch(path: &Path) -> notify::Result<(RecommendedWatcher, Receiver<()>)> {
+    let (sender, receiver) = mpsc::channel(1);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok() {
+            let _ = sender.try_send(());
+        }
+    })?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    Ok((watcher, receiver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"
+policies:
+  - name: first
+    url: http://triton:8000/v2/models/router/infer
+    llms: []
+"#;
+
+    #[test]
+    fn test_reload_swaps_valid_config_and_keeps_old_on_error() {
+        let path = std::env::temp_dir().join(format!(
+            "llm-router-config-manager-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(&path, VALID).unwrap();
+        let manager = ConfigManager::new(RouterConfig::default(), Some(path.clone())).unwrap();
+        assert!(manager.current().policies.is_empty());
+
+        manager.reload().unwrap();
+        assert_eq!(manager.current().policies[0].name, "first");
+
+        std::fs::write(&path, VALID.replace("url:", "urll:")).unwrap();
+        assert!(manager.reload().is_err());
+        assert_eq!(manager.current().policies[0].name, "first");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_rebuilds_body_logger_with_new_provider_keys() {
+        let path = std::env::temp_dir().join(format!(
+            "llm-router-config-manager-logger-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            VALID.replace(
+                "llms: []",
+                "llms:\n      - name: chat\n        api_base: http://llm\n        \
+                 api_key: rotated-provider-key\n        model: chat-model",
+            ),
+        )
+        .unwrap();
+        let manager = ConfigManager::new(RouterConfig::default(), Some(path.clone())).unwrap();
+        let redact = |manager: &ConfigManager| {
+            let (_, body_logger) = manager.current_with_body_logger();
+            body_logger.redact("key rotated-provider-key")
+        };
+        assert_eq!(redact(&manager), "key rotated-provider-key");
+
+        manager.reload().unwrap();
+        assert_eq!(redact(&manager), "key [REDACTED]");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_change_detector_debounces_rapid_writes() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let version = |secs| Some((SystemTime::UNIX_EPOCH + Duration::from_secs(secs), 10));
+        let mut detector = ChangeDetector::new(version(1), Duration::from_millis(100));
+
+        assert!(!detector.observe(version(1), at(0)));
+        // Two saves in quick succession restart the quiet period.
+        assert!(!detector.observe(version(2), at(10)));
+        assert!(!detector.observe(version(3), at(60)));
+        assert!(!detector.observe(version(3), at(150)));
+        assert!(detector.observe(version(3), at(160)));
+        assert!(!detector.observe(version(3), at(500)));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reloads_after_write() {
+        let path = std::env::temp_dir().join(format!(
+            "llm-router-config-watch-{}.yaml",
+            std::process::id()
+        ));
+        std::fs::write(&path, VALID).unwrap();
+        let manager = ConfigManager::new(RouterConfig::default(), Some(path.clone())).unwrap();
+        let settings = ConfigReloadConfig {
+            debounce_ms: 20,
+            ..ConfigReloadConfig::default()
+        };
+        let watcher = manager.spawn_watcher(&settings).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        std::fs::write(&path, VALID.replace("first", "second")).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while manager.current().policies.first().map(|p| p.name.as_str()) != Some("second") {
+            assert!(Instant::now() < deadline, "config was not reloaded");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        watcher.abort();
+        std::fs::remove_file(path).unwrap();
+    }
+}
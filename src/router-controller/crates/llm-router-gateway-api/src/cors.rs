@@ -0,0 +1,232 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CORS
+//!
+//! Answers preflight requests and adds `Access-Control-*` headers for the
+//! origins in `cors.allowed_origins`. Preflights are answered before any
+//! authentication, as browsers never send credentials with them.
+use crate::config::CorsConfig;
+use crate::error::GatewayApiError;
+use bytes::Bytes;
+use http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hyper::Response;
+
+/// Whether a request is a CORS preflight: an `OPTIONS` request from a
+/// browser announcing the method of the request it wants to send.
+pub fn is_preflight(method: &Method, headers: &HeaderMap) -> bool {
+    method == Method::OPTIONS
+        && headers.contains_key(ORIGIN)
+        && headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// `Access-Control-Allow-Origin` for the request's `Origin`, or `None` when
+/// the origin is not allowed or CORS is off.
+fn allowed_origin(config: &CorsConfig, headers: &HeaderMap) -> Option<HeaderValue> {
+    let origin = headers.get(ORIGIN)?;
+    let origin_str = origin.to_str().ok()?;
+    if config.allowed_origins.iter().any(|allowed| allowed == "*") {
+        // `*` is rejected with credentials, which need the exact origin.
+        return Some(HeaderValue::from_static("*"));
+    }
+    config
+        .allowed_origins
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+        .then(|| origin.clone())
+}
+
+/// Responses differ by origin unless every origin is allowed.
+fn add_vary(config: &CorsConfig, response: &mut HeaderMap) {
+    if !config.allowed_origins.iter().any(|allowed| allowed == "*") {
+        response.append(VARY, HeaderValue::from_static("origin"));
+    }
+}
+
+/// Adds the CORS headers of a regular response for an allowed origin.
+pub fn add_headers(config: &CorsConfig, request: &HeaderMap, response: &mut HeaderMap) {
+    if config.allowed_origins.is_empty() {
+        return;
+    }
+    add_vary(config, response);
+    let Some(origin) = allowed_origin(config, request) else {
+        return;
+    };
+    response.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    if config.allow_credentials {
+        response.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+/// Answers a preflight with `204`. The `Access-Control-*` headers are left
+/// out when the origin, method or headers are not allowed, so the browser
+/// blocks the request.
+pub fn preflight_response(
+    config: &CorsConfig,
+    request: &HeaderMap,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let mut response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Empty::new().map_err(|never| match never {}).boxed())?;
+    let method_allowed = request
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|method| method.to_str().ok())
+        .is_some_and(|method| {
+            config
+                .allowed_methods
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(method))
+        });
+    let requested_headers = request
+        .get(ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|headers| headers.to_str().ok())
+        .unwrap_or_default();
+    let any_header = config.allowed_headers.iter().any(|allowed| allowed == "*");
+    let headers_allowed = any_header
+        || requested_headers
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                config
+                    .allowed_headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            });
+    if !method_allowed || !headers_allowed {
+        add_vary(config, response.headers_mut());
+        return Ok(response);
+    }
+
+    add_headers(config, request, response.headers_mut());
+    if !response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN) {
+        return Ok(response);
+    }
+    let headers = response.headers_mut();
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_str(&config.allowed_methods.join(", "))?,
+    );
+    let allow_headers = if any_header {
+        requested_headers.to_string()
+    } else {
+        config.allowed_headers.join(", ")
+    };
+    if !allow_headers.is_empty() {
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_str(&allow_headers)?,
+        );
+    }
+    headers.insert(
+        ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from(config.max_age_secs),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            ..CorsConfig::default()
+        }
+    }
+
+    fn preflight(origin: &'static str, method: &'static str, headers: &'static str) -> HeaderMap {
+        let mut request = HeaderMap::new();
+        request.insert(ORIGIN, HeaderValue::from_static(origin));
+        request.insert(
+            ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static(method),
+        );
+        request.insert(
+            ACCESS_CONTROL_REQUEST_HEADERS,
+            HeaderValue::from_static(headers),
+        );
+        request
+    }
+
+    #[test]
+    fn test_preflight_is_answered_for_allowed_origins_only() {
+        let config = CorsConfig {
+            allow_credentials: true,
+            ..config(&["https://app.example.com"])
+        };
+        let request = preflight(
+            "https://app.example.com",
+            "POST",
+            "Authorization, Content-Type",
+        );
+        assert!(is_preflight(&Method::OPTIONS, &request));
+        assert!(!is_preflight(&Method::POST, &request));
+
+        let response = preflight_response(&config, &request).unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST, DELETE");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_HEADERS],
+            "authorization, content-type, x-request-id"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(headers[VARY], "origin");
+
+        for request in [
+            preflight("https://evil.example.com", "POST", ""),
+            preflight("https://app.example.com", "PUT", ""),
+            preflight("https://app.example.com", "POST", "x-custom"),
+        ] {
+            let response = preflight_response(&config, &request).unwrap();
+            assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        }
+    }
+
+    #[test]
+    fn test_wildcards_and_disabled_cors() {
+        let request = preflight("https://any.example.com", "GET", "x-custom");
+        let mut response = HeaderMap::new();
+        add_headers(&config(&[]), &request, &mut response);
+        assert!(response.is_empty());
+
+        add_headers(&config(&["*"]), &request, &mut response);
+        assert_eq!(response[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!response.contains_key(VARY));
+
+        let any_header = CorsConfig {
+            allowed_headers: vec!["*".to_string()],
+            ..config(&["*"])
+        };
+        let response = preflight_response(&any_header, &request).unwrap();
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_HEADERS], "x-custom");
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! DNS
+//!
+//! Resolution of upstream hostnames for the outbound clients, with pinned
+//! addresses and a cache so lookups stay off the request path.
+use crate::config::DnsConfig;
+use log::debug;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Addresses of each looked up host, and when they expire.
+type AddressCache = Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>;
+
+/// Resolves hostnames pinned in `client.dns.static_hosts` without a lookup
+/// and caches the system resolver's answers for the others.
+#[derive(Debug)]
+pub struct CachingResolver {
+    static_hosts: HashMap<String, Vec<IpAddr>>,
+    ttl: Option<Duration>,
+    cache: Arc<AddressCache>,
+}
+
+impl CachingResolver {
+    /// `None` when nothing is pinned or cached, so clients keep reqwest's
+    /// own resolver.
+    pub fn from_config(config: &DnsConfig) -> Option<Self> {
+        if config.static_hosts.is_empty() && config.cache_ttl_secs.is_none() {
+            return None;
+        }
+        Some(CachingResolver {
+            static_hosts: config
+                .static_hosts
+                .iter()
+                .map(|(host, addrs)| (host.to_ascii_lowercase(), addrs.clone()))
+                .collect(),
+            ttl: config.cache_ttl_secs.map(Duration::from_secs),
+            cache: Arc::new(AddressCache::default()),
+        })
+    }
+
+    fn cached(&self, host: &str, now: Instant) -> Option<Vec<SocketAddr>> {
+        let cache = self.cache.lock().expect("dns cache lock poisoned");
+        cache
+            .get(host)
+            .filter(|(expires, _)| *expires > now)
+            .map(|(_, addrs)| addrs.clone())
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        // reqwest replaces the port with the one of the URL.
+        if let Some(ips) = self.static_hosts.get(&host) {
+            let addrs: Vec<SocketAddr> = ips.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+        if let Some(addrs) = self.cached(&host, Instant::now()) {
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+
+        let ttl = self.ttl;
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            debug!("Resolved {} to {:?}", host, addrs);
+            // Failed lookups are not cached, so the next request retries.
+            if let Some(ttl) = ttl {
+                let mut cache = cache.lock().expect("dns cache lock poisoned");
+                cache.retain(|_, (expires, _)| *expires > Instant::now());
+                cache.insert(host, (Instant::now() + ttl, addrs.clone()));
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    async fn resolve(resolver: &CachingResolver, host: &str) -> Vec<SocketAddr> {
+        resolver
+            .resolve(Name::from_str(host).unwrap())
+            .await
+            .unwrap()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_pinned_hosts_and_cached_lookups() {
+        assert!(CachingResolver::from_config(&DnsConfig::default()).is_none());
+
+        let config = DnsConfig {
+            cache_ttl_secs: Some(60),
+            static_hosts: BTreeMap::from([(
+                "NIM.internal".to_string(),
+                vec!["10.0.0.7".parse().unwrap()],
+            )]),
+        };
+        let resolver = CachingResolver::from_config(&config).unwrap();
+        assert_eq!(
+            resolve(&resolver, "nim.internal").await,
+            vec!["10.0.0.7:0".parse().unwrap()]
+        );
+        assert!(resolver.cached("nim.internal", Instant::now()).is_none());
+
+        let addrs = resolve(&resolver, "localhost").await;
+        assert!(!addrs.is_empty());
+        assert_eq!(resolver.cached("localhost", Instant::now()), Some(addrs));
+        let expired = Instant::now() + Duration::from_secs(61);
+        assert!(resolver.cached("localhost", expired).is_none());
+    }
+}
@@ -13,6 +13,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::request_id;
 use http::header::InvalidHeaderValue;
 use http::{Response, StatusCode};
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
@@ -50,9 +51,18 @@ pub enum GatewayApiError {
         status: StatusCode,
         message: String,
         provider: String,
+        /// The provider's own error code, when it reported one.
+        code: Option<String>,
         details: Option<Value>,
     },
 
+    /// The provider rejected the router's own key for an LLM.
+    #[error("LLM provider '{provider}' rejected the router's configured credentials ({status}); this is a gateway configuration problem, not an issue with your key")]
+    LlmAuthError {
+        status: StatusCode,
+        provider: String,
+    },
+
     // Router errors
     #[error("Routing Error: {message}")]
     RoutingError {
@@ -111,6 +121,24 @@ pub enum ConfigError {
     MissingPolicyField { policy: String, field: String },
     #[error("Missing field '{field}' in LLM '{llm}'")]
     MissingLlmField { llm: String, field: String },
+    #[error("Shadow LLM '{llm}' is not one of the LLMs of policy '{policy}'")]
+    UnknownShadowLlm { policy: String, llm: String },
+    #[error("Invalid experiment '{experiment}': {message}")]
+    InvalidExperiment { experiment: String, message: String },
+    #[error("Invalid value for '{field}': {message}")]
+    InvalidField { field: String, message: String },
+    #[error("{} configuration errors:{}", .0.len(), .0.iter().map(|e| format!("\n  - {}", e)).collect::<String>())]
+    Multiple(Vec<ConfigError>),
+    #[error("Invalid TLS file '{path}': {message}")]
+    InvalidTls { path: String, message: String },
+    #[error("Invalid redact pattern '{pattern}': {message}")]
+    InvalidPattern { pattern: String, message: String },
+    #[error("Failed to resolve secret '{path}': {message}")]
+    Secret { path: String, message: String },
+    #[error("Config '{path}' failed signature verification: {message}")]
+    Signature { path: String, message: String },
+    #[error("Failed to build HTTP client: {0}")]
+    HttpClient(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -126,11 +154,24 @@ pub enum RoutingErrorType {
     TritonUnavailable,
 }
 
+impl RoutingErrorType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PolicyNotFound => "policy_not_found",
+            Self::ModelNotFound => "model_not_found",
+            Self::NoRoutingStrategy => "no_routing_strategy",
+            Self::InvalidConfiguration => "invalid_configuration",
+            Self::TritonUnavailable => "triton_unavailable",
+        }
+    }
+}
+
 impl GatewayApiError {
     pub fn error_source(&self) -> ErrorSource {
         match self {
             Self::TritonError { .. } => ErrorSource::Triton,
             Self::LlmServiceError { .. } => ErrorSource::LlmProvider,
+            Self::LlmAuthError { .. } => ErrorSource::LlmProvider,
             Self::RoutingError { .. } => ErrorSource::Router,
             Self::ClientError { .. } => ErrorSource::Client,
             _ => ErrorSource::Infrastructure,
@@ -143,6 +184,8 @@ impl GatewayApiError {
                 StatusCode::from_u16(*code).unwrap_or(StatusCode::SERVICE_UNAVAILABLE)
             }
             Self::LlmServiceError { status, .. } => *status,
+            // Not the client's key, so not a 401 or 403 either.
+            Self::LlmAuthError { .. } => StatusCode::BAD_GATEWAY,
             Self::ClientError { status, .. } => *status,
             Self::RoutingError { error_type, .. } => match error_type {
                 RoutingErrorType::PolicyNotFound => StatusCode::BAD_REQUEST,
@@ -161,17 +204,29 @@ impl GatewayApiError {
                 status,
                 message,
                 provider,
+                code,
                 details,
             } => json!({
                 "error": {
                     "type": "llm_service_error",
                     "message": message,
+                    "code": code,
                     "status": status.as_u16(),
                     "provider": provider,
                     "details": details,
                     "source": "llm_provider"
                 }
             }),
+            Self::LlmAuthError { status, provider } => json!({
+                "error": {
+                    "type": "llm_auth_error",
+                    "message": self.to_string(),
+                    "status": self.status_code().as_u16(),
+                    "upstream_status": status.as_u16(),
+                    "provider": provider,
+                    "source": "llm_provider"
+                }
+            }),
             Self::TritonError {
                 message,
                 code,
@@ -190,7 +245,7 @@ impl GatewayApiError {
                 error_type,
             } => json!({
                 "error": {
-                    "type": format!("routing_error_{:?}", error_type).to_lowercase(),
+                    "type": format!("routing_error_{}", error_type.as_str()),
                     "message": message,
                     "status": self.status_code().as_u16(),
                     "source": "router"
@@ -218,6 +273,7 @@ impl GatewayApiError {
             }),
         };
 
+        let error_response = with_request_id(error_response);
         let body_bytes = Bytes::from(serde_json::to_vec(&error_response)?);
         let boxed_body = Full::from(body_bytes)
             .map_err(|never| match never {})
@@ -247,10 +303,46 @@ impl GatewayApiError {
             status,
             message: message.into(),
             provider: provider.into(),
+            code: None,
             details: None,
         }
     }
 
+    /// Normalizes a non-2xx provider response into an OpenAI-style error,
+    /// keeping the status. The original body is kept under `details`.
+    pub fn from_provider_response(
+        status: StatusCode,
+        provider: impl Into<String>,
+        body: &[u8],
+    ) -> Self {
+        let parsed = serde_json::from_slice::<Value>(body).ok();
+        let (message, code) = parsed
+            .as_ref()
+            .and_then(provider_error_fields)
+            .unwrap_or_else(|| {
+                let text = String::from_utf8_lossy(body).trim().to_string();
+                let message = if text.is_empty() {
+                    status
+                        .canonical_reason()
+                        .unwrap_or("LLM request failed")
+                        .to_string()
+                } else {
+                    text
+                };
+                (message, None)
+            });
+        let details = parsed.or_else(|| {
+            (!body.is_empty()).then(|| Value::String(String::from_utf8_lossy(body).into_owned()))
+        });
+        Self::LlmServiceError {
+            status,
+            message,
+            provider: provider.into(),
+            code,
+            details,
+        }
+    }
+
     pub fn routing_error(message: impl Into<String>, error_type: RoutingErrorType) -> Self {
         Self::RoutingError {
             message: message.into(),
@@ -271,6 +363,37 @@ impl GatewayApiError {
     }
 }
 
+/// Reads the message and code from the error shapes providers use:
+/// `{"error": {"message", "code"}}`, `{"error": "..."}`,
+/// `{"message", "code"}` and FastAPI's `{"detail": ...}`.
+fn provider_error_fields(body: &Value) -> Option<(String, Option<String>)> {
+    let as_code = |value: &Value| match value {
+        Value::String(code) => Some(code.clone()),
+        Value::Number(code) => Some(code.to_string()),
+        _ => None,
+    };
+    let error = match &body["error"] {
+        Value::Object(_) => &body["error"],
+        Value::String(message) => return Some((message.clone(), None)),
+        _ => body,
+    };
+    if let Some(message) = error["message"].as_str() {
+        let code = as_code(&error["code"]).or_else(|| as_code(&error["type"]));
+        return Some((message.to_string(), code));
+    }
+    match &body["detail"] {
+        Value::String(message) => Some((message.clone(), None)),
+        Value::Array(items) => {
+            let messages: Vec<&str> = items
+                .iter()
+                .filter_map(|item| item["msg"].as_str())
+                .collect();
+            (!messages.is_empty()).then(|| (messages.join("; "), None))
+        }
+        _ => None,
+    }
+}
+
 impl From<reqwest::Error> for GatewayApiError {
     fn from(error: reqwest::Error) -> Self {
         if let Some(status) = error.status() {
@@ -295,6 +418,14 @@ impl From<InvalidHeaderValue> for GatewayApiError {
     }
 }
 
+/// Adds the current request's ID as `error.request_id`, when there is one.
+fn with_request_id(mut body: Value) -> Value {
+    if let Some(id) = request_id::current() {
+        body["error"]["request_id"] = Value::String(id);
+    }
+    body
+}
+
 impl IntoResponse for GatewayApiError {
     fn into_response(self) -> Response<BoxBody<Bytes, GatewayApiError>> {
         let (status, message) = match &self {
@@ -308,12 +439,12 @@ impl IntoResponse for GatewayApiError {
             _ => (self.status_code(), self.to_string()),
         };
 
-        let error_json = json!({
+        let error_json = with_request_id(json!({
             "error": {
                 "message": message,
                 "status": status.as_u16()
             }
-        });
+        }));
 
         let body = Full::from(Bytes::from(
             serde_json::to_vec(&error_json).unwrap_or_default(),
@@ -370,6 +501,64 @@ mod tests {
         assert_eq!(json["error"]["source"], "llm_provider");
     }
 
+    #[tokio::test]
+    async fn test_llm_auth_error() {
+        let error = GatewayApiError::LlmAuthError {
+            status: StatusCode::UNAUTHORIZED,
+            provider: "OpenAI".to_string(),
+        };
+        assert_eq!(error.error_source(), ErrorSource::LlmProvider);
+        let response = error.to_response().unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(json["error"]["type"], "llm_auth_error");
+        assert_eq!(json["error"]["upstream_status"], 401);
+        assert_eq!(json["error"]["source"], "llm_provider");
+    }
+
+    #[tokio::test]
+    async fn test_provider_errors_are_normalized() {
+        let cases = [
+            (
+                r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": "rate_limit_exceeded"}}"#,
+                "Rate limit reached",
+                json!("rate_limit_exceeded"),
+            ),
+            (
+                r#"{"object": "error", "message": "Model overloaded", "code": 503}"#,
+                "Model overloaded",
+                json!("503"),
+            ),
+            (
+                r#"{"detail": [{"msg": "field required"}]}"#,
+                "field required",
+                Value::Null,
+            ),
+            ("upstream exploded", "upstream exploded", Value::Null),
+        ];
+        for (body, message, code) in cases {
+            let error = GatewayApiError::from_provider_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "provider",
+                body.as_bytes(),
+            );
+            let response = error.to_response().unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+            let body_bytes = response.into_body().collect().await.unwrap().to_bytes();
+            let json: Value = serde_json::from_slice(&body_bytes).unwrap();
+            assert_eq!(json["error"]["message"], message);
+            assert_eq!(json["error"]["type"], "llm_service_error");
+            assert_eq!(json["error"]["code"], code);
+            let original = serde_json::from_str(body).unwrap_or(json!(body));
+            assert_eq!(json["error"]["details"], original);
+        }
+    }
+
     #[tokio::test]
     async fn test_triton_error() {
         let error = GatewayApiError::triton_error("Model loading failed", 503);
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Events
+//!
+//! Routing decisions streamed to operators connected to `GET /admin/events`.
+use crate::error::GatewayApiError;
+use crate::logging::AccessLogRecord;
+use crate::request_id;
+use bytes::Bytes;
+use futures_util::stream;
+use http_body::Frame;
+use http_body_util::{combinators::BoxBody, StreamBody};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Events kept for a subscriber that falls behind. Older ones are dropped.
+const EVENT_BUFFER: usize = 1024;
+
+/// One proxied request, as seen once its response was produced.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RoutingEvent {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub request_id: Option<String>,
+    pub policy: Option<String>,
+    pub model: Option<String>,
+    pub upstream: Option<String>,
+    pub latency_ms: f64,
+    pub status: u16,
+    /// `cache_hit`, `success`, `client_error` or `server_error`.
+    pub outcome: &'static str,
+}
+
+impl RoutingEvent {
+    pub fn new(access: &AccessLogRecord, cache_hit: bool) -> Self {
+        let outcome = match access.status {
+            _ if cache_hit => "cache_hit",
+            200..=399 => "success",
+            400..=499 => "client_error",
+            _ => "server_error",
+        };
+        RoutingEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            request_id: request_id::current(),
+            policy: access.policy.clone(),
+            model: access.model.clone(),
+            upstream: access.api_base.clone(),
+            latency_ms: access.latency_ms,
+            status: access.status,
+            outcome,
+        }
+    }
+}
+
+/// Bounded broadcast of routing events. Publishing never waits: a subscriber
+/// that falls more than `EVENT_BUFFER` events behind skips the oldest ones.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<RoutingEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the event built by `event`, which is only called while an
+    /// operator is subscribed.
+    pub fn publish(&self, event: impl FnOnce() -> RoutingEvent) {
+        if self.sender.receiver_count() > 0 {
+            // Fails only when the last subscriber just left.
+            let _ = self.sender.send(event());
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RoutingEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Server-sent events body relaying `receiver`, one JSON event per `data:`
+/// line. Skipped events are reported as a comment.
+pub fn event_stream(
+    receiver: broadcast::Receiver<RoutingEvent>,
+) -> BoxBody<Bytes, GatewayApiError> {
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let chunk = match receiver.recv().await {
+            Ok(event) => match serde_json::to_string(&event) {
+                Ok(json) => format!("data: {}\n\n", json),
+                Err(e) => return Some((Err(e.into()), receiver)),
+            },
+            Err(RecvError::Lagged(skipped)) => format!(": skipped {} events\n\n", skipped),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(Frame::data(Bytes::from(chunk))), receiver))
+    });
+    BoxBody::new(StreamBody::new(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn access(status: u16) -> AccessLogRecord {
+        AccessLogRecord {
+            policy: Some("test_policy".to_string()),
+            model: Some("llama".to_string()),
+            api_base: Some("http://llm".to_string()),
+            status,
+            ..AccessLogRecord::new("POST", "/v1/chat/completions")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_reach_subscribers_and_lag_drops_oldest() {
+        let bus = EventBus::new();
+        // Nobody listens, so no event is even built.
+        bus.publish(|| panic!("built without a subscriber"));
+
+        let mut body = event_stream(bus.subscribe());
+        bus.publish(|| RoutingEvent::new(&access(200), false));
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        let text = String::from_utf8(frame.to_vec()).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(text.strip_prefix("data: ").unwrap().trim_end()).unwrap();
+        assert_eq!(json["policy"], "test_policy");
+        assert_eq!(json["upstream"], "http://llm");
+        assert_eq!(json["outcome"], "success");
+
+        for _ in 0..EVENT_BUFFER + 5 {
+            bus.publish(|| RoutingEvent::new(&access(503), false));
+        }
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(frame, Bytes::from(": skipped 5 events\n\n"));
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains(r#""outcome":"server_error""#));
+    }
+}
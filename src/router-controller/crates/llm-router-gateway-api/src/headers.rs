@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Headers
+use crate::config::ClientConfig;
+use reqwest::header::HeaderMap;
+
+/// Headers that are never copied from the client request: hop-by-hop
+/// headers, credentials (the provider key is injected separately) and
+/// headers the upstream request sets itself. `accept-encoding` is left to
+/// the HTTP client, which only asks for encodings it can decode.
+const ALWAYS_STRIPPED: [&str; 17] = [
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "cookie",
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "accept",
+    "accept-encoding",
+];
+
+/// Selects the client headers to send upstream: those named in
+/// `forward_headers` (or all of them for `*`), minus `strip_headers`, the
+/// `key_headers` clients send their key in and the always-stripped set.
+pub fn forwarded_headers(
+    incoming: &HeaderMap,
+    config: &ClientConfig,
+    key_headers: &[String],
+) -> HeaderMap {
+    let forward_all = config.forward_headers.iter().any(|name| name == "*");
+    let is_listed =
+        |list: &[String], name: &str| list.iter().any(|entry| entry.eq_ignore_ascii_case(name));
+
+    let mut forwarded = HeaderMap::new();
+    for (name, value) in incoming {
+        let name_str = name.as_str();
+        if ALWAYS_STRIPPED.contains(&name_str)
+            || is_listed(&config.strip_headers, name_str)
+            || is_listed(key_headers, name_str)
+        {
+            continue;
+        }
+        if forward_all || is_listed(&config.forward_headers, name_str) {
+            forwarded.append(name.clone(), value.clone());
+        }
+    }
+    forwarded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn incoming() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("authorization", "Bearer client-key"),
+            ("cookie", "session=1"),
+            ("host", "router.internal"),
+            ("x-api-key", "client-key"),
+            ("accept-encoding", "br"),
+            ("x-request-id", "abc"),
+            ("x-trace-id", "def"),
+            ("x-internal", "secret"),
+        ] {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_only_allowlisted_headers_are_forwarded() {
+        let config = ClientConfig {
+            forward_headers: vec!["X-Request-Id".to_string(), "Authorization".to_string()],
+            ..ClientConfig::default()
+        };
+        let forwarded = forwarded_headers(&incoming(), &config, &[]);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded["x-request-id"], "abc");
+    }
+
+    #[test]
+    fn test_wildcard_respects_strip_list() {
+        let config = ClientConfig {
+            forward_headers: vec!["*".to_string()],
+            strip_headers: vec!["X-Internal".to_string()],
+            ..ClientConfig::default()
+        };
+        let forwarded = forwarded_headers(&incoming(), &config, &[]);
+        let mut names: Vec<&str> = forwarded.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["x-request-id", "x-trace-id"]);
+    }
+
+    #[test]
+    fn test_client_key_headers_are_never_forwarded() {
+        let config = ClientConfig {
+            forward_headers: vec!["*".to_string()],
+            ..ClientConfig::default()
+        };
+        let key_headers = ["X-Internal".to_string(), "Authorization".to_string()];
+        let forwarded = forwarded_headers(&incoming(), &config, &key_headers);
+        assert!(!forwarded.contains_key("x-internal"));
+        assert_eq!(forwarded.len(), 2);
+    }
+}
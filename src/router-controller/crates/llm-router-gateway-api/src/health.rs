@@ -0,0 +1,605 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Health
+use crate::circuit_breaker::{CircuitBreakerRegistry, CircuitState};
+use crate::config::{Llm, RouterConfig};
+use crate::error::GatewayApiError;
+use crate::state::AppState;
+use bytes::Bytes;
+use futures_util::future::join_all;
+use http::StatusCode;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::{Response, Uri};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HealthStatus {
+    pub status: String,
+    pub triton: bool,
+    pub llm_providers: BTreeMap<String, ProviderHealth>,
+}
+
+/// Readiness of one LLM provider endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderHealth {
+    /// Result of the live probe.
+    pub healthy: bool,
+    /// Whether the model is loaded, for LLMs with a `ready_path`. Such
+    /// providers are only healthy once it is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_ready: Option<bool>,
+    pub circuit_breaker: CircuitState,
+}
+
+impl HealthStatus {
+    fn draining() -> Self {
+        HealthStatus {
+            status: "Draining".to_string(),
+            triton: false,
+            llm_providers: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "OK" || self.status == "Degraded"
+    }
+}
+
+/// Returns the `scheme://authority` part of a Triton inference URL so the
+/// server-level readiness endpoint can be probed.
+fn triton_base_url(url: &str) -> Option<String> {
+    let uri = url.parse::<Uri>().ok()?;
+    Some(format!("{}://{}", uri.scheme_str()?, uri.authority()?))
+}
+
+fn triton_health_url(url: &str) -> Option<String> {
+    triton_base_url(url).map(|base| format!("{}/v2/health/ready", base))
+}
+
+/// Every instance of every LLM, sorted and without duplicates.
+fn provider_urls(config: &RouterConfig) -> Vec<String> {
+    let mut providers: Vec<String> = config
+        .policies
+        .iter()
+        .flat_map(|policy| policy.llms.iter().flat_map(Llm::api_bases))
+        .map(str::to_string)
+        .collect();
+    providers.sort();
+    providers.dedup();
+    providers
+}
+
+/// Every instance of every LLM with the URL of its model-ready endpoint, if
+/// any LLM served by it has a `ready_path`.
+fn provider_probes(config: &RouterConfig) -> BTreeMap<String, Option<String>> {
+    let mut providers: BTreeMap<String, Option<String>> = BTreeMap::new();
+    for llm in config.policies.iter().flat_map(|policy| &policy.llms) {
+        for api_base in llm.api_bases() {
+            let ready_url = providers.entry(api_base.to_string()).or_default();
+            if ready_url.is_none() {
+                *ready_url = llm
+                    .ready_path
+                    .as_ref()
+                    .map(|path| format!("{}{}", api_base.trim_end_matches('/'), path));
+            }
+        }
+    }
+    providers
+}
+
+async fn probe_status(
+    client: &reqwest::Client,
+    url: &str,
+    timeout: Duration,
+) -> Option<reqwest::StatusCode> {
+    match client.get(url).timeout(timeout).send().await {
+        Ok(response) => Some(response.status()),
+        Err(e) => {
+            warn!("Health probe to {} failed: {}", url, e);
+            None
+        }
+    }
+}
+
+async fn probe(client: &reqwest::Client, url: &str, timeout: Duration) -> bool {
+    probe_status(client, url, timeout)
+        .await
+        .is_some_and(|status| !status.is_server_error())
+}
+
+/// Probes a provider: `(healthy, model_ready)`. With a ready endpoint only a
+/// success status counts, as servers answer before the model is loaded.
+async fn probe_provider(
+    client: &reqwest::Client,
+    api_base: &str,
+    ready_url: Option<&str>,
+    timeout: Duration,
+) -> (bool, Option<bool>) {
+    match ready_url {
+        Some(url) => {
+            let ready = probe_status(client, url, timeout)
+                .await
+                .is_some_and(|status| status.is_success());
+            (ready, Some(ready))
+        }
+        None => (probe(client, api_base, timeout).await, None),
+    }
+}
+
+/// Probes every Triton server and unique LLM provider concurrently. Each probe
+/// is bounded by `health_check_timeout_secs`, and any probe still running when
+/// the overall `health_check_deadline_secs` passes is reported as unhealthy.
+/// An open circuit breaker on any provider degrades an otherwise OK status.
+pub async fn health_check(
+    config: &RouterConfig,
+    client: &reqwest::Client,
+    circuit_breakers: &CircuitBreakerRegistry,
+) -> HealthStatus {
+    let probe_timeout = Duration::from_secs(config.server.health_check_timeout_secs);
+    let deadline = Instant::now() + Duration::from_secs(config.server.health_check_deadline_secs);
+
+    let mut triton_urls: Vec<String> = config
+        .policies
+        .iter()
+        .filter_map(|policy| triton_health_url(&policy.url))
+        .collect();
+    triton_urls.sort();
+    triton_urls.dedup();
+
+    let providers = provider_probes(config);
+
+    let bounded_triton = |url: String| async move {
+        tokio::time::timeout_at(deadline, probe(client, &url, probe_timeout))
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Health probe to {} exceeded the overall deadline", url);
+                false
+            })
+    };
+    let bounded_provider = |(api_base, ready_url): (String, Option<String>)| async move {
+        let probe = probe_provider(client, &api_base, ready_url.as_deref(), probe_timeout);
+        tokio::time::timeout_at(deadline, probe)
+            .await
+            .unwrap_or_else(|_| {
+                warn!("Health probe to {} exceeded the overall deadline", api_base);
+                (false, ready_url.as_ref().map(|_| false))
+            })
+    };
+
+    let (triton_results, provider_results) = tokio::join!(
+        join_all(triton_urls.into_iter().map(bounded_triton)),
+        join_all(providers.clone().into_iter().map(bounded_provider)),
+    );
+
+    let triton = !triton_results.is_empty() && triton_results.iter().all(|healthy| *healthy);
+    let llm_providers: BTreeMap<String, ProviderHealth> = providers
+        .into_keys()
+        .zip(provider_results)
+        .map(|(provider, (healthy, model_ready))| {
+            let circuit_breaker = circuit_breakers.state(&provider);
+            (
+                provider,
+                ProviderHealth {
+                    healthy,
+                    model_ready,
+                    circuit_breaker,
+                },
+            )
+        })
+        .collect();
+
+    let healthy_providers = llm_providers
+        .values()
+        .filter(|provider| provider.healthy)
+        .count();
+    let any_open = llm_providers
+        .values()
+        .any(|provider| provider.circuit_breaker == CircuitState::Open);
+    let status = if !triton || healthy_providers == 0 {
+        "Unavailable"
+    } else if healthy_providers < llm_providers.len() || any_open {
+        "Degraded"
+    } else {
+        "OK"
+    };
+
+    HealthStatus {
+        status: status.to_string(),
+        triton,
+        llm_providers,
+    }
+}
+
+/// Probes every policy's Triton server and unique LLM instance once, so
+/// misconfigured URLs surface at startup rather than on the first request.
+/// Unreachable endpoints are logged; they are only returned as an error when
+/// `server.connectivity_required` is set.
+pub async fn check_connectivity(
+    config: &RouterConfig,
+    client: &reqwest::Client,
+) -> Result<(), GatewayApiError> {
+    if !config.server.validate_connectivity_on_start {
+        return Ok(());
+    }
+    let probe_timeout = Duration::from_secs(config.server.health_check_timeout_secs);
+
+    let mut tritons: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for policy in &config.policies {
+        if let Some(url) = triton_health_url(&policy.url) {
+            tritons.entry(url).or_default().push(&policy.name);
+        }
+    }
+    let targets: Vec<(String, String)> = tritons
+        .into_iter()
+        .map(|(url, policies)| (format!("Triton of policy {}", policies.join(", ")), url))
+        .chain(
+            provider_urls(config)
+                .into_iter()
+                .map(|api_base| ("LLM api_base".to_string(), api_base)),
+        )
+        .collect();
+    info!("Checking connectivity to {} endpoint(s)", targets.len());
+
+    let results = join_all(
+        targets
+            .iter()
+            .map(|(_, url)| probe(client, url, probe_timeout)),
+    )
+    .await;
+    let mut failed = Vec::new();
+    for ((target, url), reachable) in targets.iter().zip(results) {
+        if !reachable {
+            error!(
+                "Connectivity check failed: {} at {} is unreachable",
+                target, url
+            );
+            failed.push(format!("{} ({})", target, url));
+        }
+    }
+
+    if config.server.connectivity_required && !failed.is_empty() {
+        return Err(GatewayApiError::Infrastructure(format!(
+            "Unreachable at startup: {}",
+            failed.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Caches the most recent [`HealthStatus`] for `health_cache_secs`. The lock
+/// is held while probing, so a burst of concurrent readiness requests results
+/// in a single round of upstream checks.
+#[derive(Debug, Clone, Default)]
+pub struct HealthCache {
+    last: Arc<Mutex<Option<(Instant, HealthStatus)>>>,
+}
+
+impl HealthCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get_or_check(
+        &self,
+        config: &RouterConfig,
+        client: &reqwest::Client,
+        circuit_breakers: &CircuitBreakerRegistry,
+    ) -> HealthStatus {
+        let ttl = Duration::from_secs(config.server.health_cache_secs);
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, status)) = last.as_ref() {
+            if checked_at.elapsed() < ttl {
+                return status.clone();
+            }
+        }
+
+        let status = health_check(config, client, circuit_breakers).await;
+        *last = Some((Instant::now(), status.clone()));
+        status
+    }
+
+    /// Whether the last health check, if younger than `max_age`, found
+    /// `api_base` unhealthy. Never waits for a check in progress.
+    pub fn is_failing(&self, api_base: &str, max_age: Duration) -> bool {
+        let Ok(last) = self.last.try_lock() else {
+            return false;
+        };
+        last.as_ref().is_some_and(|(checked_at, status)| {
+            checked_at.elapsed() < max_age
+                && status
+                    .llm_providers
+                    .get(api_base)
+                    .is_some_and(|provider| !provider.healthy)
+        })
+    }
+}
+
+pub async fn readiness(
+    state: &AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let health_status = if state.shutdown.is_draining() {
+        HealthStatus::draining()
+    } else {
+        state
+            .health_cache
+            .get_or_check(&state.config, &state.client, &state.circuit_breakers)
+            .await
+    };
+
+    let status = if health_status.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let json_vec = serde_json::to_vec(&health_status)?;
+    let full_body = Full::from(Bytes::from(json_vec))
+        .map_err(|never| match never {})
+        .boxed();
+
+    let client_res = Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(full_body)?;
+
+    info!("/health/readiness: {health_status:?}");
+    Ok(client_res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FailureKind, Policy, ServerConfig};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn llm(name: &str, api_base: &str) -> Llm {
+        Llm {
+            name: name.to_string(),
+            api_base: api_base.to_string(),
+            api_key: "test-key".to_string(),
+            model: "meta/llama-3.1-8b-instruct".to_string(),
+            ..Llm::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_status_is_shared_across_probes() {
+        let triton = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&triton)
+            .await;
+
+        let provider = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&provider)
+            .await;
+
+        let config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: format!("{}/v2/models/router/infer", triton.uri()),
+                llms: vec![llm("provider", &provider.uri())],
+                ..Policy::default()
+            }],
+            ..RouterConfig::default()
+        };
+
+        let client = reqwest::Client::new();
+        let breakers = CircuitBreakerRegistry::new(&config.circuit_breaker);
+        let cache = HealthCache::new();
+        let statuses =
+            join_all((0..5).map(|_| cache.get_or_check(&config, &client, &breakers))).await;
+        assert!(statuses.iter().all(|status| status.status == "OK"));
+        assert_eq!(
+            cache.get_or_check(&config, &client, &breakers).await.status,
+            "OK"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_provider_is_bounded_by_deadline() {
+        let triton = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&triton)
+            .await;
+
+        let fast = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&fast)
+            .await;
+
+        let slow = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+            .mount(&slow)
+            .await;
+
+        let config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: format!("{}/v2/models/router/infer", triton.uri()),
+                llms: vec![llm("fast", &fast.uri()), llm("slow", &slow.uri())],
+                ..Policy::default()
+            }],
+            server: ServerConfig {
+                health_check_timeout_secs: 5,
+                health_check_deadline_secs: 1,
+                ..ServerConfig::default()
+            },
+            ..RouterConfig::default()
+        };
+
+        let start = Instant::now();
+        let breakers = CircuitBreakerRegistry::new(&config.circuit_breaker);
+        let status = health_check(&config, &reqwest::Client::new(), &breakers).await;
+
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(status.triton);
+        let healthy = |uri: &str| status.llm_providers.get(uri).map(|p| p.healthy);
+        assert_eq!(healthy(&fast.uri()), Some(true));
+        assert_eq!(healthy(&slow.uri()), Some(false));
+        assert_eq!(status.status, "Degraded");
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_degrades_healthy_provider() {
+        let triton = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&triton)
+            .await;
+
+        let provider = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&provider)
+            .await;
+
+        let config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: format!("{}/v2/models/router/infer", triton.uri()),
+                llms: vec![llm("provider", &provider.uri())],
+                ..Policy::default()
+            }],
+            ..RouterConfig::default()
+        };
+
+        let breakers = CircuitBreakerRegistry::new(&config.circuit_breaker);
+        let breaker = breakers.get(&provider.uri());
+        for _ in 0..config.circuit_breaker.failure_threshold {
+            breaker.record_failure(FailureKind::ServerError);
+        }
+
+        let status = health_check(&config, &reqwest::Client::new(), &breakers).await;
+        assert_eq!(status.status, "Degraded");
+        assert!(status.is_ready());
+        assert_eq!(
+            status.llm_providers.get(&provider.uri()),
+            Some(&ProviderHealth {
+                healthy: true,
+                model_ready: None,
+                circuit_breaker: CircuitState::Open,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_with_ready_path_waits_for_model() {
+        let triton = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&triton)
+            .await;
+
+        let loading = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/health/ready"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&loading)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&loading)
+            .await;
+        let loaded = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/health/ready"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&loaded)
+            .await;
+
+        let nim = |name: &str, api_base: &str| Llm {
+            ready_path: Some("/v1/health/ready".to_string()),
+            ..llm(name, api_base)
+        };
+        let config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: format!("{}/v2/models/router/infer", triton.uri()),
+                llms: vec![nim("loading", &loading.uri()), nim("loaded", &loaded.uri())],
+                ..Policy::default()
+            }],
+            ..RouterConfig::default()
+        };
+
+        let breakers = CircuitBreakerRegistry::new(&config.circuit_breaker);
+        let status = health_check(&config, &reqwest::Client::new(), &breakers).await;
+        let provider = |uri: &str| status.llm_providers[uri];
+        assert!(!provider(&loading.uri()).healthy);
+        assert_eq!(provider(&loading.uri()).model_ready, Some(false));
+        assert!(provider(&loaded.uri()).healthy);
+        assert_eq!(provider(&loaded.uri()).model_ready, Some(true));
+        assert_eq!(status.status, "Degraded");
+    }
+
+    #[tokio::test]
+    async fn test_startup_connectivity_check_names_unreachable_endpoints() {
+        let triton = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&triton)
+            .await;
+
+        let provider = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&provider)
+            .await;
+        // Nothing listens on the discard port.
+        let unreachable = "http://127.0.0.1:9";
+
+        let mut config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: format!("{}/v2/models/router/infer", triton.uri()),
+                llms: vec![llm("up", &provider.uri()), llm("down", unreachable)],
+                ..Policy::default()
+            }],
+            ..RouterConfig::default()
+        };
+        let client = reqwest::Client::new();
+        // Off by default.
+        assert!(check_connectivity(&config, &client).await.is_ok());
+
+        config.server.validate_connectivity_on_start = true;
+        assert!(check_connectivity(&config, &client).await.is_ok());
+
+        config.server.connectivity_required = true;
+        let error = check_connectivity(&config, &client).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Infrastructure Error: Unreachable at startup: LLM api_base ({})",
+                unreachable
+            )
+        );
+    }
+}
@@ -15,9 +15,40 @@
 
 //! Lib
 
+pub mod admin;
+pub mod anthropic;
+pub mod auth;
+pub mod balancer;
+pub mod batch;
+pub mod breaker_sync;
+pub mod bulkhead;
+pub mod cache;
+pub mod circuit_breaker;
+pub mod client;
+pub mod coalesce;
+pub mod cohere;
 pub mod config;
+pub mod config_manager;
+pub mod cors;
+pub mod dns;
 pub mod error;
+pub mod events;
+pub mod headers;
+pub mod health;
+pub mod logging;
 pub mod metrics;
+pub mod openmetrics;
+pub mod priority;
 pub mod proxy;
+pub mod quota;
+pub mod rate_limit;
+pub mod request_id;
+pub mod retry;
+pub mod secrets;
+pub mod shutdown;
+pub mod signature;
+pub mod state;
 pub mod stream;
+pub mod transform;
 pub mod triton;
+pub mod warmup;
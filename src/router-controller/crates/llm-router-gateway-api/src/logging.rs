@@ -0,0 +1,351 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Logging
+use crate::config::{ObservabilityConfig, RouterConfig};
+use crate::error::ConfigError;
+use crate::request_id;
+use log::{info, Level};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Resolution of `observability.sample_rate`.
+const SAMPLE_SCALE: u64 = 1_000_000;
+
+/// `observability.sample_rate` in parts per `SAMPLE_SCALE`.
+static SAMPLE_RATE: AtomicU64 = AtomicU64::new(SAMPLE_SCALE);
+
+/// Sets the fraction of requests whose info and debug lines are logged.
+pub fn set_sample_rate(rate: f64) {
+    let rate = (rate.clamp(0.0, 1.0) * SAMPLE_SCALE as f64).round() as u64;
+    SAMPLE_RATE.store(rate, Ordering::Relaxed);
+}
+
+/// Whether a request falls within `rate`, out of `SAMPLE_SCALE`. Derived
+/// from its ID alone, so every line of a request and every router instance
+/// it passes through agree.
+fn sampled(request_id: &str, rate: u64) -> bool {
+    if rate >= SAMPLE_SCALE {
+        return true;
+    }
+    let digest = openssl::sha::sha256(request_id.as_bytes());
+    let hash = u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"));
+    hash % SAMPLE_SCALE < rate
+}
+
+/// Whether the request with this ID is logged below warning level.
+pub fn is_sampled(request_id: &str) -> bool {
+    sampled(request_id, SAMPLE_RATE.load(Ordering::Relaxed))
+}
+
+/// Initializes `env_logger` (configured through `RUST_LOG`) with a format
+/// that tags every line logged while handling a request with its ID. Info
+/// and debug lines of requests outside `observability.sample_rate` are
+/// dropped once `set_sample_rate` is called.
+pub fn init() {
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let request_id = request_id::current();
+            if record.level() > Level::Warn
+                && request_id.as_deref().is_some_and(|id| !is_sampled(id))
+            {
+                return Ok(());
+            }
+            let timestamp = buf.timestamp();
+            match request_id {
+                Some(id) => writeln!(
+                    buf,
+                    "[{} {:<5} {} request_id={}] {}",
+                    timestamp,
+                    record.level(),
+                    record.target(),
+                    id,
+                    record.args()
+                ),
+                None => writeln!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    timestamp,
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ),
+            }
+        })
+        .init();
+}
+
+/// Patterns applied before any configured `redact_patterns`.
+const DEFAULT_PATTERNS: [&str; 2] = [
+    // Email addresses
+    r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+    // Phone numbers, e.g. +1 (555) 123-4567 or 555.123.4567
+    r"\+?\d{1,3}?[\s.-]?\(?\d{3}\)?[\s.-]?\d{3}[\s.-]?\d{4}",
+];
+
+/// Emits prompt and completion logs with PII and provider keys removed.
+/// Does nothing unless `observability.log_bodies` is enabled.
+#[derive(Debug, Clone)]
+pub struct BodyLogger {
+    enabled: bool,
+    patterns: Vec<Regex>,
+    secrets: Vec<String>,
+}
+
+impl BodyLogger {
+    pub fn new(config: &RouterConfig) -> Result<Self, ConfigError> {
+        let observability = &config.observability;
+        let patterns = DEFAULT_PATTERNS
+            .iter()
+            .copied()
+            .chain(observability.redact_patterns.iter().map(String::as_str))
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| ConfigError::InvalidPattern {
+                    pattern: pattern.to_string(),
+                    message: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Provider keys are scrubbed too in case a client echoes one back.
+        let mut secrets: Vec<String> = config
+            .policies
+            .iter()
+            .flat_map(|policy| policy.llms.iter().map(|llm| llm.api_key.clone()))
+            .filter(|key| !key.is_empty())
+            .collect();
+        secrets.sort();
+        secrets.dedup();
+
+        Ok(BodyLogger {
+            enabled: observability.log_bodies,
+            patterns,
+            secrets,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for secret in &self.secrets {
+            redacted = redacted.replace(secret.as_str(), REDACTED);
+        }
+        for pattern in &self.patterns {
+            redacted = pattern.replace_all(&redacted, REDACTED).into_owned();
+        }
+        redacted
+    }
+
+    /// Logs the `messages` of a chat completion request.
+    pub fn log_prompt(&self, policy: &str, llm: &str, request: &Value) {
+        if !self.enabled {
+            return;
+        }
+        let prompt = request.get("messages").cloned().unwrap_or(Value::Null);
+        self.emit("prompt", policy, llm, &prompt.to_string());
+    }
+
+    /// Logs the assistant message content of a chat completion response.
+    pub fn log_completion(&self, policy: &str, llm: &str, response: &Value) {
+        if !self.enabled {
+            return;
+        }
+        let completion = response["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default();
+        self.emit("completion", policy, llm, completion);
+    }
+
+    fn emit(&self, kind: &str, policy: &str, llm: &str, text: &str) {
+        let record = serde_json::json!({
+            "type": kind,
+            "policy": policy,
+            "llm": llm,
+            "body": self.redact(text),
+        });
+        info!(target: "llm_router::bodies", "{}", record);
+    }
+}
+
+/// One line per proxied request. Holds no credentials: the path excludes the
+/// query string, and provider keys and client tokens are never recorded.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccessLogRecord {
+    pub method: String,
+    pub path: String,
+    pub policy: Option<String>,
+    pub model: Option<String>,
+    pub api_base: Option<String>,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// Matches what `PROXY_OVERHEAD_LATENCY` records for the request.
+    pub overhead_ms: f64,
+    /// Only known for non-streaming responses when the request is logged.
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+    /// Retries of the upstream call; unset when no call was made.
+    pub retries: Option<u32>,
+}
+
+impl AccessLogRecord {
+    pub fn new(method: &str, path: &str) -> Self {
+        AccessLogRecord {
+            method: method.to_string(),
+            path: path.to_string(),
+            ..AccessLogRecord::default()
+        }
+    }
+
+    /// Copies the token counts from a chat completion's `usage`.
+    pub fn set_usage(&mut self, response: &Value) {
+        let usage = &response["usage"];
+        self.prompt_tokens = usage["prompt_tokens"].as_u64();
+        self.completion_tokens = usage["completion_tokens"].as_u64();
+        self.total_tokens = usage["total_tokens"].as_u64();
+    }
+
+    fn to_text(&self) -> String {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        let count = |value: Option<u64>| value.map_or("-".to_string(), |v| v.to_string());
+        format!(
+            "method={} path={} policy={} model={} api_base={} status={} latency_ms={:.3} overhead_ms={:.3} prompt_tokens={} completion_tokens={} total_tokens={} retries={}",
+            self.method,
+            self.path,
+            optional(&self.policy),
+            optional(&self.model),
+            optional(&self.api_base),
+            self.status,
+            self.latency_ms,
+            self.overhead_ms,
+            count(self.prompt_tokens),
+            count(self.completion_tokens),
+            count(self.total_tokens),
+            count(self.retries.map(u64::from)),
+        )
+    }
+
+    pub fn emit(&self, config: &ObservabilityConfig) {
+        if !config.access_log {
+            return;
+        }
+        let line = if config.json_logging {
+            serde_json::to_string(self).unwrap_or_default()
+        } else {
+            self.to_text()
+        };
+        info!(target: "llm_router::access", "{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Llm, ObservabilityConfig, Policy};
+
+    fn logger(redact_patterns: Vec<String>) -> BodyLogger {
+        let config = RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: "http://triton:8000".to_string(),
+                llms: vec![Llm {
+                    api_key: "nvapi-secret".to_string(),
+                    ..Llm::default()
+                }],
+                ..Policy::default()
+            }],
+            observability: ObservabilityConfig {
+                log_bodies: true,
+                redact_patterns,
+                ..ObservabilityConfig::default()
+            },
+            ..RouterConfig::default()
+        };
+        BodyLogger::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_redacts_default_custom_and_secret_values() {
+        let logger = logger(vec![r"\bACCT-\d+\b".to_string()]);
+        let redacted = logger.redact(
+            "mail jane.doe@example.com or call +1 (555) 123-4567 about ACCT-991 with nvapi-secret",
+        );
+        assert_eq!(
+            redacted,
+            "mail [REDACTED] or call [REDACTED] about [REDACTED] with [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_access_log_record_formats() {
+        let mut record = AccessLogRecord::new("POST", "/v1/chat/completions");
+        record.policy = Some("test_policy".to_string());
+        record.status = 200;
+        record.retries = Some(1);
+        record.set_usage(&serde_json::json!({
+            "usage": {"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7}
+        }));
+
+        let json: Value = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["policy"], "test_policy");
+        assert_eq!(json["total_tokens"], 7);
+        assert!(json["api_base"].is_null());
+
+        let text = record.to_text();
+        assert!(
+            text.starts_with("method=POST path=/v1/chat/completions policy=test_policy model=-")
+        );
+        assert!(text.ends_with("prompt_tokens=3 completion_tokens=4 total_tokens=7 retries=1"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let config = RouterConfig {
+            observability: ObservabilityConfig {
+                log_bodies: true,
+                redact_patterns: vec!["(".to_string()],
+                ..ObservabilityConfig::default()
+            },
+            ..RouterConfig::default()
+        };
+        assert!(matches!(
+            BodyLogger::new(&config),
+            Err(ConfigError::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_per_request_id() {
+        let ids: Vec<String> = (0..10_000).map(|i| format!("request-{}", i)).collect();
+        let kept = |rate: u64| ids.iter().filter(|id| sampled(id, rate)).count();
+        assert_eq!(kept(SAMPLE_SCALE), ids.len());
+        assert_eq!(kept(0), 0);
+        let one_percent = kept(SAMPLE_SCALE / 100);
+        assert!((50..=150).contains(&one_percent), "{}", one_percent);
+        // A request kept at some rate is kept at every higher one.
+        assert!(ids
+            .iter()
+            .filter(|id| sampled(id, SAMPLE_SCALE / 100))
+            .all(|id| sampled(id, SAMPLE_SCALE / 10)));
+    }
+}
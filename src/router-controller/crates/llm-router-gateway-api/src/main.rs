@@ -14,14 +14,19 @@
 // limitations under the License.
 
 //! Main
-use clap::{arg, command, Parser};
-use env_logger;
+use clap::Parser;
 use hyper::service::service_fn;
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use llm_router_gateway_api::config::RouterConfig;
+use llm_router_gateway_api::health::check_connectivity;
+use llm_router_gateway_api::logging;
 use llm_router_gateway_api::proxy::handler;
-use log::{error, info};
+use llm_router_gateway_api::shutdown::shutdown_signal;
+use llm_router_gateway_api::state::{AppState, ClientAddr};
+use llm_router_gateway_api::warmup::warmup;
+use log::{error, info, warn};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::net::TcpListener;
 
 #[derive(Parser, Debug)]
@@ -33,7 +38,7 @@ struct Args {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    logging::init();
     // cargo run -- --config foobar
     info!("Gateway API is active and running.");
     let args = Args::parse();
@@ -44,25 +49,77 @@ async fn main() -> anyhow::Result<()> {
             return Err(e.into());
         }
     };
+    logging::set_sample_rate(config.observability.sample_rate);
+    let drain_timeout = Duration::from_secs(config.server.drain_timeout_secs);
+    let state = match AppState::new(config) {
+        Ok(state) => state.with_config_path(&args.config_path),
+        Err(e) => {
+            error!("Failed to initialize gateway: {}", e);
+            return Err(e.into());
+        }
+    };
+    if let Err(e) = check_connectivity(&state.config, &state.client).await {
+        error!("Failed connectivity check: {}", e);
+        return Err(e.into());
+    }
+    if let Err(e) = warmup(&state.config, &state.upstream_clients).await {
+        error!("Failed to warm up LLMs: {}", e);
+        return Err(e.into());
+    }
+    if state.config.security.admin_api_key.is_none() {
+        warn!("Admin endpoints are disabled; set security.admin_api_key to enable them");
+    }
+    let shutdown = state.shutdown.clone();
+    state.cache.spawn_size_updater();
+    state.circuit_breakers.spawn_sync();
+    state
+        .config_manager
+        .spawn_watcher(&state.config.server.config_reload);
+
     let addr = SocketAddr::from(([0, 0, 0, 0], 8084));
     let listener = TcpListener::bind(addr).await?;
     info!("Listening on http://{}", addr);
 
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let io = TokioIo::new(stream);
+    // Keep accepting connections while draining so the readiness probe can
+    // report `Draining` and requests already routed here still get served.
+    let server = tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            let io = TokioIo::new(stream);
 
-        let config_clone = config.clone();
-        tokio::task::spawn(async move {
-            if let Err(err) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                .serve_connection(
-                    io,
-                    service_fn(move |req| handler(req, config_clone.clone())),
-                )
-                .await
-            {
-                error!("Error serving connection: {:?}", err);
-            }
-        });
-    }
+            let state_clone = state.clone();
+            tokio::task::spawn(async move {
+                if let Err(err) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                    .serve_connection(
+                        io,
+                        service_fn(move |mut req| {
+                            req.extensions_mut().insert(ClientAddr(peer));
+                            handler(req, state_clone.clone())
+                        }),
+                    )
+                    .await
+                {
+                    error!("Error serving connection: {:?}", err);
+                }
+            });
+        }
+    });
+
+    shutdown_signal().await;
+    info!(
+        "Shutdown requested, draining {} in-flight request(s) for up to {:?}",
+        shutdown.in_flight(),
+        drain_timeout
+    );
+    shutdown.begin_drain();
+    shutdown.wait_for_drain(drain_timeout).await;
+    server.abort();
+    info!("Gateway API stopped.");
+    Ok(())
 }
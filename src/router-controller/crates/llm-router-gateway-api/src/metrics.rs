@@ -13,18 +13,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::circuit_breaker::CircuitState;
+use crate::config::TokenPricing;
+use crate::quota::QuotaUsage;
 use lazy_static::lazy_static;
 use prometheus::{
-    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
-    Histogram, HistogramVec, IntCounter, IntCounterVec,
+    register_counter_vec, register_gauge_vec, register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    CounterVec, GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec,
 };
 use serde_json::Value;
+use std::time::Duration;
 
 lazy_static! {
     pub static ref NUM_REQUESTS: IntCounter =
         register_int_counter!("num_requests", "Total number of requests")
             .expect("Failed to create num_requests counter");
 
+    // Only registered once used, so deployments without tenant labels do
+    // not export them.
+    pub static ref NUM_REQUESTS_PER_TENANT: IntCounterVec = register_int_counter_vec!(
+        "num_requests_per_tenant",
+        "Total number of requests per tenant",
+        &["tenant"]
+    )
+    .expect("Failed to create num_requests_per_tenant counter vector");
+
     pub static ref REQUESTS_PER_POLICY: IntCounterVec = register_int_counter_vec!(
         "requests_per_policy",
         "Total number of requests per policy",
@@ -69,42 +84,334 @@ lazy_static! {
     )
     .expect("Failed to create model_selection_time histogram");
 
+    pub static ref AUTH_DURATION: Histogram = register_histogram!(
+        "auth_duration_seconds",
+        "Time (in seconds) taken to authenticate a client request"
+    )
+    .expect("Failed to create auth_duration histogram");
+
+    pub static ref SANITIZE_DURATION: Histogram = register_histogram!(
+        "sanitize_duration_seconds",
+        "Time (in seconds) taken to parse and rewrite a request body before forwarding"
+    )
+    .expect("Failed to create sanitize_duration histogram");
+
+    pub static ref UPSTREAM_CONNECT_DURATION: HistogramVec = register_histogram_vec!(
+        "upstream_connect_duration_seconds",
+        "Time (in seconds) from forwarding a request until the LLM's response headers arrived, including concurrency waits and retries",
+        &["llm"]
+    )
+    .expect("Failed to create upstream_connect_duration histogram vector");
+
     pub static ref LLM_RESPONSE_TIME: HistogramVec = register_histogram_vec!(
         "llm_response_time_seconds",
-        "Response time (in seconds) for each LLM",
-        &["llm"]
+        "Response time (in seconds) for each LLM; shadow=\"true\" for mirrored requests",
+        &["llm", "shadow"]
     )
     .expect("Failed to create llm_response_time histogram vector");
 
+    pub static ref OUTPUT_TOKENS_PER_SECOND: HistogramVec = register_histogram_vec!(
+        "llm_output_tokens_per_second",
+        "Streamed content deltas per second between the first and last one, per model",
+        &["model"],
+        vec![1.0, 5.0, 10.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 200.0, 300.0, 500.0, 1000.0]
+    )
+    .expect("Failed to create llm_output_tokens_per_second histogram vector");
+
     pub static ref TOKEN_USAGE: IntCounterVec = register_int_counter_vec!(
         "llm_token_usage",
-        "Token usage per LLM category",
-        &["llm_name", "category"]
+        "Token usage per LLM category; shadow=\"true\" for mirrored requests",
+        &["llm_name", "category", "shadow"]
     )
     .unwrap();
 
+    pub static ref TOKEN_USAGE_PER_TENANT: IntCounterVec = register_int_counter_vec!(
+        "llm_token_usage_per_tenant",
+        "Token usage per tenant, LLM and category",
+        &["tenant", "llm_name", "category"]
+    )
+    .expect("Failed to create llm_token_usage_per_tenant counter vector");
+
+    pub static ref LLM_COST_USD: CounterVec = register_counter_vec!(
+        "llm_cost_usd_total",
+        "Spend in USD from configured token prices, by client key ID and model",
+        &["api_key_id", "model"]
+    )
+    .expect("Failed to create llm_cost_usd counter vector");
+
+    pub static ref STREAM_DISCONNECTS: IntCounterVec = register_int_counter_vec!(
+        "stream_disconnects_total",
+        "Streams cancelled because the client disconnected before the LLM finished",
+        &["llm_name"]
+    )
+    .expect("Failed to create stream_disconnects counter vector");
+
     pub static ref PROXY_OVERHEAD_LATENCY: Histogram = register_histogram!(
         "proxy_overhead_latency_seconds",
         "Overhead latency of the proxy, calculated as overall latency minus model selection and LLM response time"
     )
     .expect("Failed to create proxy_overhead_latency histogram");
+
+    pub static ref EXPERIMENT_VARIANT: IntCounterVec = register_int_counter_vec!(
+        "experiment_variant_total",
+        "Number of requests routed to each experiment variant",
+        &["experiment", "variant"]
+    )
+    .expect("Failed to create experiment_variant counter vector");
+
+    pub static ref CACHE_HITS: IntCounterVec = register_int_counter_vec!(
+        "cache_hits_total",
+        "Total response cache hits, by match type (exact, semantic)",
+        &["match_type"]
+    )
+    .expect("Failed to create cache_hits counter vector");
+
+    pub static ref CACHE_MISSES: IntCounter =
+        register_int_counter!("cache_misses_total", "Total response cache misses")
+            .expect("Failed to create cache_misses counter");
+
+    pub static ref CACHE_STALENESS_DETECTED: IntCounterVec = register_int_counter_vec!(
+        "cache_staleness_detected_total",
+        "Revalidated cache hits whose content differs from a fresh response of the LLM",
+        &["llm_name"]
+    )
+    .expect("Failed to create cache_staleness_detected counter vector");
+
+    pub static ref REQUEST_COALESCED: IntCounter = register_int_counter!(
+        "request_coalesced_total",
+        "Requests served from the response of an identical in-flight request"
+    )
+    .expect("Failed to create request_coalesced counter");
+
+    pub static ref CACHE_SIZE: IntGauge =
+        register_int_gauge!("cache_size", "Number of entries in the response cache")
+            .expect("Failed to create cache_size gauge");
+
+    pub static ref CACHE_COMPRESSED_BYTES: IntGauge = register_int_gauge!(
+        "cache_compressed_bytes",
+        "Bytes held by compressed response cache bodies"
+    )
+    .expect("Failed to create cache_compressed_bytes gauge");
+
+    pub static ref CACHE_UNCOMPRESSED_BYTES: IntGauge = register_int_gauge!(
+        "cache_uncompressed_bytes",
+        "Size before compression of the compressed response cache bodies"
+    )
+    .expect("Failed to create cache_uncompressed_bytes gauge");
+
+    pub static ref WARMUP_DURATION: GaugeVec = register_gauge_vec!(
+        "warmup_duration_seconds",
+        "Duration of the slowest startup warmup request per model",
+        &["model"]
+    )
+    .expect("Failed to create warmup_duration gauge vector");
+
+    pub static ref PROVIDER_AUTH_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "provider_auth_failures_total",
+        "Upstream 401 and 403 responses, i.e. rejected provider keys",
+        &["llm_name"]
+    )
+    .expect("Failed to create provider_auth_failures counter vector");
+
+    pub static ref REGION_FAILOVER: IntCounterVec = register_int_counter_vec!(
+        "region_failover_total",
+        "Requests sent to another region because no instance in the local region was available",
+        &["from", "to"]
+    )
+    .expect("Failed to create region_failover counter vector");
+
+    pub static ref CONCURRENCY_REJECTED: IntCounterVec = register_int_counter_vec!(
+        "concurrency_rejected_total",
+        "Requests rejected because a concurrency limit was reached",
+        &["llm_name"]
+    )
+    .expect("Failed to create concurrency_rejected counter vector");
+
+    pub static ref QUEUE_DEPTH: IntGaugeVec = register_int_gauge_vec!(
+        "queue_depth",
+        "Requests waiting in the priority queue, by priority",
+        &["priority"]
+    )
+    .expect("Failed to create queue_depth gauge vector");
+
+    pub static ref QUEUE_WAIT: HistogramVec = register_histogram_vec!(
+        "queue_wait_seconds",
+        "Time requests spent in the priority queue, by priority",
+        &["priority"]
+    )
+    .expect("Failed to create queue_wait histogram vector");
+
+    pub static ref CIRCUIT_BREAKER_OPEN: IntCounterVec = register_int_counter_vec!(
+        "circuit_breaker_open_total",
+        "Number of times the circuit breaker of an upstream endpoint opened",
+        &["endpoint"]
+    )
+    .expect("Failed to create circuit_breaker_open counter vector");
+
+    pub static ref CIRCUIT_BREAKER_STATE_DURATION: HistogramVec = register_histogram_vec!(
+        "circuit_breaker_state_duration_seconds",
+        "Time (in seconds) an upstream endpoint's circuit breaker spent in a state, recorded when it left it",
+        &["endpoint", "state"],
+        vec![1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0, 21600.0, 86400.0]
+    )
+    .expect("Failed to create circuit_breaker_state_duration histogram vector");
+
+    pub static ref SERVED_FALLBACK_RESPONSE: IntCounterVec = register_int_counter_vec!(
+        "served_fallback_response_total",
+        "Requests answered with a policy's fallback_response because every instance of the chosen LLM failed",
+        &["policy"]
+    )
+    .expect("Failed to create served_fallback_response counter vector");
+
+    pub static ref HEDGED_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "hedged_requests_total",
+        "Requests also sent to a second instance of the LLM after the policy's hedge_after_ms",
+        &["llm", "winner"]
+    )
+    .expect("Failed to create hedged_requests counter vector");
+
+    pub static ref MODEL_SELECTION_REJECTED: IntCounterVec = register_int_counter_vec!(
+        "model_selection_rejected_total",
+        "Requests for which an LLM outside the policy's allowed_models was selected",
+        &["policy"]
+    )
+    .expect("Failed to create model_selection_rejected counter vector");
+
+    pub static ref CIRCUIT_BREAKER_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "circuit_breaker_state",
+        "Circuit breaker state per upstream endpoint (0 closed, 1 half-open, 2 open)",
+        &["endpoint"]
+    )
+    .expect("Failed to create circuit_breaker_state gauge vector");
 }
 
 pub fn track_token_usage(json: &Value, llm_name: &str) {
+    record_token_usage(json, llm_name, "false");
+}
+
+/// Records token usage of a mirrored request under `shadow="true"`.
+pub fn track_shadow_token_usage(json: &Value, llm_name: &str) {
+    record_token_usage(json, llm_name, "true");
+}
+
+/// Label for requests not authenticated with a configured client key, which
+/// keeps `api_key_id` bounded to the configured keys.
+pub const ANONYMOUS_KEY_ID: &str = "anonymous";
+
+/// Charges the cost of a response to a client key ID.
+#[derive(Debug, Clone)]
+pub struct CostUsage {
+    pub api_key_id: String,
+    pub model: String,
+    pub pricing: TokenPricing,
+}
+
+impl CostUsage {
+    pub fn record(&self, json: &Value) {
+        let usage = &json["usage"];
+        let prompt = usage["prompt_tokens"].as_u64().unwrap_or(0);
+        let completion = usage["completion_tokens"].as_u64().unwrap_or(0);
+        if prompt + completion == 0 {
+            return;
+        }
+        LLM_COST_USD
+            .with_label_values(&[self.api_key_id.as_str(), self.model.as_str()])
+            .inc_by(self.pricing.cost(prompt, completion));
+    }
+}
+
+/// Publishes a breaker transition to `circuit_breaker_state`, counting
+/// openings in `circuit_breaker_open_total` and the time spent in the state
+/// left in `circuit_breaker_state_duration_seconds`.
+pub fn update_circuit_breaker_status(
+    endpoint: &str,
+    left: CircuitState,
+    time_in_left: Duration,
+    state: CircuitState,
+) {
+    CIRCUIT_BREAKER_STATE_DURATION
+        .with_label_values(&[endpoint, left.as_str()])
+        .observe(time_in_left.as_secs_f64());
+    let value = match state {
+        CircuitState::Closed => 0,
+        CircuitState::HalfOpen => 1,
+        CircuitState::Open => {
+            CIRCUIT_BREAKER_OPEN.with_label_values(&[endpoint]).inc();
+            2
+        }
+    };
+    CIRCUIT_BREAKER_STATE
+        .with_label_values(&[endpoint])
+        .set(value);
+}
+
+/// Everything charged for the tokens of one response: `llm_token_usage`,
+/// the client's quota, its cost and the tenant's usage.
+#[derive(Debug, Clone)]
+pub struct UsageAccounting {
+    pub llm_name: String,
+    pub quota: Option<QuotaUsage>,
+    pub cost: Option<CostUsage>,
+    pub tenant: Option<String>,
+}
+
+impl UsageAccounting {
+    pub fn new(llm_name: &str) -> Self {
+        UsageAccounting {
+            llm_name: llm_name.to_string(),
+            quota: None,
+            cost: None,
+            tenant: None,
+        }
+    }
+
+    /// Charges the `usage` of the response the client got. Callers record
+    /// each response once: upstream attempts that were retried are never
+    /// passed here, and a stream is charged for the last usage it reported.
+    pub fn record(&self, json: &Value) {
+        if !json["usage"].is_object() {
+            return;
+        }
+        track_token_usage(json, &self.llm_name);
+        if let Some(quota) = &self.quota {
+            quota.record(json);
+        }
+        if let Some(cost) = &self.cost {
+            cost.record(json);
+        }
+        if let Some(tenant) = &self.tenant {
+            track_tenant_token_usage(json, &self.llm_name, tenant);
+        }
+    }
+}
+
+/// Records token usage of a request under its tenant label.
+pub fn track_tenant_token_usage(json: &Value, llm_name: &str, tenant: &str) {
+    let usage = &json["usage"];
+    for category in ["prompt", "completion", "total"] {
+        if let Some(tokens) = usage[format!("{}_tokens", category)].as_u64() {
+            TOKEN_USAGE_PER_TENANT
+                .with_label_values(&[tenant, llm_name, category])
+                .inc_by(tokens);
+        }
+    }
+}
+
+fn record_token_usage(json: &Value, llm_name: &str, shadow: &str) {
     if let Some(usage) = json.get("usage") {
         if let Some(prompt) = usage["prompt_tokens"].as_u64() {
             TOKEN_USAGE
-                .with_label_values(&[llm_name, "prompt"])
+                .with_label_values(&[llm_name, "prompt", shadow])
                 .inc_by(prompt);
         }
         if let Some(completion) = usage["completion_tokens"].as_u64() {
             TOKEN_USAGE
-                .with_label_values(&[llm_name, "completion"])
+                .with_label_values(&[llm_name, "completion", shadow])
                 .inc_by(completion);
         }
         if let Some(total) = usage["total_tokens"].as_u64() {
             TOKEN_USAGE
-                .with_label_values(&[llm_name, "total"])
+                .with_label_values(&[llm_name, "total", shadow])
                 .inc_by(total);
         }
     }
@@ -0,0 +1,270 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenMetrics
+//!
+//! The prometheus crate only encodes the legacy text format, so the
+//! OpenMetrics 1.0 exposition is written here from the gathered families.
+use http::header::ACCEPT;
+use http::HeaderMap;
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use std::fmt::Write;
+
+pub const OPENMETRICS_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const OPENMETRICS_MEDIA_TYPE: &str = "application/openmetrics-text";
+const TEXT_MEDIA_TYPE: &str = "text/plain";
+
+/// Whether the `Accept` header ranks OpenMetrics at least as high as the
+/// legacy text format. Requests without an OpenMetrics entry get the legacy
+/// format, so existing scrapers are unaffected.
+pub fn prefers_openmetrics(headers: &HeaderMap) -> bool {
+    let mut openmetrics_q = 0.0;
+    let mut text_q = 0.0;
+    for value in headers.get_all(ACCEPT) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for range in value.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let q = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            match media_type.as_str() {
+                OPENMETRICS_MEDIA_TYPE => openmetrics_q = f64::max(openmetrics_q, q),
+                TEXT_MEDIA_TYPE => text_q = f64::max(text_q, q),
+                _ => {}
+            }
+        }
+    }
+    openmetrics_q > 0.0 && openmetrics_q >= text_q
+}
+
+/// Encodes `families` in the OpenMetrics text format, terminated by `# EOF`.
+/// Counter families drop a `_total` suffix from their name and their samples
+/// gain it, as the format requires.
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let metric_type = family.get_field_type();
+        let name = match metric_type {
+            MetricType::COUNTER => family
+                .get_name()
+                .strip_suffix("_total")
+                .unwrap_or(family.get_name()),
+            _ => family.get_name(),
+        };
+        let type_name = match metric_type {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", name, type_name);
+        if !family.get_help().is_empty() {
+            let _ = writeln!(out, "# HELP {} {}", name, escape(family.get_help()));
+        }
+
+        for metric in family.get_metric() {
+            match metric_type {
+                MetricType::COUNTER => {
+                    let value = metric.get_counter().get_value();
+                    write_sample(&mut out, name, "_total", metric, None, value);
+                }
+                MetricType::GAUGE => {
+                    let value = metric.get_gauge().get_value();
+                    write_sample(&mut out, name, "", metric, None, value);
+                }
+                MetricType::UNTYPED => {
+                    let value = metric.get_untyped().get_value();
+                    write_sample(&mut out, name, "", metric, None, value);
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let mut inf_seen = false;
+                    for bucket in histogram.get_bucket() {
+                        let upper_bound = bucket.get_upper_bound();
+                        inf_seen |= upper_bound == f64::INFINITY;
+                        write_sample(
+                            &mut out,
+                            name,
+                            "_bucket",
+                            metric,
+                            Some(("le", &format_float(upper_bound))),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    if !inf_seen {
+                        write_sample(
+                            &mut out,
+                            name,
+                            "_bucket",
+                            metric,
+                            Some(("le", "+Inf")),
+                            count,
+                        );
+                    }
+                    write_sample(&mut out, name, "_count", metric, None, count);
+                    let sum = histogram.get_sample_sum();
+                    write_sample(&mut out, name, "_sum", metric, None, sum);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        write_sample(
+                            &mut out,
+                            name,
+                            "",
+                            metric,
+                            Some(("quantile", &format_float(quantile.get_quantile()))),
+                            quantile.get_value(),
+                        );
+                    }
+                    let count = summary.get_sample_count() as f64;
+                    write_sample(&mut out, name, "_count", metric, None, count);
+                    let sum = summary.get_sample_sum();
+                    write_sample(&mut out, name, "_sum", metric, None, sum);
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    metric: &Metric,
+    extra_label: Option<(&str, &str)>,
+    value: f64,
+) {
+    out.push_str(name);
+    out.push_str(suffix);
+    write_labels(out, metric.get_label(), extra_label);
+    out.push(' ');
+    out.push_str(&format_float(value));
+    let timestamp_ms = metric.get_timestamp_ms();
+    if timestamp_ms != 0 {
+        // OpenMetrics timestamps are in seconds.
+        let _ = write!(out, " {}", timestamp_ms as f64 / 1000.0);
+    }
+    out.push('\n');
+}
+
+fn write_labels(out: &mut String, labels: &[LabelPair], extra_label: Option<(&str, &str)>) {
+    let pairs: Vec<(&str, &str)> = labels
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .chain(extra_label)
+        .collect();
+    if pairs.is_empty() {
+        return;
+    }
+    out.push('{');
+    for (i, (name, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}=\"{}\"", name, escape(value));
+    }
+    out.push('}');
+}
+
+/// Escapes backslashes, double quotes and newlines in label values and help.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Floats in their canonical OpenMetrics form, e.g. `1.0`, `0.005`, `+Inf`.
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use prometheus::{CounterVec, Histogram, HistogramOpts, IntGauge, Opts, Registry};
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_negotiation_defaults_to_legacy_text() {
+        assert!(!prefers_openmetrics(&HeaderMap::new()));
+        assert!(!prefers_openmetrics(&accept("*/*")));
+        assert!(!prefers_openmetrics(&accept("text/plain;version=0.0.4")));
+        assert!(!prefers_openmetrics(&accept(
+            "application/openmetrics-text;q=0.3,text/plain;q=0.5"
+        )));
+        assert!(prefers_openmetrics(&accept(
+            "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1"
+        )));
+    }
+
+    #[test]
+    fn test_encodes_openmetrics_conventions() {
+        let registry = Registry::new();
+        let cost =
+            CounterVec::new(Opts::new("cost_usd_total", "Spend in \"USD\""), &["model"]).unwrap();
+        cost.with_label_values(&["llama"]).inc_by(1.5);
+        let size = IntGauge::new("cache_size", "Entries").unwrap();
+        size.set(3);
+        let latency = Histogram::with_opts(
+            HistogramOpts::new("latency_seconds", "Latency").buckets(vec![1.0]),
+        )
+        .unwrap();
+        latency.observe(0.5);
+        registry.register(Box::new(cost)).unwrap();
+        registry.register(Box::new(size)).unwrap();
+        registry.register(Box::new(latency)).unwrap();
+
+        let encoded = encode(&registry.gather());
+        assert_eq!(
+            encoded,
+            "# TYPE cache_size gauge\n\
+             # HELP cache_size Entries\n\
+             cache_size 3.0\n\
+             # TYPE cost_usd counter\n\
+             # HELP cost_usd Spend in \\\"USD\\\"\n\
+             cost_usd_total{model=\"llama\"} 1.5\n\
+             # TYPE latency_seconds histogram\n\
+             # HELP latency_seconds Latency\n\
+             latency_seconds_bucket{le=\"1.0\"} 1.0\n\
+             latency_seconds_bucket{le=\"+Inf\"} 1.0\n\
+             latency_seconds_count 1.0\n\
+             latency_seconds_sum 0.5\n\
+             # EOF\n"
+        );
+    }
+}
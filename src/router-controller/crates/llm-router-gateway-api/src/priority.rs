@@ -0,0 +1,262 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Priority
+use crate::config::{Priority, PriorityQueueConfig};
+use crate::metrics::{QUEUE_DEPTH, QUEUE_WAIT};
+use http::HeaderMap;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+const PRIORITIES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Priority named by the configured header. Missing or unknown values are
+/// `normal`.
+pub fn request_priority(headers: &HeaderMap, settings: &PriorityQueueConfig) -> Priority {
+    let value = headers
+        .get(settings.header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    PRIORITIES
+        .into_iter()
+        .find(|priority| priority.as_str().eq_ignore_ascii_case(value.trim()))
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    in_flight: usize,
+    next_id: u64,
+    waiters: [VecDeque<Waiter>; 3],
+}
+
+/// A fixed number of workers fed from one queue per priority. A freed worker
+/// always goes to the oldest request of the highest non-empty priority.
+#[derive(Debug)]
+pub struct PriorityQueue {
+    settings: PriorityQueueConfig,
+    state: Arc<Mutex<State>>,
+}
+
+/// A worker held by one request, handed to the next queued request when
+/// dropped.
+#[derive(Debug)]
+pub struct WorkerSlot {
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        release(&self.state);
+    }
+}
+
+/// Gives the worker to the next queued request, or frees it.
+fn release(state: &Mutex<State>) {
+    let mut state = state.lock().expect("priority queue lock poisoned");
+    for priority in PRIORITIES {
+        while let Some(waiter) = state.waiters[priority.index()].pop_front() {
+            QUEUE_DEPTH.with_label_values(&[priority.as_str()]).dec();
+            // Fails only when the request is gone; try the next one.
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+    }
+    state.in_flight -= 1;
+}
+
+/// Leaves the queue when dropped without having been woken, e.g. on timeout
+/// or when the client disconnects.
+struct QueueTicket<'a> {
+    state: &'a Mutex<State>,
+    priority: Priority,
+    id: u64,
+    woken: oneshot::Receiver<()>,
+    granted: bool,
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        let mut state = self.state.lock().expect("priority queue lock poisoned");
+        let queue = &mut state.waiters[self.priority.index()];
+        if let Some(position) = queue.iter().position(|waiter| waiter.id == self.id) {
+            queue.remove(position);
+            QUEUE_DEPTH
+                .with_label_values(&[self.priority.as_str()])
+                .dec();
+            return;
+        }
+        drop(state);
+        // Woken just as it gave up: pass the worker on.
+        if self.woken.try_recv().is_ok() {
+            release(self.state);
+        }
+    }
+}
+
+impl PriorityQueue {
+    pub fn new(settings: &PriorityQueueConfig) -> Self {
+        PriorityQueue {
+            settings: settings.clone(),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Priority of a request per the configured header.
+    pub fn priority_of(&self, headers: &HeaderMap) -> Priority {
+        request_priority(headers, &self.settings)
+    }
+
+    /// Takes a worker, waiting behind requests of the same or a higher
+    /// priority. `None` once `max_queue_wait_ms` has passed.
+    pub async fn acquire(&self, priority: Priority) -> Option<WorkerSlot> {
+        let start = Instant::now();
+        let (id, woken) = {
+            let mut state = self.state.lock().expect("priority queue lock poisoned");
+            if state.in_flight < self.settings.workers {
+                state.in_flight += 1;
+                QUEUE_WAIT
+                    .with_label_values(&[priority.as_str()])
+                    .observe(0.0);
+                return Some(self.slot());
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            let (wake, woken) = oneshot::channel();
+            state.waiters[priority.index()].push_back(Waiter { id, wake });
+            QUEUE_DEPTH.with_label_values(&[priority.as_str()]).inc();
+            (id, woken)
+        };
+
+        let mut ticket = QueueTicket {
+            state: &self.state,
+            priority,
+            id,
+            woken,
+            granted: false,
+        };
+        let max_wait = Duration::from_millis(self.settings.max_queue_wait_ms);
+        let woken = tokio::time::timeout(max_wait, &mut ticket.woken).await;
+        QUEUE_WAIT
+            .with_label_values(&[priority.as_str()])
+            .observe(start.elapsed().as_secs_f64());
+        match woken {
+            Ok(Ok(())) => {
+                ticket.granted = true;
+                Some(self.slot())
+            }
+            _ => None,
+        }
+    }
+
+    fn slot(&self) -> WorkerSlot {
+        WorkerSlot {
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn settings(workers: usize, max_queue_wait_ms: u64) -> PriorityQueueConfig {
+        PriorityQueueConfig {
+            header: "X-Priority".to_string(),
+            workers,
+            max_queue_wait_ms,
+        }
+    }
+
+    fn queue(workers: usize, max_queue_wait_ms: u64) -> Arc<PriorityQueue> {
+        Arc::new(PriorityQueue::new(&settings(workers, max_queue_wait_ms)))
+    }
+
+    #[test]
+    fn test_priority_from_header() {
+        let settings = settings(1, 0);
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_priority(&headers, &settings), Priority::Normal);
+        headers.insert("x-priority", HeaderValue::from_static("HIGH"));
+        assert_eq!(request_priority(&headers, &settings), Priority::High);
+        headers.insert("x-priority", HeaderValue::from_static("urgent"));
+        assert_eq!(request_priority(&headers, &settings), Priority::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_is_dequeued_first() {
+        let queue = queue(1, 5_000);
+        let slot = queue.acquire(Priority::Normal).await.unwrap();
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for priority in [Priority::Low, Priority::Normal, Priority::High] {
+            let queue = queue.clone();
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _slot = queue.acquire(priority).await.unwrap();
+                order_tx.send(priority).unwrap();
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        drop(slot);
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            order.push(order_rx.recv().await.unwrap());
+        }
+        assert_eq!(order, [Priority::High, Priority::Normal, Priority::Low]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_over_limit_gives_up_and_frees_queue() {
+        let queue = queue(1, 20);
+        let slot = queue.acquire(Priority::High).await.unwrap();
+        assert!(queue.acquire(Priority::Low).await.is_none());
+
+        drop(slot);
+        assert!(queue.acquire(Priority::Low).await.is_some());
+    }
+}
@@ -14,46 +14,180 @@
 // limitations under the License.
 
 //! Proxy
-use crate::config::{Policy, RouterConfig};
-use crate::error::{GatewayApiError, IntoResponse};
+use crate::admin::{
+    cache_stats, effective_config, json_response, purge_cache, quota_status, reload_config,
+    routing_events,
+};
+use crate::anthropic::{
+    convert_response_body, to_openai_request, AnthropicStream, CHAT_COMPLETIONS_PATH, MESSAGES_PATH,
+};
+use crate::auth::{
+    authenticate_client_key, is_admin_request_authorized, is_metrics_request_authorized,
+    provided_client_key, without_client_key_params, ExternalAuthDuration, HmacLayer,
+};
+use crate::batch::{self, BatchItem};
+use crate::bulkhead::{Bulkhead, ConcurrencyPermit};
+use crate::cache::{
+    accepts_encoding, compute_embedding, content_hash, generate_embeddings_key,
+    generate_policy_key, generate_scope, is_cacheable, CachedResponse,
+};
+use crate::circuit_breaker::{CircuitBreakerRegistry, CircuitState};
+use crate::coalesce::{wait_for_leader, Flight};
+use crate::config::{
+    ApiKeys, BatchFailurePolicy, DefaultParams, Experiment, ExperimentVariant, FailureKind, Llm,
+    LoadBalancingConfig, ObservabilityConfig, Policy, RateLimitConfig, RouterConfig,
+    SecurityConfig, SystemPromptConfig, SystemPromptMode, TokenEstimator,
+};
+use crate::cors;
+use crate::error::{GatewayApiError, IntoResponse, RoutingErrorType};
+use crate::events::RoutingEvent;
+use crate::headers::forwarded_headers;
+use crate::health::readiness;
+use crate::logging::AccessLogRecord;
 use crate::metrics::{
-    track_token_usage, LLM_RESPONSE_TIME, MODEL_SELECTION_TIME, NUM_REQUESTS,
-    PROXY_OVERHEAD_LATENCY, REQUESTS_PER_MODEL, REQUESTS_PER_POLICY, REQUEST_FAILURE,
-    REQUEST_LATENCY, REQUEST_SUCCESS, ROUTING_POLICY_USAGE,
+    track_shadow_token_usage, CostUsage, UsageAccounting, ANONYMOUS_KEY_ID, AUTH_DURATION,
+    CACHE_HITS, CACHE_MISSES, CACHE_STALENESS_DETECTED, EXPERIMENT_VARIANT, HEDGED_REQUESTS,
+    LLM_RESPONSE_TIME, MODEL_SELECTION_REJECTED, MODEL_SELECTION_TIME, NUM_REQUESTS,
+    NUM_REQUESTS_PER_TENANT, PROVIDER_AUTH_FAILURES, PROXY_OVERHEAD_LATENCY, REQUESTS_PER_MODEL,
+    REQUESTS_PER_POLICY, REQUEST_COALESCED, REQUEST_FAILURE, REQUEST_LATENCY, REQUEST_SUCCESS,
+    ROUTING_POLICY_USAGE, SANITIZE_DURATION, SERVED_FALLBACK_RESPONSE, UPSTREAM_CONNECT_DURATION,
+};
+use crate::openmetrics;
+use crate::rate_limit::{
+    client_ip, RateLimiter, RATE_LIMIT_SCOPE_GLOBAL, RATE_LIMIT_SCOPE_HEADER,
+    RATE_LIMIT_SCOPE_POLICY,
 };
-use crate::stream::ReqwestStreamAdapter;
+use crate::request_id::{self, REQUEST_ID_HEADER};
+use crate::retry::{with_retry, SendError};
+use crate::state::{AppState, ClientAddr};
+use crate::stream::{estimate_prompt_tokens, ReqwestStreamAdapter};
+use crate::transform::{from_provider_response_body, to_provider_request};
 use crate::triton::{InferInputTensor, InferInputs, Output};
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use flate2::write::GzEncoder;
+use futures_util::stream::{self, StreamExt};
 use http::StatusCode;
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
-use hyper::body::Incoming;
+use hyper::body::Body;
 use hyper::{Method, Request, Response, Uri};
-use log::{debug, error, info};
+use log::{debug, error, info, trace, warn};
 use prometheus::{gather, Encoder, TextEncoder};
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use rand::Rng;
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH,
+    CONTENT_TYPE, RETRY_AFTER, VARY,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::future::Future;
+use std::io::Write;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// OpenAI embeddings, routed like completions.
+pub const EMBEDDINGS_PATH: &str = "/v1/embeddings";
+
+/// Durations of a request, added when `observability.server_timing` is on.
+const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Set on responses that did not come from an LLM because it failed.
+pub const DEGRADED_HEADER: &str = "x-degraded";
+
+/// Upstream timeout a client asks for, in milliseconds.
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+/// Sent by clients that accept duplicate processing of a request, which lets
+/// policies with `hedge_after_ms` hedge it.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Multiple of an instance's latency average it is given before a request
+/// to it is hedged.
+const HEDGE_LATENCY_FACTOR: f64 = 2.0;
+
 fn print_config(config: &RouterConfig) {
     debug!("{:#?}", config);
 }
 
-fn extract_forward_uri_path_and_query(req: &Request<Incoming>) -> Result<Uri, GatewayApiError> {
-    let uri = req
-        .uri()
-        .path_and_query()
-        .map(|x| x.as_str())
-        .unwrap_or("")
-        .to_string()
-        .parse::<Uri>()
+/// Path and query sent upstream, without any client key query parameter.
+fn forward_uri_path_and_query(path: &str, query: Option<&str>) -> Result<Uri, GatewayApiError> {
+    let query = query
+        .map(without_client_key_params)
+        .filter(|query| !query.is_empty());
+    let uri = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    uri.parse::<Uri>()
         .map_err(|e| GatewayApiError::InvalidRequest {
             message: format!("Invalid URI: {}", e),
-        })?;
+        })
+}
 
-    Ok(uri)
+fn request_too_large(limit: u64) -> GatewayApiError {
+    GatewayApiError::client_error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Request body exceeds the maximum of {} bytes", limit),
+        "request_too_large",
+    )
+}
+
+/// Buffers the request body, failing with 413 as soon as it grows past `limit`.
+async fn read_request_body<B>(body: B, limit: Option<u64>) -> Result<Bytes, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    let Some(limit) = limit else {
+        return Ok(body.collect().await?.to_bytes());
+    };
+
+    let mut body = std::pin::pin!(body);
+    let mut buffer = BytesMut::new();
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame?.into_data() {
+            if (buffer.len() + data.len()) as u64 > limit {
+                return Err(request_too_large(limit));
+            }
+            buffer.extend_from_slice(&data);
+        }
+    }
+    Ok(buffer.freeze())
+}
+
+/// Buffers an upstream response body, aborting once it grows past `limit`
+/// rather than holding an oversized response in memory.
+async fn read_response_body(
+    mut response: reqwest::Response,
+    limit: Option<u64>,
+    provider: &str,
+) -> Result<Bytes, GatewayApiError> {
+    let Some(limit) = limit else {
+        return Ok(response.bytes().await?);
+    };
+
+    let too_large = || {
+        error!("Response from {} exceeds {} bytes", provider, limit);
+        GatewayApiError::llm_error(
+            StatusCode::BAD_GATEWAY,
+            format!("LLM response exceeds the maximum of {} bytes", limit),
+            provider,
+        )
+    };
+
+    if response.content_length().is_some_and(|len| len > limit) {
+        return Err(too_large());
+    }
+
+    let mut buffer = BytesMut::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (buffer.len() + chunk.len()) as u64 > limit {
+            return Err(too_large());
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer.freeze())
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -82,6 +216,20 @@ fn get_last_message_for_triton(messages: &Messages) -> String {
         .unwrap_or_default()
 }
 
+/// Text of an embeddings request's `input` for Triton. Pre-tokenized input
+/// has no text to classify.
+fn get_embeddings_input_for_triton(value: &Value) -> String {
+    match value.get("input") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(inputs)) => inputs
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
 fn shorten_string(s: &str, max_length: usize) -> String {
     let len = s.len();
     if len <= max_length {
@@ -97,8 +245,46 @@ async fn choose_model(
     text_input: &str,
     _threshold: f64,
 ) -> Result<usize, GatewayApiError> {
+    let scores = classify(policy, client, text_input).await?;
+    highest_score_index(&biased_scores(policy, &scores))
+}
+
+/// Adds each LLM's `bias` to its classifier score.
+fn biased_scores(policy: &Policy, scores: &[f64]) -> Vec<f64> {
+    scores
+        .iter()
+        .enumerate()
+        .map(|(index, score)| score + policy.llms.get(index).map_or(0.0, |llm| llm.bias))
+        .collect()
+}
+
+fn highest_score_index(scores: &[f64]) -> Result<usize, GatewayApiError> {
+    let model_index = scores
+        .iter()
+        .enumerate()
+        .max_by(|&(_, a), &(_, b)| a.partial_cmp(b).unwrap())
+        .map(|(idx, _)| idx)
+        .ok_or_else(|| {
+            error!("Invalid probability distribution from Triton");
+            GatewayApiError::TritonServiceError {
+                status_code: 500,
+                message: "Could not determine model selection from probability distribution"
+                    .to_string(),
+            }
+        })?;
+
+    info!("model_index chosen by classifier: {:#?}", model_index);
+    Ok(model_index)
+}
+
+/// Returns the classifier's score for each LLM of `policy`, in order.
+async fn classify(
+    policy: &Policy,
+    client: &reqwest::Client,
+    text_input: &str,
+) -> Result<Vec<f64>, GatewayApiError> {
     info!("Using policy: {}", &policy.name);
-    info!("Triton input text: {:#?}", &text_input);
+    trace!("Triton input text: {:#?}", &text_input);
     let text_tensor = InferInputTensor {
         name: "INPUT".to_string(),
         datatype: "BYTES".to_string(),
@@ -166,23 +352,56 @@ async fn choose_model(
                 message: "No outputs returned from the Triton response".to_string(),
             })?;
 
-    let model_index = output_tensor
-        .data
-        .iter()
-        .enumerate()
-        .max_by(|&(_, a), &(_, b)| a.partial_cmp(b).unwrap())
-        .map(|(idx, _)| idx)
-        .ok_or_else(|| {
-            error!("Invalid probability distribution from Triton");
-            GatewayApiError::TritonServiceError {
-                status_code: 500,
-                message: "Could not determine model selection from probability distribution"
-                    .to_string(),
-            }
-        })?;
+    Ok(output_tensor.data.clone())
+}
 
-    info!("model_index chosen by classifier: {:#?}", model_index);
-    Ok(model_index)
+/// Picks a variant when the request names an experiment instead of a policy.
+fn choose_experiment_variant<'a>(
+    config: &'a RouterConfig,
+    json: &Value,
+) -> Option<(&'a Experiment, &'a ExperimentVariant)> {
+    let experiment = extract_nim_llm_router_params(json)
+        .and_then(|params| config.get_experiment_by_name(&params.policy))?;
+    let roll = rand::thread_rng().gen_range(0..experiment.total_weight());
+    experiment
+        .choose_variant(roll)
+        .map(|variant| (experiment, variant))
+}
+
+/// Names `default_policy` in requests that name no policy, routing them with
+/// the classifier unless they pin a model.
+fn apply_default_policy(mut json: Value, default_policy: Option<&str>) -> Value {
+    let Some(default_policy) = default_policy else {
+        return json;
+    };
+    if !json.is_object() || json["nim-llm-router"]["policy"].is_string() {
+        return json;
+    }
+    if !json["nim-llm-router"].is_object() {
+        json["nim-llm-router"] = Value::Object(Default::default());
+    }
+    let params = &mut json["nim-llm-router"];
+    params["policy"] = Value::String(default_policy.to_string());
+    let pinned = params.get("model").is_some() || params.get("llm_name").is_some();
+    if params.get("routing_strategy").is_none() && !pinned {
+        params["routing_strategy"] = Value::String("triton".to_string());
+    }
+    json
+}
+
+/// The session header if present, otherwise the client IP.
+fn session_key(parts: &http::request::Parts, config: &LoadBalancingConfig) -> Option<String> {
+    parts
+        .headers
+        .get(config.session_header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| {
+            parts
+                .extensions
+                .get::<ClientAddr>()
+                .map(|addr| addr.0.ip().to_string())
+        })
 }
 
 fn modify_model(value: Value, model: &str) -> Result<Value, GatewayApiError> {
@@ -191,6 +410,50 @@ fn modify_model(value: Value, model: &str) -> Result<Value, GatewayApiError> {
     Ok(json)
 }
 
+/// Sets the `X-LLM-Router-*` headers from the routing decision when
+/// `observability.routing_headers` is on. Headers of those names from the
+/// upstream are dropped either way.
+fn set_routing_headers(
+    headers: &mut HeaderMap,
+    config: &ObservabilityConfig,
+    access: &AccessLogRecord,
+    cache_hit: bool,
+) {
+    let cache = access
+        .policy
+        .as_ref()
+        .map(|_| if cache_hit { "hit" } else { "miss" });
+    let retries = access.retries.map(|retries| retries.to_string());
+    let values = [
+        ("X-LLM-Router-Policy", access.policy.as_deref()),
+        ("X-LLM-Router-Model", access.model.as_deref()),
+        ("X-LLM-Router-Upstream", access.api_base.as_deref()),
+        ("X-LLM-Router-Cache", cache),
+        ("X-LLM-Router-Retries", retries.as_deref()),
+    ];
+    for (name, value) in values {
+        headers.remove(name);
+        if !config.routing_headers {
+            continue;
+        }
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(value).ok()) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// `Server-Timing` value with the durations, in seconds, that make up a
+/// request's latency, as shown by browser developer tools.
+fn server_timing(select: f64, upstream: f64, overhead: f64) -> HeaderValue {
+    let value = format!(
+        "select;dur={:.1}, upstream;dur={:.1}, overhead;dur={:.1}",
+        select * 1000.0,
+        upstream * 1000.0,
+        overhead * 1000.0
+    );
+    HeaderValue::from_str(&value).expect("durations are valid header values")
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 enum RoutingStrategy {
@@ -202,10 +465,23 @@ enum RoutingStrategy {
 struct NimLlmRouterParams {
     policy: String,
     routing_strategy: Option<RoutingStrategy>,
+    /// Name (or alias) of the LLM to use. Pins the request to it whatever
+    /// the routing strategy.
+    #[serde(alias = "llm_name")]
     model: Option<String>,
     threshold: Option<f64>,
 }
 
+impl NimLlmRouterParams {
+    /// `manual` when a model is pinned, otherwise the requested strategy.
+    fn strategy(&self) -> Option<RoutingStrategy> {
+        match self.model {
+            Some(_) => Some(RoutingStrategy::Manual),
+            None => self.routing_strategy.clone(),
+        }
+    }
+}
+
 fn extract_nim_llm_router_params(value: &Value) -> Option<NimLlmRouterParams> {
     value
         .get("nim-llm-router")
@@ -219,6 +495,433 @@ fn remove_nim_llm_router_params(mut value: Value) -> Value {
     value
 }
 
+/// Checks the structure of chat completion, completion and embeddings
+/// bodies, so that malformed requests get a precise 400 instead of a
+/// confusing upstream error. Bodies of other endpoints are not checked.
+fn validate_request_body(path: &str, value: &Value) -> Result<(), GatewayApiError> {
+    let chat = path.ends_with("/chat/completions");
+    let embeddings = path.ends_with("/embeddings");
+    if !chat && !embeddings && !path.ends_with("/completions") {
+        return Ok(());
+    }
+    let invalid = |message: String| {
+        GatewayApiError::client_error(StatusCode::BAD_REQUEST, message, "invalid_request_body")
+    };
+
+    let Some(body) = value.as_object() else {
+        return Err(invalid("Request body must be a JSON object".to_string()));
+    };
+    if body.get("model").is_some_and(|model| !model.is_string()) {
+        return Err(invalid("'model' must be a string".to_string()));
+    }
+    if embeddings {
+        let is_tokens = |value: &Value| {
+            value
+                .as_array()
+                .is_some_and(|tokens| tokens.iter().all(Value::is_u64))
+        };
+        return match body.get("input") {
+            Some(Value::String(_)) => Ok(()),
+            Some(Value::Array(inputs))
+                if !inputs.is_empty()
+                    && (inputs.iter().all(Value::is_string)
+                        || inputs.iter().all(Value::is_u64)
+                        || inputs.iter().all(is_tokens)) =>
+            {
+                Ok(())
+            }
+            Some(_) => Err(invalid(
+                "'input' must be a string, or a non-empty array of strings or token arrays"
+                    .to_string(),
+            )),
+            None => Err(invalid("'input' is required".to_string())),
+        };
+    }
+    if !chat {
+        return match body.get("prompt") {
+            Some(Value::String(_)) => Ok(()),
+            Some(Value::Array(prompts)) if prompts.iter().all(Value::is_string) => Ok(()),
+            Some(_) => Err(invalid(
+                "'prompt' must be a string or an array of strings".to_string(),
+            )),
+            None => Err(invalid("'prompt' is required".to_string())),
+        };
+    }
+
+    let messages = match body.get("messages") {
+        Some(Value::Array(messages)) if !messages.is_empty() => messages,
+        Some(Value::Array(_)) => return Err(invalid("'messages' must not be empty".to_string())),
+        Some(_) => return Err(invalid("'messages' must be an array".to_string())),
+        None => return Err(invalid("'messages' is required".to_string())),
+    };
+    for (i, message) in messages.iter().enumerate() {
+        let Some(message) = message.as_object() else {
+            return Err(invalid(format!("'messages[{}]' must be an object", i)));
+        };
+        match message.get("role") {
+            Some(Value::String(_)) => {}
+            Some(_) => return Err(invalid(format!("'messages[{}].role' must be a string", i))),
+            None => return Err(invalid(format!("'messages[{}].role' is required", i))),
+        }
+        match message.get("content") {
+            Some(Value::String(_) | Value::Array(_) | Value::Null) => {}
+            Some(_) => {
+                return Err(invalid(format!(
+                    "'messages[{}].content' must be a string or an array",
+                    i
+                )))
+            }
+            // Assistant messages calling tools may leave out the content.
+            None if message.contains_key("tool_calls") || message.contains_key("function_call") => {
+            }
+            None => return Err(invalid(format!("'messages[{}].content' is required", i))),
+        }
+    }
+    Ok(())
+}
+
+/// Whether a request offers the model `tools` or legacy `functions`.
+fn uses_tools(value: &Value) -> bool {
+    ["tools", "functions"]
+        .iter()
+        .any(|field| match value.get(field) {
+            None | Some(Value::Null) => false,
+            Some(Value::Array(items)) => !items.is_empty(),
+            Some(_) => true,
+        })
+}
+
+/// Index of the LLM that serves a request routed to `index`. Requests with
+/// tools for an LLM that does not support them go to its `tools_fallback`,
+/// or are rejected with 400 instead of failing upstream.
+fn resolve_tool_support(
+    policy: &Policy,
+    index: usize,
+    value: &Value,
+) -> Result<usize, GatewayApiError> {
+    let Some(llm) = policy.llms.get(index) else {
+        return Ok(index);
+    };
+    if llm.supports_tools() || !uses_tools(value) {
+        return Ok(index);
+    }
+    let fallback = llm
+        .tools_fallback
+        .as_deref()
+        .and_then(|name| policy.llms.iter().position(|llm| llm.name == name));
+    match fallback {
+        Some(fallback) => {
+            info!(
+                "{} does not support tools, routing to {}",
+                llm.name, policy.llms[fallback].name
+            );
+            Ok(fallback)
+        }
+        None => Err(GatewayApiError::client_error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Model '{}' does not support tools or functions. Remove them from the request or choose a model that supports them.",
+                llm.name
+            ),
+            "tools_not_supported",
+        )),
+    }
+}
+
+/// Fills in the sampling parameters of `defaults` the request leaves out.
+fn apply_default_params(mut value: Value, defaults: Option<&DefaultParams>) -> Value {
+    let (Some(defaults), Some(map)) = (defaults, value.as_object_mut()) else {
+        return value;
+    };
+    let params = [
+        ("temperature", defaults.temperature.map(Value::from)),
+        ("top_p", defaults.top_p.map(Value::from)),
+        ("max_tokens", defaults.max_tokens.map(Value::from)),
+    ];
+    for (name, default) in params {
+        if let Some(default) = default {
+            map.entry(name).or_insert(default);
+        }
+    }
+    value
+}
+
+/// Index of the LLM a request goes to once the policy's `allowed_models` is
+/// applied to the selected `index`: itself when allowed, otherwise the
+/// policy's `default_model`.
+fn allowed_model_index(policy: &Policy, index: usize) -> Result<usize, GatewayApiError> {
+    let Some(llm) = policy.llms.get(index) else {
+        return Ok(index);
+    };
+    let is_allowed = |name: &str| {
+        policy
+            .allowed_models
+            .iter()
+            .any(|allowed| allowed.trim() == name.trim())
+    };
+    if policy.allowed_models.is_empty() || is_allowed(&llm.name) {
+        return Ok(index);
+    }
+    let default = policy
+        .default_model
+        .as_deref()
+        .and_then(|name| policy.llms.iter().position(|llm| llm.name == name));
+    match default {
+        Some(default) => {
+            warn!(
+                "{} is not allowed by policy {}, routing to {}",
+                llm.name, policy.name, policy.llms[default].name
+            );
+            Ok(default)
+        }
+        None => Err(GatewayApiError::routing_error(
+            format!("{} is not allowed by policy {}", llm.name, policy.name),
+            RoutingErrorType::ModelNotFound,
+        )),
+    }
+}
+
+/// Serialized body of an upstream request, gzipped when `llm` compresses
+/// bodies of its size. The cache key is derived from the JSON beforehand, so
+/// compression never changes it.
+fn upstream_body(json: &Value, llm: &Llm) -> Result<(Vec<u8>, bool), GatewayApiError> {
+    let body = serde_json::to_vec(json)?;
+    if !llm.compresses_request(body.len()) {
+        return Ok((body, false));
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&body)?;
+    let compressed = encoder.finish()?;
+    debug!(
+        "Compressed request body for {} from {} to {} bytes",
+        llm.name,
+        body.len(),
+        compressed.len()
+    );
+    Ok((compressed, true))
+}
+
+/// Removes the top-level fields a policy forbids.
+fn remove_forbidden_params(mut value: Value, policy: &Policy) -> Value {
+    if let Some(map) = value.as_object_mut() {
+        for param in &policy.forbidden_params {
+            if map.remove(param).is_some() {
+                debug!("Removed '{}' forbidden by policy {}", param, policy.name);
+            }
+        }
+    }
+    value
+}
+
+/// Checks the request fields a policy constrains with `param_constraints`.
+/// Out-of-range numbers are clamped and disallowed values removed when the
+/// policy sets `clamp_params`; otherwise the request is rejected with 400.
+fn enforce_param_constraints(mut value: Value, policy: &Policy) -> Result<Value, GatewayApiError> {
+    let Some(map) = value.as_object_mut() else {
+        return Ok(value);
+    };
+    let invalid = |message: String| {
+        GatewayApiError::client_error(StatusCode::BAD_REQUEST, message, "invalid_parameter")
+    };
+    for (param, constraint) in &policy.param_constraints {
+        let Some(requested) = map.get(param) else {
+            continue;
+        };
+        if let Some(allowed) = &constraint.allowed {
+            if !allowed.contains(requested) {
+                if !policy.clamp_params {
+                    return Err(invalid(format!(
+                        "'{}' must be one of {}",
+                        param,
+                        Value::from(allowed.clone())
+                    )));
+                }
+                debug!("Removed '{}' not allowed by policy {}", param, policy.name);
+                map.remove(param);
+                continue;
+            }
+        }
+        if constraint.min.is_none() && constraint.max.is_none() {
+            continue;
+        }
+        let Some(number) = requested.as_f64() else {
+            return Err(invalid(format!("'{}' must be a number", param)));
+        };
+        let bound = match (constraint.min, constraint.max) {
+            (Some(min), _) if number < min => ("at least", min),
+            (_, Some(max)) if number > max => ("at most", max),
+            _ => continue,
+        };
+        if !policy.clamp_params {
+            return Err(invalid(format!(
+                "'{}' must be {} {}",
+                param, bound.0, bound.1
+            )));
+        }
+        debug!(
+            "Clamped {} from {} to {} for policy {}",
+            param, number, bound.1, policy.name
+        );
+        // Integer fields such as `n` stay integers.
+        let clamped = if requested.is_i64() && bound.1.fract() == 0.0 {
+            Value::from(bound.1 as i64)
+        } else {
+            Value::from(bound.1)
+        };
+        map.insert(param.clone(), clamped);
+    }
+    Ok(value)
+}
+
+/// Upstream timeout requested with `X-Request-Timeout-Ms`, capped at
+/// `client.max_request_timeout_ms`. `None` when the header is missing or
+/// malformed, or no cap is configured.
+fn requested_timeout(headers: &HeaderMap, max_ms: Option<u64>) -> Option<Duration> {
+    let max_ms = max_ms?;
+    let value = headers.get(REQUEST_TIMEOUT_HEADER)?;
+    let Some(ms) = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+    else {
+        debug!("Ignoring malformed {}: {:?}", REQUEST_TIMEOUT_HEADER, value);
+        return None;
+    };
+    if ms > max_ms {
+        info!(
+            "Clamping requested timeout of {}ms to the maximum of {}ms",
+            ms, max_ms
+        );
+    }
+    Some(Duration::from_millis(ms.min(max_ms)))
+}
+
+/// Longest wait for an instance's `/tokenize` endpoint before forwarding the
+/// request uncounted.
+const TOKENIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prompt tokens of a request to `llm` per its `token_estimator`, or `None`
+/// when they could not be counted, in which case the request is forwarded.
+async fn count_prompt_tokens(
+    client: &reqwest::Client,
+    llm: &Llm,
+    api_base: &str,
+    json: &Value,
+) -> Option<u64> {
+    if llm.token_estimator == TokenEstimator::Heuristic {
+        return Some(estimate_prompt_tokens(json));
+    }
+    let mut body = serde_json::json!({"model": json["model"]});
+    for field in ["messages", "prompt"] {
+        if let Some(value) = json.get(field) {
+            body[field] = value.clone();
+        }
+    }
+    let (auth_name, auth_value) = llm.auth_header();
+    let response = client
+        .post(format!("{}/tokenize", api_base.trim_end_matches('/')))
+        .header(auth_name, auth_value)
+        .timeout(TOKENIZE_TIMEOUT)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let count = match response {
+        Ok(response) => response
+            .json::<Value>()
+            .await
+            .map(|json| json["count"].as_u64()),
+        Err(e) => Err(e),
+    };
+    match count {
+        Ok(Some(count)) => Some(count),
+        Ok(None) => {
+            warn!("Tokenizer of {} returned no count, forwarding", llm.name);
+            None
+        }
+        Err(e) => {
+            warn!(
+                "Failed to count prompt tokens for {}, forwarding: {}",
+                llm.name, e
+            );
+            None
+        }
+    }
+}
+
+/// Caps `max_tokens` and `max_completion_tokens`, whichever are present, at
+/// a policy's `max_tokens_limit`, filling `max_tokens` in when the request
+/// leaves both out. Runs after the LLM's default params are applied, so a
+/// default cannot exceed the limit either.
+fn clamp_max_tokens(mut value: Value, policy: &Policy) -> Value {
+    const FIELDS: [&str; 2] = ["max_tokens", "max_completion_tokens"];
+    let (Some(limit), Some(map)) = (policy.max_tokens_limit, value.as_object_mut()) else {
+        return value;
+    };
+    if !FIELDS.iter().any(|field| map.contains_key(*field)) {
+        map.insert("max_tokens".to_string(), Value::from(limit));
+        return value;
+    }
+    for field in FIELDS {
+        let Some(requested) = map.get(field) else {
+            continue;
+        };
+        if requested
+            .as_u64()
+            .is_some_and(|requested| requested <= limit)
+        {
+            continue;
+        }
+        debug!(
+            "Clamped {} from {} to {} for policy {}",
+            field, requested, limit, policy.name
+        );
+        map.insert(field.to_string(), Value::from(limit));
+    }
+    value
+}
+
+/// Adds a policy's system prompt to a chat request, merging it into or
+/// replacing the client's system message per `mode`. Completion requests
+/// only change when a `prompt_template` is configured.
+fn apply_system_prompt(mut value: Value, system_prompt: &SystemPromptConfig) -> Value {
+    if let Some(messages) = value.get_mut("messages").and_then(Value::as_array_mut) {
+        let content = system_prompt.content.as_str();
+        let is_system = |message: &Value| message["role"] == "system";
+        match system_prompt.mode {
+            SystemPromptMode::Override => {
+                messages.retain(|message| !is_system(message));
+                messages.insert(0, serde_json::json!({"role": "system", "content": content}));
+            }
+            SystemPromptMode::Prepend => match messages.iter_mut().find(|m| is_system(m)) {
+                Some(message) => match &mut message["content"] {
+                    Value::String(existing) => *existing = format!("{content}\n\n{existing}"),
+                    Value::Array(parts) => {
+                        parts.insert(0, serde_json::json!({"type": "text", "text": content}))
+                    }
+                    other => *other = Value::String(content.to_string()),
+                },
+                None => {
+                    messages.insert(0, serde_json::json!({"role": "system", "content": content}))
+                }
+            },
+        }
+    } else if let Some(template) = &system_prompt.prompt_template {
+        let render = |prompt: &mut Value| {
+            if let Value::String(text) = prompt {
+                *text = template.replace("{prompt}", text);
+            }
+        };
+        match value.get_mut("prompt") {
+            Some(Value::Array(prompts)) => prompts.iter_mut().for_each(render),
+            Some(prompt) => render(prompt),
+            None => {}
+        }
+    }
+    value
+}
+
 // This might break response if the stream_options is not supported by the model,
 // if you want to use this function, please make sure the model supports it.
 // fn include_usage(mut value: Value) -> Value {
@@ -235,6 +938,149 @@ fn remove_nim_llm_router_params(mut value: Value) -> Value {
 //     value
 // }
 
+/// Sends the request behind a cache hit again and compares the content of
+/// the fresh response with the cached one, counting a difference in
+/// `cache_staleness_detected_total`. The client already got the cached
+/// response; failures are only logged. Like any other request it is
+/// counted by the LLM's circuit breaker and concurrency limits, and it is
+/// skipped when the breaker is open or no slot is free.
+fn spawn_cache_revalidation(
+    client: reqwest::Client,
+    breakers: &CircuitBreakerRegistry,
+    bulkhead: &Bulkhead,
+    llm: Llm,
+    path_and_query: String,
+    json: Value,
+    cached_hash: String,
+) {
+    let Some(permit) = bulkhead.try_acquire(&llm) else {
+        debug!(
+            "Skipping cache revalidation of {}, no slot is free",
+            llm.name
+        );
+        return;
+    };
+    let breaker = breakers.get(&llm.api_base);
+    let Some(admission) = breaker.admit() else {
+        debug!(
+            "Skipping cache revalidation of {}, its circuit is open",
+            llm.name
+        );
+        return;
+    };
+    let request_id = request_id::current().unwrap_or_else(request_id::generate);
+    tokio::spawn(request_id::scope(request_id.clone(), async move {
+        let _permit = permit;
+        let (auth_name, auth_value) = llm.auth_header();
+        let mut request = client
+            .post(llm.upstream_url(&llm.api_base, &path_and_query))
+            .headers(llm.custom_headers())
+            .header(auth_name, auth_value)
+            .header(ACCEPT, "application/json")
+            .header(REQUEST_ID_HEADER, request_id)
+            .json(&json);
+        if let Some(timeout) = llm.request_timeout_secs {
+            request = request.timeout(Duration::from_secs(timeout));
+        }
+
+        let result = request.send().await.map_err(SendError::from);
+        let failure = match &result {
+            Ok(response) => FailureKind::from_status(response.status()),
+            Err(e) => FailureKind::from_error(e),
+        };
+        match failure {
+            Some(kind) => breaker.record_failure(kind),
+            None if result.is_ok() => breaker.record_success(),
+            None => {}
+        }
+        drop(admission);
+
+        let response = match result {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                warn!(
+                    "Cache revalidation request to {} returned {}",
+                    llm.name,
+                    response.status()
+                );
+                return;
+            }
+            Err(e) => {
+                warn!("Cache revalidation request to {} failed: {}", llm.name, e);
+                return;
+            }
+        };
+        // Cached bodies were stored as the client gets them.
+        let body = response
+            .bytes()
+            .await
+            .map(|body| from_provider_response_body(&llm.provider_type, body));
+        match body {
+            Ok(body) if content_hash(&body) != cached_hash => {
+                warn!("Cached response of {} is stale", llm.name);
+                CACHE_STALENESS_DETECTED
+                    .with_label_values(&[llm.name.as_str()])
+                    .inc();
+            }
+            Ok(_) => debug!("Cached response of {} is still current", llm.name),
+            Err(e) => warn!(
+                "Cache revalidation response from {} failed: {}",
+                llm.name, e
+            ),
+        }
+    }));
+}
+
+/// Mirrors a request to a shadow LLM in the background. The response is
+/// discarded; only its latency and token usage are recorded, and failures are
+/// logged without affecting the primary request.
+fn spawn_shadow_request(client: reqwest::Client, llm: Llm, path_and_query: String, json: Value) {
+    let request_id = request_id::current().unwrap_or_else(request_id::generate);
+    tokio::spawn(request_id::scope(request_id.clone(), async move {
+        let json = apply_default_params(json, llm.default_params.as_ref());
+        let Ok(mut json) = modify_model(json, &llm.model) else {
+            return;
+        };
+        // Buffered so that usage is always reported in the response.
+        json["stream"] = Value::Bool(false);
+        if let Some(map) = json.as_object_mut() {
+            map.remove("stream_options");
+        }
+
+        let (auth_name, auth_value) = llm.auth_header();
+        let mut request = client
+            .post(llm.upstream_url(&llm.api_base, &path_and_query))
+            .headers(llm.custom_headers())
+            .header(auth_name, auth_value)
+            .header(ACCEPT, "application/json")
+            .header(REQUEST_ID_HEADER, request_id)
+            .json(&json);
+        if let Some(timeout) = llm.request_timeout_secs {
+            request = request.timeout(Duration::from_secs(timeout));
+        }
+
+        let start = Instant::now();
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Shadow request to {} failed: {}", llm.name, e);
+                return;
+            }
+        };
+        let status = response.status();
+        let body = response.json::<Value>().await;
+        LLM_RESPONSE_TIME
+            .with_label_values(&[llm.name.as_str(), "true"])
+            .observe(start.elapsed().as_secs_f64());
+
+        match body {
+            Ok(body) if status.is_success() => track_shadow_token_usage(&body, &llm.name),
+            Ok(_) => warn!("Shadow request to {} returned {}", llm.name, status),
+            Err(e) => warn!("Shadow response from {} was not JSON: {}", llm.name, e),
+        }
+    }));
+}
+
 pub fn config(
     config: RouterConfig,
 ) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
@@ -252,8 +1098,17 @@ pub fn config(
     Ok(client_res)
 }
 
-pub fn health() -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
-    let body = serde_json::json!({ "status": "OK" });
+pub fn health(
+    draining: bool,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    let (status, body) = if draining {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "Draining" }),
+        )
+    } else {
+        (StatusCode::OK, serde_json::json!({ "status": "OK" }))
+    };
     let json_vec = serde_json::to_vec(&body).expect("Serialization to JSON should succeed.");
     let body_bytes = Bytes::from(json_vec);
 
@@ -261,15 +1116,31 @@ pub fn health() -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiE
         .map_err(|never| match never {})
         .boxed();
 
-    let client_res = Response::builder().status(200).body(full_body)?;
+    let client_res = Response::builder().status(status).body(full_body)?;
 
     info!("/health: {client_res:#?}");
     Ok(client_res)
 }
 
-pub fn metrics() -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
-    let encoder = TextEncoder::new();
+/// Metrics in the OpenMetrics format when the `Accept` header asks for it,
+/// otherwise in the legacy Prometheus text format.
+pub fn metrics(
+    headers: &HeaderMap,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
     let metric_families = gather();
+    if openmetrics::prefers_openmetrics(headers) {
+        let full_body = Full::from(openmetrics::encode(&metric_families))
+            .map_err(|never| match never {})
+            .boxed();
+        let client_res = Response::builder()
+            .header("Content-Type", openmetrics::OPENMETRICS_FORMAT)
+            .status(200)
+            .body(full_body)?;
+        info!("/metrics: {client_res:#?}");
+        return Ok(client_res);
+    }
+
+    let encoder = TextEncoder::new();
     let mut buffer = Vec::new();
 
     if let Err(err) = encoder.encode(&metric_families, &mut buffer) {
@@ -314,29 +1185,452 @@ pub fn unavailable() -> Result<Response<BoxBody<Bytes, GatewayApiError>>, Gatewa
     Ok(client_res)
 }
 
-pub async fn handler(
-    req: Request<Incoming>,
-    cfg: RouterConfig,
+/// `GET /v1/models`: the unique upstream models in OpenAI's list format,
+/// limited to the policies `client_key` may use. `policies` is an extension
+/// field naming the policies that route to each model.
+pub fn models(
+    config: &RouterConfig,
+    client_key: Option<&str>,
 ) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
-    let uri_path = req.uri().path();
-    info!("Received request for URI: {}", uri_path);
-
-    match uri_path {
+    let mut models: Vec<(&str, Vec<&str>)> = Vec::new();
+    for policy in &config.policies {
+        if client_key.is_some_and(|key| !config.security.api_keys.allows_policy(key, &policy.name))
+        {
+            continue;
+        }
+        for llm in &policy.llms {
+            let index = match models.iter().position(|(model, _)| *model == llm.model) {
+                Some(index) => index,
+                None => {
+                    models.push((&llm.model, Vec::new()));
+                    models.len() - 1
+                }
+            };
+            let policies = &mut models[index].1;
+            if !policies.contains(&policy.name.as_str()) {
+                policies.push(&policy.name);
+            }
+        }
+    }
+
+    let data: Vec<Value> = models
+        .into_iter()
+        .map(|(model, policies)| {
+            serde_json::json!({
+                "id": model,
+                "object": "model",
+                "owned_by": "llm-router",
+                "policies": policies,
+            })
+        })
+        .collect();
+    json_response(
+        StatusCode::OK,
+        &serde_json::json!({ "object": "list", "data": data }),
+    )
+}
+
+/// Endpoints called by clients rather than operators, authorized by
+/// `security.external_auth` when it is set.
+fn is_client_endpoint(path: &str) -> bool {
+    matches!(
+        path,
+        "/v1/chat/completions"
+            | "/completions"
+            | MESSAGES_PATH
+            | EMBEDDINGS_PATH
+            | "/v1/models"
+            | "/v1/route/explain"
+    )
+}
+
+fn client_unauthorized() -> Response<BoxBody<Bytes, GatewayApiError>> {
+    unauthorized_error().into_response()
+}
+
+fn unauthorized_error() -> GatewayApiError {
+    GatewayApiError::client_error(
+        StatusCode::UNAUTHORIZED,
+        "Missing or invalid API key",
+        "authentication_error",
+    )
+}
+
+/// How a request to a routing endpoint was authenticated.
+struct Authenticated<'a> {
+    /// Whether it carries a valid `security.hmac` signature.
+    signed: bool,
+    /// The client key it was sent with. `None` for signed requests and when
+    /// no keys are configured.
+    client_key: Option<&'a str>,
+}
+
+/// Verifies the request signature, then the client key of unsigned
+/// requests. `signed` is the outcome of a check already made, e.g. on the
+/// batch the request was split from.
+fn authenticate<'a>(
+    parts: &http::request::Parts,
+    body: &[u8],
+    security: &'a SecurityConfig,
+    signed: Option<bool>,
+) -> Result<Authenticated<'a>, GatewayApiError> {
+    let signed = match (signed, &security.hmac) {
+        (Some(signed), _) => signed,
+        (None, Some(hmac)) => HmacLayer::new(hmac)
+            .verify(&parts.headers, body)
+            .inspect_err(|error| warn!("Rejected request signature: {}", error))?,
+        (None, None) => false,
+    };
+    // Signed requests are trusted without a client key.
+    let client_key = if signed || security.api_keys.is_empty() {
+        None
+    } else {
+        let key = authenticate_client_key(&parts.headers, &parts.uri, security);
+        Some(key.ok_or_else(unauthorized_error)?)
+    };
+    Ok(Authenticated { signed, client_key })
+}
+
+/// Counts the request against the per-IP limit, returning a 429 (or 503,
+/// per `rejection_status`) with `Retry-After` once the client IP is over it.
+/// Probes are not limited.
+fn rate_limited<B>(
+    req: &Request<B>,
+    state: &AppState,
+) -> Option<Response<BoxBody<Bytes, GatewayApiError>>> {
+    let settings = &state.config.security.rate_limit;
+    settings.per_ip.as_ref()?;
+    let peer = req.extensions().get::<ClientAddr>().map(|addr| addr.0.ip());
+    let ip = client_ip(peer, req.headers(), &settings.trusted_proxies)?;
+    let retry_after = state.rate_limiter.check(ip, settings).err()?;
+    warn!("Rate limit exceeded for {}", ip);
+    Some(rate_limit_response(
+        "Too many requests from this client IP",
+        RATE_LIMIT_SCOPE_GLOBAL,
+        settings,
+        retry_after,
+    ))
+}
+
+/// Enforces `policy.rate_limit` on a request already admitted by the global
+/// limit.
+fn policy_rate_limited(
+    parts: &http::request::Parts,
+    policy: &Policy,
+    settings: &RateLimitConfig,
+    limiter: &RateLimiter<(String, IpAddr)>,
+) -> Option<Response<BoxBody<Bytes, GatewayApiError>>> {
+    let limit = policy.rate_limit.as_ref()?;
+    let peer = parts.extensions.get::<ClientAddr>().map(|addr| addr.0.ip());
+    let ip = client_ip(peer, &parts.headers, &settings.trusted_proxies)?;
+    let retry_after = limiter
+        .check_limit((policy.name.clone(), ip), settings, limit)
+        .err()?;
+    warn!("Rate limit of policy {} exceeded for {}", policy.name, ip);
+    Some(rate_limit_response(
+        format!(
+            "Too many requests from this client IP for policy '{}'",
+            policy.name
+        ),
+        RATE_LIMIT_SCOPE_POLICY,
+        settings,
+        retry_after,
+    ))
+}
+
+/// A rejection of a request over a rate limit. `Retry-After` is the wait in
+/// whole seconds, rounded up so that clients retry once the limit admits them.
+fn rate_limit_response(
+    message: impl Into<String>,
+    scope: &'static str,
+    settings: &RateLimitConfig,
+    retry_after: Duration,
+) -> Response<BoxBody<Bytes, GatewayApiError>> {
+    let status =
+        StatusCode::from_u16(settings.rejection_status).unwrap_or(StatusCode::TOO_MANY_REQUESTS);
+    let mut response =
+        GatewayApiError::client_error(status, message, "rate_limit_exceeded").into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        RETRY_AFTER,
+        HeaderValue::from(retry_after.as_secs_f64().ceil().max(1.0) as u64),
+    );
+    headers.insert(RATE_LIMIT_SCOPE_HEADER, HeaderValue::from_static(scope));
+    response
+}
+
+/// Which of two hedged requests answered the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HedgeWinner {
+    Primary,
+    Hedge,
+}
+
+impl HedgeWinner {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HedgeWinner::Primary => "primary",
+            HedgeWinner::Hedge => "hedge",
+        }
+    }
+}
+
+/// How long to wait for an instance before hedging: `hedge_after_ms`, or
+/// longer for an instance whose latency average says it usually takes
+/// longer, so that only its outliers are hedged.
+fn hedge_delay(hedge_after_ms: u64, latency: Option<Duration>) -> Duration {
+    let configured = Duration::from_millis(hedge_after_ms);
+    latency.map_or(configured, |latency| {
+        configured.max(latency.mul_f64(HEDGE_LATENCY_FACTOR))
+    })
+}
+
+/// Awaits `primary`, and also the future `hedge()` returns once `primary`
+/// has not finished within `delay`, so at most one hedge is sent. Returns
+/// the first output that is `usable`, or the last one when neither is, with
+/// the request it came from when the hedge was sent. The future still
+/// running is dropped, which cancels its request.
+async fn race_hedge<T, P, H>(
+    primary: P,
+    delay: Duration,
+    hedge: impl FnOnce() -> Option<H>,
+    usable: impl Fn(&T) -> bool,
+) -> (T, Option<HedgeWinner>)
+where
+    P: Future<Output = T>,
+    H: Future<Output = T>,
+{
+    tokio::pin!(primary);
+    tokio::select! {
+        output = &mut primary => return (output, None),
+        _ = tokio::time::sleep(delay) => {}
+    }
+    let Some(hedge) = hedge() else {
+        return (primary.await, None);
+    };
+    tokio::pin!(hedge);
+    tokio::select! {
+        output = &mut primary => {
+            if usable(&output) {
+                (output, Some(HedgeWinner::Primary))
+            } else {
+                (hedge.await, Some(HedgeWinner::Hedge))
+            }
+        }
+        output = &mut hedge => {
+            if usable(&output) {
+                (output, Some(HedgeWinner::Hedge))
+            } else {
+                (primary.await, Some(HedgeWinner::Primary))
+            }
+        }
+    }
+}
+
+/// A policy's `fallback_response`, marked as degraded.
+fn fallback_response(
+    policy: &str,
+    fallback: &Value,
+    anthropic: bool,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    SERVED_FALLBACK_RESPONSE.with_label_values(&[policy]).inc();
+    let body = Bytes::from(serde_json::to_vec(fallback)?);
+    let body = if anthropic {
+        convert_response_body(&body)
+    } else {
+        body
+    };
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(DEGRADED_HEADER, "fallback-response")
+        .body(Full::from(body).map_err(|never| match never {}).boxed())?;
+    Ok(response)
+}
+
+fn admin_unauthorized(security: &SecurityConfig) -> Response<BoxBody<Bytes, GatewayApiError>> {
+    if security.admin_api_key.is_none() {
+        return GatewayApiError::client_error(
+            StatusCode::FORBIDDEN,
+            "Admin endpoints are disabled; set security.admin_api_key to enable them",
+            "admin_disabled",
+        )
+        .into_response();
+    }
+    GatewayApiError::client_error(
+        StatusCode::UNAUTHORIZED,
+        "Missing or invalid admin API key",
+        "authentication_error",
+    )
+    .into_response()
+}
+
+/// Entry point for every request. Assigns the request ID, runs the route
+/// inside its logging scope and turns errors into JSON error responses.
+pub async fn handler<B>(
+    mut req: Request<B>,
+    mut state: AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    state.refresh_config();
+    let request_id = request_id::from_headers_or_generate(req.headers());
+    let header_value = HeaderValue::from_str(&request_id)?;
+    req.headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value.clone());
+
+    let cors_enabled = !state.config.cors.allowed_origins.is_empty();
+    if cors_enabled && cors::is_preflight(req.method(), req.headers()) {
+        let mut response = cors::preflight_response(&state.config.cors, req.headers())?;
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, header_value);
+        return Ok(response);
+    }
+    // Kept to add the CORS headers once the request is answered.
+    let cors = cors_enabled.then(|| (state.config.cors.clone(), req.headers().clone()));
+
+    let mut response = request_id::scope(request_id, async {
+        route(req, state).await.unwrap_or_else(|e| {
+            error!("Request failed: {}", e);
+            e.into_response()
+        })
+    })
+    .await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value);
+    if let Some((config, request_headers)) = cors {
+        cors::add_headers(&config, &request_headers, response.headers_mut());
+    }
+    Ok(response)
+}
+
+async fn route<B>(
+    mut req: Request<B>,
+    state: AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    let uri_path = req.uri().path().to_owned();
+    info!("Received request for URI: {}", uri_path);
+
+    if !uri_path.starts_with("/health") && uri_path != "/metrics" {
+        if let Some(response) = rate_limited(&req, &state) {
+            return Ok(response);
+        }
+    }
+
+    if let Some(external_auth) = &state.config.security.external_auth {
+        if is_client_endpoint(&uri_path) {
+            let auth_start = Instant::now();
+            if let Err(error) = state
+                .external_auth
+                .check(&state.client, external_auth, req.headers())
+                .await
+            {
+                return Ok(error.into_response());
+            }
+            // Added to the local checks' time once the body is read.
+            req.extensions_mut()
+                .insert(ExternalAuthDuration(auth_start.elapsed()));
+        }
+    }
+
+    match uri_path.as_str() {
         "/config" => {
             info!("Routing to config handler");
-            config(cfg)
+            config(state.config)
         }
         "/health" => {
             info!("Routing to health handler");
-            health()
+            health(state.shutdown.is_draining())
+        }
+        "/health/readiness" => {
+            info!("Routing to readiness handler");
+            readiness(&state).await
         }
         "/metrics" => {
             info!("Routing to metrics handler");
-            metrics()
+            if !is_metrics_request_authorized(&req, &state.config.security) {
+                let error = GatewayApiError::client_error(
+                    StatusCode::UNAUTHORIZED,
+                    "Missing or invalid metrics API key",
+                    "authentication_error",
+                );
+                return Ok(error.into_response());
+            }
+            metrics(req.headers())
+        }
+        "/admin/cache" if req.method() == Method::DELETE => {
+            info!("Routing to cache purge handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            purge_cache(&req, &state)
+        }
+        "/admin/cache/stats" if req.method() == Method::GET => {
+            info!("Routing to cache stats handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            cache_stats(&state)
+        }
+        "/admin/events" if req.method() == Method::GET => {
+            info!("Routing to routing events handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            routing_events(&state)
+        }
+        "/admin/config" if req.method() == Method::GET => {
+            info!("Routing to effective config handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            effective_config(&state)
+        }
+        "/admin/reload" if req.method() == Method::POST => {
+            info!("Routing to config reload handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            reload_config(&state).await
+        }
+        path if path.starts_with("/admin/quota/") && req.method() == Method::GET => {
+            info!("Routing to quota status handler");
+            if !is_admin_request_authorized(&req, &state.config.security) {
+                return Ok(admin_unauthorized(&state.config.security));
+            }
+            quota_status(&state, &path["/admin/quota/".len()..])
+        }
+        "/v1/models" if req.method() == Method::GET => {
+            info!("Routing to models handler");
+            let security = &state.config.security;
+            let client_key = if security.api_keys.is_empty() {
+                None
+            } else {
+                match authenticate_client_key(req.headers(), req.uri(), security) {
+                    Some(key) => Some(key),
+                    None => return Ok(client_unauthorized()),
+                }
+            };
+            models(&state.config, client_key)
+        }
+        "/v1/route/explain" if req.method() == Method::POST => {
+            info!("Routing to route explain handler");
+            explain(req, state).await
         }
-        "/v1/chat/completions" | "/completions" => {
+        "/v1/chat/completions" | "/completions" | MESSAGES_PATH | EMBEDDINGS_PATH => {
             info!("Routing to proxy handler");
-            proxy(req, cfg).await
+            let guard = state.shutdown.track();
+            batch_proxy(req, state)
+                .await
+                .map(|response| response.map(|body| guard.attach(body)))
         }
         _ => {
             info!("Routing to Unavailable Path");
@@ -345,32 +1639,402 @@ pub async fn handler(
     }
 }
 
-pub async fn proxy(
-    req: Request<Incoming>,
-    config: RouterConfig,
+/// `proxy` for a body that was already read. Outside `batch_proxy`, whose
+/// bound on its own body's error would be picked for this one.
+async fn proxy_buffered(
+    req: Request<Full<Bytes>>,
+    state: AppState,
 ) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError> {
+    proxy(req, state).await
+}
+
+/// Sends each prompt of a batch request (see `batch::split`) through `proxy`,
+/// at most `server.batching.max_concurrency` at a time, and merges their
+/// responses. Other requests go to `proxy` as they are.
+async fn batch_proxy<B>(
+    req: Request<B>,
+    state: AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    let config = state.config.clone();
+    let path = req.uri().path();
+    let batching = match &config.server.batching {
+        Some(batching) if path == "/v1/chat/completions" || path == "/completions" => batching,
+        _ => return proxy(req, state).await,
+    };
+    let completions = path == "/completions";
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = match read_request_body(body, config.server.max_request_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(error @ GatewayApiError::ClientError { .. }) => return Ok(error.into_response()),
+        Err(error) => return Err(error),
+    };
+    let json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+    let items = match batch::split(&json, completions, batching) {
+        Ok(Some(items)) => items,
+        Ok(None) => {
+            let request = Request::from_parts(parts, Full::new(body_bytes));
+            return proxy_buffered(request, state).await;
+        }
+        Err(error) => return Ok(error.into_response()),
+    };
+    // The parts' bodies differ from the signed one, so the batch is
+    // authenticated as a whole here.
+    let signed = match authenticate(&parts, &body_bytes, &config.security, None) {
+        Ok(auth) => auth.signed,
+        Err(error) => return Ok(error.into_response()),
+    };
+    info!("Splitting batch request into {} requests", items.len());
+
+    let mut requests = Vec::with_capacity(items.len());
+    for item in items {
+        let mut request = Request::new(Full::new(Bytes::from(serde_json::to_vec(&item)?)));
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.headers_mut() = parts.headers.clone();
+        request.headers_mut().remove(CONTENT_LENGTH);
+        // The parts' bodies are parsed to be merged, so cache hits must not
+        // come back compressed.
+        request.headers_mut().remove(ACCEPT_ENCODING);
+        *request.extensions_mut() = parts.extensions.clone();
+        request.extensions_mut().insert(BatchItem { signed });
+        requests.push(request);
+    }
+    let mut responses = stream::iter(requests)
+        .map(|request| {
+            let state = state.clone();
+            async move {
+                let (parts, body) = proxy_buffered(request, state).await?.into_parts();
+                let body = body.collect().await?.to_bytes();
+                Ok::<_, GatewayApiError>((parts.status, body))
+            }
+        })
+        .buffered(batching.max_concurrency);
+
+    let mut merged = Vec::new();
+    let mut first_failure = None;
+    while let Some(response) = responses.next().await {
+        let (status, body) = response?;
+        if status.is_success() {
+            merged.push(Ok(serde_json::from_slice(&body)?));
+            continue;
+        }
+        if batching.failure_policy == BatchFailurePolicy::FailAll {
+            first_failure = Some((status, body));
+            break;
+        }
+        let error: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        merged.push(Err(match error.get("error") {
+            Some(error) => error.clone(),
+            None => error,
+        }));
+        first_failure.get_or_insert((status, body));
+    }
+
+    let (status, body) = match first_failure {
+        Some(failure) if !merged.iter().any(Result::is_ok) => failure,
+        Some(failure) if batching.failure_policy == BatchFailurePolicy::FailAll => failure,
+        _ => (
+            StatusCode::OK,
+            Bytes::from(serde_json::to_vec(&batch::merge(merged))?),
+        ),
+    };
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::from(body).map_err(|never| match never {}).boxed())?;
+    Ok(response)
+}
+
+/// A `403` when `client_key` may not use `policy`. A key allowed the policy
+/// the client asked for may also use the experiment variant it resolved to.
+fn policy_not_allowed(
+    client_key: Option<&str>,
+    api_keys: &ApiKeys,
+    policy: &str,
+    requested_policy: Option<&str>,
+) -> Option<Response<BoxBody<Bytes, GatewayApiError>>> {
+    let key = client_key?;
+    let allowed = api_keys.allows_policy(key, policy)
+        || requested_policy.is_some_and(|requested| api_keys.allows_policy(key, requested));
+    (!allowed).then(|| {
+        GatewayApiError::client_error(
+            StatusCode::FORBIDDEN,
+            format!("This API key may not use policy '{}'", policy),
+            "policy_not_allowed",
+        )
+        .into_response()
+    })
+}
+
+/// `POST /v1/route/explain`: reports the policy, classifier scores and
+/// upstream instance a chat completion request would be routed to, without
+/// calling the LLM. Authenticated like the request it explains, and without
+/// changing the instance later requests are sent to.
+async fn explain<B>(
+    req: Request<B>,
+    state: AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    let config = &state.config;
+    let (parts, body) = req.into_parts();
+    let body_bytes = match read_request_body(body, config.server.max_request_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(error @ GatewayApiError::ClientError { .. }) => return Ok(error.into_response()),
+        Err(error) => return Err(error),
+    };
+    let client_key = match authenticate(&parts, &body_bytes, &config.security, None) {
+        Ok(auth) => auth.client_key,
+        Err(error) => return Ok(error.into_response()),
+    };
+
+    let json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+    let mut json = apply_default_policy(json, config.default_policy.as_deref());
+    let requested_policy = extract_nim_llm_router_params(&json).map(|params| params.policy);
+
+    let experiment = choose_experiment_variant(config, &json).map(|(experiment, variant)| {
+        json["nim-llm-router"]["policy"] = Value::String(variant.policy.clone());
+        serde_json::json!({"name": experiment.name, "variant": variant.policy})
+    });
+
+    let Some(params) = extract_nim_llm_router_params(&json) else {
+        let error = GatewayApiError::InvalidRequest {
+            message: "Missing required 'nim-llm-router' parameters in request body".to_string(),
+        };
+        return Ok(error.into_response());
+    };
+    let Some(policy) = config.get_policy_by_name(&params.policy) else {
+        return Ok(GatewayApiError::PolicyNotFound(params.policy).into_response());
+    };
+    if let Some(response) = policy_not_allowed(
+        client_key,
+        &config.security.api_keys,
+        &policy.name,
+        requested_policy.as_deref(),
+    ) {
+        return Ok(response);
+    }
+
+    let (model_index, scores) = match params.strategy() {
+        Some(RoutingStrategy::Manual) => {
+            let model = params
+                .model
+                .clone()
+                .ok_or_else(|| GatewayApiError::InvalidRequest {
+                    message: "No model specified for manual routing".to_string(),
+                })?;
+            match policy.find_llm(&model, &config.model_aliases) {
+                Some((index, _)) => (index, None),
+                None => return Ok(GatewayApiError::ModelNotFound(model).into_response()),
+            }
+        }
+        Some(RoutingStrategy::Triton) => {
+            let messages = extract_messages(&json).unwrap_or_default();
+            let triton_text = get_last_message_for_triton(&messages);
+            let scores = classify(&policy, &state.client, &triton_text).await?;
+            (
+                highest_score_index(&biased_scores(&policy, &scores))?,
+                Some(scores),
+            )
+        }
+        None => {
+            return Err(GatewayApiError::InvalidRequest {
+                message: "No routing strategy specified".to_string(),
+            });
+        }
+    };
+
+    let model_index = match allowed_model_index(&policy, model_index) {
+        Ok(index) => index,
+        Err(error) => return Ok(error.into_response()),
+    };
+    let llm = policy.get_llm_by_index(model_index).ok_or_else(|| {
+        GatewayApiError::ModelNotFound(format!("LLM not found at index {}", model_index))
+    })?;
+    let health_max_age = Duration::from_secs(config.server.health_cache_secs);
+    let api_base = state.balancer.peek_instance(
+        &config.load_balancing,
+        &llm,
+        session_key(&parts, &config.load_balancing).as_deref(),
+        |instance| {
+            state.circuit_breakers.is_available(instance)
+                && !state.health_cache.is_failing(instance, health_max_age)
+        },
+    );
+    let classifier_scores = scores.map(|scores| {
+        policy
+            .llms
+            .iter()
+            .zip(scores)
+            .map(|(llm, score)| {
+                serde_json::json!({"llm_name": llm.name, "score": score, "bias": llm.bias})
+            })
+            .collect::<Vec<_>>()
+    });
+
+    json_response(
+        StatusCode::OK,
+        &serde_json::json!({
+            "policy": policy.name,
+            "experiment": experiment,
+            "routing_strategy": params.strategy(),
+            "classifier_scores": classifier_scores,
+            "llm_name": llm.name,
+            "model": llm.model,
+            "api_base": api_base,
+            "circuit_open": state.circuit_breakers.state(&api_base) == CircuitState::Open,
+        }),
+    )
+}
+
+pub async fn proxy<B>(
+    req: Request<B>,
+    state: AppState,
+) -> Result<Response<BoxBody<Bytes, GatewayApiError>>, GatewayApiError>
+where
+    B: Body<Data = Bytes>,
+    GatewayApiError: From<B::Error>,
+{
+    let config = state.config;
+    let client = state.client;
+    let upstream_clients = state.upstream_clients;
+    let cache = state.cache;
+    let coalescer = state.coalescer;
+    let balancer = state.balancer;
+    let circuit_breakers = state.circuit_breakers;
+    let health_cache = state.health_cache;
+    let bulkhead = state.bulkhead;
+    let body_logger = state.body_logger;
+    let quota_tracker = state.quota;
+    let policy_rate_limiter = state.policy_rate_limiter;
+    let events = state.events;
     let overall_start = Instant::now();
     let mut model_selection_time = 0.0;
+    let mut experiment_variant: Option<String> = None;
+    let mut cache_hit = false;
     let llm_resp_time_holder = Arc::new(Mutex::new(0.0));
+    let mut access = AccessLogRecord::new(req.method().as_str(), req.uri().path());
 
     NUM_REQUESTS.inc();
+    let provided_key = provided_client_key(req.headers(), req.uri(), &config.security);
+    let tenant = config
+        .observability
+        .tenant_labels
+        .then(|| config.security.tenant(provided_key.as_deref()).to_string());
+    if let Some(tenant) = &tenant {
+        NUM_REQUESTS_PER_TENANT.with_label_values(&[tenant]).inc();
+    }
 
-    let result = (async {
+    // Held while this request fetches the response identical requests wait
+    // for.
+    let mut flight = None;
+    let mut result = (async {
         print_config(&config);
 
-        let forward_uri_path_and_query = extract_forward_uri_path_and_query(&req)?;
+        // Anthropic Messages requests are translated and sent to the
+        // upstream's OpenAI-compatible chat completions endpoint.
+        let anthropic = req.uri().path() == MESSAGES_PATH;
+        let embeddings = req.uri().path() == EMBEDDINGS_PATH;
+        let forward_path = if anthropic {
+            CHAT_COMPLETIONS_PATH
+        } else {
+            req.uri().path()
+        };
+        let forward_uri_path_and_query = forward_uri_path_and_query(forward_path, req.uri().query())?;
         info!("forward_uri_path_and_query: {forward_uri_path_and_query:#?}");
 
+        let max_request_body_bytes = config.server.max_request_body_bytes;
+        if let Some(limit) = max_request_body_bytes {
+            let content_length = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if content_length.is_some_and(|len| len > limit) {
+                return Ok(request_too_large(limit).into_response());
+            }
+        }
+
         let (parts, body) = req.into_parts();
-        info!("parts: {parts:#?}");
+        // Headers and bodies carry credentials and prompts, so they are only
+        // traced; `observability.log_bodies` logs prompts redacted.
+        trace!("parts: {parts:#?}");
+
+        let body_bytes = match read_request_body(body, max_request_body_bytes).await {
+            Ok(bytes) => bytes,
+            Err(error @ GatewayApiError::ClientError { .. }) => return Ok(error.into_response()),
+            Err(error) => return Err(error),
+        };
+        trace!("body_bytes: {body_bytes:#?}");
 
-        let body_bytes = body.collect().await?.to_bytes();
-        info!("body_bytes: {body_bytes:#?}");
+        let auth_start = Instant::now();
+        let batch_signed = parts.extensions.get::<BatchItem>().map(|item| item.signed);
+        let client_key = match authenticate(&parts, &body_bytes, &config.security, batch_signed)
+        {
+            Ok(auth) => auth.client_key,
+            Err(error) => return Ok(error.into_response()),
+        };
+        let external_auth = parts
+            .extensions
+            .get::<ExternalAuthDuration>()
+            .map_or(Duration::ZERO, |duration| duration.0);
+        AUTH_DURATION.observe((external_auth + auth_start.elapsed()).as_secs_f64());
 
+        // Time spent reshaping the body, observed once before it is sent.
+        let mut sanitize_time = Duration::ZERO;
+        let sanitize_start = Instant::now();
         let body_str = String::from_utf8_lossy(&body_bytes);
-        info!("body_str: {:#?}", &body_str);
+        trace!("body_str: {:#?}", &body_str);
         let json: Value = serde_json::from_str(&body_str).unwrap_or(Value::Null);
-        info!("json: {:#?}", &json);
+        trace!("json: {:#?}", &json);
+        let json = if anthropic {
+            match to_openai_request(&json) {
+                Ok(json) => json,
+                Err(error) => return Ok(error.into_response()),
+            }
+        } else {
+            json
+        };
+        // Malformed bodies are rejected before they cost a cache lookup,
+        // an embedding or a classification.
+        let client_body = remove_nim_llm_router_params(json.clone());
+        if let Err(error) = validate_request_body(forward_uri_path_and_query.path(), &client_body) {
+            return Ok(error.into_response());
+        }
+
+        // The request's tokens are reserved as it is admitted, so requests
+        // in flight together cannot overrun the quota between them.
+        let quota_usage = match provided_key
+            .as_deref()
+            .and_then(|key| config.security.get_quota(key))
+        {
+            Some(quota) => {
+                let max_tokens = client_body["max_completion_tokens"]
+                    .as_u64()
+                    .or(client_body["max_tokens"].as_u64())
+                    .unwrap_or(0);
+                let tokens = estimate_prompt_tokens(&client_body) + max_tokens;
+                match quota_tracker.reserve(quota, tokens) {
+                    Ok(usage) => Some(usage),
+                    Err(window) => {
+                        return Ok(GatewayApiError::client_error(
+                            StatusCode::TOO_MANY_REQUESTS,
+                            format!("The {} token quota for this API key is exhausted", window),
+                            "quota_exceeded",
+                        )
+                        .into_response());
+                    }
+                }
+            }
+            None => None,
+        };
 
         let is_stream = if parts.method == Method::POST
             && parts
@@ -386,11 +2050,28 @@ pub async fn proxy(
         info!("is_stream: {is_stream:#?}");
 
         let messages = extract_messages(&json).unwrap_or_default();
-        info!("messages: {:#?}", &messages);
+        trace!("messages: {:#?}", &messages);
         let text_input = convert_messages_to_text_input(&messages);
-        info!("text_input: {:#?}", &text_input);
+        trace!("text_input: {:#?}", &text_input);
+        sanitize_time += sanitize_start.elapsed();
 
-        let client = reqwest::Client::new();
+        let mut json = apply_default_policy(json, config.default_policy.as_deref());
+        let requested_policy = extract_nim_llm_router_params(&json).map(|params| params.policy);
+        if let Some((experiment, variant)) = choose_experiment_variant(&config, &json) {
+            info!(
+                "Experiment {} routed to policy {}",
+                experiment.name, variant.policy
+            );
+            EXPERIMENT_VARIANT
+                .with_label_values(&[experiment.name.as_str(), variant.policy.as_str()])
+                .inc();
+            // Resolve as the variant from here on, which also keeps the
+            // variants' cache entries apart.
+            json["nim-llm-router"]["policy"] = Value::String(variant.policy.clone());
+            if experiment.echo_header {
+                experiment_variant = Some(variant.policy.clone());
+            }
+        }
 
         let policy = if let Some(nim_llm_router_params) = extract_nim_llm_router_params(&json) {
             match config.get_policy_by_name(nim_llm_router_params.policy.as_str()) {
@@ -407,13 +2088,173 @@ pub async fn proxy(
             return Ok(error.into_response());
         };
 
+        if let Some(response) = policy_not_allowed(
+            client_key,
+            &config.security.api_keys,
+            &policy.name,
+            requested_policy.as_deref(),
+        ) {
+            return Ok(response);
+        }
+
         REQUESTS_PER_POLICY
             .with_label_values(&[policy.name.as_str()])
             .inc();
+        access.policy = Some(policy.name.clone());
+
+        if let Some(response) = policy_rate_limited(
+            &parts,
+            &policy,
+            &config.security.rate_limit,
+            &policy_rate_limiter,
+        ) {
+            return Ok(response);
+        }
+
+        // Before the cache lookup, so the key reflects what is forwarded.
+        let sanitize_start = Instant::now();
+        let json = remove_forbidden_params(json, &policy);
+        let json = match enforce_param_constraints(json, &policy) {
+            Ok(json) => json,
+            Err(error) => return Ok(error.into_response()),
+        };
+        sanitize_time += sanitize_start.elapsed();
+
+        let cache_key = if is_cacheable(&config.caching, &policy, is_stream) {
+            let key = if embeddings {
+                generate_embeddings_key(&policy, &json)
+            } else {
+                generate_policy_key(&policy, &json)
+            };
+            Some((key, generate_scope(&policy, &json)))
+        } else {
+            None
+        };
+        let mut cache_embedding = None;
+
+        if let Some((key, scope)) = &cache_key {
+            // Set once an identical request finished while this one waited.
+            let mut coalesced = false;
+            loop {
+                let mut cached = cache.get(key);
+                if cached.is_some() {
+                    CACHE_HITS.with_label_values(&["exact"]).inc();
+                } else if !coalesced && !embeddings {
+                    // Similar inputs have different embeddings, so embeddings
+                    // requests only hit exact matches.
+                    if let Some(semantic) = &config.caching.semantic {
+                        match compute_embedding(&client, semantic, &text_input).await {
+                            Ok(embedding) => {
+                                cached = cache.get_similar(
+                                    scope,
+                                    &embedding,
+                                    semantic.similarity_threshold,
+                                );
+                                if cached.is_some() {
+                                    CACHE_HITS.with_label_values(&["semantic"]).inc();
+                                }
+                                cache_embedding = Some(embedding);
+                            }
+                            Err(e) => {
+                                error!("Failed to compute embedding for semantic cache: {}", e)
+                            }
+                        }
+                    }
+                }
+
+                // Compressed bodies are sent as is to clients that accept the
+                // encoding. Entries that fail to decompress count as misses.
+                let hit = cached.and_then(|cached| {
+                    let passthrough = cached.encoding.filter(|encoding| {
+                        !anthropic
+                            && accepts_encoding(&parts.headers, encoding.content_encoding())
+                    });
+                    let body = match passthrough {
+                        Some(_) => cached.body.clone(),
+                        None => cached
+                            .decoded_body()
+                            .map_err(|e| error!("Failed to decompress cached response: {}", e))
+                            .ok()?,
+                    };
+                    Some((cached, body, passthrough))
+                });
+                match hit {
+                    Some((cached, body, passthrough)) => {
+                        info!("Serving response from cache");
+                        cache_hit = true;
+                        access.model = Some(cached.model.clone());
+                        if let (Some(settings), Some((request, hash))) =
+                            (&config.caching.revalidation, &cached.revalidation)
+                        {
+                            if rand::random::<f64>() < settings.sample_rate {
+                                if let Some(llm) = policy.get_llm_by_name(&cached.classifier) {
+                                    spawn_cache_revalidation(
+                                        upstream_clients.for_llm(&llm),
+                                        &circuit_breakers,
+                                        &bulkhead,
+                                        llm,
+                                        forward_uri_path_and_query.to_string(),
+                                        request.clone(),
+                                        hash.clone(),
+                                    );
+                                }
+                            }
+                        }
+                        if coalesced {
+                            REQUEST_COALESCED.inc();
+                        }
+                        let cached_body = if anthropic {
+                            convert_response_body(&body)
+                        } else {
+                            body
+                        };
+                        let body = Full::from(cached_body)
+                            .map_err(|never| match never {})
+                            .boxed();
+                        let mut builder = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(
+                                "X-Chosen-Classifier",
+                                HeaderValue::from_str(&cached.classifier)?,
+                            );
+                        if cached.encoding.is_some() {
+                            builder = builder.header(VARY, "accept-encoding");
+                        }
+                        if let Some(encoding) = passthrough {
+                            builder =
+                                builder.header(CONTENT_ENCODING, encoding.content_encoding());
+                        }
+                        let client_res = builder.body(body)?;
+                        return Ok(client_res);
+                    }
+                    // Only one of several identical requests goes upstream; the
+                    // others retry the cache once it is done, or get its
+                    // response if it failed.
+                    None => match coalescer.join(key) {
+                        Flight::Leader(guard) => {
+                            CACHE_MISSES.inc();
+                            flight = Some(guard);
+                            break;
+                        }
+                        Flight::Follower(done) => {
+                            if let Some(failure) = wait_for_leader(done).await {
+                                REQUEST_COALESCED.inc();
+                                return Ok(failure.response());
+                            }
+                            coalesced = true;
+                        }
+                    },
+                }
+            }
+        }
 
         let routing_strategy =
-            extract_nim_llm_router_params(&json).and_then(|params| params.routing_strategy);
+            extract_nim_llm_router_params(&json).and_then(|params| params.strategy());
 
+        // Set when manual routing went through `model_aliases`, so metrics
+        // show the name the client asked for.
+        let mut requested_alias = None;
         let model_index = match routing_strategy {
             Some(RoutingStrategy::Manual) => {
                 ROUTING_POLICY_USAGE.with_label_values(&["manual"]).inc();
@@ -423,8 +2264,13 @@ pub async fn proxy(
                             message: "No model specified for manual routing".to_string(),
                         }
                     })?;
-                    match policy.llms.iter().position(|llm| llm.name == model) {
-                        Some(index) => index,
+                    match policy.find_llm(&model, &config.model_aliases) {
+                        Some((index, aliased)) => {
+                            if aliased {
+                                requested_alias = Some(model);
+                            }
+                            index
+                        }
                         None => {
                             let error_body = format!("Model not found: {}", model);
                             let body = Full::from(error_body.into_bytes())
@@ -452,7 +2298,11 @@ pub async fn proxy(
                 let threshold = extract_nim_llm_router_params(&json)
                     .and_then(|params| params.threshold)
                     .unwrap_or(0.5);
-                let triton_text = get_last_message_for_triton(&messages);
+                let triton_text = if embeddings {
+                    get_embeddings_input_for_triton(&json)
+                } else {
+                    get_last_message_for_triton(&messages)
+                };
                 match choose_model(&policy, &client, &triton_text, threshold).await {
                     Ok(index) => {
                         model_selection_time = selection_start.elapsed().as_secs_f64();
@@ -489,6 +2339,17 @@ pub async fn proxy(
             }
         };
 
+        let model_index = resolve_tool_support(&policy, model_index, &json)?;
+        let allowed_index = allowed_model_index(&policy, model_index);
+        if !matches!(allowed_index, Ok(index) if index == model_index) {
+            MODEL_SELECTION_REJECTED
+                .with_label_values(&[policy.name.as_str()])
+                .inc();
+        }
+        let model_index = match allowed_index {
+            Ok(index) => index,
+            Err(error) => return Ok(error.into_response()),
+        };
         let chosen_llm = policy.get_llm_by_index(model_index).ok_or_else(|| {
             GatewayApiError::ModelNotFound(format!("LLM not found at index {}", model_index))
         })?;
@@ -500,120 +2361,457 @@ pub async fn proxy(
         info!("Chosen Classifier: {:#?}", &chosen_classifier);
 
         REQUESTS_PER_MODEL
-            .with_label_values(&[chosen_llm.name.as_str()])
+            .with_label_values(&[requested_alias.as_deref().unwrap_or(&chosen_llm.name)])
             .inc();
 
-        let api_base = &chosen_llm.api_base;
-        let api_key = &chosen_llm.api_key;
+        let session_key = session_key(&parts, &config.load_balancing);
+        let health_max_age = Duration::from_secs(config.server.health_cache_secs);
+        let is_available = |instance: &str| {
+            circuit_breakers.is_available(instance)
+                && !health_cache.is_failing(instance, health_max_age)
+        };
+        let api_base = &balancer.select_instance(
+            &config.load_balancing,
+            &chosen_llm,
+            session_key.as_deref(),
+            is_available,
+        );
         let model = &chosen_llm.model;
 
         info!("api_base: {:#?}", api_base);
         info!("model: {:#?}", model);
+        access.model = Some(model.clone());
+        access.api_base = Some(api_base.clone());
+        // Charged once, for the response the client gets.
+        let usage = UsageAccounting {
+            llm_name: chosen_llm.name.clone(),
+            quota: quota_usage,
+            cost: chosen_llm.pricing.map(|pricing| CostUsage {
+                api_key_id: client_key.map_or(ANONYMOUS_KEY_ID.to_string(), |key| {
+                    config.security.api_keys.key_id(key)
+                }),
+                model: model.clone(),
+                pricing,
+            }),
+            tenant: tenant.clone(),
+        };
 
+        let sanitize_start = Instant::now();
         let json = remove_nim_llm_router_params(json);
-        info!("json after removing nim llm router params: {json:?}");
+        trace!("json after removing nim llm router params: {json:?}");
+        // Prompts and sampling parameters only apply to generation.
+        let json = match &policy.system_prompt {
+            Some(system_prompt) if !embeddings => apply_system_prompt(json, system_prompt),
+            _ => json,
+        };
+
+        if let Some(shadow) = &policy.shadow {
+            if rand::random::<f64>() < shadow.sample_rate {
+                if let Some(shadow_llm) = policy.get_llm_by_name(&shadow.llm) {
+                    spawn_shadow_request(
+                        upstream_clients.for_llm(&shadow_llm),
+                        shadow_llm,
+                        forward_uri_path_and_query.to_string(),
+                        json.clone(),
+                    );
+                }
+            }
+        }
 
+        let json = if embeddings {
+            json
+        } else {
+            let json = apply_default_params(json, chosen_llm.default_params.as_ref());
+            clamp_max_tokens(json, &policy)
+        };
         let json = modify_model(json, model)?;
-        debug!("json after modifying model: {:#?}", &json);
+        trace!("json after modifying model: {:#?}", &json);
+        sanitize_time += sanitize_start.elapsed();
+        SANITIZE_DURATION.observe(sanitize_time.as_secs_f64());
+        body_logger.log_prompt(&policy.name, &chosen_llm.name, &json);
 
         // Turn on this line if you want to include usage options in the request
         // let json = if is_stream { include_usage(json) } else { json };
         // info!("json after including usage options: {:#?}", &json);
 
         let method = http::Method::POST;
-        let mut headers = http::HeaderMap::new();
-        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", api_key))?,
+        let mut headers = forwarded_headers(
+            &parts.headers,
+            &config.client,
+            &config.security.api_key_headers,
         );
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.extend(chosen_llm.upstream_headers(&parts.headers));
+        let (auth_name, auth_value) = chosen_llm.auth_header();
+        headers.insert(auth_name, HeaderValue::from_str(&auth_value)?);
+        if let Some(request_id) = parts.headers.get(REQUEST_ID_HEADER) {
+            headers.insert(REQUEST_ID_HEADER, request_id.clone());
+        }
 
-        let uri = format!("{}{}", api_base, forward_uri_path_and_query);
-        let mut reqwest_request = client.request(method, uri).json(&json);
-        info!("reqwest_request: {reqwest_request:#?}");
+        // Anthropic and Cohere stream in their own event formats, which are
+        // not translated into chat completion chunks.
+        if is_stream && chosen_llm.provider_type.transforms_body() {
+            let message = format!("Streaming from {} is not supported", chosen_llm.name);
+            return Ok(GatewayApiError::client_error(
+                StatusCode::BAD_REQUEST,
+                message,
+                "stream_not_supported",
+            )
+            .into_response());
+        }
 
-        for (name, value) in headers.iter() {
-            reqwest_request = reqwest_request.header(name, value);
+        if let Some(limit) = chosen_llm.max_context_tokens {
+            let client = upstream_clients.for_llm(&chosen_llm);
+            let tokens = count_prompt_tokens(&client, &chosen_llm, api_base, &json).await;
+            if let Some(tokens) = tokens.filter(|tokens| *tokens > limit) {
+                let message = format!(
+                    "The prompt is about {} tokens, over the {} token context of {}",
+                    tokens, limit, chosen_llm.name
+                );
+                return Ok(GatewayApiError::client_error(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    "context_length_exceeded",
+                )
+                .into_response());
+            }
+        }
+        let json = to_provider_request(&chosen_llm.provider_type, json);
+        let (body, compressed) = upstream_body(&json, &chosen_llm)?;
+        let estimated_prompt_tokens = (is_stream && chosen_llm.estimate_stream_usage)
+            .then(|| estimate_prompt_tokens(&json));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if compressed {
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
         }
 
+        let client = upstream_clients.for_llm(&chosen_llm);
+        let path_and_query = forward_uri_path_and_query.to_string();
+        let body = Bytes::from(body);
+        let timeout = requested_timeout(&parts.headers, config.client.max_request_timeout_ms)
+            .or(chosen_llm.request_timeout_secs.map(Duration::from_secs));
+        // Buffered responses only send headers once the whole completion is
+        // generated, so a slow first byte does not mean a stalled LLM.
+        let first_byte_timeout = config
+            .client
+            .first_byte_timeout_secs
+            .filter(|_| is_stream)
+            .map(Duration::from_secs);
+        let retry_config = &config.client.retry;
+        let retry_on_timeout = policy.retry_on_timeout;
+        let breakers = &circuit_breakers;
+        // Sends the request to one instance, with retries, and reports the
+        // outcome to its circuit breaker. The permit is held until the
+        // response is done with.
+        let send = |instance: String, permit: ConcurrencyPermit| {
+            let uri = chosen_llm.upstream_url(&instance, &path_and_query);
+            let mut reqwest_request = client.request(method.clone(), uri).body(body.clone());
+            if let Some(timeout) = timeout {
+                // Overrides the client-wide timeout for this call only; each
+                // retry gets the full timeout again.
+                reqwest_request = reqwest_request.timeout(timeout);
+            }
+            for (name, value) in headers.iter() {
+                reqwest_request = reqwest_request.header(name, value);
+            }
+            trace!("reqwest_request: {reqwest_request:#?}");
+            let in_flight = balancer.start_request(&instance);
+            let breaker = breakers.get(&instance);
+            let balancer = &*balancer;
+            // Only one request at a time tries a half-open instance; the
+            // balancer sends the others elsewhere unless no instance is left.
+            let admission = breaker.admit();
+            async move {
+                let (result, retries) =
+                    with_retry(retry_config, retry_on_timeout, first_byte_timeout, || {
+                        reqwest_request
+                            .try_clone()
+                            .expect("buffered request bodies can be cloned")
+                            .send()
+                    })
+                    .await;
+                match &result {
+                    Ok(response) => match FailureKind::from_status(response.status()) {
+                        Some(kind) => {
+                            breaker.record_failure(kind);
+                            balancer.record_failure(&instance);
+                        }
+                        None => breaker.record_success(),
+                    },
+                    Err(e) => {
+                        if let Some(kind) = FailureKind::from_error(e) {
+                            breaker.record_failure(kind);
+                            balancer.record_failure(&instance);
+                        }
+                    }
+                }
+                drop(admission);
+                (result, retries, in_flight, permit)
+            }
+        };
+        // A hedge duplicates the completion, so only requests that may be
+        // processed twice are hedged. Streams cannot be raced, and a hedge
+        // needs another instance.
+        let may_duplicate =
+            policy.retry_on_timeout || parts.headers.contains_key(IDEMPOTENCY_KEY_HEADER);
+        let hedge = policy
+            .hedge_after_ms
+            .filter(|_| may_duplicate && !is_stream)
+            .and_then(|hedge_after_ms| {
+                let instance = balancer.peek_instance(
+                    &config.load_balancing,
+                    &chosen_llm,
+                    None,
+                    |instance| instance != api_base.as_str() && is_available(instance),
+                );
+                (instance != *api_base && is_available(&instance))
+                    .then(|| (hedge_delay(hedge_after_ms, balancer.latency(api_base)), instance))
+            });
+
+        let connect_start = Instant::now();
+        let permit = bulkhead.acquire(&chosen_llm, &parts.headers).await?;
         let llm_req_start = Instant::now();
-        let reqwest_response = reqwest_request.send().await.map_err(|e| {
+        let primary = send(api_base.clone(), permit);
+        let ((reqwest_response, retries, in_flight, permit), winner) = match &hedge {
+            Some((delay, instance)) => {
+                race_hedge(
+                    primary,
+                    *delay,
+                    || {
+                        // Counted against the LLM's concurrency limit like
+                        // any other request, and skipped when it is reached.
+                        let Some(permit) = bulkhead.try_acquire(&chosen_llm) else {
+                            info!("Not hedging the request to {}, no slot is free", api_base);
+                            return None;
+                        };
+                        info!("Hedging the request to {} with {}", api_base, instance);
+                        Some(send(instance.clone(), permit))
+                    },
+                    |(result, _, _, _)| {
+                        result
+                            .as_ref()
+                            .is_ok_and(|response| response.status().is_success())
+                    },
+                )
+                .await
+            }
+            None => (primary.await, None),
+        };
+        if let Some(winner) = winner {
+            HEDGED_REQUESTS
+                .with_label_values(&[chosen_llm.name.as_str(), winner.as_str()])
+                .inc();
+        }
+        let api_base = match (winner, &hedge) {
+            (Some(HedgeWinner::Hedge), Some((_, instance))) => {
+                access.api_base = Some(instance.clone());
+                instance
+            }
+            _ => api_base,
+        };
+        access.retries = Some(retries);
+
+        // `with_retry` only gives up on a failure that is final: not
+        // retryable, or out of retries. The fallback replaces it once the
+        // breaker of every instance of the LLM is open, so a failing instance
+        // among healthy ones is not masked; without breakers, right away.
+        let exhausted_fallback = || {
+            let fallback = policy.fallback_response.as_ref()?;
+            let all_open = !config.circuit_breaker.enabled
+                || chosen_llm
+                    .api_bases()
+                    .into_iter()
+                    .all(|instance| circuit_breakers.state(instance) == CircuitState::Open);
+            (!is_stream && all_open).then_some(fallback)
+        };
+        if let (Err(e), Some(fallback)) = (&reqwest_response, exhausted_fallback()) {
+            warn!(
+                "Failed to reach {} ({}), serving the fallback response of policy {}",
+                chosen_llm.name, e, policy.name
+            );
+            return fallback_response(&policy.name, fallback, anthropic);
+        }
+        let reqwest_response = reqwest_response.map_err(|e| {
             error!("Failed to reach LLM server: {:?}", e);
+            let (status, message) = if let SendError::FirstByteTimeout(_) = e {
+                (StatusCode::GATEWAY_TIMEOUT, "LLM server did not start responding in time")
+            } else if e.is_timeout() {
+                (StatusCode::GATEWAY_TIMEOUT, "LLM server timed out")
+            } else {
+                (StatusCode::SERVICE_UNAVAILABLE, "LLM server is unreachable")
+            };
             GatewayApiError::LlmServiceError {
-                status: StatusCode::SERVICE_UNAVAILABLE,
-                message: "LLM server is unreachable".to_string(),
+                status,
+                message: message.to_string(),
                 provider: chosen_llm.name.clone(),
+                code: None,
                 details: None,
             }
         })?;
         let current_llm_resp = llm_req_start.elapsed().as_secs_f64();
+        UPSTREAM_CONNECT_DURATION
+            .with_label_values(&[chosen_llm.name.as_str()])
+            .observe(connect_start.elapsed().as_secs_f64());
         {
             let mut guard = llm_resp_time_holder.lock().await;
             *guard = current_llm_resp;
         }
         LLM_RESPONSE_TIME
-            .with_label_values(&[chosen_llm.name.as_str()])
+            .with_label_values(&[chosen_llm.name.as_str(), "false"])
             .observe(current_llm_resp);
 
         let status = reqwest_response.status();
+        // Failures were recorded as slow responses by `send`; a fast error
+        // must not make an instance look fast.
+        if status.is_success() {
+            let latency = Duration::from_secs_f64(current_llm_resp);
+            let latency = match (winner, &hedge) {
+                (Some(HedgeWinner::Hedge), Some((delay, _))) => latency.saturating_sub(*delay),
+                _ => latency,
+            };
+            balancer.record_latency(api_base, latency);
+        }
         let headers = reqwest_response.headers().clone();
 
-        // If status is not successful, pass through the error response
-        if !status.is_success() {
-            let error_body = reqwest_response.bytes().await?;
-            let status_code = status.as_u16();
-            info!("status_code: {status_code:#?}");
-
-            // Create a response that directly uses the error body
-            let body = Full::from(error_body)
-                .map_err(|never| match never {})
-                .boxed();
-
-            let mut error_response = Response::builder()
-                .status(status)
-                .header(CONTENT_TYPE, "application/json")
-                .body(body)?;
-
-            // Add the original headers and classifier
-            *error_response.headers_mut() = headers;
+        // The provider refused our key. Passing its 401/403 on would have the
+        // client look for a problem with its own key.
+        if FailureKind::from_status(status) == Some(FailureKind::Auth) {
+            error!(
+                "LLM {} rejected the configured API key with {}",
+                chosen_llm.name, status
+            );
+            PROVIDER_AUTH_FAILURES
+                .with_label_values(&[chosen_llm.name.as_str()])
+                .inc();
+            let mut error_response = GatewayApiError::LlmAuthError {
+                status,
+                provider: chosen_llm.name.clone(),
+            }
+            .into_response();
             error_response.headers_mut().insert(
                 "X-Chosen-Classifier",
                 HeaderValue::from_str(&chosen_classifier).unwrap(),
             );
-
-            error!("error_response: {error_response:#?}");
+            return Ok(error_response);
+        }
+
+        if status.is_server_error() {
+            if let Some(fallback) = exhausted_fallback() {
+                warn!(
+                    "{} returned {}, serving the fallback response of policy {}",
+                    chosen_llm.name, status, policy.name
+                );
+                return fallback_response(&policy.name, fallback, anthropic);
+            }
+        }
+
+        // If status is not successful, pass through the error response
+        if !status.is_success() {
+            let error_body = read_response_body(
+                reqwest_response,
+                config.server.max_response_body_bytes,
+                &chosen_llm.name,
+            )
+            .await?;
+            let status_code = status.as_u16();
+            info!("status_code: {status_code:#?}");
+
+            // Re-emit the provider's error in the gateway's OpenAI-style shape
+            let mut error_response =
+                GatewayApiError::from_provider_response(status, &chosen_llm.name, &error_body)
+                    .into_response();
+            if let Some(retry_after) = headers.get(RETRY_AFTER) {
+                error_response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, retry_after.clone());
+            }
+            error_response.headers_mut().insert(
+                "X-Chosen-Classifier",
+                HeaderValue::from_str(&chosen_classifier).unwrap(),
+            );
+
+            error!("error_response: {error_response:#?}");
             return Ok(error_response);
         }
 
         if is_stream {
             let stream = reqwest_response.bytes_stream();
-            let body = ReqwestStreamAdapter {
-                inner: Box::pin(stream),
-                llm_name: chosen_llm.name.clone(),
+            let mut body = ReqwestStreamAdapter::new(Box::pin(stream), chosen_llm.name.clone());
+            body.usage = Some(usage);
+            body.estimated_prompt_tokens = estimated_prompt_tokens;
+            if let Some(secs) = config.server.stream_keepalive_secs {
+                body.keep_alive(Duration::from_secs(secs));
+            }
+            body.truncate_on_error = config.server.truncate_streams_on_error;
+            body.in_flight = Some(in_flight);
+            body.permit = Some(permit);
+            let boxed_body = if anthropic {
+                BoxBody::new(AnthropicStream::new(BoxBody::new(body)))
+            } else {
+                BoxBody::new(body)
             };
-            let boxed_body = BoxBody::new(body);
 
             let mut client_res = Response::new(boxed_body);
             *client_res.status_mut() = status;
             *client_res.headers_mut() = headers;
+            if anthropic {
+                client_res.headers_mut().remove(CONTENT_LENGTH);
+            }
             client_res.headers_mut().insert(
                 "X-Chosen-Classifier",
                 HeaderValue::from_str(&chosen_classifier).unwrap(),
             );
             Ok(client_res)
         } else {
-            let body_bytes = reqwest_response.bytes().await?;
+            let body_bytes = read_response_body(
+                reqwest_response,
+                config.server.max_response_body_bytes,
+                &chosen_llm.name,
+            )
+            .await?;
+            let transformed = chosen_llm.provider_type.transforms_body();
+            let body_bytes = from_provider_response_body(&chosen_llm.provider_type, body_bytes);
             let body_clone = body_bytes.clone();
             // Parse and track token usage for non-streaming response
             if let Ok(json) = serde_json::from_slice::<Value>(&body_clone) {
-                track_token_usage(&json, &chosen_llm.name);
+                usage.record(&json);
+                access.set_usage(&json);
+                body_logger.log_completion(&policy.name, &chosen_llm.name, &json);
+            }
+            if let Some((key, scope)) = cache_key {
+                let revalidation = config
+                    .caching
+                    .revalidation
+                    .is_some()
+                    .then(|| (json.clone(), content_hash(&body_clone)));
+                cache.set_with_ttl(
+                    key,
+                    scope,
+                    CachedResponse {
+                        body: body_clone,
+                        classifier: chosen_classifier.clone(),
+                        model: model.clone(),
+                        encoding: None,
+                        revalidation,
+                    },
+                    cache_embedding,
+                    Duration::from_secs(policy.cache_ttl_seconds(&config.caching)),
+                );
             }
+            let body_bytes = if anthropic {
+                convert_response_body(&body_bytes)
+            } else {
+                body_bytes
+            };
             let body = Full::from(body_bytes)
                 .map_err(|never| match never {}) // never happens
                 .boxed();
 
             let mut client_res = Response::builder().status(status).body(body)?;
             *client_res.headers_mut() = headers;
+            if anthropic || transformed {
+                client_res.headers_mut().remove(CONTENT_LENGTH);
+            }
             client_res.headers_mut().insert(
                 "X-Chosen-Classifier",
                 HeaderValue::from_str(&chosen_classifier).unwrap(),
@@ -623,6 +2821,23 @@ pub async fn proxy(
         }
     })
     .await;
+    if let Some(flight) = flight {
+        result = flight.finish(result).await;
+    }
+
+    if let (Ok(response), Some(variant)) = (&mut result, experiment_variant) {
+        if let Ok(value) = HeaderValue::from_str(&variant) {
+            response.headers_mut().insert("X-Experiment-Variant", value);
+        }
+    }
+    if let Ok(response) = &mut result {
+        set_routing_headers(
+            response.headers_mut(),
+            &config.observability,
+            &access,
+            cache_hit,
+        );
+    }
 
     let overall_latency = overall_start.elapsed().as_secs_f64();
     REQUEST_LATENCY.observe(overall_latency);
@@ -630,6 +2845,19 @@ pub async fn proxy(
     let llm_resp_time = *llm_resp_time_holder.lock().await;
     let proxy_overhead = overall_latency - llm_resp_time - model_selection_time;
     PROXY_OVERHEAD_LATENCY.observe(proxy_overhead);
+    if let (Ok(response), true) = (&mut result, config.observability.server_timing) {
+        let timing = server_timing(model_selection_time, llm_resp_time, proxy_overhead);
+        response.headers_mut().insert(SERVER_TIMING_HEADER, timing);
+    }
+
+    access.status = match &result {
+        Ok(response) => response.status().as_u16(),
+        Err(e) => e.status_code().as_u16(),
+    };
+    access.latency_ms = overall_latency * 1000.0;
+    access.overhead_ms = proxy_overhead * 1000.0;
+    access.emit(&config.observability);
+    events.publish(|| RoutingEvent::new(&access, cache_hit));
 
     match &result {
         Ok(response) => {
@@ -659,10 +2887,18 @@ pub async fn proxy(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Llm;
-    use hyper::body::Body;
+    use crate::config::{
+        ApiKeyQuota, BatchingConfig, CacheCompression, CacheRevalidationConfig, Experiment,
+        ExperimentVariant, Instance, PerIpRateLimit, PolicyCachingConfig, ProviderType,
+        ShadowConfig, UNKNOWN_TENANT,
+    };
+    use crate::metrics::{TOKEN_USAGE, TOKEN_USAGE_PER_TENANT};
     use hyper::Request;
+    use reqwest::header::AUTHORIZATION;
     use serde_json::json;
+    use std::collections::BTreeMap;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     fn create_test_config() -> RouterConfig {
         RouterConfig {
@@ -675,21 +2911,40 @@ mod tests {
                         api_base: "https://integrate.api.nvidia.com".to_string(),
                         api_key: "test-key".to_string(),
                         model: "meta/llama-3.1-8b-instruct".to_string(),
+                        ..Llm::default()
                     },
                     Llm {
                         name: "Code Generation".to_string(),
                         api_base: "https://integrate.api.nvidia.com".to_string(),
                         api_key: "test-key".to_string(),
                         model: "meta/llama-3.1-8b-instruct".to_string(),
+                        ..Llm::default()
                     },
                 ],
+                ..Policy::default()
             }],
+            ..RouterConfig::default()
         }
     }
 
+    fn create_test_state() -> AppState {
+        AppState::new(create_test_config()).expect("Failed to create state")
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_draining() {
+        let response = health(false).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = health(true).unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "Draining");
+    }
+
     #[tokio::test]
     async fn test_missing_nim_llm_router_params() {
-        let config = create_test_config();
         let body = json!({
             "messages": [{"role": "user", "content": "Hello"}]
         });
@@ -701,13 +2956,12 @@ mod tests {
             .body(Full::new(Bytes::from(serde_json::to_vec(&body).unwrap())))
             .expect("Failed to create request");
 
-        let response = proxy(req, config).await.unwrap();
+        let response = proxy(req, create_test_state()).await.unwrap();
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
     async fn test_policy_not_found() {
-        let config = create_test_config();
         let body = json!({
             "messages": [{"role": "user", "content": "Hello"}],
             "nim-llm-router": {
@@ -721,16 +2975,38 @@ mod tests {
             .method("POST")
             .uri("/v1/chat/completions")
             .header("content-type", "application/json")
-            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .body(Full::new(Bytes::from(serde_json::to_vec(&body).unwrap())))
             .expect("Failed to create request");
 
-        let response = proxy(req, config).await.unwrap();
+        let response = proxy(req, create_test_state()).await.unwrap();
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn test_request_id_is_echoed_in_error_responses() {
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual"
+            }
+        });
+        let mut req = create_request(&body);
+        req.headers_mut()
+            .insert(REQUEST_ID_HEADER, HeaderValue::from_static("ticket-123"));
+
+        // Manual routing without a model fails inside the route, which used to
+        // drop the connection instead of answering.
+        let response = handler(req, create_test_state()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "ticket-123");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["request_id"], "ticket-123");
+    }
+
     #[tokio::test]
     async fn test_model_not_found() {
-        let config = create_test_config();
         let body = json!({
             "messages": [{"role": "user", "content": "Hello"}],
             "nim-llm-router": {
@@ -744,10 +3020,2561 @@ mod tests {
             .method("POST")
             .uri("/v1/chat/completions")
             .header("content-type", "application/json")
-            .body(hyper::Body::from(serde_json::to_vec(&body).unwrap()))
+            .body(Full::new(Bytes::from(serde_json::to_vec(&body).unwrap())))
             .expect("Failed to create request");
 
-        let response = proxy(req, config).await.unwrap();
+        let response = proxy(req, create_test_state()).await.unwrap();
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    fn create_request(body: &Value) -> Request<Full<Bytes>> {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_vec(body).unwrap())))
+            .expect("Failed to create request")
+    }
+
+    #[tokio::test]
+    async fn test_per_llm_timeout_overrides_client_default() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"choices": []}))
+                    .set_delay(Duration::from_secs(3)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.request_timeout_secs = Some(30);
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].llms[0].request_timeout_secs = Some(1);
+        let state = AppState::new(config).unwrap();
+
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let start = Instant::now();
+        let err = proxy(create_request(&body), state).await.unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert_eq!(err.status_code(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_request_body_limit() {
+        let mut config = create_test_config();
+        config.server.max_request_body_bytes = Some(16);
+        let body = json!({
+            "messages": [{"role": "user", "content": "This prompt is far too long"}]
+        });
+
+        // Rejected up front from the Content-Length header.
+        let mut req = create_request(&body);
+        req.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from_static("1024"));
+        let response = proxy(req, AppState::new(config.clone()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Without a Content-Length header the limit is enforced while reading.
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_response_body_limit() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(1024)))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.server.max_response_body_bytes = Some(512);
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let err = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_failure_does_not_affect_primary() {
+        let primary = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&primary)
+            .await;
+        let shadow = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(wiremock::matchers::body_partial_json(
+                json!({"stream": false}),
+            ))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&shadow)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = primary.uri();
+        config.policies[0].llms[1].api_base = shadow.uri();
+        config.policies[0].shadow = Some(ShadowConfig {
+            llm: "Code Generation".to_string(),
+            sample_rate: 1.0,
+        });
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "stream": true,
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while shadow.received_requests().await.unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "shadow request was never sent");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quota_rejects_requests_once_exhausted() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [],
+                "usage": {"prompt_tokens": 6, "completion_tokens": 4, "total_tokens": 10}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.security.quotas = vec![ApiKeyQuota {
+            api_key: "client-key".to_string(),
+            daily_tokens: Some(10),
+            monthly_tokens: None,
+        }];
+        config.security.admin_api_key = Some("admin-key".to_string());
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let request = || {
+            let mut request = create_request(&body);
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, HeaderValue::from_static("Bearer client-key"));
+            request
+        };
+
+        let response = proxy(request(), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = proxy(request(), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let key_id = state.config.security.api_keys.key_id("client-key");
+        let status = Request::builder()
+            .uri(format!("/admin/quota/{}", key_id))
+            .header(AUTHORIZATION, "Bearer admin-key")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handler(status, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["daily"]["used"], 10);
+        assert_eq!(json["daily"]["remaining"], 0);
+        assert!(json["monthly"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_skips_auth_and_responses_get_cors_headers() {
+        let mut config = create_test_config();
+        config.security.admin_api_key = Some("admin-key".to_string());
+        config.cors.allowed_origins = vec!["https://app.example.com".to_string()];
+        let state = AppState::new(config).unwrap();
+
+        let preflight = Request::builder()
+            .method("OPTIONS")
+            .uri("/admin/config")
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "authorization")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handler(preflight, state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+
+        // Rejected requests still carry the headers, so browsers can read
+        // the error.
+        let request = Request::builder()
+            .uri("/admin/config")
+            .header("origin", "https://app.example.com")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handler(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints_are_disabled_without_admin_key() {
+        let state = AppState::new(create_test_config()).unwrap();
+        let request = Request::builder()
+            .method("POST")
+            .uri("/admin/reload")
+            .header(AUTHORIZATION, "Bearer anything")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handler(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        let message = json["error"]["message"].as_str().unwrap();
+        assert!(message.contains("security.admin_api_key"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_admin_config_is_sanitized_and_requires_admin_key() {
+        let mut config = create_test_config();
+        config.security.admin_api_key = Some("admin-key".to_string());
+        let state = AppState::new(config).unwrap();
+        let request = |token: &'static str| {
+            Request::builder()
+                .uri("/admin/config")
+                .header(AUTHORIZATION, token)
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let response = handler(request("Bearer wrong"), state.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = handler(request("Bearer admin-key"), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["config_loaded_at"].as_u64().unwrap() > 0);
+        let config = &json["config"];
+        assert_eq!(config["policies"][0]["llms"][0]["api_key"], "[REDACTED]");
+        assert_eq!(config["security"]["admin_api_key"], "[REDACTED]");
+    }
+
+    #[tokio::test]
+    async fn test_experiment_routes_to_variant_and_echoes_it() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.experiments = vec![Experiment {
+            name: "ab_test".to_string(),
+            variants: vec![ExperimentVariant {
+                policy: "test_policy".to_string(),
+                weight: 1,
+            }],
+            echo_header: true,
+        }];
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "ab_test",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Experiment-Variant"], "test_policy");
+    }
+
+    #[tokio::test]
+    async fn test_upstream_gets_allowlisted_headers_and_provider_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(wiremock::matchers::header(
+                "authorization",
+                "Bearer test-key",
+            ))
+            .and(wiremock::matchers::header("x-request-id", "req-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.forward_headers = vec!["X-Request-Id".to_string()];
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let mut request = create_request(&body);
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer client-key"));
+        headers.insert("x-request-id", HeaderValue::from_static("req-1"));
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+
+        let response = proxy(request, AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let received = &mock_server.received_requests().await.unwrap()[0];
+        assert!(received.headers.get("cookie").is_none());
+        assert_eq!(received.headers.get_all("authorization").iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_key_is_not_forwarded_in_query_or_key_header() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.forward_headers = vec!["*".to_string()];
+        config.security.api_key_headers = vec!["X-Client-Key".to_string()];
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let mut request = create_request(&body);
+        *request.uri_mut() = "/v1/chat/completions?api_key=client-key&trace=1"
+            .parse()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("x-client-key", HeaderValue::from_static("client-key"));
+
+        let response = proxy(request, AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let received = &mock_server.received_requests().await.unwrap()[0];
+        assert_eq!(received.url.query(), Some("trace=1"));
+        assert!(received.headers.get("x-client-key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_azure_llm_uses_deployment_url_and_api_key_header() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt-4o/chat/completions"))
+            .and(query_param("api-version", "2024-06-01"))
+            .and(wiremock::matchers::header("api-key", "test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.model = "gpt-4o".to_string();
+        llm.provider_type = ProviderType::Azure;
+        llm.api_version = Some("2024-06-01".to_string());
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let received = &mock_server.received_requests().await.unwrap()[0];
+        assert!(received.headers.get("authorization").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_llm_gets_messages_request_and_key_header() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", "test-key"))
+            .and(body_partial_json(json!({
+                "system": "Be brief.",
+                "stop_sequences": ["END"],
+                "max_tokens": 4096
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_01",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet",
+                "content": [{"type": "text", "text": "Hi there"}],
+                "stop_reason": "end_turn",
+                "stop_sequence": null,
+                "usage": {"input_tokens": 3, "output_tokens": 5}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.provider_type = ProviderType::Anthropic;
+        let body = json!({
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hello"}
+            ],
+            "stop": ["END"],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["choices"][0]["message"]["content"], "Hi there");
+        assert_eq!(json["choices"][0]["finish_reason"], "stop");
+        assert_eq!(json["usage"]["prompt_tokens"], 3);
+        assert_eq!(json["usage"]["completion_tokens"], 5);
+
+        let received = &mock_server.received_requests().await.unwrap()[0];
+        assert!(received.headers.get("authorization").is_none());
+        let sent: Value = serde_json::from_slice(&received.body).unwrap();
+        assert!(sent.get("stop").is_none());
+        assert_eq!(
+            sent["messages"],
+            json!([{"role": "user", "content": "Hello"}])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streams_to_anthropic_llms_are_rejected() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.provider_type = ProviderType::Anthropic;
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "stream": true,
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Streaming from Brainstroming is not supported"));
+    }
+
+    #[tokio::test]
+    async fn test_first_byte_timeout_does_not_resend_buffered_completions() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"choices": []}))
+                    .set_delay(Duration::from_millis(1500)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.first_byte_timeout_secs = Some(1);
+        config.client.retry.max_retries = 2;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Write a long story"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prompts_over_max_context_tokens_are_rejected() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/tokenize"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"count": 50})))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.max_context_tokens = Some(10);
+        let body = |content: &str| {
+            json!({
+                "messages": [{"role": "user", "content": content}],
+                "nim-llm-router": {
+                    "policy": "test_policy",
+                    "routing_strategy": "manual",
+                    "model": "Brainstroming"
+                }
+            })
+        };
+
+        // 41 characters are estimated at 11 tokens.
+        let state = AppState::new(config.clone()).unwrap();
+        let long = body(&"a".repeat(41));
+        let response = proxy(create_request(&long), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error = response.into_body().collect().await.unwrap().to_bytes();
+        let error: Value = serde_json::from_slice(&error).unwrap();
+        let message = error["error"]["message"].as_str().unwrap();
+        assert!(message.contains("about 11 tokens, over the 10 token context"));
+
+        config.policies[0].llms[0].token_estimator = TokenEstimator::Tokenize;
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body("Hello")), state.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // Without a tokenizer to ask, the request is forwarded.
+        let response = proxy(create_request(&body("Hello")), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_identical_requests_are_served_from_cache() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.security.admin_api_key = Some("admin-key".to_string());
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        for _ in 0..2 {
+            let response = proxy(create_request(&body), state.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["X-Chosen-Classifier"], "Brainstroming");
+        }
+
+        let stats = Request::builder()
+            .uri("/admin/cache/stats")
+            .header(AUTHORIZATION, "Bearer admin-key")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handler(stats, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let stats: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats["active_entries"], 1);
+        assert!(stats["hits"].as_u64().unwrap() >= 1);
+        assert!(stats["memory_bytes"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_sampled_cache_hits_are_revalidated() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "first",
+                "choices": [{"message": {"role": "assistant", "content": "Hi"}}]
+            })))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "second",
+                "choices": [{"message": {"role": "assistant", "content": "Hello"}}]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.caching.revalidation = Some(CacheRevalidationConfig { sample_rate: 1.0 });
+        config.policies[0].llms[0].name = "revalidation-test-llm".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "revalidation-test-llm"
+            }
+        });
+        let stale = CACHE_STALENESS_DETECTED.with_label_values(&["revalidation-test-llm"]);
+
+        for _ in 0..2 {
+            let response = proxy(create_request(&body), state.clone()).await.unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            // The client always gets the cached response.
+            assert_eq!(json["id"], "first");
+        }
+        for _ in 0..50 {
+            if stale.get() > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(stale.get(), 1);
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_revalidation_compares_transformed_provider_responses() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "same",
+                "finish_reason": "COMPLETE",
+                "message": {"role": "assistant", "content": [{"type": "text", "text": "Hi"}]},
+                "usage": {"tokens": {"input_tokens": 3, "output_tokens": 1}}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.caching.revalidation = Some(CacheRevalidationConfig { sample_rate: 1.0 });
+        config.policies[0].llms[0].name = "revalidation-provider-llm".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].llms[0].provider_type = ProviderType::Cohere;
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "revalidation-provider-llm"
+            }
+        });
+        let stale = CACHE_STALENESS_DETECTED.with_label_values(&["revalidation-provider-llm"]);
+
+        for _ in 0..2 {
+            let response = proxy(create_request(&body), state.clone()).await.unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["usage"]["prompt_tokens"], 3);
+        }
+        for _ in 0..50 {
+            if mock_server.received_requests().await.unwrap().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // Let the revalidation finish comparing.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 2);
+        assert_eq!(stale.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_revalidation_skips_llms_with_an_open_circuit() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "first",
+                "choices": [{"message": {"role": "assistant", "content": "Hi"}}]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.caching.revalidation = Some(CacheRevalidationConfig { sample_rate: 1.0 });
+        config.circuit_breaker.failure_threshold = 1;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        state
+            .circuit_breakers
+            .get(&mock_server.uri())
+            .record_failure(FailureKind::ServerError);
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_routing_headers_describe_the_decision_only_when_enabled() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"choices": []}))
+                    .insert_header("X-LLM-Router-Policy", "spoofed"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.observability.routing_headers = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello headers"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let state = AppState::new(config.clone()).unwrap();
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers["X-LLM-Router-Policy"], "test_policy");
+        assert_eq!(headers["X-LLM-Router-Model"], "meta/llama-3.1-8b-instruct");
+        assert_eq!(headers["X-LLM-Router-Upstream"], mock_server.uri().as_str());
+        assert_eq!(headers["X-LLM-Router-Cache"], "miss");
+        assert_eq!(headers["X-LLM-Router-Retries"], "0");
+
+        let response = proxy(create_request(&body), state).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers["X-LLM-Router-Model"], "meta/llama-3.1-8b-instruct");
+        assert_eq!(headers["X-LLM-Router-Cache"], "hit");
+        assert!(!headers.contains_key("X-LLM-Router-Upstream"));
+        assert!(!headers.contains_key("X-LLM-Router-Retries"));
+
+        config.observability.routing_headers = false;
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key("X-LLM-Router-Policy"));
+        assert!(!response.headers().contains_key("X-LLM-Router-Cache"));
+        assert!(!response.headers().contains_key(SERVER_TIMING_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_server_timing_header_when_enabled() {
+        assert_eq!(
+            server_timing(0.0123, 0.8401, 0.00049),
+            "select;dur=12.3, upstream;dur=840.1, overhead;dur=0.5"
+        );
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&mock_server)
+            .await;
+        let mut config = create_test_config();
+        config.observability.server_timing = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        let timing = response.headers()[SERVER_TIMING_HEADER].to_str().unwrap();
+        let names: Vec<&str> = timing
+            .split(", ")
+            .map(|metric| metric.split(";dur=").next().unwrap())
+            .collect();
+        assert_eq!(names, ["select", "upstream", "overhead"]);
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_policy_use_default_policy() {
+        let body = json!({"messages": []});
+        assert_eq!(apply_default_policy(body.clone(), None), body);
+        assert_eq!(
+            apply_default_policy(body, Some("test_policy"))["nim-llm-router"],
+            json!({"policy": "test_policy", "routing_strategy": "triton"})
+        );
+        let named = json!({"nim-llm-router": {"policy": "other"}});
+        assert_eq!(
+            apply_default_policy(named.clone(), Some("test_policy")),
+            named
+        );
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {"model": "Brainstroming"}
+        });
+
+        let state = AppState::new(config.clone()).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        config.default_policy = Some("test_policy".to_string());
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_requests_share_one_upstream_call() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"choices": []}))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello coalesced"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let coalesced_before = REQUEST_COALESCED.get();
+        let responses = futures_util::future::join_all(
+            (0..3).map(|_| proxy(create_request(&body), state.clone())),
+        )
+        .await;
+        for response in responses {
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+        assert!(REQUEST_COALESCED.get() >= coalesced_before + 2);
+    }
+
+    #[tokio::test]
+    async fn test_identical_requests_share_the_error_of_a_failed_call() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(json!({"error": {"message": "context too long"}}))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello failing"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let responses = futures_util::future::join_all(
+            (0..3).map(|_| proxy(create_request(&body), state.clone())),
+        )
+        .await;
+        for response in responses {
+            let response = response.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert!(String::from_utf8_lossy(&body).contains("context too long"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tenant_labels_are_bounded_to_configured_tenants() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.observability.tenant_labels = true;
+        config.security.tenants =
+            BTreeMap::from([("tenant-key".to_string(), "tenant-label-test".to_string())]);
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let unknown_before = NUM_REQUESTS_PER_TENANT
+            .with_label_values(&[UNKNOWN_TENANT])
+            .get();
+        for key in ["tenant-key", "some-other-key"] {
+            let mut request = create_request(&body);
+            request.headers_mut().insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", key)).unwrap(),
+            );
+            let response = proxy(request, state.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let tenant_requests = NUM_REQUESTS_PER_TENANT.with_label_values(&["tenant-label-test"]);
+        assert_eq!(tenant_requests.get(), 1);
+        let unknown_requests = NUM_REQUESTS_PER_TENANT.with_label_values(&[UNKNOWN_TENANT]);
+        assert!(unknown_requests.get() > unknown_before);
+        let tenant_tokens = TOKEN_USAGE_PER_TENANT.with_label_values(&[
+            "tenant-label-test",
+            "Brainstroming",
+            "total",
+        ]);
+        assert_eq!(tenant_tokens.get(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_model_alias_routes_to_target_llm() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(wiremock::matchers::body_partial_json(json!({
+                "model": "meta/llama-3.1-8b-instruct"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.model_aliases = BTreeMap::from([(
+            "alias-test-gpt-4".to_string(),
+            "Code Generation".to_string(),
+        )]);
+        config.policies[0].llms[1].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "alias-test-gpt-4"
+            }
+        });
+
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let alias_requests = REQUESTS_PER_MODEL.with_label_values(&["alias-test-gpt-4"]);
+        assert_eq!(alias_requests.get(), 1);
+
+        let mut unknown = body.clone();
+        unknown["nim-llm-router"]["model"] = json!("not-an-alias");
+        let response = proxy(create_request(&unknown), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_provider_key_is_a_gateway_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": {"message": "Incorrect API key provided"}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.circuit_breaker.failure_threshold = 1;
+        config.circuit_breaker.trip_on.push(FailureKind::Auth);
+        config.policies[0].llms[0].name = "auth-test-llm".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "auth-test-llm"
+            }
+        });
+
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        let message = json["error"]["message"].as_str().unwrap();
+        assert!(message.contains("not an issue with your key"), "{message}");
+        let failures = PROVIDER_AUTH_FAILURES.with_label_values(&["auth-test-llm"]);
+        assert_eq!(failures.get(), 1);
+        assert_eq!(
+            state.circuit_breakers.state(&mock_server.uri()),
+            CircuitState::Open
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body_is_rejected_before_forwarding() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[1].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}, {"content": "Hi"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Code Generation"
+            }
+        });
+
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["error"]["message"],
+            "Client Error: 'messages[1].role' is required"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body_is_rejected_before_classification() {
+        // The Triton URL is unreachable, so classifying first would fail
+        // with a routing error instead.
+        let body = json!({
+            "messages": [{"role": "user", "content": 42}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "triton"
+            }
+        });
+
+        let response = proxy(create_request(&body), create_test_state())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_llm_bypasses_triton() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // The Triton URL is unreachable, so classifying would fail.
+        let mut config = create_test_config();
+        config.policies[0].llms[1].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "triton",
+                "llm_name": "Code Generation"
+            }
+        });
+
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Chosen-Classifier"], "Code Generation");
+
+        let mut unknown = body.clone();
+        unknown["nim-llm-router"]["llm_name"] = json!("not-in-policy");
+        let response = proxy(create_request(&unknown), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_policy_can_opt_out_of_global_cache() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].caching = Some(PolicyCachingConfig {
+            enabled: Some(false),
+            ttl_seconds: None,
+        });
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Write a poem"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        for _ in 0..2 {
+            let response = proxy(create_request(&body), state.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert!(state.cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_messages_are_translated() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(wiremock::matchers::body_partial_json(json!({
+                "max_tokens": 64,
+                "messages": [
+                    {"role": "system", "content": "Be brief."},
+                    {"role": "user", "content": "Hello"}
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-1",
+                "model": "meta/llama-3.1-8b-instruct",
+                "choices": [{"message": {"role": "assistant", "content": "Hi!"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 4, "completion_tokens": 2, "total_tokens": 6}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "max_tokens": 64,
+            "system": "Be brief.",
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "Hello"}]}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let mut request = create_request(&body);
+        *request.uri_mut() = Uri::from_static(MESSAGES_PATH);
+
+        let response = proxy(request, AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "message");
+        assert_eq!(json["content"][0]["text"], "Hi!");
+        assert_eq!(json["stop_reason"], "end_turn");
+    }
+
+    #[tokio::test]
+    async fn test_route_explain_classifies_without_calling_llm() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/models/router/infer"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model_name": "router",
+                "model_version": "1",
+                "parameters": {"sequence_id": 0, "sequence_start": false, "sequence_end": false},
+                "outputs": [{"name": "OUTPUT", "datatype": "FP32", "shape": [1, 2], "data": [0.2, 0.8]}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].url = format!("{}/v2/models/router/infer", mock_server.uri());
+        config.policies[0].llms[1].api_base = mock_server.uri();
+        let state = AppState::new(config).unwrap();
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/route/explain")
+            .body(Full::new(Bytes::from(
+                serde_json::to_vec(&json!({
+                    "messages": [{"role": "user", "content": "Write a sort function"}],
+                    "nim-llm-router": {"policy": "test_policy", "routing_strategy": "triton"}
+                }))
+                .unwrap(),
+            )))
+            .unwrap();
+
+        let response = handler(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["policy"], "test_policy");
+        assert_eq!(json["llm_name"], "Code Generation");
+        assert_eq!(json["api_base"], mock_server.uri());
+        assert_eq!(json["classifier_scores"][1]["score"], 0.8);
+        assert_eq!(json["circuit_open"], false);
+    }
+
+    #[tokio::test]
+    async fn test_route_explain_is_authenticated_and_leaves_routing_alone() {
+        let mut config = create_test_config();
+        config.policies[0].llms[0].instances =
+            vec![Instance::Url("http://second-instance".to_string())];
+        config.security.api_keys =
+            serde_yaml::from_str("other-key: {allowed_policies: [other]}\nfull-key: {}").unwrap();
+        let state = AppState::new(config).unwrap();
+
+        let explain = |key: Option<&'static str>| {
+            let mut request = Request::builder().method("POST").uri("/v1/route/explain");
+            if let Some(key) = key {
+                request = request.header(AUTHORIZATION, format!("Bearer {key}"));
+            }
+            let body = json!({
+                "messages": [{"role": "user", "content": "Hello"}],
+                "nim-llm-router": {
+                    "policy": "test_policy",
+                    "routing_strategy": "manual",
+                    "model": "Brainstroming"
+                }
+            });
+            let request = request
+                .body(Full::new(Bytes::from(serde_json::to_vec(&body).unwrap())))
+                .unwrap();
+            handler(request, state.clone())
+        };
+
+        let response = explain(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = explain(Some("other-key")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let first_instance = create_test_config().policies[0].llms[0].api_base.clone();
+        for _ in 0..2 {
+            let response = explain(Some("full-key")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["api_base"], first_instance.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_prompts_are_fanned_out_and_merged() {
+        let mock_server = MockServer::start().await;
+        let completion = |text: &str| {
+            json!({
+                "id": format!("cmpl-{}", text),
+                "object": "text_completion",
+                "choices": [{"index": 0, "text": text, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5}
+            })
+        };
+        for prompt in ["one", "two"] {
+            Mock::given(method("POST"))
+                .and(path("/completions"))
+                .and(body_partial_json(json!({"prompt": prompt})))
+                .respond_with(ResponseTemplate::new(200).set_body_json(completion(prompt)))
+                .expect(2)
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/completions"))
+            .and(body_partial_json(json!({"prompt": "three"})))
+            .respond_with(
+                ResponseTemplate::new(503)
+                    .set_body_json(json!({"error": {"message": "overloaded"}})),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.server.batching = Some(BatchingConfig::default());
+        let state = AppState::new(config.clone()).unwrap();
+        let body = json!({
+            "prompt": ["one", "two", "three"],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let request = |body: &Value| {
+            let mut request = create_request(body);
+            *request.uri_mut() = Uri::from_static("/completions");
+            request
+        };
+
+        let response = proxy_with_batching(request(&body), state).await;
+        assert_eq!(response.0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.1["error"]["message"],
+            "LLM Service Error: overloaded"
+        );
+
+        config.server.batching = Some(BatchingConfig {
+            failure_policy: BatchFailurePolicy::BestEffort,
+            ..BatchingConfig::default()
+        });
+        let state = AppState::new(config).unwrap();
+        let (status, json) = proxy_with_batching(request(&body), state).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["id"], "cmpl-one");
+        assert_eq!(json["choices"][1]["text"], "two");
+        assert_eq!(json["choices"][1]["index"], 1);
+        assert_eq!(json["choices"][2]["finish_reason"], "error");
+        assert_eq!(
+            json["choices"][2]["error"]["message"],
+            "LLM Service Error: overloaded"
+        );
+        assert_eq!(json["usage"]["total_tokens"], 10);
+    }
+
+    #[tokio::test]
+    async fn test_batch_parts_served_from_a_compressed_cache_are_merged() {
+        let mock_server = MockServer::start().await;
+        for prompt in ["one", "two"] {
+            Mock::given(method("POST"))
+                .and(path("/completions"))
+                .and(body_partial_json(json!({"prompt": prompt})))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "choices": [{"index": 0, "text": prompt, "finish_reason": "stop"}]
+                })))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.server.batching = Some(BatchingConfig::default());
+        config.caching.enabled = true;
+        config.caching.compression = Some(CacheCompression::Gzip);
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "prompt": ["one", "two"],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        // The second batch is answered from the cache.
+        for _ in 0..2 {
+            let mut request = create_request(&body);
+            *request.uri_mut() = Uri::from_static("/completions");
+            request
+                .headers_mut()
+                .insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+            let (status, json) = proxy_with_batching(request, state.clone()).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(json["choices"][0]["text"], "one");
+            assert_eq!(json["choices"][1]["text"], "two");
+        }
+    }
+
+    async fn proxy_with_batching(
+        request: Request<Full<Bytes>>,
+        state: AppState,
+    ) -> (StatusCode, Value) {
+        let response = batch_proxy(request, state).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_upstream_is_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.client.retry.max_retries = 1;
+        config.client.retry.initial_backoff_ms = 1;
+        config.observability.routing_headers = true;
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-LLM-Router-Retries"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_retried_request_charges_tokens_once() {
+        let mock_server = MockServer::start().await;
+        let usage = json!({"prompt_tokens": 10, "completion_tokens": 20, "total_tokens": 30});
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(503).set_body_json(json!({"usage": usage})))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"choices": [], "usage": usage})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].name = "retry-usage-test".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.client.retry.max_retries = 1;
+        config.client.retry.initial_backoff_ms = 1;
+        let state = AppState::new(config).unwrap();
+        let total_tokens = TOKEN_USAGE.with_label_values(&["retry-usage-test", "total", "false"]);
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "retry-usage-test"
+            }
+        });
+
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.into_body().collect().await.unwrap();
+        assert_eq!(total_tokens.get(), 30);
+    }
+
+    #[tokio::test]
+    async fn test_models_lists_unique_models_visible_to_key() {
+        let mut config = create_test_config();
+        config.policies[0].llms[1].model = "meta/codellama-70b".to_string();
+        let mut cheap = config.policies[0].clone();
+        cheap.name = "cheap".to_string();
+        cheap.llms.truncate(1);
+        config.policies.push(cheap);
+        config.security.api_keys =
+            serde_yaml::from_str("cheap-key: {allowed_policies: [cheap]}\nfull-key: {}").unwrap();
+        let state = AppState::new(config).unwrap();
+
+        let list = |key: &'static str| {
+            let request = Request::builder()
+                .uri("/v1/models")
+                .header(AUTHORIZATION, format!("Bearer {key}"))
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            handler(request, state.clone())
+        };
+
+        let response = list("full-key").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["object"], "list");
+        assert_eq!(json["data"].as_array().unwrap().len(), 2);
+        assert_eq!(json["data"][0]["id"], "meta/llama-3.1-8b-instruct");
+        assert_eq!(json["data"][0]["policies"], json!(["test_policy", "cheap"]));
+
+        let response = list("cheap-key").await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"].as_array().unwrap().len(), 1);
+        assert_eq!(json["data"][0]["policies"], json!(["cheap"]));
+
+        let response = list("wrong-key").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_system_prompt_is_merged_replaced_or_templated() {
+        let prepend = SystemPromptConfig {
+            content: "Be brief.".to_string(),
+            mode: SystemPromptMode::Prepend,
+            prompt_template: None,
+        };
+        let chat = json!({"messages": [
+            {"role": "system", "content": "Answer in French."},
+            {"role": "user", "content": "Hello"}
+        ]});
+
+        let merged = apply_system_prompt(chat.clone(), &prepend);
+        assert_eq!(
+            merged["messages"][0]["content"],
+            "Be brief.\n\nAnswer in French."
+        );
+        assert_eq!(merged["messages"].as_array().unwrap().len(), 2);
+
+        let inserted = apply_system_prompt(
+            json!({"messages": [{"role": "user", "content": "Hello"}]}),
+            &prepend,
+        );
+        assert_eq!(
+            inserted["messages"][0],
+            json!({"role": "system", "content": "Be brief."})
+        );
+
+        let override_mode = SystemPromptConfig {
+            mode: SystemPromptMode::Override,
+            ..prepend.clone()
+        };
+        let replaced = apply_system_prompt(chat, &override_mode);
+        assert_eq!(
+            replaced["messages"],
+            json!([
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hello"}
+            ])
+        );
+
+        let completion = json!({"prompt": "Hello"});
+        assert_eq!(
+            apply_system_prompt(completion.clone(), &prepend),
+            completion
+        );
+        let templated = SystemPromptConfig {
+            prompt_template: Some("Be brief.\n{prompt}".to_string()),
+            ..prepend
+        };
+        assert_eq!(
+            apply_system_prompt(json!({"prompt": ["Hello", "Hi"]}), &templated)["prompt"],
+            json!(["Be brief.\nHello", "Be brief.\nHi"])
+        );
+    }
+
+    #[test]
+    fn test_bias_nudges_classifier_choice() {
+        let mut policy = create_test_config().policies.remove(0);
+        let scores = [0.45, 0.55];
+        assert_eq!(
+            highest_score_index(&biased_scores(&policy, &scores)).unwrap(),
+            1
+        );
+
+        policy.llms[0].bias = 0.2;
+        assert_eq!(biased_scores(&policy, &scores), [0.65, 0.55]);
+        assert_eq!(
+            highest_score_index(&biased_scores(&policy, &scores)).unwrap(),
+            0
+        );
+        // Scores without an LLM are left as they are.
+        assert_eq!(biased_scores(&policy, &[0.1, 0.2, 0.3])[2], 0.3);
+    }
+
+    #[test]
+    fn test_policy_limits_clamp_max_tokens_and_strip_params() {
+        let mut policy = create_test_config().policies.remove(0);
+        policy.max_tokens_limit = Some(256);
+        policy.forbidden_params = vec!["logprobs".to_string(), "top_logprobs".to_string()];
+
+        let request = json!({"messages": [], "max_tokens": 4096, "logprobs": true});
+        let request = clamp_max_tokens(remove_forbidden_params(request, &policy), &policy);
+        assert_eq!(request, json!({"messages": [], "max_tokens": 256}));
+        assert_eq!(
+            clamp_max_tokens(json!({"max_tokens": 64}), &policy),
+            json!({"max_tokens": 64})
+        );
+        assert_eq!(
+            clamp_max_tokens(json!({"messages": []}), &policy),
+            json!({"messages": [], "max_tokens": 256})
+        );
+        assert_eq!(
+            clamp_max_tokens(json!({"max_completion_tokens": 1000}), &policy),
+            json!({"max_completion_tokens": 256})
+        );
+    }
+
+    #[test]
+    fn test_clamp_caps_both_max_token_fields() {
+        let mut policy = create_test_config().policies.remove(0);
+        policy.max_tokens_limit = Some(256);
+        assert_eq!(
+            clamp_max_tokens(
+                json!({"max_completion_tokens": 100, "max_tokens": 1_000_000}),
+                &policy
+            ),
+            json!({"max_completion_tokens": 100, "max_tokens": 256})
+        );
+    }
+
+    #[test]
+    fn test_clamp_caps_default_max_tokens_of_the_llm() {
+        let mut policy = create_test_config().policies.remove(0);
+        policy.max_tokens_limit = Some(256);
+        let defaults = DefaultParams {
+            max_tokens: Some(4096),
+            ..DefaultParams::default()
+        };
+        let request = json!({"max_completion_tokens": 100});
+        let request = clamp_max_tokens(apply_default_params(request, Some(&defaults)), &policy);
+        assert_eq!(
+            request,
+            json!({"max_completion_tokens": 100, "max_tokens": 256})
+        );
+    }
+
+    #[test]
+    fn test_param_constraints_reject_or_clamp() {
+        let mut policy = create_test_config().policies.remove(0);
+        policy.param_constraints = serde_yaml::from_str(
+            "{temperature: {min: 0.1, max: 1.0}, n: {max: 1}, response_format: {allowed: [null]}}",
+        )
+        .unwrap();
+        let within = json!({"temperature": 0.7, "n": 1, "messages": []});
+        assert_eq!(
+            enforce_param_constraints(within.clone(), &policy).unwrap(),
+            within
+        );
+
+        for (request, message) in [
+            (
+                json!({"temperature": 1.5}),
+                "'temperature' must be at most 1",
+            ),
+            (
+                json!({"temperature": 0.0}),
+                "'temperature' must be at least 0.1",
+            ),
+            (json!({"n": "2"}), "'n' must be a number"),
+            (
+                json!({"response_format": {"type": "json_object"}}),
+                "'response_format' must be one of [null]",
+            ),
+        ] {
+            let error = enforce_param_constraints(request, &policy).unwrap_err();
+            assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+
+        policy.clamp_params = true;
+        let request = json!({
+            "temperature": 1.5,
+            "n": 4,
+            "response_format": {"type": "json_object"}
+        });
+        assert_eq!(
+            enforce_param_constraints(request, &policy).unwrap(),
+            json!({"temperature": 1.0, "n": 1})
+        );
+        // Values that cannot be clamped are still rejected.
+        assert!(enforce_param_constraints(json!({"n": "2"}), &policy).is_err());
+    }
+
+    #[test]
+    fn test_default_params_only_fill_omitted_values() {
+        let defaults = DefaultParams {
+            temperature: Some(0.2),
+            top_p: None,
+            max_tokens: Some(512),
+        };
+        let request = json!({"messages": [], "max_tokens": 64});
+        assert_eq!(
+            apply_default_params(request.clone(), Some(&defaults)),
+            json!({"messages": [], "max_tokens": 64, "temperature": 0.2})
+        );
+        assert_eq!(apply_default_params(request.clone(), None), request);
+    }
+
+    #[test]
+    fn test_request_body_validation_names_the_offending_field() {
+        let chat = "/v1/chat/completions";
+        let error =
+            |path: &str, body: Value| validate_request_body(path, &body).unwrap_err().to_string();
+
+        assert!(validate_request_body(
+            chat,
+            &json!({"model": "m", "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "Hi"}]},
+                {"role": "assistant", "tool_calls": []}
+            ]})
+        )
+        .is_ok());
+        assert!(validate_request_body("/completions", &json!({"prompt": "Hi"})).is_ok());
+        assert!(validate_request_body("/completions", &json!({"prompt": ["Hi", "Yo"]})).is_ok());
+        assert!(validate_request_body(EMBEDDINGS_PATH, &json!({"input": ["Hi", "Yo"]})).is_ok());
+        assert!(validate_request_body(EMBEDDINGS_PATH, &json!({"input": [[1, 2], [3]]})).is_ok());
+        assert!(validate_request_body("/v1/moderations", &Value::Null).is_ok());
+
+        assert_eq!(
+            error(chat, Value::Null),
+            "Client Error: Request body must be a JSON object"
+        );
+        assert_eq!(
+            error(chat, json!({"model": 3, "messages": []})),
+            "Client Error: 'model' must be a string"
+        );
+        assert_eq!(
+            error(chat, json!({"messages": {"role": "user"}})),
+            "Client Error: 'messages' must be an array"
+        );
+        assert_eq!(
+            error(chat, json!({"messages": []})),
+            "Client Error: 'messages' must not be empty"
+        );
+        assert_eq!(
+            error(
+                chat,
+                json!({"messages": [{"role": "user", "content": "Hi"}, {"content": "Hi"}]})
+            ),
+            "Client Error: 'messages[1].role' is required"
+        );
+        assert_eq!(
+            error(chat, json!({"messages": [{"role": "user", "content": 1}]})),
+            "Client Error: 'messages[0].content' must be a string or an array"
+        );
+        assert_eq!(
+            error("/completions", json!({"messages": []})),
+            "Client Error: 'prompt' is required"
+        );
+        assert_eq!(
+            error(EMBEDDINGS_PATH, json!({"input": ["Hi", 1]})),
+            "Client Error: 'input' must be a string, or a non-empty array of strings or token arrays"
+        );
+    }
+
+    #[test]
+    fn test_requests_with_tools_avoid_llms_without_tool_support() {
+        let mut policy = Policy {
+            name: "tools".to_string(),
+            url: "http://triton:8000".to_string(),
+            llms: vec![
+                Llm {
+                    name: "mixtral".to_string(),
+                    supports_tools: Some(false),
+                    ..Llm::default()
+                },
+                Llm {
+                    name: "llama".to_string(),
+                    ..Llm::default()
+                },
+            ],
+            ..Policy::default()
+        };
+        let with_tools = json!({"messages": [], "tools": [{"type": "function"}]});
+        let without_tools = json!({"messages": [], "tools": []});
+
+        assert_eq!(resolve_tool_support(&policy, 0, &without_tools).unwrap(), 0);
+        let rejected = resolve_tool_support(&policy, 0, &with_tools).unwrap_err();
+        assert_eq!(rejected.status_code(), StatusCode::BAD_REQUEST);
+
+        policy.llms[0].tools_fallback = Some("llama".to_string());
+        assert_eq!(resolve_tool_support(&policy, 0, &with_tools).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejection_status_and_retry_after() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.security.rate_limit.per_ip = Some(PerIpRateLimit {
+            requests: 2,
+            window_secs: 30,
+        });
+        config.security.rate_limit.rejection_status = 503;
+        let state = AppState::new(config).unwrap();
+
+        let send = || {
+            let mut request = create_request(&json!({
+                "messages": [{"role": "user", "content": "Hello"}],
+                "nim-llm-router": {
+                    "policy": "test_policy",
+                    "routing_strategy": "manual",
+                    "model": "Brainstroming"
+                }
+            }));
+            request
+                .extensions_mut()
+                .insert(ClientAddr("10.0.0.2:4000".parse().unwrap()));
+            handler(request, state.clone())
+        };
+
+        for _ in 0..2 {
+            assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+        }
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        // Nothing was admitted in an earlier window, so the client waits out
+        // the current one.
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["error"]["message"],
+            "Client Error: Too many requests from this client IP"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_rate_limit_applies_on_top_of_global() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let mut limited = config.policies[0].clone();
+        limited.name = "limited_policy".to_string();
+        limited.rate_limit = Some(PerIpRateLimit {
+            requests: 1,
+            window_secs: 60,
+        });
+        config.policies.push(limited);
+        config.security.rate_limit.per_ip = Some(PerIpRateLimit {
+            requests: 3,
+            window_secs: 60,
+        });
+        let state = AppState::new(config).unwrap();
+
+        let send = |policy: &str| {
+            let mut request = create_request(&json!({
+                "messages": [{"role": "user", "content": "Hello"}],
+                "nim-llm-router": {
+                    "policy": policy,
+                    "routing_strategy": "manual",
+                    "model": "Brainstroming"
+                }
+            }));
+            request
+                .extensions_mut()
+                .insert(ClientAddr("10.0.0.1:4000".parse().unwrap()));
+            handler(request, state.clone())
+        };
+        let scope = |response: &Response<_>| {
+            response
+                .headers()
+                .get(RATE_LIMIT_SCOPE_HEADER)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        let response = send("limited_policy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(scope(&response), None);
+        let response = send("limited_policy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(scope(&response).as_deref(), Some("policy"));
+        assert!(response.headers().contains_key(RETRY_AFTER));
+
+        // Other policies only count against the global limit.
+        let response = send("test_policy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send("test_policy").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(scope(&response).as_deref(), Some("global"));
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_routed_and_cached_by_input() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [{"object": "embedding", "index": 0, "embedding": [0.1, 0.2]}],
+                "model": "meta/llama-3.1-8b-instruct",
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.caching.enabled = true;
+        config.policies[0].llms[0].name = "embeddings-test-llm".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].llms[0].default_params = Some(DefaultParams {
+            temperature: Some(0.2),
+            ..DefaultParams::default()
+        });
+        config.policies[0].system_prompt = Some(SystemPromptConfig {
+            content: "Be brief.".to_string(),
+            mode: SystemPromptMode::Prepend,
+            prompt_template: Some("Q: {prompt}".to_string()),
+        });
+        let state = AppState::new(config).unwrap();
+        let prompt_tokens =
+            TOKEN_USAGE.with_label_values(&["embeddings-test-llm", "prompt", "false"]);
+
+        for user in ["alice", "bob"] {
+            let body = json!({
+                "input": "Hello world",
+                "user": user,
+                "nim-llm-router": {
+                    "policy": "test_policy",
+                    "routing_strategy": "manual",
+                    "model": "embeddings-test-llm"
+                }
+            });
+            let mut request = create_request(&body);
+            *request.uri_mut() = Uri::from_static(EMBEDDINGS_PATH);
+            let response = handler(request, state.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["data"][0]["embedding"], json!([0.1, 0.2]));
+        }
+        assert_eq!(prompt_tokens.get(), 4);
+
+        // Forwarded without the router's parameters, a system prompt or
+        // sampling defaults.
+        let sent: Value = mock_server.received_requests().await.unwrap()[0]
+            .body_json()
+            .unwrap();
+        assert_eq!(
+            sent,
+            json!({
+                "input": "Hello world",
+                "user": "alice",
+                "model": "meta/llama-3.1-8b-instruct"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_response_only_once_every_instance_failed() {
+        let failing = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&failing)
+            .await;
+        let other = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&other)
+            .await;
+
+        let fallback = json!({
+            "choices": [{"message": {"role": "assistant", "content": "Try again later."}}]
+        });
+        let mut config = create_test_config();
+        config.client.retry.max_retries = 1;
+        config.circuit_breaker.failure_threshold = 2;
+        config.policies[0].llms[0].api_base = failing.uri();
+        config.policies[0].fallback_response = Some(fallback.clone());
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let served = SERVED_FALLBACK_RESPONSE.with_label_values(&["test_policy"]);
+        let before = served.get();
+
+        // Another instance could still serve the next request.
+        let mut with_other = config.clone();
+        with_other.policies[0].llms[0].instances = vec![Instance::Url(other.uri())];
+        let state = AppState::new(with_other).unwrap();
+        for _ in 0..3 {
+            let response = proxy(create_request(&body), state.clone()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(served.get(), before);
+
+        // A single failure does not open the breaker, so it is not masked.
+        let sent = failing.received_requests().await.unwrap().len();
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(served.get(), before);
+
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[DEGRADED_HEADER], "fallback-response");
+        let received = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&received).unwrap(),
+            fallback
+        );
+        assert_eq!(served.get(), before + 1);
+        // The retries were made before giving up.
+        assert_eq!(failing.received_requests().await.unwrap().len(), sent + 4);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_response_for_final_errors_without_breakers() {
+        let failing = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&failing)
+            .await;
+
+        let mut config = create_test_config();
+        config.circuit_breaker.enabled = false;
+        config.policies[0].llms[0].api_base = failing.uri();
+        config.policies[0].fallback_response = Some(json!({"choices": []}));
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        // A 500 is not retried, and is final at once.
+        let response = proxy(create_request(&body), AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[DEGRADED_HEADER], "fallback-response");
+    }
+
+    #[tokio::test]
+    async fn test_slow_instance_is_hedged_only_when_duplicates_are_allowed() {
+        let slow = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"id": "slow", "choices": []}))
+                    .set_delay(Duration::from_millis(500)),
+            )
+            .mount(&slow)
+            .await;
+        let fast = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"id": "fast", "choices": []})),
+            )
+            .mount(&fast)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].hedge_after_ms = Some(50);
+        config.policies[0].llms[0].name = "Hedged".to_string();
+        config.policies[0].llms[0].api_base = slow.uri();
+        config.policies[0].llms[0].instances = vec![Instance::Url(fast.uri())];
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Hedged"
+            }
+        });
+        let hedged = HEDGED_REQUESTS.with_label_values(&["Hedged", "hedge"]);
+        let before = hedged.get();
+        let id = |response: Response<BoxBody<Bytes, GatewayApiError>>| async move {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<Value>(&body).unwrap()["id"].clone()
+        };
+
+        // Round robin sends the first request of each state to the slow
+        // `api_base`. Without an idempotency key it waits for it.
+        let state = AppState::new(config.clone()).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(id(response).await, "slow");
+        assert_eq!(hedged.get(), before);
+        assert!(fast.received_requests().await.unwrap().is_empty());
+
+        let state = AppState::new(config).unwrap();
+        let mut request = create_request(&body);
+        request
+            .headers_mut()
+            .insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static("req-1"));
+        let response = proxy(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(id(response).await, "fast");
+        assert_eq!(hedged.get(), before + 1);
+        assert_eq!(slow.received_requests().await.unwrap().len(), 2);
+        assert_eq!(fast.received_requests().await.unwrap().len(), 1);
+
+        // Instances that are usually slow get longer.
+        assert_eq!(hedge_delay(50, None), Duration::from_millis(50));
+        assert_eq!(
+            hedge_delay(50, Some(Duration::from_millis(100))),
+            Duration::from_millis(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hedge_errors_do_not_beat_a_slow_success() {
+        let slow = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"id": "slow", "choices": []}))
+                    .set_delay(Duration::from_millis(300)),
+            )
+            .mount(&slow)
+            .await;
+        let limited = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&limited)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.retry.max_retries = 0;
+        config.policies[0].hedge_after_ms = Some(50);
+        config.policies[0].llms[0].name = "Hedged Limited".to_string();
+        config.policies[0].llms[0].api_base = slow.uri();
+        config.policies[0].llms[0].instances = vec![Instance::Url(limited.uri())];
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Hedged Limited"
+            }
+        });
+        let mut request = create_request(&body);
+        request
+            .headers_mut()
+            .insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static("req-1"));
+
+        // The hedge's 429 arrives first, but the primary still answers.
+        let response = proxy(request, AppState::new(config).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["id"], "slow");
+    }
+
+    #[tokio::test]
+    async fn test_stage_durations_are_recorded() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].name = "Stage Timing".to_string();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Stage Timing"
+            }
+        });
+        // Other tests record too, so only growth is checked.
+        let auth_before = AUTH_DURATION.get_sample_count();
+        let sanitize_before = SANITIZE_DURATION.get_sample_count();
+        let upstream = UPSTREAM_CONNECT_DURATION.with_label_values(&["Stage Timing"]);
+
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(AUTH_DURATION.get_sample_count() > auth_before);
+        assert!(SANITIZE_DURATION.get_sample_count() > sanitize_before);
+        assert_eq!(upstream.get_sample_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_large_request_bodies_are_gzipped_upstream() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].llms[0].compress_request = true;
+        config.policies[0].llms[0].compress_request_min_bytes = Some(200);
+        let state = AppState::new(config).unwrap();
+        let body = |content: &str| {
+            json!({
+                "messages": [{"role": "user", "content": content}],
+                "nim-llm-router": {
+                    "policy": "test_policy",
+                    "routing_strategy": "manual",
+                    "model": "Brainstroming"
+                }
+            })
+        };
+
+        let response = proxy(create_request(&body("Hello")), state.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let prompt = "context ".repeat(100);
+        let response = proxy(create_request(&body(&prompt)), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let received = mock_server.received_requests().await.unwrap();
+        assert!(received[0].headers.get(CONTENT_ENCODING).is_none());
+        assert_eq!(
+            received[0].body_json::<Value>().unwrap()["messages"][0]["content"],
+            "Hello"
+        );
+        assert_eq!(received[1].headers[CONTENT_ENCODING], "gzip");
+        assert_eq!(received[1].headers[CONTENT_TYPE], "application/json");
+        assert!(received[1].body.len() < prompt.len());
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(received[1].body.as_slice()),
+            &mut decoded,
+        )
+        .unwrap();
+        let sent: Value = serde_json::from_slice(&decoded).unwrap();
+        assert_eq!(sent["messages"][0]["content"], prompt);
+        assert_eq!(sent["model"], "meta/llama-3.1-8b-instruct");
+    }
+
+    #[tokio::test]
+    async fn test_selection_outside_allowed_models_is_redirected_or_rejected() {
+        let mut config = create_test_config();
+        config.policies[0].allowed_models = vec!["Brainstroming".to_string()];
+        let policy = config.policies[0].clone();
+        assert_eq!(allowed_model_index(&policy, 0).unwrap(), 0);
+        let rejected = allowed_model_index(&policy, 1).unwrap_err();
+        assert_eq!(rejected.status_code(), StatusCode::NOT_FOUND);
+
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Code Generation"
+            }
+        });
+        let rejections = MODEL_SELECTION_REJECTED.with_label_values(&["test_policy"]);
+        let before = rejections.get();
+        let state = AppState::new(config.clone()).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(rejections.get(), before + 1);
+
+        // With a default, the request goes there instead.
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].default_model = Some("Brainstroming".to_string());
+        let state = AppState::new(config).unwrap();
+        let response = proxy(create_request(&body), state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rejections.get(), before + 2);
+    }
+
+    #[tokio::test]
+    async fn test_llm_headers_are_sent_without_replacing_auth() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("anthropic-version", "2023-06-01"))
+            .and(header("x-routing", "from-config"))
+            .and(header("authorization", "Bearer test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        config.client.forward_headers = vec!["*".to_string()];
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.headers = BTreeMap::from([
+            ("anthropic-version".to_string(), "2023-06-01".to_string()),
+            ("x-routing".to_string(), "from-config".to_string()),
+        ]);
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let mut request = create_request(&body);
+        request
+            .headers_mut()
+            .insert("x-routing", HeaderValue::from_static("from-client"));
+
+        let state = AppState::new(config).unwrap();
+        let response = proxy(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_organization_headers_from_config_or_client() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("openai-organization", "org-client"))
+            .and(header("openai-project", "proj-config"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"choices": []})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut config = create_test_config();
+        let llm = &mut config.policies[0].llms[0];
+        llm.api_base = mock_server.uri();
+        llm.organization = Some("org-config".to_string());
+        llm.project = Some("proj-config".to_string());
+        llm.forward_organization_headers = true;
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+        let mut request = create_request(&body);
+        request.headers_mut().insert(
+            "OpenAI-Organization",
+            HeaderValue::from_static("org-client"),
+        );
+
+        let state = AppState::new(config).unwrap();
+        let response = proxy(request, state).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_header_overrides_llm_timeout() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(REQUEST_TIMEOUT_HEADER, HeaderValue::from_static(value));
+            headers
+        };
+        let max = Some(5_000);
+        assert_eq!(
+            requested_timeout(&headers("250"), max),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            requested_timeout(&headers("60000"), max),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(requested_timeout(&headers("soon"), max), None);
+        assert_eq!(requested_timeout(&headers("0"), max), None);
+        assert_eq!(requested_timeout(&headers("250"), None), None);
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"choices": []}))
+                    .set_delay(Duration::from_millis(1_300)),
+            )
+            .mount(&mock_server)
+            .await;
+        let mut config = create_test_config();
+        config.client.max_request_timeout_ms = max;
+        config.policies[0].llms[0].api_base = mock_server.uri();
+        config.policies[0].llms[0].request_timeout_secs = Some(1);
+        let state = AppState::new(config).unwrap();
+        let body = json!({
+            "messages": [{"role": "user", "content": "Hello"}],
+            "nim-llm-router": {
+                "policy": "test_policy",
+                "routing_strategy": "manual",
+                "model": "Brainstroming"
+            }
+        });
+
+        let mut request = create_request(&body);
+        request
+            .headers_mut()
+            .insert(REQUEST_TIMEOUT_HEADER, HeaderValue::from_static("3000"));
+        let response = proxy(request, state.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut request = create_request(&body);
+        request
+            .headers_mut()
+            .insert(REQUEST_TIMEOUT_HEADER, HeaderValue::from_static("3s"));
+        let error = proxy(request, state).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::GATEWAY_TIMEOUT);
+    }
 }
@@ -0,0 +1,393 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Quota
+//!
+//! Daily and monthly token allowances per client API key. Usage is counted
+//! from the `total_tokens` reported by the LLM and resets at UTC midnight and
+//! on the first day of each UTC month. Requests reserve an estimate of their
+//! tokens when they are admitted, until their usage is charged.
+use crate::config::ApiKeyQuota;
+use log::info;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// `(year, month)` of a day counted from 1970-01-01.
+fn year_month(days: i64) -> (i64, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month)
+}
+
+/// Calendar windows containing a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Windows {
+    day: i64,
+    month: (i64, u32),
+}
+
+impl Windows {
+    fn at(now: i64) -> Self {
+        let day = now.div_euclid(SECONDS_PER_DAY);
+        Windows {
+            day,
+            month: year_month(day),
+        }
+    }
+
+    fn day_resets_at(&self) -> i64 {
+        (self.day + 1) * SECONDS_PER_DAY
+    }
+
+    fn month_resets_at(&self) -> i64 {
+        let (year, month) = self.month;
+        let (year, month) = if month == 12 {
+            (year + 1, 1)
+        } else {
+            (year, month + 1)
+        };
+        days_from_civil(year, month, 1) * SECONDS_PER_DAY
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    windows: Windows,
+    day_tokens: u64,
+    month_tokens: u64,
+    /// Tokens reserved by requests in flight. They belong to no window, so
+    /// they are not dropped when one ends.
+    reserved: u64,
+}
+
+impl Usage {
+    fn new(windows: Windows) -> Self {
+        Usage {
+            windows,
+            day_tokens: 0,
+            month_tokens: 0,
+            reserved: 0,
+        }
+    }
+
+    /// Drops the counts of windows that have ended.
+    fn roll(&mut self, windows: Windows) {
+        if self.windows.month != windows.month {
+            self.month_tokens = 0;
+        }
+        if self.windows.day != windows.day {
+            self.day_tokens = 0;
+        }
+        self.windows = windows;
+    }
+}
+
+/// Allowance left in one window.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Allowance {
+    pub limit: u64,
+    pub used: u64,
+    /// Reserved by requests in flight, and not yet charged.
+    pub reserved: u64,
+    pub remaining: u64,
+    /// Unix timestamp (seconds) when the window resets.
+    pub resets_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QuotaStatus {
+    pub daily: Option<Allowance>,
+    pub monthly: Option<Allowance>,
+}
+
+impl QuotaStatus {
+    /// Name of the first exhausted window, if any.
+    pub fn exceeded(&self) -> Option<&'static str> {
+        if self.daily.as_ref().is_some_and(|a| a.remaining == 0) {
+            Some("daily")
+        } else if self.monthly.as_ref().is_some_and(|a| a.remaining == 0) {
+            Some("monthly")
+        } else {
+            None
+        }
+    }
+}
+
+fn allowance(limit: Option<u64>, used: u64, reserved: u64, resets_at: i64) -> Option<Allowance> {
+    limit.map(|limit| Allowance {
+        limit,
+        used,
+        reserved,
+        remaining: limit.saturating_sub(used.saturating_add(reserved)),
+        resets_at,
+    })
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Token usage per client API key.
+#[derive(Debug, Default)]
+pub struct QuotaTracker {
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl QuotaTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self, quota: &ApiKeyQuota) -> QuotaStatus {
+        self.status_at(quota, unix_now())
+    }
+
+    pub fn record(&self, key: &str, tokens: u64) {
+        self.record_at(key, tokens, unix_now())
+    }
+
+    /// Admits a request of `quota`'s key unless a window is exhausted,
+    /// counting reservations of requests in flight, and reserves `tokens`
+    /// for it in the same step. Returns the exhausted window otherwise.
+    pub fn reserve(
+        self: &Arc<Self>,
+        quota: &ApiKeyQuota,
+        tokens: u64,
+    ) -> Result<QuotaUsage, &'static str> {
+        self.reserve_at(quota, tokens, unix_now())
+    }
+
+    fn reserve_at(
+        self: &Arc<Self>,
+        quota: &ApiKeyQuota,
+        tokens: u64,
+        now: i64,
+    ) -> Result<QuotaUsage, &'static str> {
+        let windows = Windows::at(now);
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        let usage = usage
+            .entry(quota.api_key.clone())
+            .or_insert_with(|| Usage::new(windows));
+        usage.roll(windows);
+        if let Some(window) = Self::status_of(quota, usage).exceeded() {
+            return Err(window);
+        }
+        usage.reserved = usage.reserved.saturating_add(tokens);
+        Ok(QuotaUsage {
+            reservation: Arc::new(Reservation {
+                tracker: self.clone(),
+                key: quota.api_key.clone(),
+                tokens: AtomicU64::new(tokens),
+            }),
+        })
+    }
+
+    fn release(&self, key: &str, tokens: u64) {
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        if let Some(usage) = usage.get_mut(key) {
+            usage.reserved = usage.reserved.saturating_sub(tokens);
+        }
+    }
+
+    fn status_at(&self, quota: &ApiKeyQuota, now: i64) -> QuotaStatus {
+        let windows = Windows::at(now);
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        let usage = usage
+            .get_mut(&quota.api_key)
+            .map(|usage| {
+                usage.roll(windows);
+                *usage
+            })
+            .unwrap_or_else(|| Usage::new(windows));
+        Self::status_of(quota, &usage)
+    }
+
+    fn status_of(quota: &ApiKeyQuota, usage: &Usage) -> QuotaStatus {
+        QuotaStatus {
+            daily: allowance(
+                quota.daily_tokens,
+                usage.day_tokens,
+                usage.reserved,
+                usage.windows.day_resets_at(),
+            ),
+            monthly: allowance(
+                quota.monthly_tokens,
+                usage.month_tokens,
+                usage.reserved,
+                usage.windows.month_resets_at(),
+            ),
+        }
+    }
+
+    fn record_at(&self, key: &str, tokens: u64, now: i64) {
+        let windows = Windows::at(now);
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        let usage = usage
+            .entry(key.to_string())
+            .or_insert_with(|| Usage::new(windows));
+        usage.roll(windows);
+        usage.day_tokens = usage.day_tokens.saturating_add(tokens);
+        usage.month_tokens = usage.month_tokens.saturating_add(tokens);
+    }
+}
+
+/// Tokens set aside on a key's quota for a request in flight, given back
+/// once its usage is charged or when it ends without usage.
+#[derive(Debug)]
+struct Reservation {
+    tracker: Arc<QuotaTracker>,
+    key: String,
+    tokens: AtomicU64,
+}
+
+impl Reservation {
+    fn release(&self) {
+        let tokens = self.tokens.swap(0, Ordering::AcqRel);
+        if tokens > 0 {
+            self.tracker.release(&self.key, tokens);
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Charges the tokens of a response to a client key, in place of the
+/// tokens reserved for it by [`QuotaTracker::reserve`].
+#[derive(Debug, Clone)]
+pub struct QuotaUsage {
+    reservation: Arc<Reservation>,
+}
+
+impl QuotaUsage {
+    /// Records `usage.total_tokens` from a chat completion (or final stream
+    /// chunk), if present.
+    pub fn record(&self, json: &Value) {
+        if let Some(total) = json["usage"]["total_tokens"].as_u64() {
+            info!("Charging {} tokens to client quota", total);
+            let reservation = &self.reservation;
+            reservation.tracker.record(&reservation.key, total);
+            reservation.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota() -> ApiKeyQuota {
+        ApiKeyQuota {
+            api_key: "client-key".to_string(),
+            daily_tokens: Some(100),
+            monthly_tokens: Some(150),
+        }
+    }
+
+    #[test]
+    fn test_calendar_conversions() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 3, 1), 19_783);
+        assert_eq!(year_month(19_783), (2024, 3));
+        assert_eq!(year_month(19_782), (2024, 2));
+    }
+
+    #[test]
+    fn test_daily_and_monthly_caps_reset_on_calendar_boundaries() {
+        let tracker = QuotaTracker::new();
+        let quota = quota();
+        // 2024-01-31T12:00:00Z
+        let jan_31 = days_from_civil(2024, 1, 31) * SECONDS_PER_DAY + 12 * 3600;
+
+        tracker.record_at("client-key", 100, jan_31);
+        let status = tracker.status_at(&quota, jan_31);
+        assert_eq!(status.exceeded(), Some("daily"));
+        assert_eq!(status.monthly.as_ref().unwrap().remaining, 50);
+        assert_eq!(
+            status.daily.unwrap().resets_at,
+            days_from_civil(2024, 2, 1) * SECONDS_PER_DAY
+        );
+
+        // A new day in a new month resets both windows.
+        let feb_1 = jan_31 + SECONDS_PER_DAY;
+        assert_eq!(tracker.status_at(&quota, feb_1).exceeded(), None);
+        tracker.record_at("client-key", 60, feb_1);
+        tracker.record_at("client-key", 90, feb_1 + SECONDS_PER_DAY);
+        let status = tracker.status_at(&quota, feb_1 + SECONDS_PER_DAY);
+        assert_eq!(status.daily.as_ref().unwrap().used, 90);
+        assert_eq!(status.exceeded(), Some("monthly"));
+    }
+
+    #[test]
+    fn test_requests_in_flight_reserve_the_allowance() {
+        let tracker = Arc::new(QuotaTracker::new());
+        let quota = quota();
+        // Usage is charged at the current time.
+        let now = unix_now();
+
+        let first = tracker.reserve_at(&quota, 60, now).unwrap();
+        let second = tracker.reserve_at(&quota, 60, now).unwrap();
+        // Nothing is charged yet, but nothing is left either.
+        let status = tracker.status_at(&quota, now);
+        assert_eq!(status.daily.as_ref().unwrap().reserved, 120);
+        assert_eq!(status.daily.as_ref().unwrap().used, 0);
+        assert_eq!(tracker.reserve_at(&quota, 1, now).unwrap_err(), "daily");
+
+        // Charging replaces the reservation with the reported usage.
+        first.record(&serde_json::json!({"usage": {"total_tokens": 30}}));
+        let status = tracker.status_at(&quota, now).daily.unwrap();
+        assert_eq!(
+            (status.used, status.reserved, status.remaining),
+            (30, 60, 10)
+        );
+        drop(second);
+        let status = tracker.status_at(&quota, now).daily.unwrap();
+        assert_eq!(
+            (status.used, status.reserved, status.remaining),
+            (30, 0, 70)
+        );
+    }
+}
@@ -0,0 +1,328 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rate Limit
+use crate::config::{PerIpRateLimit, RateLimitConfig};
+use http::HeaderMap;
+use ipnet::IpNet;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+/// Names the limit a 429 response was caused by.
+pub const RATE_LIMIT_SCOPE_HEADER: &str = "x-ratelimit-scope";
+pub const RATE_LIMIT_SCOPE_GLOBAL: &str = "global";
+pub const RATE_LIMIT_SCOPE_POLICY: &str = "policy";
+
+/// The IP a request is attributed to. `X-Forwarded-For` is only honored when
+/// the peer is a trusted proxy; it is then walked from the right, skipping
+/// further trusted proxies, so a client cannot pick its own address by
+/// prepending entries.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let mut client = peer;
+    let hops = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !is_trusted(&ip) {
+                    break;
+                }
+            }
+            // Anything left of a malformed entry cannot be trusted.
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+/// Request counts of the current and previous fixed window. The rate over
+/// the sliding window is estimated by weighting the previous count by how
+/// much of it the sliding window still overlaps.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    start: Instant,
+    current: u32,
+    previous: u32,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Window {
+            start: now,
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    fn advance(&mut self, now: Instant, length: Duration) {
+        let elapsed = now.duration_since(self.start);
+        if elapsed >= length * 2 {
+            *self = Window::new(now);
+        } else if elapsed >= length {
+            self.previous = self.current;
+            self.current = 0;
+            self.start += length;
+        }
+    }
+
+    /// Admits one request, or returns how long until one would be admitted.
+    fn admit(&mut self, now: Instant, limit: &PerIpRateLimit) -> Result<(), Duration> {
+        let length = Duration::from_secs(limit.window_secs);
+        self.advance(now, length);
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        let length_secs = length.as_secs_f64();
+        let overlap = 1.0 - elapsed / length_secs;
+        let estimate = self.previous as f64 * overlap + self.current as f64;
+        if estimate + 1.0 <= limit.requests as f64 {
+            self.current += 1;
+            return Ok(());
+        }
+
+        let remaining = length_secs - elapsed;
+        let headroom = limit.requests as f64 - self.current as f64 - 1.0;
+        let wait = if headroom >= 0.0 && self.previous > 0 {
+            // The previous window's weight falls until the request fits.
+            length_secs * (1.0 - headroom / self.previous as f64) - elapsed
+        } else {
+            remaining
+        };
+        Err(Duration::from_secs_f64(wait.clamp(0.0, remaining)))
+    }
+
+    fn is_idle(&self, now: Instant, length: Duration) -> bool {
+        now.duration_since(self.start) >= length * 2
+    }
+}
+
+/// Windows of the tracked keys, with when each was last used.
+#[derive(Debug)]
+struct Tracked<K> {
+    windows: HashMap<K, (Window, u64)>,
+    /// Keys by their last use, least recent first.
+    recency: BTreeMap<u64, K>,
+    uses: u64,
+}
+
+/// Sliding-window request counts per key, by default the client IP, bounded
+/// to `max_tracked_ips` entries. The least recently used keys are evicted
+/// first.
+#[derive(Debug)]
+pub struct RateLimiter<K = IpAddr> {
+    tracked: Mutex<Tracked<K>>,
+}
+
+impl<K> Default for RateLimiter<K> {
+    fn default() -> Self {
+        RateLimiter {
+            tracked: Mutex::new(Tracked {
+                windows: HashMap::new(),
+                recency: BTreeMap::new(),
+                uses: 0,
+            }),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Counts a request from `ip`. Returns the time to wait before retrying
+    /// when the limit is reached.
+    pub fn check(&self, ip: IpAddr, config: &RateLimitConfig) -> Result<(), Duration> {
+        match &config.per_ip {
+            Some(limit) => self.check_at(ip, config, limit, Instant::now()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a request for `key` against `limit`, e.g. a policy's own limit.
+    /// Returns the time to wait before retrying when the limit is reached.
+    pub fn check_limit(
+        &self,
+        key: K,
+        config: &RateLimitConfig,
+        limit: &PerIpRateLimit,
+    ) -> Result<(), Duration> {
+        self.check_at(key, config, limit, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        key: K,
+        config: &RateLimitConfig,
+        limit: &PerIpRateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let length = Duration::from_secs(limit.window_secs);
+        let capacity = config.max_tracked_ips.max(1);
+        let mut tracked = self.tracked.lock().expect("rate limit lock poisoned");
+        let Tracked {
+            windows,
+            recency,
+            uses,
+        } = &mut *tracked;
+        *uses += 1;
+        match windows.get(&key) {
+            Some((_, last_use)) => {
+                recency.remove(last_use);
+            }
+            None if windows.len() >= capacity => {
+                // Makes room with the least recently used key, and drops the
+                // idle ones after it: they carry no state a new window would
+                // not have. Each key is removed at most once, so the loop
+                // costs no more than the insertions did.
+                while let Some(entry) = recency.first_entry() {
+                    let idle = windows[entry.get()].0.is_idle(now, length);
+                    if !idle && windows.len() < capacity {
+                        break;
+                    }
+                    windows.remove(&entry.remove());
+                }
+            }
+            None => {}
+        }
+        recency.insert(*uses, key.clone());
+        let (window, last_use) = windows.entry(key).or_insert_with(|| (Window::new(now), 0));
+        *last_use = *uses;
+        window.admit(now, limit)
+    }
+
+    pub fn tracked_ips(&self) -> usize {
+        let tracked = self.tracked.lock().expect("rate limit lock poisoned");
+        tracked.windows.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn config(requests: u32, max_tracked_ips: usize) -> (RateLimitConfig, PerIpRateLimit) {
+        let limit = PerIpRateLimit {
+            requests,
+            window_secs: 10,
+        };
+        let config = RateLimitConfig {
+            per_ip: Some(limit),
+            max_tracked_ips,
+            ..RateLimitConfig::default()
+        };
+        (config, limit)
+    }
+
+    #[test]
+    fn test_sliding_window_weights_previous_window() {
+        let (config, limit) = config(4, 10);
+        let limiter = RateLimiter::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        for _ in 0..4 {
+            assert!(limiter.check_at(ip, &config, &limit, at(0)).is_ok());
+        }
+        let wait = limiter.check_at(ip, &config, &limit, at(0)).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(10));
+
+        // Halfway into the next window half of the previous 4 still count.
+        assert!(limiter.check_at(ip, &config, &limit, at(15)).is_ok());
+        assert!(limiter.check_at(ip, &config, &limit, at(15)).is_ok());
+        assert!(limiter.check_at(ip, &config, &limit, at(15)).is_err());
+        // Another IP has its own window.
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert!(limiter.check_at(other, &config, &limit, at(15)).is_ok());
+    }
+
+    #[test]
+    fn test_tracked_ips_are_bounded() {
+        let (config, limit) = config(1, 3);
+        let limiter = RateLimiter::new();
+        let now = Instant::now();
+        for i in 0..100u8 {
+            let ip = IpAddr::from([10, 0, 1, i]);
+            assert!(limiter.check_at(ip, &config, &limit, now).is_ok());
+        }
+        assert_eq!(limiter.tracked_ips(), 3);
+    }
+
+    #[test]
+    fn test_least_recently_used_ip_is_evicted() {
+        let (config, limit) = config(2, 2);
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let ip = |last: u8| IpAddr::from([10, 0, 2, last]);
+
+        assert!(limiter.check_at(ip(1), &config, &limit, at(0)).is_ok());
+        assert!(limiter.check_at(ip(2), &config, &limit, at(1)).is_ok());
+        assert!(limiter.check_at(ip(1), &config, &limit, at(2)).is_ok());
+        // The third IP takes the place of the second, which was used less
+        // recently even though its window started later.
+        assert!(limiter.check_at(ip(3), &config, &limit, at(3)).is_ok());
+        assert_eq!(limiter.tracked_ips(), 2);
+        assert!(limiter.check_at(ip(1), &config, &limit, at(4)).is_err());
+
+        // Idle windows are dropped along with the evicted one.
+        assert!(limiter.check_at(ip(4), &config, &limit, at(30)).is_ok());
+        assert_eq!(limiter.tracked_ips(), 1);
+    }
+
+    #[test]
+    fn test_forwarded_for_requires_trusted_peer() {
+        let trusted: Vec<IpNet> = vec!["10.1.0.0/16".parse().unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            HeaderValue::from_static("1.2.3.4, 203.0.113.7, 10.1.0.9"),
+        );
+
+        let untrusted_peer = "198.51.100.1".parse().ok();
+        assert_eq!(
+            client_ip(untrusted_peer, &headers, &trusted),
+            untrusted_peer
+        );
+
+        let proxy_peer = "10.1.0.2".parse().ok();
+        assert_eq!(
+            client_ip(proxy_peer, &headers, &trusted),
+            "203.0.113.7".parse().ok()
+        );
+    }
+}
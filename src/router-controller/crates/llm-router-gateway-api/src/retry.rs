@@ -0,0 +1,327 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retry
+use crate::config::{Jitter, RetryConfig};
+use futures_util::StreamExt;
+use http_body_util::BodyDataStream;
+use log::{debug, warn};
+use rand::Rng;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Why an attempt got no response.
+#[derive(Debug, Error)]
+pub enum SendError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    /// No response headers and first body chunk within
+    /// `first_byte_timeout_secs`.
+    #[error("no response within {0:?}")]
+    FirstByteTimeout(Duration),
+}
+
+impl SendError {
+    pub fn is_connect(&self) -> bool {
+        matches!(self, SendError::Request(e) if e.is_connect())
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            SendError::Request(e) => e.is_timeout(),
+            SendError::FirstByteTimeout(_) => true,
+        }
+    }
+}
+
+/// Computes the delay before each retry.
+#[derive(Debug)]
+pub struct Backoff<'a> {
+    config: &'a RetryConfig,
+    attempt: u32,
+    previous_ms: u64,
+}
+
+impl<'a> Backoff<'a> {
+    pub fn new(config: &'a RetryConfig) -> Self {
+        Backoff {
+            config,
+            attempt: 0,
+            previous_ms: config.initial_backoff_ms,
+        }
+    }
+
+    pub fn next_delay(&mut self, rng: &mut impl Rng) -> Duration {
+        let cap = self.config.max_backoff_ms;
+        let exponential = (self.config.initial_backoff_ms as f64
+            * self.config.multiplier.powi(self.attempt as i32))
+        .min(cap as f64) as u64;
+        let delay_ms = match self.config.jitter {
+            Jitter::None => exponential,
+            Jitter::Full => rng.gen_range(0..=exponential),
+            Jitter::Equal => exponential / 2 + rng.gen_range(0..=exponential - exponential / 2),
+            Jitter::Decorrelated => {
+                let low = self.config.initial_backoff_ms.min(cap);
+                let high = self.previous_ms.saturating_mul(3).clamp(low, cap);
+                rng.gen_range(low..=high)
+            }
+        };
+        self.attempt += 1;
+        self.previous_ms = delay_ms;
+        Duration::from_millis(delay_ms)
+    }
+}
+
+/// Whether an attempt may be repeated. Completions are not idempotent: a
+/// request that reached the LLM may have been processed (and billed) even if
+/// no response came back, so repeating it risks a duplicate completion.
+///
+/// Connection errors, including DNS failures and connect timeouts, happen
+/// before anything is sent and are always retryable, as are `502` and `503`.
+/// A timeout after the request was sent, or a `504` from an intermediary, is
+/// only retried when `retry_on_timeout` accepts that risk. The exception is
+/// a stream that sent nothing within the first-byte timeout: it is treated
+/// as stalled and always retried. Callers only set that timeout for streams,
+/// whose headers come before any generation.
+pub fn is_retryable(result: &Result<reqwest::Response, SendError>, retry_on_timeout: bool) -> bool {
+    match result {
+        Ok(response) => match response.status() {
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => true,
+            StatusCode::GATEWAY_TIMEOUT => retry_on_timeout,
+            _ => false,
+        },
+        Err(SendError::FirstByteTimeout(_)) => true,
+        Err(e) if e.is_connect() => true,
+        Err(e) => e.is_timeout() && retry_on_timeout,
+    }
+}
+
+/// Waits for the first chunk of the body and returns a response that still
+/// yields it. Streaming upstreams send their headers right away, so only the
+/// first chunk shows that generation has started.
+async fn with_first_chunk(
+    mut response: reqwest::Response,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let first = response.chunk().await?;
+    let (parts, rest) = http::Response::from(response).into_parts();
+    let body = futures_util::stream::iter(first.map(Ok)).chain(BodyDataStream::new(rest));
+    let body = reqwest::Body::wrap_stream(body);
+    Ok(reqwest::Response::from(http::Response::from_parts(
+        parts, body,
+    )))
+}
+
+/// Calls `send` until it succeeds, fails with a non-retryable error (see
+/// [`is_retryable`]), or `max_retries` is used up, sleeping between attempts.
+/// An attempt that has not produced the response headers and the first body
+/// chunk within `first_byte_timeout` is abandoned. Returns the last result
+/// with the number of retries made.
+pub async fn with_retry<F, Fut>(
+    config: &RetryConfig,
+    retry_on_timeout: bool,
+    first_byte_timeout: Option<Duration>,
+    mut send: F,
+) -> (Result<reqwest::Response, SendError>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<reqwest::Response, reqwest::Error>>,
+{
+    let mut backoff = Backoff::new(config);
+    let mut retries = 0;
+    loop {
+        let result = match first_byte_timeout {
+            Some(limit) => {
+                match tokio::time::timeout(limit, async { with_first_chunk(send().await?).await })
+                    .await
+                {
+                    Ok(result) => result.map_err(SendError::from),
+                    Err(_) => Err(SendError::FirstByteTimeout(limit)),
+                }
+            }
+            None => send().await.map_err(SendError::from),
+        };
+        if !is_retryable(&result, retry_on_timeout) || retries >= config.max_retries {
+            return (result, retries);
+        }
+
+        retries += 1;
+        let delay = backoff.next_delay(&mut rand::thread_rng());
+        match &result {
+            Ok(response) => warn!("LLM returned {}, retrying", response.status()),
+            Err(e) => warn!("LLM request failed, retrying: {}", e),
+        }
+        debug!(
+            "Retry {}/{} in {} ms",
+            retries,
+            config.max_retries,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(jitter: Jitter) -> RetryConfig {
+        RetryConfig {
+            max_retries: 5,
+            initial_backoff_ms: 100,
+            multiplier: 2.0,
+            max_backoff_ms: 500,
+            jitter,
+        }
+    }
+
+    fn delays(config: &RetryConfig) -> Vec<u64> {
+        let mut backoff = Backoff::new(config);
+        let mut rng = rand::thread_rng();
+        (0..5)
+            .map(|_| backoff.next_delay(&mut rng).as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn test_backoff_grows_and_respects_cap_for_each_jitter() {
+        assert_eq!(delays(&config(Jitter::None)), [100, 200, 400, 500, 500]);
+
+        let exponential = [100, 200, 400, 500, 500];
+        for (delay, max) in delays(&config(Jitter::Full)).iter().zip(exponential) {
+            assert!(*delay <= max);
+        }
+        for (delay, max) in delays(&config(Jitter::Equal)).iter().zip(exponential) {
+            assert!(*delay >= max / 2 && *delay <= max);
+        }
+        for delay in delays(&config(Jitter::Decorrelated)) {
+            assert!((100..=500).contains(&delay));
+        }
+    }
+
+    fn immediate() -> RetryConfig {
+        RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            ..config(Jitter::None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_retried_regardless_of_timeout_setting() {
+        // Nothing listens on port 1, so the request is never sent.
+        let client = reqwest::Client::new();
+        let attempts = AtomicU32::new(0);
+        let (result, retries) = with_retry(&immediate(), false, None, || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            client.post("http://127.0.0.1:1/v1/chat/completions").send()
+        })
+        .await;
+        assert!(result.unwrap_err().is_connect());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(retries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_after_send_are_only_retried_when_enabled() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&mock_server)
+            .await;
+        let client = reqwest::Client::new();
+        let send = || {
+            client
+                .post(mock_server.uri())
+                .timeout(Duration::from_millis(50))
+                .send()
+        };
+
+        let (result, retries) = with_retry(&immediate(), false, None, send).await;
+        assert!(result.unwrap_err().is_timeout());
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+        assert_eq!(retries, 0);
+
+        mock_server.reset().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&mock_server)
+            .await;
+        let (result, _) = with_retry(&immediate(), true, None, send).await;
+        assert!(result.unwrap_err().is_timeout());
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_first_byte_timeouts_are_always_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&mock_server)
+            .await;
+        let client = reqwest::Client::new();
+
+        let limit = Some(Duration::from_millis(50));
+        let (result, _) = with_retry(&immediate(), false, limit, || {
+            client.post(mock_server.uri()).send()
+        })
+        .await;
+        assert!(matches!(result, Err(SendError::FirstByteTimeout(_))));
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_first_byte_timeout_covers_streams_stalled_after_headers() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // The first connection sends its headers and then nothing; the
+        // second streams an event.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for stall in [true, false] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await.unwrap();
+                let headers = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                               transfer-encoding: chunked\r\n\r\n";
+                socket.write_all(headers.as_bytes()).await.unwrap();
+                if stall {
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        drop(socket);
+                    });
+                } else {
+                    socket
+                        .write_all(b"9\r\ndata: x\n\n\r\n0\r\n\r\n")
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        let client = reqwest::Client::new();
+        let url = format!("http://{}", address);
+
+        let limit = Some(Duration::from_millis(200));
+        let (result, retries) =
+            with_retry(&immediate(), false, limit, || client.post(&url).send()).await;
+        assert_eq!(retries, 1);
+        assert_eq!(result.unwrap().text().await.unwrap(), "data: x\n\n");
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Secrets
+//!
+//! Config string values of the form `<scheme>://<reference>` are replaced by
+//! the secret they name when the config is loaded or reloaded, e.g.
+//! `vault://secret/data/openai#key`.
+use crate::client::apply_tls;
+use crate::config::VaultConfig;
+use crate::error::ConfigError;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Fetches the secrets of one reference scheme.
+pub trait SecretResolver {
+    /// Scheme handled, e.g. `vault` for `vault://...`.
+    fn scheme(&self) -> &'static str;
+
+    /// Secret named by `reference`, the part after `<scheme>://`. Errors
+    /// must not contain the secret.
+    fn resolve(&mut self, reference: &str) -> Result<String, String>;
+}
+
+/// Replaces every string value naming a secret of a known scheme. Fails on
+/// the first secret that cannot be fetched.
+pub fn resolve_secrets(
+    value: &mut serde_yaml::Value,
+    resolvers: &mut [Box<dyn SecretResolver>],
+) -> Result<(), ConfigError> {
+    match value {
+        serde_yaml::Value::String(s) => {
+            let Some((scheme, reference)) = s.split_once("://") else {
+                return Ok(());
+            };
+            let Some(resolver) = resolvers.iter_mut().find(|r| r.scheme() == scheme) else {
+                return Ok(());
+            };
+            *s = resolver
+                .resolve(reference)
+                .map_err(|message| ConfigError::Secret {
+                    path: s.clone(),
+                    message,
+                })?;
+        }
+        serde_yaml::Value::Sequence(items) => {
+            for item in items {
+                resolve_secrets(item, resolvers)?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for item in map.values_mut() {
+                resolve_secrets(item, resolvers)?;
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => resolve_secrets(&mut tagged.value, resolvers)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads `vault://<path>#<key>` from HashiCorp Vault's HTTP API. Both KV
+/// version 1 and 2 paths work, e.g. `secret/data/openai` for version 2.
+pub struct VaultResolver {
+    settings: VaultConfig,
+    /// Secrets fetched during this load, so a path is read once.
+    fetched: HashMap<String, Map<String, Value>>,
+}
+
+impl VaultResolver {
+    pub fn new(settings: VaultConfig) -> Self {
+        VaultResolver {
+            settings,
+            fetched: HashMap::new(),
+        }
+    }
+
+    fn setting(value: &Option<String>, env_var: &str) -> Option<String> {
+        value
+            .clone()
+            .or_else(|| std::env::var(env_var).ok())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn fetch(&self, path: &str) -> Result<Map<String, Value>, String> {
+        let address = Self::setting(&self.settings.address, "VAULT_ADDR")
+            .ok_or("Vault address not set; set secrets.vault.address or VAULT_ADDR")?;
+        let token = Self::setting(&self.settings.token, "VAULT_TOKEN")
+            .ok_or("Vault token not set; set secrets.vault.token or VAULT_TOKEN")?;
+        let namespace = Self::setting(&self.settings.namespace, "VAULT_NAMESPACE");
+        let url = format!(
+            "{}/v1/{}",
+            address.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        );
+        let timeout = Duration::from_secs(self.settings.timeout_secs);
+        let tls = self.settings.tls.clone().unwrap_or_default();
+
+        // Config loading is synchronous and may run on a runtime thread, so
+        // the request gets a thread and runtime of its own.
+        let body = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(|e| format!("failed to start runtime: {}", e))?;
+                    runtime.block_on(async {
+                        let client = apply_tls(reqwest::Client::builder(), &tls)
+                            .map_err(|e| e.to_string())?
+                            .build()
+                            .map_err(|e| format!("failed to build Vault client: {}", e))?;
+                        let mut request = client
+                            .get(&url)
+                            .timeout(timeout)
+                            .header("X-Vault-Token", token);
+                        if let Some(namespace) = namespace {
+                            request = request.header("X-Vault-Namespace", namespace);
+                        }
+                        let response = request
+                            .send()
+                            .await
+                            .map_err(|e| format!("request to Vault failed: {}", e))?;
+                        let status = response.status();
+                        let body: Value = response
+                            .json()
+                            .await
+                            .map_err(|e| format!("invalid response from Vault: {}", e))?;
+                        if !status.is_success() {
+                            let errors = body["errors"]
+                                .as_array()
+                                .map(|errors| {
+                                    errors
+                                        .iter()
+                                        .filter_map(Value::as_str)
+                                        .collect::<Vec<_>>()
+                                        .join("; ")
+                                })
+                                .unwrap_or_default();
+                            return Err(format!("Vault returned {}: {}", status, errors));
+                        }
+                        Ok(body)
+                    })
+                })
+                .join()
+                .unwrap_or_else(|_| Err("Vault request panicked".to_string()))
+        })?;
+
+        // KV version 2 nests the secret one level deeper.
+        let data = match &body["data"]["data"] {
+            Value::Object(data) => data,
+            _ => body["data"]
+                .as_object()
+                .ok_or("Vault response has no data")?,
+        };
+        Ok(data.clone())
+    }
+}
+
+impl SecretResolver for VaultResolver {
+    fn scheme(&self) -> &'static str {
+        "vault"
+    }
+
+    fn resolve(&mut self, reference: &str) -> Result<String, String> {
+        let (path, key) = reference
+            .split_once('#')
+            .ok_or("expected vault://<path>#<key>")?;
+        if !self.fetched.contains_key(path) {
+            let data = self.fetch(path)?;
+            self.fetched.insert(path.to_string(), data);
+        }
+        match self.fetched[path].get(key) {
+            Some(Value::String(secret)) => Ok(secret.clone()),
+            Some(_) => Err(format!("key '{}' is not a string", key)),
+            None => Err(format!("key '{}' not found", key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::RouterConfig;
+    use crate::error::ConfigError;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn yaml(address: &str, api_key: &str) -> String {
+        format!(
+            r#"
+secrets:
+  vault:
+    address: {address}
+    token: test-token
+policies:
+  - name: test_policy
+    url: http://triton:8000/v2/models/router/infer
+    llms:
+      - name: Chatbot
+        api_base: http://nim.internal/v1
+        api_key: {api_key}
+        model: meta/llama-3.1-8b-instruct
+      - name: Coder
+        api_base: http://nim.internal/v1
+        api_key: vault://secret/data/openai#key
+        model: meta/llama-3.1-8b-instruct
+"#
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_vault_references_are_resolved() {
+        let vault = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/secret/data/openai"))
+            .and(header("X-Vault-Token", "test-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": {"data": {"key": "sk-from-vault", "count": 3}, "metadata": {}}
+            })))
+            // Once per load, however many references name the path.
+            .expect(2)
+            .mount(&vault)
+            .await;
+
+        let config =
+            RouterConfig::from_yaml(&yaml(&vault.uri(), "vault://secret/data/openai#key")).unwrap();
+        assert_eq!(config.policies[0].llms[0].api_key, "sk-from-vault");
+        assert_eq!(config.policies[0].llms[1].api_key, "sk-from-vault");
+        assert_eq!(
+            config.sanitized().secrets.vault.unwrap().token.unwrap(),
+            "[REDACTED]"
+        );
+
+        let error =
+            RouterConfig::from_yaml(&yaml(&vault.uri(), "vault://secret/data/openai#count"))
+                .unwrap_err();
+        assert!(matches!(error, ConfigError::Secret { .. }));
+        assert_eq!(
+            error.to_string(),
+            "Failed to resolve secret 'vault://secret/data/openai#count': key 'count' is not a string"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_vault_errors_name_the_path() {
+        let vault = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(403).set_body_json(json!({"errors": ["permission denied"]})),
+            )
+            .mount(&vault)
+            .await;
+
+        let error = RouterConfig::from_yaml(&yaml(&vault.uri(), "plain-key")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to resolve secret 'vault://secret/data/openai#key': Vault returned 403 Forbidden: permission denied"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_vault_connections_use_the_configured_tls() {
+        let vault = MockServer::start().await;
+        let tls = "\nclient:\n  tls:\n    ca_cert_path: /nonexistent/ca.pem\n";
+        let config = yaml(&vault.uri(), "plain-key") + tls;
+        let error = RouterConfig::from_yaml(&config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to resolve secret 'vault://secret/data/openai#key': Invalid TLS file \
+             '/nonexistent/ca.pem': No such file or directory (os error 2)"
+        );
+        assert!(vault.received_requests().await.unwrap().is_empty());
+    }
+}
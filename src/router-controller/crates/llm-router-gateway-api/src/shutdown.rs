@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shutdown
+use crate::error::GatewayApiError;
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::combinators::BoxBody;
+use log::{info, warn};
+use pin_project_lite::pin_project;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Coordinates a graceful shutdown: once draining starts the readiness probe
+/// reports `Draining`, and the server waits for in-flight requests to finish.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownCoordinator {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl ShutdownCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_drain(&self) {
+        self.inner.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Registers an in-flight request. The request counts as active until the
+    /// returned guard is dropped.
+    pub fn track(&self) -> InFlightGuard {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Waits until there are no in-flight requests or `timeout` elapses.
+    /// Returns `true` if every request finished in time.
+    pub async fn wait_for_drain(&self, timeout: Duration) -> bool {
+        let drained = async {
+            loop {
+                let idle = self.inner.idle.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        };
+
+        match tokio::time::timeout(timeout, drained).await {
+            Ok(()) => {
+                info!("All in-flight requests completed");
+                true
+            }
+            Err(_) => {
+                warn!(
+                    "Drain timeout of {:?} elapsed with {} request(s) still in flight",
+                    timeout,
+                    self.in_flight()
+                );
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InFlightGuard {
+    inner: Arc<Inner>,
+}
+
+impl InFlightGuard {
+    /// Ties the guard to a response body so streaming responses stay tracked
+    /// until the last frame is sent or the client goes away.
+    pub fn attach(self, body: BoxBody<Bytes, GatewayApiError>) -> BoxBody<Bytes, GatewayApiError> {
+        BoxBody::new(TrackedBody {
+            inner: body,
+            _guard: self,
+        })
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+pin_project! {
+    struct TrackedBody {
+        #[pin]
+        inner: BoxBody<Bytes, GatewayApiError>,
+        _guard: InFlightGuard,
+    }
+}
+
+impl Body for TrackedBody {
+    type Data = Bytes;
+    type Error = GatewayApiError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.project().inner.poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Resolves when the process receives SIGTERM or Ctrl-C.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_for_drain_completes_when_requests_finish() {
+        let coordinator = ShutdownCoordinator::new();
+        let guard = coordinator.track();
+        coordinator.begin_drain();
+        assert!(coordinator.is_draining());
+
+        let waiter = coordinator.clone();
+        let handle =
+            tokio::spawn(async move { waiter.wait_for_drain(Duration::from_secs(5)).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(guard);
+
+        assert!(handle.await.unwrap());
+        assert_eq!(coordinator.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_drain_times_out() {
+        let coordinator = ShutdownCoordinator::new();
+        let _guard = coordinator.track();
+        assert!(!coordinator.wait_for_drain(Duration::from_millis(20)).await);
+        assert_eq!(coordinator.in_flight(), 1);
+    }
+}
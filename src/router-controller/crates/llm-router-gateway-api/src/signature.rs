@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signature
+//!
+//! Verification of the config file against a detached signature, so that a
+//! config that was changed after it was signed is never loaded.
+use crate::error::ConfigError;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+
+/// Path of the PEM public key config files must be signed with. Configs are
+/// loaded without verification when unset. It is read from the environment
+/// rather than the config, which could otherwise name its own key.
+pub const CONFIG_PUBLIC_KEY_ENV: &str = "LLM_ROUTER_CONFIG_PUBLIC_KEY";
+
+/// Suffix of the detached signature next to the config file.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// `SHA256:<hex>` of the DER encoding of a public key.
+fn fingerprint(key: &PKey<Public>) -> Result<String, openssl::error::ErrorStack> {
+    let digest = openssl::sha::sha256(&key.public_key_to_der()?);
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("SHA256:{}", hex))
+}
+
+/// Checks `content`, read from `config_path`, against the signature in
+/// `<config_path>.sig` made with the private half of the PEM key at
+/// `public_key_path`: Ed25519, or RSA or ECDSA over SHA-256 as made by
+/// `openssl dgst -sha256 -sign`. Returns the key's fingerprint.
+pub fn verify_config(
+    config_path: &str,
+    content: &[u8],
+    public_key_path: &str,
+) -> Result<String, ConfigError> {
+    let signature_path = format!("{}{}", config_path, SIGNATURE_SUFFIX);
+    let error = |message: String| ConfigError::Signature {
+        path: config_path.to_string(),
+        message,
+    };
+
+    let pem = std::fs::read(public_key_path)
+        .map_err(|e| error(format!("cannot read public key {}: {}", public_key_path, e)))?;
+    let key = PKey::public_key_from_pem(&pem)
+        .map_err(|e| error(format!("invalid public key {}: {}", public_key_path, e)))?;
+    let signature = std::fs::read(&signature_path)
+        .map_err(|e| error(format!("cannot read signature {}: {}", signature_path, e)))?;
+
+    let verified = if key.id() == Id::ED25519 {
+        Verifier::new_without_digest(&key)
+            .and_then(|mut verifier| verifier.verify_oneshot(&signature, content))
+    } else {
+        Verifier::new(MessageDigest::sha256(), &key).and_then(|mut verifier| {
+            verifier.update(content)?;
+            verifier.verify(&signature)
+        })
+    };
+    match verified {
+        Ok(true) => fingerprint(&key).map_err(|e| error(e.to_string())),
+        // OpenSSL reports some malformed signatures as errors, others as a
+        // mismatch.
+        Ok(false) | Err(_) => Err(error(format!(
+            "does not match signature {}",
+            signature_path
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::sign::Signer;
+
+    #[test]
+    fn test_config_must_match_detached_signature() {
+        let dir = format!("llm-router-signature-{}", std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.yaml").to_string_lossy().into_owned();
+        let key_path = dir.join("public.pem").to_string_lossy().into_owned();
+
+        let key = PKey::generate_ed25519().unwrap();
+        std::fs::write(&key_path, key.public_key_to_pem().unwrap()).unwrap();
+        let content = b"policies: []\n";
+        let signature = Signer::new_without_digest(&key)
+            .unwrap()
+            .sign_oneshot_to_vec(content)
+            .unwrap();
+        std::fs::write(format!("{}.sig", config_path), signature).unwrap();
+
+        let fingerprint = verify_config(&config_path, content, &key_path).unwrap();
+        assert!(fingerprint.starts_with("SHA256:"));
+        assert_eq!(fingerprint.len(), "SHA256:".len() + 64);
+
+        let error = verify_config(&config_path, b"policies: [tampered]\n", &key_path);
+        assert!(matches!(error, Err(ConfigError::Signature { .. })));
+
+        std::fs::remove_file(format!("{}.sig", config_path)).unwrap();
+        let error = verify_config(&config_path, content, &key_path).unwrap_err();
+        assert!(
+            error.to_string().contains("cannot read signature"),
+            "{}",
+            error
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State
+use crate::auth::ExternalAuthLayer;
+use crate::balancer::LoadBalancer;
+use crate::bulkhead::Bulkhead;
+use crate::cache::ResponseCache;
+use crate::circuit_breaker::{CircuitBreakerRegistry, StateChangeHook};
+use crate::client::{create_http_client, UpstreamClients};
+use crate::coalesce::RequestCoalescer;
+use crate::config::RouterConfig;
+use crate::config_manager::ConfigManager;
+use crate::error::ConfigError;
+use crate::events::EventBus;
+use crate::health::HealthCache;
+use crate::logging::BodyLogger;
+use crate::quota::QuotaTracker;
+use crate::rate_limit::RateLimiter;
+use crate::shutdown::ShutdownCoordinator;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Peer address of the connection a request arrived on, stored in the
+/// request extensions.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// Shared state handed to every request handler.
+#[derive(Debug, Clone)]
+pub struct AppState {
+    /// Snapshot of the live config taken when the request started.
+    pub config: RouterConfig,
+    pub config_manager: ConfigManager,
+    /// Client for Triton and health checks.
+    pub client: reqwest::Client,
+    /// Clients for the LLMs, honouring their pool settings.
+    pub upstream_clients: Arc<UpstreamClients>,
+    pub shutdown: ShutdownCoordinator,
+    pub health_cache: HealthCache,
+    pub cache: Arc<ResponseCache>,
+    /// Identical cacheable requests in flight upstream.
+    pub coalescer: Arc<RequestCoalescer>,
+    pub balancer: Arc<LoadBalancer>,
+    /// Built from `config` and rebuilt on reload.
+    pub body_logger: Arc<BodyLogger>,
+    pub quota: Arc<QuotaTracker>,
+    pub circuit_breakers: Arc<CircuitBreakerRegistry>,
+    pub bulkhead: Arc<Bulkhead>,
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Request counts per policy and client IP, for `Policy::rate_limit`.
+    pub policy_rate_limiter: Arc<RateLimiter<(String, IpAddr)>>,
+    /// Credentials recently allowed by `security.external_auth`.
+    pub external_auth: Arc<ExternalAuthLayer>,
+    /// Routing decisions for `GET /admin/events`.
+    pub events: EventBus,
+}
+
+impl AppState {
+    pub fn new(config: RouterConfig) -> Result<Self, ConfigError> {
+        let client = create_http_client(&config.client)?;
+        let upstream_clients = Arc::new(UpstreamClients::new(&config.client, client.clone()));
+        let cache = Arc::new(ResponseCache::new(&config.caching));
+        let config_manager = ConfigManager::new(config.clone(), None)?;
+        let (_, body_logger) = config_manager.current_with_body_logger();
+        let mut circuit_breakers = CircuitBreakerRegistry::new(&config.circuit_breaker);
+        if let Some(url) = &config.circuit_breaker.webhook_url {
+            let hook = StateChangeHook::webhook(client.clone(), url.clone());
+            circuit_breakers = circuit_breakers.with_state_change_hook(hook);
+        }
+        let circuit_breakers = Arc::new(circuit_breakers);
+        let bulkhead = Arc::new(Bulkhead::new(&config.server.concurrency));
+        let balancer = Arc::new(match config.load_balancing.seed {
+            Some(seed) => LoadBalancer::seeded(seed),
+            None => LoadBalancer::new(),
+        });
+        Ok(AppState {
+            config_manager,
+            config,
+            client,
+            upstream_clients,
+            shutdown: ShutdownCoordinator::new(),
+            health_cache: HealthCache::new(),
+            cache,
+            coalescer: Arc::new(RequestCoalescer::new()),
+            balancer,
+            body_logger,
+            quota: Arc::new(QuotaTracker::new()),
+            circuit_breakers,
+            bulkhead,
+            rate_limiter: Arc::new(RateLimiter::new()),
+            policy_rate_limiter: Arc::new(RateLimiter::new()),
+            external_auth: Arc::new(ExternalAuthLayer::new()),
+            events: EventBus::new(),
+        })
+    }
+
+    /// Enables `ConfigManager::reload` from the file at `path`.
+    pub fn with_config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_manager = self.config_manager.with_path(path.into());
+        self
+    }
+
+    /// Replaces the config snapshot and body logger with the live ones.
+    pub fn refresh_config(&mut self) {
+        let (config, body_logger) = self.config_manager.current_with_body_logger();
+        self.config = config.as_ref().clone();
+        self.body_logger = body_logger;
+    }
+}
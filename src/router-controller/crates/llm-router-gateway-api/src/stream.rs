@@ -14,22 +14,246 @@
 // limitations under the License.
 
 //! Stream
+use crate::balancer::InFlightGuard;
+use crate::bulkhead::ConcurrencyPermit;
 use crate::error::GatewayApiError;
-use crate::metrics::track_token_usage;
-use bytes::Bytes;
+use crate::metrics::{UsageAccounting, OUTPUT_TOKENS_PER_SECOND, STREAM_DISCONNECTS};
+use bytes::{Bytes, BytesMut};
 use futures_util::Stream;
 use http_body::Frame;
 use log::{debug, info, warn};
 use pin_project_lite::pin_project;
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::future::Future;
 use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+
+/// SSE comment sent while waiting for the first chunk. Clients ignore
+/// comment lines, but the bytes keep intermediaries from closing the
+/// connection as idle.
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
 
 pin_project! {
+    /// Relays an upstream SSE stream to the client. Chunks are only pulled
+    /// from upstream when hyper polls for the next frame, i.e. once the
+    /// previous one has been written, so a slow client holds back the
+    /// upstream instead of growing a buffer. When the client disconnects
+    /// hyper drops the body, which drops the upstream response and closes
+    /// its connection.
     pub struct ReqwestStreamAdapter {
         #[pin]
         pub inner: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send + Sync>>,
         pub llm_name: String,
+        // Charged once, when the stream ends, then cleared.
+        pub usage: Option<UsageAccounting>,
+        // Last event with usage. Providers may report usage cumulatively on
+        // several chunks, so only the latest counts.
+        reported_usage: Option<Value>,
+        // Keeps the upstream instance counted as busy until the stream ends.
+        pub in_flight: Option<InFlightGuard>,
+        // Holds the request's concurrency slots until the stream ends.
+        pub permit: Option<ConcurrencyPermit>,
+        finished: bool,
+        // Content deltas relayed so far, and when the first and last arrived.
+        deltas: u64,
+        content_span: Option<(Instant, Instant)>,
+        // Pending until the next keep-alive; cleared by the first chunk.
+        keep_alive: Option<(Duration, Pin<Box<Sleep>>)>,
+        // Ends the stream with a truncation chunk when upstream fails.
+        pub truncate_on_error: bool,
+        // Last event with choices, whose fields the truncation chunk reuses.
+        last_event: Option<Value>,
+        // Start of an event whose end has not arrived yet, if the last
+        // chunk stopped inside one.
+        partial_event: BytesMut,
+        // Set for LLMs with `estimate_stream_usage`: a usage chunk is added
+        // before `[DONE]` when upstream sends none.
+        pub estimated_prompt_tokens: Option<u64>,
+        // Characters of content relayed so far.
+        content_chars: u64,
+    }
+
+    impl PinnedDrop for ReqwestStreamAdapter {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if !*this.finished {
+                info!("Client disconnected before the {} stream finished", this.llm_name);
+                STREAM_DISCONNECTS.with_label_values(&[this.llm_name.as_str()]).inc();
+            }
+            ReqwestStreamAdapter::charge_usage(this.usage, this.reported_usage);
+        }
+    }
+}
+
+impl ReqwestStreamAdapter {
+    pub fn new(
+        inner: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send + Sync>>,
+        llm_name: String,
+    ) -> Self {
+        ReqwestStreamAdapter {
+            inner,
+            usage: Some(UsageAccounting::new(&llm_name)),
+            reported_usage: None,
+            llm_name,
+            in_flight: None,
+            permit: None,
+            finished: false,
+            deltas: 0,
+            content_span: None,
+            keep_alive: None,
+            truncate_on_error: false,
+            last_event: None,
+            partial_event: BytesMut::new(),
+            estimated_prompt_tokens: None,
+            content_chars: 0,
+        }
+    }
+
+    /// Final events of a stream cut short: a chunk finishing every choice of
+    /// `last_event` with `finish_reason: "length"`, then `[DONE]`.
+    fn truncation_events(last_event: Option<&Value>, mid_event: bool) -> Bytes {
+        let last_event = last_event.unwrap_or(&Value::Null);
+        let completion = last_event["object"] == "text_completion";
+        let indices: Vec<Value> = match last_event["choices"].as_array() {
+            Some(choices) if !choices.is_empty() => choices
+                .iter()
+                .map(|choice| choice.get("index").cloned().unwrap_or(json!(0)))
+                .collect(),
+            _ => vec![json!(0)],
+        };
+        let choices: Vec<Value> = indices
+            .into_iter()
+            .map(|index| {
+                if completion {
+                    json!({"index": index, "text": "", "finish_reason": "length"})
+                } else {
+                    json!({"index": index, "delta": {}, "finish_reason": "length"})
+                }
+            })
+            .collect();
+        let mut event = json!({"object": "chat.completion.chunk", "choices": choices});
+        for field in ["id", "object", "created", "model"] {
+            if let Some(value) = last_event.get(field) {
+                event[field] = value.clone();
+            }
+        }
+        // Closes an event the upstream left unfinished.
+        let separator = if mid_event { "\n\n" } else { "" };
+        Bytes::from(format!("{}data: {}\n\ndata: [DONE]\n\n", separator, event))
+    }
+
+    /// Content of each streamed choice of an event.
+    fn contents(json: &Value) -> impl Iterator<Item = &str> {
+        json["choices"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|choice| {
+                choice["delta"]["content"]
+                    .as_str()
+                    .or(choice["text"].as_str())
+            })
+    }
+
+    /// Streamed deltas carrying content, roughly one token each.
+    fn count_deltas(json: &Value) -> u64 {
+        Self::contents(json)
+            .filter(|content| !content.is_empty())
+            .count() as u64
+    }
+
+    /// Charges the last usage upstream reported, if any. It is charged
+    /// once, so `usage` is `None` afterwards.
+    fn charge_usage(usage: &mut Option<UsageAccounting>, reported: &mut Option<Value>) {
+        let Some(json) = reported.take() else {
+            return;
+        };
+        if let Some(usage) = usage.take() {
+            info!(
+                "Usage statistics: prompt={}, completion={}, total={}",
+                json["usage"]["prompt_tokens"],
+                json["usage"]["completion_tokens"],
+                json["usage"]["total_tokens"]
+            );
+            usage.record(&json);
+        }
+    }
+
+    /// OpenAI-style usage event, marked with `"estimated": true`, for a
+    /// stream of an LLM with `estimate_stream_usage` whose upstream has not
+    /// reported usage. It is charged here, so `None` once charged.
+    fn estimated_usage(
+        usage: &mut Option<UsageAccounting>,
+        prompt_tokens: Option<u64>,
+        last_event: Option<&Value>,
+        content_chars: u64,
+    ) -> Option<Value> {
+        let prompt_tokens = prompt_tokens?;
+        let usage = usage.take()?;
+        let completion_tokens = estimate_tokens(content_chars);
+        let mut event = json!({
+            "object": "chat.completion.chunk",
+            "choices": [],
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+                "estimated": true,
+            },
+        });
+        let last_event = last_event.unwrap_or(&Value::Null);
+        for field in ["id", "object", "created", "model"] {
+            if let Some(value) = last_event.get(field) {
+                event[field] = value.clone();
+            }
+        }
+        info!("Estimated usage statistics: {}", event["usage"]);
+        usage.record(&event);
+        Some(event)
     }
+
+    /// Sends an SSE comment every `interval` until upstream produces the
+    /// first chunk.
+    pub fn keep_alive(&mut self, interval: Duration) {
+        self.keep_alive = Some((interval, Box::pin(tokio::time::sleep(interval))));
+    }
+}
+
+/// Rough token count of `chars` characters of text, at four per token.
+pub fn estimate_tokens(chars: u64) -> u64 {
+    chars.div_ceil(4)
+}
+
+/// Estimated prompt tokens of a chat completion or completion request: its
+/// message text, or its prompts.
+pub fn estimate_prompt_tokens(request: &Value) -> u64 {
+    let text_len = |value: &Value| match value {
+        Value::String(text) => text.chars().count() as u64,
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.as_str().or(part["text"].as_str()))
+            .map(|text| text.chars().count() as u64)
+            .sum(),
+        _ => 0,
+    };
+    let chars = match request["messages"].as_array() {
+        Some(messages) => messages
+            .iter()
+            .map(|message| text_len(&message["content"]))
+            .sum(),
+        None => text_len(&request["prompt"]),
+    };
+    estimate_tokens(chars)
+}
+
+/// Length of the first SSE event of `events`, with its blank line; all of
+/// `events` when it has no blank line.
+fn event_len(events: &[u8]) -> usize {
+    events
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .map_or(events.len(), |end| end + 2)
 }
 
 impl http_body::Body for ReqwestStreamAdapter {
@@ -40,52 +264,416 @@ impl http_body::Body for ReqwestStreamAdapter {
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        let this = self.project();
-        match this.inner.poll_next(cx) {
-            std::task::Poll::Ready(Some(Ok(chunk))) => {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                for event in chunk_str.split("\n\n") {
-                    let cleaned_event = event.trim().strip_prefix("data: ").unwrap_or(event);
-
-                    if cleaned_event.is_empty() || cleaned_event == "[DONE]" {
-                        continue;
+        let mut this = self.project();
+        if *this.finished {
+            return std::task::Poll::Ready(None);
+        }
+        // Network chunks need not end on event boundaries, so only complete
+        // events are parsed and relayed; the rest waits for the next chunk.
+        // At the end of the stream whatever is left is relayed as is.
+        let (complete, ended) = loop {
+            let polled = this.inner.as_mut().poll_next(cx);
+            if !matches!(polled, std::task::Poll::Pending) {
+                *this.keep_alive = None;
+            }
+            match polled {
+                std::task::Poll::Ready(Some(Ok(chunk))) => {
+                    this.partial_event.extend_from_slice(&chunk);
+                    let end = this
+                        .partial_event
+                        .windows(2)
+                        .rposition(|window| window == b"\n\n");
+                    if let Some(end) = end {
+                        break (this.partial_event.split_to(end + 2).freeze(), false);
+                    }
+                }
+                std::task::Poll::Ready(None) => break (this.partial_event.split().freeze(), true),
+                std::task::Poll::Ready(Some(Err(e))) => {
+                    *this.finished = true;
+                    if !*this.truncate_on_error {
+                        return std::task::Poll::Ready(Some(Err(GatewayApiError::from(e))));
+                    }
+                    warn!(
+                        "The {} stream failed, ending it as truncated: {}",
+                        this.llm_name, e
+                    );
+                    // The start of an unfinished event is relayed before
+                    // the truncation closes it.
+                    let mut events = this.partial_event.split();
+                    let mid_event = !events.is_empty();
+                    events.extend_from_slice(&Self::truncation_events(
+                        this.last_event.as_ref(),
+                        mid_event,
+                    ));
+                    return std::task::Poll::Ready(Some(Ok(Frame::data(events.freeze()))));
+                }
+                std::task::Poll::Pending => {
+                    let Some((interval, sleep)) = this.keep_alive else {
+                        return std::task::Poll::Pending;
+                    };
+                    if sleep.as_mut().poll(cx).is_pending() {
+                        return std::task::Poll::Pending;
                     }
+                    sleep
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + *interval);
+                    debug!("Sending keep-alive while waiting for {}", this.llm_name);
+                    return std::task::Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(
+                        KEEP_ALIVE,
+                    )))));
+                }
+            }
+        };
+
+        let mut relayed = BytesMut::with_capacity(complete.len());
+        let mut rest = &complete[..];
+        while !rest.is_empty() {
+            let (raw, next) = rest.split_at(event_len(rest));
+            rest = next;
+            let event = String::from_utf8_lossy(raw);
+            let event = event.trim();
+            let cleaned_event = event.strip_prefix("data: ").unwrap_or(event);
 
-                    debug!("Processing event: {}", cleaned_event);
-
-                    match serde_json::from_str::<Value>(cleaned_event) {
-                        Ok(json) => {
-                            // Handle final usage statistics
-                            if let Some(finish_reason) =
-                                json["choices"][0]["finish_reason"].as_str()
-                            {
-                                if finish_reason == "stop" {
-                                    if let Some(usage) = json.get("usage") {
-                                        let prompt = usage["prompt_tokens"].as_u64().unwrap_or(0);
-                                        let completion =
-                                            usage["completion_tokens"].as_u64().unwrap_or(0);
-                                        let total = usage["total_tokens"].as_u64().unwrap_or(0);
-                                        info!(
-                                            "Usage statistics: prompt={}, completion={}, total={}",
-                                            prompt, completion, total
-                                        );
-                                        track_token_usage(&json, this.llm_name);
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse JSON: {} in {}", e, cleaned_event);
-                        }
+            if cleaned_event == "[DONE]" {
+                Self::charge_usage(this.usage, this.reported_usage);
+                // Usage estimated for an upstream that sent none goes last,
+                // right before `[DONE]`.
+                let estimated = Self::estimated_usage(
+                    this.usage,
+                    *this.estimated_prompt_tokens,
+                    this.last_event.as_ref(),
+                    *this.content_chars,
+                );
+                if let Some(event) = estimated {
+                    relayed.extend_from_slice(format!("data: {}\n\n", event).as_bytes());
+                }
+                relayed.extend_from_slice(raw);
+                continue;
+            }
+            relayed.extend_from_slice(raw);
+            if cleaned_event.is_empty() {
+                continue;
+            }
+
+            debug!("Processing event: {}", cleaned_event);
+
+            match serde_json::from_str::<Value>(cleaned_event) {
+                Ok(json) => {
+                    *this.content_chars += Self::contents(&json)
+                        .map(|content| content.chars().count() as u64)
+                        .sum::<u64>();
+                    let deltas = Self::count_deltas(&json);
+                    if deltas > 0 {
+                        *this.deltas += deltas;
+                        let now = Instant::now();
+                        let first = this.content_span.map_or(now, |(first, _)| first);
+                        *this.content_span = Some((first, now));
                     }
+                    // Providers send usage with the last choice, in a chunk
+                    // of its own, or as a running total on several chunks;
+                    // the latest is charged at the end.
+                    if json["usage"].is_object() {
+                        *this.reported_usage = Some(json.clone());
+                    }
+                    if (*this.truncate_on_error || this.estimated_prompt_tokens.is_some())
+                        && json["choices"].as_array().is_some_and(|c| !c.is_empty())
+                    {
+                        *this.last_event = Some(json);
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to parse JSON: {} in {}", e, cleaned_event);
                 }
-                std::task::Poll::Ready(Some(Ok(Frame::data(chunk))))
             }
-            std::task::Poll::Ready(Some(Err(e))) => {
-                std::task::Poll::Ready(Some(Err(GatewayApiError::from(e))))
+        }
+
+        if ended {
+            *this.finished = true;
+            // The first delta starts the clock, so it is not counted.
+            if let Some((first, last)) = this.content_span {
+                let elapsed = last.duration_since(*first).as_secs_f64();
+                if *this.deltas > 1 && elapsed > 0.0 {
+                    OUTPUT_TOKENS_PER_SECOND
+                        .with_label_values(&[this.llm_name.as_str()])
+                        .observe((*this.deltas - 1) as f64 / elapsed);
+                }
+            }
+            Self::charge_usage(this.usage, this.reported_usage);
+            // Upstream ended without `[DONE]`, so usage comes last.
+            let estimated = Self::estimated_usage(
+                this.usage,
+                *this.estimated_prompt_tokens,
+                this.last_event.as_ref(),
+                *this.content_chars,
+            );
+            if let Some(event) = estimated {
+                relayed.extend_from_slice(format!("data: {}\n\n", event).as_bytes());
+            }
+            if relayed.is_empty() {
+                return std::task::Poll::Ready(None);
             }
-            std::task::Poll::Ready(None) => std::task::Poll::Ready(None),
-            std::task::Poll::Pending => std::task::Poll::Pending,
         }
+        std::task::Poll::Ready(Some(Ok(Frame::data(relayed.freeze()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::TOKEN_USAGE;
+    use http_body_util::BodyExt;
+
+    fn adapter(chunks: Vec<&'static str>, then_hang: bool) -> ReqwestStreamAdapter {
+        let chunks = futures_util::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from(chunk))),
+        );
+        let inner: Pin<Box<dyn Stream<Item = _> + Send + Sync>> = if then_hang {
+            Box::pin(futures_util::StreamExt::chain(
+                chunks,
+                futures_util::stream::pending(),
+            ))
+        } else {
+            Box::pin(chunks)
+        };
+        ReqwestStreamAdapter::new(inner, "disconnect-test".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_only_unfinished_streams_count_as_disconnects() {
+        let disconnects = || {
+            STREAM_DISCONNECTS
+                .with_label_values(&["disconnect-test"])
+                .get()
+        };
+        let before = disconnects();
+
+        let body = adapter(vec!["data: {}\n\n"], false);
+        body.collect().await.unwrap();
+        assert_eq!(disconnects(), before);
+
+        let mut body = adapter(vec!["data: {}\n\n"], true);
+        body.frame().await.unwrap().unwrap();
+        drop(body);
+        assert_eq!(disconnects(), before + 1);
+    }
+
+    #[tokio::test]
+    async fn test_output_rate_counts_content_deltas() {
+        let delta = |content: &str| {
+            format!(
+                "data: {{\"choices\": [{{\"delta\": {{\"content\": \"{}\"}}}}]}}\n\n",
+                content
+            )
+        };
+        let chunks = [delta(""), delta("Hel"), delta("lo"), delta("!")];
+        let chunks =
+            futures_util::StreamExt::then(futures_util::stream::iter(chunks), |chunk| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, reqwest::Error>(Bytes::from(chunk))
+            });
+        let rate = OUTPUT_TOKENS_PER_SECOND.with_label_values(&["rate-test"]);
+
+        let body = ReqwestStreamAdapter::new(Box::pin(chunks), "rate-test".to_string());
+        body.collect().await.unwrap();
+        assert_eq!(rate.get_sample_count(), 1);
+        // Two deltas after the first, about 50ms apart.
+        let observed = rate.get_sample_sum();
+        assert!(observed > 5.0 && observed <= 20.0, "{}", observed);
+    }
+
+    #[tokio::test]
+    async fn test_cumulative_usage_is_charged_once_with_the_last_total() {
+        let usage = |llm: &str, category: &str| {
+            TOKEN_USAGE
+                .with_label_values(&[llm, category, "false"])
+                .get()
+        };
+        let chunks = || {
+            [
+                r#"data: {"choices": [{"delta": {"content": "A"}}], "usage": {"prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6}}"#,
+                r#"data: {"choices": [{"delta": {"content": "B"}}], "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}}"#,
+                r#"data: {"choices": [{"delta": {}, "finish_reason": "stop"}], "usage": {"prompt_tokens": 5, "completion_tokens": 3, "total_tokens": 8}}"#,
+            ]
+            .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from(format!("{}\n\n", chunk))))
+        };
+
+        let finished = futures_util::StreamExt::chain(
+            futures_util::stream::iter(chunks()),
+            futures_util::stream::once(async { Ok(Bytes::from("data: [DONE]\n\n")) }),
+        );
+        let body = ReqwestStreamAdapter::new(Box::pin(finished), "usage-test".to_string());
+        body.collect().await.unwrap();
+        assert_eq!(usage("usage-test", "prompt"), 5);
+        assert_eq!(usage("usage-test", "completion"), 3);
+        assert_eq!(usage("usage-test", "total"), 8);
+
+        // A client that leaves before `[DONE]` is charged what was reported.
+        let hanging = futures_util::StreamExt::chain(
+            futures_util::stream::iter(chunks()),
+            futures_util::stream::pending(),
+        );
+        let mut body = ReqwestStreamAdapter::new(Box::pin(hanging), "usage-drop-test".to_string());
+        for _ in 0..3 {
+            body.frame().await.unwrap().unwrap();
+        }
+        assert_eq!(usage("usage-drop-test", "total"), 0);
+        drop(body);
+        assert_eq!(usage("usage-drop-test", "total"), 8);
+    }
+
+    #[tokio::test]
+    async fn test_events_split_across_chunks_are_parsed_once_complete() {
+        let total = || {
+            TOKEN_USAGE
+                .with_label_values(&["split-test", "total", "false"])
+                .get()
+        };
+        let chunks = [
+            r#"data: {"choices": [{"delta": {"content": "Hi"}}]}"#,
+            "\n\ndata: {\"choices\": [], \"usage\": {\"total",
+            "_tokens\": 9}}\n",
+            "\ndata: [DONE]\n\n",
+        ]
+        .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from(chunk)));
+
+        let body = ReqwestStreamAdapter::new(
+            Box::pin(futures_util::stream::iter(chunks)),
+            "split-test".to_string(),
+        );
+        let relayed = body.collect().await.unwrap().to_bytes();
+        assert_eq!(total(), 9);
+        assert!(relayed.starts_with(b"data: {\"choices\""));
+        assert!(relayed.ends_with(b"data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_estimated_before_done_when_missing() {
+        let chunks = [
+            r#"data: {"id": "c1", "model": "llama", "choices": [{"delta": {"content": "Hello"}}]}"#,
+            r#"data: {"id": "c1", "choices": [{"delta": {"content": " world!"}}]}"#,
+            "data: [DONE]",
+        ]
+        .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from(format!("{}\n\n", chunk))));
+        let mut body = ReqwestStreamAdapter::new(
+            Box::pin(futures_util::stream::iter(chunks)),
+            "estimate-test".to_string(),
+        );
+        body.estimated_prompt_tokens = Some(estimate_prompt_tokens(&json!({
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [{"type": "text", "text": "Hi there"}]}
+            ]
+        })));
+
+        let output = body.collect().await.unwrap().to_bytes();
+        let output = String::from_utf8(output.to_vec()).unwrap();
+        let (before_done, _) = output.split_once("data: [DONE]").unwrap();
+        let usage_event = before_done.trim_end().rsplit("\n\n").next().unwrap();
+        let event: Value =
+            serde_json::from_str(usage_event.strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(event["id"], "c1");
+        assert_eq!(event["choices"], json!([]));
+        // 17 prompt and 12 completion characters.
+        assert_eq!(
+            event["usage"],
+            json!({"prompt_tokens": 5, "completion_tokens": 3, "total_tokens": 8, "estimated": true})
+        );
+        let total = TOKEN_USAGE.with_label_values(&["estimate-test", "total", "false"]);
+        assert_eq!(total.get(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_estimated_usage_precedes_a_split_done() {
+        let chunks = [
+            "data: {\"choices\": [{\"delta\": {\"content\": \"Hello\"}}]}\n\ndata: [DO",
+            "NE]\n",
+            "\n",
+        ]
+        .map(|chunk| Ok::<_, reqwest::Error>(Bytes::from(chunk)));
+        let mut body = ReqwestStreamAdapter::new(
+            Box::pin(futures_util::stream::iter(chunks)),
+            "estimate-split-test".to_string(),
+        );
+        body.estimated_prompt_tokens = Some(3);
+
+        let output = body.collect().await.unwrap().to_bytes();
+        let output = String::from_utf8(output.to_vec()).unwrap();
+        let events: Vec<&str> = output.split_terminator("\n\n").collect();
+        assert_eq!(events.len(), 3, "{}", output);
+        assert!(events[1].contains("\"estimated\":true"), "{}", output);
+        assert_eq!(events[2], "data: [DONE]");
+        let total = TOKEN_USAGE.with_label_values(&["estimate-split-test", "total", "false"]);
+        assert_eq!(total.get(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_until_first_chunk() {
+        let delayed = futures_util::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(130)).await;
+            Ok::<_, reqwest::Error>(Bytes::from("data: {}\n\n"))
+        });
+        let chunks = futures_util::StreamExt::chain(
+            delayed,
+            futures_util::stream::once(async {
+                tokio::time::sleep(Duration::from_millis(130)).await;
+                Ok::<_, reqwest::Error>(Bytes::from("data: [DONE]\n\n"))
+            }),
+        );
+        let mut body = ReqwestStreamAdapter::new(Box::pin(chunks), "keep-alive-test".to_string());
+        body.keep_alive(Duration::from_millis(50));
+
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert_eq!(
+            text,
+            ": keep-alive\n\n: keep-alive\n\ndata: {}\n\ndata: [DONE]\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_ends_truncated_when_enabled() {
+        // Nothing listens on port 1, so this fails to connect.
+        let error = || async { reqwest::get("http://127.0.0.1:1").await.unwrap_err() };
+        let chunks = || {
+            futures_util::stream::iter([
+                Ok(Bytes::from(
+                    "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":7,\"model\":\"llama\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+                )),
+                Ok(Bytes::from("data: {\"choices\":[{\"ind")),
+            ])
+        };
+
+        let failing = futures_util::StreamExt::chain(
+            chunks(),
+            futures_util::stream::once(async move { Err(error().await) }),
+        );
+        let body = ReqwestStreamAdapter::new(Box::pin(failing), "truncate-test".to_string());
+        assert!(body.collect().await.is_err());
+
+        let failing = futures_util::StreamExt::chain(
+            chunks(),
+            futures_util::stream::once(async move { Err(error().await) }),
+        );
+        let mut body = ReqwestStreamAdapter::new(Box::pin(failing), "truncate-test".to_string());
+        body.truncate_on_error = true;
+        let bytes = body.collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let (relayed, truncation) = text.split_once("ind\n\n").unwrap();
+        assert!(relayed.contains("Hel"));
+        let events: Vec<&str> = truncation.split_terminator("\n\n").collect();
+        assert_eq!(events[1], "data: [DONE]");
+        let event: Value = serde_json::from_str(events[0].strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(
+            event,
+            json!({
+                "id": "c1",
+                "object": "chat.completion.chunk",
+                "created": 7,
+                "model": "llama",
+                "choices": [{"index": 0, "delta": {}, "finish_reason": "length"}]
+            })
+        );
     }
 }
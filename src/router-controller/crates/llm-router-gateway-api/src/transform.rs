@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transform
+//!
+//! Converts OpenAI request and response bodies to and from the shape a
+//! provider expects. Anthropic bodies are translated by [`crate::anthropic`]
+//! and Cohere responses by [`crate::cohere`]; Cohere requests only have their
+//! fields renamed, passing through those without a counterpart.
+use crate::anthropic::{self, to_anthropic_request};
+use crate::cohere;
+use crate::config::ProviderType;
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+/// OpenAI name and provider name of each renamed request field.
+type Renames = &'static [(&'static str, &'static str)];
+
+const COHERE_REQUEST: Renames = &[
+    ("max_completion_tokens", "max_tokens"),
+    ("stop", "stop_sequences"),
+    ("top_p", "p"),
+    ("top_k", "k"),
+];
+
+impl ProviderType {
+    /// Whether bodies are changed on their way to and from the provider.
+    pub fn transforms_body(&self) -> bool {
+        matches!(self, ProviderType::Anthropic | ProviderType::Cohere)
+    }
+}
+
+/// Moves each field named on the OpenAI side of `renames` to the provider's
+/// name. A field is left alone when its new name is already taken.
+fn rename(fields: &mut Map<String, Value>, renames: Renames) {
+    for &(from, to) in renames {
+        if fields.contains_key(to) {
+            continue;
+        }
+        if let Some(value) = fields.remove(from) {
+            fields.insert(to.to_string(), value);
+        }
+    }
+}
+
+/// A request body as the provider expects it.
+pub fn to_provider_request(provider: &ProviderType, mut body: Value) -> Value {
+    match provider {
+        ProviderType::Anthropic => to_anthropic_request(&body),
+        ProviderType::Cohere => {
+            if let Some(fields) = body.as_object_mut() {
+                rename(fields, COHERE_REQUEST);
+            }
+            body
+        }
+        ProviderType::Openai | ProviderType::Azure => body,
+    }
+}
+
+/// A provider's response body as an OpenAI chat completion.
+pub fn from_provider_response(provider: &ProviderType, body: Value) -> Value {
+    match provider {
+        ProviderType::Anthropic => anthropic::to_openai_response(&body),
+        ProviderType::Cohere => cohere::to_openai_response(&body),
+        ProviderType::Openai | ProviderType::Azure => body,
+    }
+}
+
+/// [`from_provider_response`] for a serialized body. Bodies that are not
+/// JSON are returned unchanged.
+pub fn from_provider_response_body(provider: &ProviderType, body: Bytes) -> Bytes {
+    if !provider.transforms_body() {
+        return body;
+    }
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(json) => serde_json::to_vec(&from_provider_response(provider, json))
+            .map(Bytes::from)
+            .unwrap_or(body),
+        Err(_) => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_fields_are_renamed_and_unknown_ones_kept() {
+        let request = json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "max_completion_tokens": 64,
+            "stop": ["\n"],
+            "top_p": 0.9,
+            "top_k": 40,
+            "vendor_extension": true
+        });
+        assert_eq!(
+            to_provider_request(&ProviderType::Openai, request.clone()),
+            request
+        );
+        assert_eq!(
+            to_provider_request(&ProviderType::Cohere, request),
+            json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_tokens": 64,
+                "stop_sequences": ["\n"],
+                "p": 0.9,
+                "k": 40,
+                "vendor_extension": true
+            })
+        );
+
+        // An explicit provider field wins over the OpenAI one.
+        let both = json!({"max_tokens": 10, "max_completion_tokens": 64});
+        assert_eq!(
+            to_provider_request(&ProviderType::Cohere, both.clone()),
+            both
+        );
+    }
+
+    #[test]
+    fn test_anthropic_bodies_are_translated() {
+        let request = json!({
+            "model": "claude-sonnet",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"}
+            ],
+            "max_tokens": 64
+        });
+        let sent = to_provider_request(&ProviderType::Anthropic, request);
+        assert_eq!(sent["system"], "Be brief.");
+        assert_eq!(sent["messages"], json!([{"role": "user", "content": "Hi"}]));
+
+        // As returned by the Messages API, which has no `choices`.
+        let message = Bytes::from_static(
+            br#"{"id":"msg_01","type":"message","role":"assistant","model":"claude-sonnet",
+                "content":[{"type":"text","text":"Hello"}],"stop_reason":"end_turn",
+                "stop_sequence":null,"usage":{"input_tokens":3,"output_tokens":5}}"#,
+        );
+        let converted = from_provider_response_body(&ProviderType::Anthropic, message);
+        let converted: Value = serde_json::from_slice(&converted).unwrap();
+        assert_eq!(converted["choices"][0]["message"]["content"], "Hello");
+        assert_eq!(converted["choices"][0]["finish_reason"], "stop");
+        assert_eq!(converted["usage"]["prompt_tokens"], 3);
+        assert_eq!(converted["usage"]["completion_tokens"], 5);
+    }
+
+    #[test]
+    fn test_cohere_responses_are_translated() {
+        // As returned by Cohere's v2 chat API, which has no `choices`.
+        let body = Bytes::from_static(
+            br#"{"id":"c14c80c3","finish_reason":"COMPLETE","message":{"role":"assistant",
+                "content":[{"type":"text","text":"Hello"}]},"usage":{"billed_units":
+                {"input_tokens":1,"output_tokens":2},"tokens":{"input_tokens":8,"output_tokens":2}}}"#,
+        );
+        let converted = from_provider_response_body(&ProviderType::Cohere, body);
+        let converted: Value = serde_json::from_slice(&converted).unwrap();
+        assert_eq!(converted["choices"][0]["message"]["content"], "Hello");
+        assert_eq!(converted["choices"][0]["finish_reason"], "stop");
+        assert_eq!(converted["usage"]["prompt_tokens"], 8);
+        assert_eq!(converted["usage"]["completion_tokens"], 2);
+        let not_json = Bytes::from_static(b"upstream error");
+        assert_eq!(
+            from_provider_response_body(&ProviderType::Cohere, not_json.clone()),
+            not_json
+        );
+    }
+}
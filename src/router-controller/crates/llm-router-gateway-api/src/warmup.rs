@@ -0,0 +1,198 @@
+// SPDX-FileCopyrightText: Copyright (c) 2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Warmup
+use crate::anthropic::CHAT_COMPLETIONS_PATH;
+use crate::client::UpstreamClients;
+use crate::config::{Llm, RouterConfig, WarmupConfig};
+use crate::error::GatewayApiError;
+use crate::metrics::WARMUP_DURATION;
+use futures_util::future::join_all;
+use http::header::ACCEPT;
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/// Sends one tiny completion to an instance. Returns how long it took.
+async fn warm_instance(
+    client: reqwest::Client,
+    llm: &Llm,
+    api_base: &str,
+    settings: &WarmupConfig,
+) -> Result<Duration, String> {
+    let (auth_name, auth_value) = llm.auth_header();
+    let start = Instant::now();
+    let response = client
+        .post(llm.upstream_url(api_base, CHAT_COMPLETIONS_PATH))
+        .headers(llm.custom_headers())
+        .header(auth_name, auth_value)
+        .header(ACCEPT, "application/json")
+        .timeout(Duration::from_secs(settings.timeout_secs))
+        .json(&serde_json::json!({
+            "model": llm.model,
+            "messages": [{"role": "user", "content": settings.prompt}],
+            "max_tokens": settings.max_tokens,
+            "stream": false,
+        }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    // Reading the body returns the connection to the pool.
+    let _ = response.bytes().await;
+    if !status.is_success() {
+        return Err(format!("returned {}", status));
+    }
+    Ok(start.elapsed())
+}
+
+/// Warms every instance of every configured LLM concurrently, so the first
+/// user requests find pooled connections and paged-in models. Failures are
+/// logged; they are only returned when `server.warmup_required` is set.
+pub async fn warmup(
+    config: &RouterConfig,
+    clients: &UpstreamClients,
+) -> Result<(), GatewayApiError> {
+    let Some(settings) = &config.server.warmup else {
+        return Ok(());
+    };
+
+    let mut seen = BTreeSet::new();
+    let targets: Vec<(&Llm, &str)> = config
+        .policies
+        .iter()
+        .flat_map(|policy| &policy.llms)
+        .flat_map(|llm| {
+            llm.api_bases()
+                .into_iter()
+                .map(move |api_base| (llm, api_base))
+        })
+        .filter(|(llm, api_base)| seen.insert((llm.model.as_str(), *api_base)))
+        .collect();
+    info!("Warming up {} LLM instance(s)", targets.len());
+
+    let results = join_all(targets.iter().map(|(llm, api_base)| async move {
+        (
+            llm,
+            api_base,
+            warm_instance(clients.for_llm(llm), llm, api_base, settings).await,
+        )
+    }))
+    .await;
+
+    let mut slowest: BTreeMap<&str, Duration> = BTreeMap::new();
+    let mut failed = Vec::new();
+    for (llm, api_base, result) in results {
+        match result {
+            Ok(elapsed) => {
+                info!("Warmed up {} at {} in {:?}", llm.name, api_base, elapsed);
+                let entry = slowest.entry(llm.model.as_str()).or_default();
+                *entry = (*entry).max(elapsed);
+            }
+            Err(e) => {
+                warn!("Warmup of {} at {} failed: {}", llm.name, api_base, e);
+                failed.push(format!("{} ({})", llm.name, api_base));
+            }
+        }
+    }
+    for (model, elapsed) in slowest {
+        WARMUP_DURATION
+            .with_label_values(&[model])
+            .set(elapsed.as_secs_f64());
+    }
+
+    if config.server.warmup_required && !failed.is_empty() {
+        return Err(GatewayApiError::Infrastructure(format!(
+            "Warmup failed for {}",
+            failed.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ClientConfig, Policy, ServerConfig};
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn clients() -> UpstreamClients {
+        UpstreamClients::new(&ClientConfig::default(), reqwest::Client::new())
+    }
+
+    fn config(api_base: &str, required: bool) -> RouterConfig {
+        RouterConfig {
+            policies: vec![Policy {
+                name: "test_policy".to_string(),
+                url: "http://triton:8000".to_string(),
+                llms: vec![Llm {
+                    name: "warm".to_string(),
+                    api_base: api_base.to_string(),
+                    api_key: "test-key".to_string(),
+                    model: "warmup-test-model".to_string(),
+                    ..Llm::default()
+                }],
+                ..Policy::default()
+            }],
+            server: ServerConfig {
+                warmup: Some(WarmupConfig::default()),
+                warmup_required: required,
+                ..ServerConfig::default()
+            },
+            ..RouterConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warmup_sends_tiny_completion_and_records_duration() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"max_tokens": 1})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let clients = clients();
+        warmup(&config(&mock_server.uri(), true), &clients)
+            .await
+            .unwrap();
+        assert!(
+            WARMUP_DURATION
+                .with_label_values(&["warmup-test-model"])
+                .get()
+                > 0.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_warmup_failure_only_blocks_when_required() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let clients = clients();
+        assert!(warmup(&config(&mock_server.uri(), false), &clients)
+            .await
+            .is_ok());
+        assert!(warmup(&config(&mock_server.uri(), true), &clients)
+            .await
+            .is_err());
+    }
+}